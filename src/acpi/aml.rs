@@ -0,0 +1,74 @@
+//! Just enough AML scanning to find sleep state packages
+//!
+//! There is no AML interpreter yet. `_Sx_` objects are almost always
+//! plain `Name(_S5_, Package() { a, b, 0, 0 })` definitions, so a byte
+//! scan of the definition block is enough to read them.
+
+use super::sdt::{Table, SDT_HEADER_SIZE};
+
+const NAME_OP: u8 = 0x08;
+const PACKAGE_OP: u8 = 0x12;
+const ROOT_PREFIX: u8 = 0x5C;
+const ZERO_OP: u8 = 0x00;
+const ONE_OP: u8 = 0x01;
+const BYTE_PREFIX: u8 = 0x0A;
+const ONES_OP: u8 = 0xFF;
+
+/// SLP_TYP values to write to the PM1a/PM1b control registers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SleepType {
+    pub pm1a: u8,
+    pub pm1b: u8,
+}
+
+/// Find the `_S5_` (soft-off) package in a DSDT or SSDT
+pub fn find_s5(table: &Table) -> Option<SleepType> {
+    find_sleep_package(table, b"_S5_")
+}
+
+fn find_sleep_package(table: &Table, name: &[u8; 4]) -> Option<SleepType> {
+    let aml = table.bytes.slice(SDT_HEADER_SIZE, table.bytes.len() - SDT_HEADER_SIZE)?;
+
+    aml.windows(5)
+        .enumerate()
+        .filter(|(_, w)| &w[..4] == name && w[4] == PACKAGE_OP)
+        .filter(|&(i, _)| {
+            // Must be the NameString of a Name() definition
+            i >= 1
+                && (aml[i - 1] == NAME_OP
+                    || (aml[i - 1] == ROOT_PREFIX && i >= 2 && aml[i - 2] == NAME_OP))
+        })
+        .find_map(|(i, _)| parse_package(&aml[i + 5..]))
+}
+
+/// Parse `PkgLength NumElements a b ...` following a PackageOp
+fn parse_package(bytes: &[u8]) -> Option<SleepType> {
+    let lead = *bytes.first()?;
+    let pkg_length_bytes = ((lead >> 6) & 0x3) as usize + 1;
+    let mut rest = bytes.get(pkg_length_bytes..)?;
+
+    let num_elements = *rest.first()?;
+    if num_elements < 1 {
+        return None;
+    }
+    rest = &rest[1..];
+
+    let (pm1a, rest) = parse_integer(rest)?;
+    // Some firmware only provides the PM1a value
+    let pm1b = match num_elements {
+        1 => 0,
+        _ => parse_integer(rest).map(|(value, _)| value).unwrap_or(0),
+    };
+    Some(SleepType { pm1a, pm1b })
+}
+
+/// Parse a small integer constant, returning it and the remaining bytes
+fn parse_integer(bytes: &[u8]) -> Option<(u8, &[u8])> {
+    match *bytes.first()? {
+        ZERO_OP => Some((0, &bytes[1..])),
+        ONE_OP => Some((1, &bytes[1..])),
+        ONES_OP => Some((0xFF, &bytes[1..])),
+        BYTE_PREFIX => Some((*bytes.get(1)?, &bytes[2..])),
+        _ => None,
+    }
+}
@@ -0,0 +1,86 @@
+//! Fixed ACPI Description Table ("FACP")
+
+use super::sdt::{GenericAddress, Table};
+
+/// FADT flag: the reset register is supported
+const FLAG_RESET_REG_SUP: u32 = 1 << 10;
+
+/// FADT flag: the platform is hardware-reduced (no PM1 blocks)
+const FLAG_HW_REDUCED_ACPI: u32 = 1 << 20;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Fadt {
+    pub revision: u8,
+    pub dsdt_address: u64,
+    pub sci_interrupt: u16,
+    pub smi_command_port: u32,
+    pub acpi_enable: u8,
+    pub acpi_disable: u8,
+    pub pm1a_event_block: u32,
+    pub pm1b_event_block: u32,
+    pub pm1a_control_block: u32,
+    pub pm1b_control_block: u32,
+    pub pm_timer_block: u32,
+    pub pm_timer_length: u8,
+    pub century_register: u8,
+    pub iapc_boot_arch: u16,
+    pub flags: u32,
+    pub reset_register: Option<GenericAddress>,
+    pub reset_value: u8,
+}
+
+impl Fadt {
+    /// Whether `reset_register` may be used to reboot
+    pub fn supports_reset_register(&self) -> bool {
+        self.flags & FLAG_RESET_REG_SUP != 0 && self.reset_register.is_some()
+    }
+
+    pub fn hardware_reduced(&self) -> bool {
+        self.flags & FLAG_HW_REDUCED_ACPI != 0
+    }
+
+    /// IA-PC boot architecture flag: an 8042 keyboard controller is present.
+    ///
+    /// ACPI 1.0 tables don't have the field, so assume legacy hardware there.
+    pub fn has_8042(&self) -> bool {
+        self.revision < 2 || self.iapc_boot_arch & 0x2 != 0
+    }
+
+    pub fn parse(table: &Table) -> Option<Self> {
+        let b = table.bytes;
+
+        // ACPI 2.0+ fields; fall back to the 32-bit ones when zero or absent
+        let x_dsdt = b.u64(140).unwrap_or(0);
+        let x_pm1a_cnt = GenericAddress::parse(b, 172).filter(|g| !g.is_null());
+        let x_pm1b_cnt = GenericAddress::parse(b, 184).filter(|g| !g.is_null());
+        let x_pm1a_evt = GenericAddress::parse(b, 148).filter(|g| !g.is_null());
+        let x_pm1b_evt = GenericAddress::parse(b, 160).filter(|g| !g.is_null());
+
+        let io_port = |generic: Option<GenericAddress>, legacy: u32| {
+            generic
+                .filter(|g| g.address_space == GenericAddress::SYSTEM_IO)
+                .map(|g| g.address as u32)
+                .unwrap_or(legacy)
+        };
+
+        Some(Fadt {
+            revision: table.header.revision,
+            dsdt_address: if x_dsdt != 0 { x_dsdt } else { b.u32(40)? as u64 },
+            sci_interrupt: b.u16(46)?,
+            smi_command_port: b.u32(48)?,
+            acpi_enable: b.u8(52)?,
+            acpi_disable: b.u8(53)?,
+            pm1a_event_block: io_port(x_pm1a_evt, b.u32(56)?),
+            pm1b_event_block: io_port(x_pm1b_evt, b.u32(60)?),
+            pm1a_control_block: io_port(x_pm1a_cnt, b.u32(64)?),
+            pm1b_control_block: io_port(x_pm1b_cnt, b.u32(68)?),
+            pm_timer_block: b.u32(76)?,
+            pm_timer_length: b.u8(91)?,
+            century_register: b.u8(108)?,
+            iapc_boot_arch: b.u16(109).unwrap_or(0),
+            flags: b.u32(112)?,
+            reset_register: GenericAddress::parse(b, 116).filter(|g| !g.is_null()),
+            reset_value: b.u8(128).unwrap_or(0),
+        })
+    }
+}
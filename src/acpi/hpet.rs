@@ -0,0 +1,39 @@
+//! High Precision Event Timer description table ("HPET")
+
+use super::sdt::{GenericAddress, Table, SDT_HEADER_SIZE};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Hpet {
+    pub event_timer_block_id: u32,
+    pub base_address: GenericAddress,
+    pub hpet_number: u8,
+    /// Minimum tick in periodic mode, in main counter ticks
+    pub minimum_tick: u16,
+    pub page_protection: u8,
+}
+
+impl Hpet {
+    pub fn vendor_id(&self) -> u16 {
+        (self.event_timer_block_id >> 16) as u16
+    }
+
+    /// Number of comparators in the timer block
+    pub fn comparator_count(&self) -> u8 {
+        (((self.event_timer_block_id >> 8) & 0x1F) + 1) as u8
+    }
+
+    pub fn counter_is_64bit(&self) -> bool {
+        self.event_timer_block_id & (1 << 13) != 0
+    }
+
+    pub fn parse(table: &Table) -> Option<Self> {
+        let b = table.bytes;
+        Some(Hpet {
+            event_timer_block_id: b.u32(SDT_HEADER_SIZE)?,
+            base_address: GenericAddress::parse(b, SDT_HEADER_SIZE + 4)?,
+            hpet_number: b.u8(SDT_HEADER_SIZE + 16)?,
+            minimum_tick: b.u16(SDT_HEADER_SIZE + 17)?,
+            page_protection: b.u8(SDT_HEADER_SIZE + 19)?,
+        })
+    }
+}
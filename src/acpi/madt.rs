@@ -0,0 +1,149 @@
+//! Multiple APIC Description Table ("APIC")
+
+use super::sdt::{Table, SDT_HEADER_SIZE};
+use alloc::vec::Vec;
+
+/// A processor's local APIC (or x2APIC)
+#[derive(Debug, Clone, Copy)]
+pub struct Processor {
+    pub processor_uid: u32,
+    pub apic_id: u32,
+    pub enabled: bool,
+    /// Disabled now but can be brought online later
+    pub online_capable: bool,
+    pub x2apic: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct IoApic {
+    pub id: u8,
+    pub address: u32,
+    pub gsi_base: u32,
+}
+
+/// Remapping of an ISA interrupt to a global system interrupt
+#[derive(Debug, Clone, Copy)]
+pub struct InterruptOverride {
+    pub bus: u8,
+    pub source: u8,
+    pub gsi: u32,
+    pub flags: u16,
+}
+
+impl InterruptOverride {
+    /// Polarity: 0 = bus default, 1 = active high, 3 = active low
+    pub fn polarity(&self) -> u16 {
+        self.flags & 0x3
+    }
+
+    /// Trigger mode: 0 = bus default, 1 = edge, 3 = level
+    pub fn trigger_mode(&self) -> u16 {
+        (self.flags >> 2) & 0x3
+    }
+}
+
+/// Local APIC pin wired to NMI
+#[derive(Debug, Clone, Copy)]
+pub struct LocalApicNmi {
+    /// 0xFF (or 0xFFFFFFFF for x2APIC) means all processors
+    pub processor_uid: u32,
+    pub flags: u16,
+    pub lint: u8,
+}
+
+#[derive(Debug, Clone)]
+pub struct Madt {
+    pub local_apic_address: u64,
+    pub flags: u32,
+    pub processors: Vec<Processor>,
+    pub io_apics: Vec<IoApic>,
+    pub overrides: Vec<InterruptOverride>,
+    pub nmis: Vec<LocalApicNmi>,
+}
+
+impl Madt {
+    /// The system also has dual 8259 PICs that must be masked before using the APIC
+    pub fn has_8259(&self) -> bool {
+        self.flags & 1 != 0
+    }
+
+    /// Global system interrupt an ISA IRQ is routed to
+    pub fn isa_irq_to_gsi(&self, irq: u8) -> u32 {
+        self.overrides
+            .iter()
+            .find(|o| o.bus == 0 && o.source == irq)
+            .map(|o| o.gsi)
+            .unwrap_or(irq as u32)
+    }
+
+    pub fn parse(table: &Table) -> Option<Self> {
+        let bytes = table.bytes;
+        let mut madt = Madt {
+            local_apic_address: bytes.u32(SDT_HEADER_SIZE)? as u64,
+            flags: bytes.u32(SDT_HEADER_SIZE + 4)?,
+            processors: Vec::new(),
+            io_apics: Vec::new(),
+            overrides: Vec::new(),
+            nmis: Vec::new(),
+        };
+
+        let mut offset = SDT_HEADER_SIZE + 8;
+        while offset + 2 <= bytes.len() {
+            let entry_type = bytes.u8(offset)?;
+            let length = bytes.u8(offset + 1)? as usize;
+            if length < 2 || offset + length > bytes.len() {
+                break;
+            }
+
+            match entry_type {
+                0 => {
+                    let flags = bytes.u32(offset + 4)?;
+                    madt.processors.push(Processor {
+                        processor_uid: bytes.u8(offset + 2)? as u32,
+                        apic_id: bytes.u8(offset + 3)? as u32,
+                        enabled: flags & 1 != 0,
+                        online_capable: flags & 2 != 0,
+                        x2apic: false,
+                    });
+                }
+                1 => madt.io_apics.push(IoApic {
+                    id: bytes.u8(offset + 2)?,
+                    address: bytes.u32(offset + 4)?,
+                    gsi_base: bytes.u32(offset + 8)?,
+                }),
+                2 => madt.overrides.push(InterruptOverride {
+                    bus: bytes.u8(offset + 2)?,
+                    source: bytes.u8(offset + 3)?,
+                    gsi: bytes.u32(offset + 4)?,
+                    flags: bytes.u16(offset + 8)?,
+                }),
+                4 => madt.nmis.push(LocalApicNmi {
+                    processor_uid: bytes.u8(offset + 2)? as u32,
+                    flags: bytes.u16(offset + 3)?,
+                    lint: bytes.u8(offset + 5)?,
+                }),
+                5 => madt.local_apic_address = bytes.u64(offset + 4)?,
+                9 => {
+                    let flags = bytes.u32(offset + 8)?;
+                    madt.processors.push(Processor {
+                        processor_uid: bytes.u32(offset + 12)?,
+                        apic_id: bytes.u32(offset + 4)?,
+                        enabled: flags & 1 != 0,
+                        online_capable: flags & 2 != 0,
+                        x2apic: true,
+                    });
+                }
+                0xA => madt.nmis.push(LocalApicNmi {
+                    processor_uid: bytes.u32(offset + 4)?,
+                    flags: bytes.u16(offset + 2)?,
+                    lint: bytes.u8(offset + 8)?,
+                }),
+                _ => {} // Entry types we don't use yet
+            }
+
+            offset += length;
+        }
+
+        Some(madt)
+    }
+}
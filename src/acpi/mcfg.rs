@@ -0,0 +1,55 @@
+//! PCI Express memory-mapped configuration table ("MCFG")
+
+use super::sdt::{Table, SDT_HEADER_SIZE};
+use alloc::vec::Vec;
+
+/// Size of one configuration space allocation entry
+const ENTRY_SIZE: usize = 16;
+
+/// ECAM region for a range of buses in one PCI segment group
+#[derive(Debug, Clone, Copy)]
+pub struct McfgEntry {
+    pub base_address: u64,
+    pub segment_group: u16,
+    pub start_bus: u8,
+    pub end_bus: u8,
+}
+
+impl McfgEntry {
+    /// Physical address of a function's 4 KiB configuration space
+    pub fn config_address(&self, bus: u8, device: u8, function: u8) -> Option<u64> {
+        if bus < self.start_bus || bus > self.end_bus || device >= 32 || function >= 8 {
+            return None;
+        }
+        let offset = ((bus - self.start_bus) as u64) << 20
+            | (device as u64) << 15
+            | (function as u64) << 12;
+        Some(self.base_address + offset)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Mcfg {
+    pub entries: Vec<McfgEntry>,
+}
+
+impl Mcfg {
+    pub fn parse(table: &Table) -> Option<Self> {
+        let b = table.bytes;
+        // 8 reserved bytes follow the header
+        let mut offset = SDT_HEADER_SIZE + 8;
+        let mut entries = Vec::new();
+
+        while offset + ENTRY_SIZE <= b.len() {
+            entries.push(McfgEntry {
+                base_address: b.u64(offset)?,
+                segment_group: b.u16(offset + 8)?,
+                start_bus: b.u8(offset + 10)?,
+                end_bus: b.u8(offset + 11)?,
+            });
+            offset += ENTRY_SIZE;
+        }
+
+        Some(Mcfg { entries })
+    }
+}
@@ -0,0 +1,204 @@
+//! ACPI table discovery and parsing
+//!
+//! Finds the RSDP left by the BIOS, walks the RSDT/XSDT and parses the
+//! tables the rest of the kernel cares about. Everything is read through
+//! the bootloader's physical memory mapping, so `memory::init` must run
+//! before `acpi::init`.
+
+pub mod aml;
+pub mod fadt;
+pub mod hpet;
+pub mod madt;
+pub mod mcfg;
+pub mod rsdp;
+pub mod sdt;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use conquer_once::spin::OnceCell;
+use core::fmt;
+
+pub use aml::SleepType;
+pub use fadt::Fadt;
+pub use hpet::Hpet;
+pub use madt::Madt;
+pub use mcfg::Mcfg;
+pub use rsdp::Rsdp;
+pub use sdt::{GenericAddress, SdtHeader, Table};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AcpiError {
+    NoRsdp,
+    BadChecksum([u8; 4]),
+    InvalidTable,
+    NotInitialized,
+    AlreadyInitialized,
+}
+
+impl fmt::Display for AcpiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AcpiError::NoRsdp => write!(f, "RSDP not found"),
+            AcpiError::BadChecksum(sig) => write!(
+                f,
+                "Bad checksum in {} table",
+                core::str::from_utf8(sig).unwrap_or("????")
+            ),
+            AcpiError::InvalidTable => write!(f, "Invalid ACPI table"),
+            AcpiError::NotInitialized => write!(f, "ACPI not initialized"),
+            AcpiError::AlreadyInitialized => write!(f, "ACPI already initialized"),
+        }
+    }
+}
+
+pub type AcpiResult<T> = Result<T, AcpiError>;
+
+/// Summary of one table referenced by the RSDT/XSDT
+#[derive(Debug, Clone)]
+pub struct TableInfo {
+    pub signature: String,
+    pub address: u64,
+    pub length: u32,
+    pub revision: u8,
+    pub oem_id: String,
+    pub oem_table_id: String,
+    /// False if the table failed validation and was not parsed
+    pub valid: bool,
+}
+
+/// Everything parsed from the firmware's ACPI tables
+#[derive(Debug, Clone)]
+pub struct AcpiInfo {
+    pub rsdp: Rsdp,
+    /// Whether the root table is the 64-bit XSDT rather than the RSDT
+    pub uses_xsdt: bool,
+    pub tables: Vec<TableInfo>,
+    pub madt: Option<Madt>,
+    pub fadt: Option<Fadt>,
+    pub hpet: Option<Hpet>,
+    pub mcfg: Option<Mcfg>,
+    /// SLP_TYP values for soft-off, from the DSDT or an SSDT
+    pub s5: Option<SleepType>,
+}
+
+static ACPI: OnceCell<AcpiInfo> = OnceCell::uninit();
+
+/// Discover and parse the ACPI tables
+pub fn init() -> AcpiResult<&'static AcpiInfo> {
+    if ACPI.is_initialized() {
+        return Err(AcpiError::AlreadyInitialized);
+    }
+
+    let info = discover()?;
+    ACPI.try_init_once(|| info)
+        .map_err(|_| AcpiError::AlreadyInitialized)?;
+    info_ref()
+}
+
+/// Parsed ACPI information, if `init` succeeded
+pub fn info() -> Option<&'static AcpiInfo> {
+    ACPI.get()
+}
+
+fn info_ref() -> AcpiResult<&'static AcpiInfo> {
+    ACPI.get().ok_or(AcpiError::NotInitialized)
+}
+
+/// Find the first table with the given signature
+pub fn find_table(signature: &[u8; 4]) -> AcpiResult<Table> {
+    let info = info_ref()?;
+    let entry = info
+        .tables
+        .iter()
+        .find(|t| t.valid && t.signature.as_bytes() == signature)
+        .ok_or(AcpiError::InvalidTable)?;
+    sdt::load_table(entry.address)
+}
+
+fn discover() -> AcpiResult<AcpiInfo> {
+    let rsdp = rsdp::find()?;
+
+    // Prefer the XSDT on ACPI 2.0+; its entries are 64-bit
+    let (root, entry_size) = match rsdp.xsdt_address {
+        Some(xsdt) => (sdt::load_table(xsdt)?, 8),
+        None => (sdt::load_table(rsdp.rsdt_address as u64)?, 4),
+    };
+
+    let mut info = AcpiInfo {
+        rsdp,
+        uses_xsdt: entry_size == 8,
+        tables: Vec::new(),
+        madt: None,
+        fadt: None,
+        hpet: None,
+        mcfg: None,
+        s5: None,
+    };
+
+    let count = (root.bytes.len() - sdt::SDT_HEADER_SIZE) / entry_size;
+    for i in 0..count {
+        let offset = sdt::SDT_HEADER_SIZE + i * entry_size;
+        let address = if entry_size == 8 {
+            root.bytes.u64(offset)
+        } else {
+            root.bytes.u32(offset).map(|a| a as u64)
+        };
+        let Some(address) = address.filter(|&a| a != 0) else {
+            continue;
+        };
+
+        match sdt::load_table(address) {
+            Ok(table) => {
+                info.tables.push(table_info(&table, true));
+                parse_table(&mut info, &table);
+            }
+            Err(AcpiError::BadChecksum(signature)) => {
+                let header = unsafe { sdt::phys_bytes(address, sdt::SDT_HEADER_SIZE) };
+                info.tables.push(TableInfo {
+                    signature: sdt::ident(&signature),
+                    address,
+                    length: sdt::TableBytes(header).u32(4).unwrap_or(0),
+                    revision: header[8],
+                    oem_id: sdt::ident(&header[10..16]),
+                    oem_table_id: sdt::ident(&header[16..24]),
+                    valid: false,
+                });
+            }
+            Err(_) => {}
+        }
+    }
+
+    Ok(info)
+}
+
+fn table_info(table: &Table, valid: bool) -> TableInfo {
+    TableInfo {
+        signature: String::from(table.header.signature_str()),
+        address: table.address,
+        length: table.header.length,
+        revision: table.header.revision,
+        oem_id: table.header.oem_id.clone(),
+        oem_table_id: table.header.oem_table_id.clone(),
+        valid,
+    }
+}
+
+fn parse_table(info: &mut AcpiInfo, table: &Table) {
+    match &table.header.signature {
+        b"APIC" if info.madt.is_none() => info.madt = Madt::parse(table),
+        b"FACP" if info.fadt.is_none() => {
+            info.fadt = Fadt::parse(table);
+            // The DSDT is only referenced from the FADT
+            if let Some(dsdt) = info.fadt.as_ref().map(|f| f.dsdt_address) {
+                if let Ok(dsdt) = sdt::load_table(dsdt) {
+                    info.tables.push(table_info(&dsdt, true));
+                    info.s5 = info.s5.or(aml::find_s5(&dsdt));
+                }
+            }
+        }
+        b"HPET" if info.hpet.is_none() => info.hpet = Hpet::parse(table),
+        b"MCFG" if info.mcfg.is_none() => info.mcfg = Mcfg::parse(table),
+        b"SSDT" if info.s5.is_none() => info.s5 = aml::find_s5(table),
+        _ => {}
+    }
+}
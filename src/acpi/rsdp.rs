@@ -0,0 +1,79 @@
+//! Root System Description Pointer discovery
+
+use super::sdt::{checksum_ok, ident, phys_bytes, TableBytes};
+use super::{AcpiError, AcpiResult};
+use alloc::string::String;
+
+const SIGNATURE: &[u8; 8] = b"RSD PTR ";
+
+/// Size of the ACPI 1.0 part of the RSDP
+const RSDP_V1_SIZE: usize = 20;
+
+/// Size of the ACPI 2.0+ RSDP
+const RSDP_V2_SIZE: usize = 36;
+
+/// Physical address of the word holding the EBDA segment
+const EBDA_POINTER: u64 = 0x40E;
+
+/// Read-only BIOS area searched after the EBDA
+const BIOS_AREA_START: u64 = 0xE0000;
+const BIOS_AREA_END: u64 = 0x100000;
+
+/// Parsed RSDP
+#[derive(Debug, Clone)]
+pub struct Rsdp {
+    pub address: u64,
+    pub revision: u8,
+    pub oem_id: String,
+    pub rsdt_address: u32,
+    pub xsdt_address: Option<u64>,
+}
+
+/// Search the EBDA and the BIOS area for the RSDP
+pub fn find() -> AcpiResult<Rsdp> {
+    let ebda_segment = unsafe { TableBytes(phys_bytes(EBDA_POINTER, 2)).u16(0).unwrap() };
+    let ebda = (ebda_segment as u64) << 4;
+
+    // The RSDP lies in the first KiB of the EBDA or in the BIOS area
+    if ebda != 0 {
+        if let Some(rsdp) = scan(ebda, ebda + 1024) {
+            return Ok(rsdp);
+        }
+    }
+    scan(BIOS_AREA_START, BIOS_AREA_END).ok_or(AcpiError::NoRsdp)
+}
+
+/// Look for a valid RSDP on 16-byte boundaries in `[start, end)`
+fn scan(start: u64, end: u64) -> Option<Rsdp> {
+    let region = unsafe { phys_bytes(start, (end - start) as usize) };
+    (0..region.len().saturating_sub(RSDP_V1_SIZE))
+        .step_by(16)
+        .filter(|&offset| &region[offset..offset + 8] == SIGNATURE)
+        .find_map(|offset| parse(start + offset as u64, &region[offset..]))
+}
+
+fn parse(address: u64, bytes: &[u8]) -> Option<Rsdp> {
+    if !checksum_ok(&bytes[..RSDP_V1_SIZE]) {
+        return None;
+    }
+    let fields = TableBytes(bytes);
+    let revision = fields.u8(15)?;
+
+    let mut xsdt_address = None;
+    if revision >= 2 && bytes.len() >= RSDP_V2_SIZE {
+        let length = fields.u32(20)? as usize;
+        let extended = bytes.get(..length.max(RSDP_V2_SIZE))?;
+        if !checksum_ok(extended) {
+            return None;
+        }
+        xsdt_address = fields.u64(24).filter(|&addr| addr != 0);
+    }
+
+    Some(Rsdp {
+        address,
+        revision,
+        oem_id: ident(fields.slice(9, 6)?),
+        rsdt_address: fields.u32(16)?,
+        xsdt_address,
+    })
+}
@@ -0,0 +1,174 @@
+//! Raw access to ACPI tables in physical memory
+
+use super::{AcpiError, AcpiResult};
+use crate::memory;
+use alloc::string::String;
+use x86_64::PhysAddr;
+
+/// Size of the common System Description Table header
+pub const SDT_HEADER_SIZE: usize = 36;
+
+/// View `len` bytes of physical memory
+///
+/// # Safety
+/// The range must be covered by the bootloader's physical memory mapping.
+pub unsafe fn phys_bytes(addr: u64, len: usize) -> &'static [u8] {
+    let virt = memory::phys_to_virt(PhysAddr::new(addr));
+    core::slice::from_raw_parts(virt.as_ptr::<u8>(), len)
+}
+
+/// All bytes of a table must sum to zero
+pub fn checksum_ok(bytes: &[u8]) -> bool {
+    bytes.iter().fold(0u8, |sum, &b| sum.wrapping_add(b)) == 0
+}
+
+/// Little-endian field reader over a table's bytes
+#[derive(Clone, Copy)]
+pub struct TableBytes<'a>(pub &'a [u8]);
+
+impl<'a> TableBytes<'a> {
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn u8(&self, offset: usize) -> Option<u8> {
+        self.0.get(offset).copied()
+    }
+
+    pub fn u16(&self, offset: usize) -> Option<u16> {
+        let b = self.0.get(offset..offset + 2)?;
+        Some(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    pub fn u32(&self, offset: usize) -> Option<u32> {
+        let b = self.0.get(offset..offset + 4)?;
+        Some(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    pub fn u64(&self, offset: usize) -> Option<u64> {
+        let b = self.0.get(offset..offset + 8)?;
+        let mut word = [0u8; 8];
+        word.copy_from_slice(b);
+        Some(u64::from_le_bytes(word))
+    }
+
+    pub fn slice(&self, offset: usize, len: usize) -> Option<&'a [u8]> {
+        self.0.get(offset..offset + len)
+    }
+}
+
+/// Turn a fixed-size ACPI identifier into a string, dropping padding
+pub fn ident(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .take_while(|&&b| b != 0)
+        .map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '?' })
+        .collect::<String>()
+        .trim_end()
+        .into()
+}
+
+/// Header shared by every System Description Table
+#[derive(Debug, Clone)]
+pub struct SdtHeader {
+    pub signature: [u8; 4],
+    pub length: u32,
+    pub revision: u8,
+    pub oem_id: String,
+    pub oem_table_id: String,
+    pub oem_revision: u32,
+    pub creator_id: u32,
+    pub creator_revision: u32,
+}
+
+impl SdtHeader {
+    pub fn signature_str(&self) -> &str {
+        core::str::from_utf8(&self.signature).unwrap_or("????")
+    }
+}
+
+/// A validated table: its header and all of its bytes
+pub struct Table {
+    pub address: u64,
+    pub header: SdtHeader,
+    pub bytes: TableBytes<'static>,
+}
+
+/// Map and validate the table at `address`
+pub fn load_table(address: u64) -> AcpiResult<Table> {
+    if address == 0 {
+        return Err(AcpiError::InvalidTable);
+    }
+
+    let head = TableBytes(unsafe { phys_bytes(address, SDT_HEADER_SIZE) });
+    let mut signature = [0u8; 4];
+    signature.copy_from_slice(head.slice(0, 4).unwrap());
+    let length = head.u32(4).unwrap();
+    if (length as usize) < SDT_HEADER_SIZE {
+        return Err(AcpiError::InvalidTable);
+    }
+
+    let bytes = unsafe { phys_bytes(address, length as usize) };
+    if !checksum_ok(bytes) {
+        return Err(AcpiError::BadChecksum(signature));
+    }
+
+    let header = SdtHeader {
+        signature,
+        length,
+        revision: head.u8(8).unwrap(),
+        oem_id: ident(head.slice(10, 6).unwrap()),
+        oem_table_id: ident(head.slice(16, 8).unwrap()),
+        oem_revision: head.u32(24).unwrap(),
+        creator_id: head.u32(28).unwrap(),
+        creator_revision: head.u32(32).unwrap(),
+    };
+
+    Ok(Table { address, header, bytes: TableBytes(bytes) })
+}
+
+/// Generic Address Structure, used to locate registers in any address space
+#[derive(Debug, Clone, Copy)]
+pub struct GenericAddress {
+    pub address_space: u8,
+    pub bit_width: u8,
+    pub bit_offset: u8,
+    pub access_size: u8,
+    pub address: u64,
+}
+
+impl GenericAddress {
+    pub const SYSTEM_MEMORY: u8 = 0;
+    pub const SYSTEM_IO: u8 = 1;
+    pub const PCI_CONFIG: u8 = 2;
+
+    /// Size of the structure inside a table
+    pub const SIZE: usize = 12;
+
+    pub fn parse(bytes: TableBytes, offset: usize) -> Option<Self> {
+        Some(GenericAddress {
+            address_space: bytes.u8(offset)?,
+            bit_width: bytes.u8(offset + 1)?,
+            bit_offset: bytes.u8(offset + 2)?,
+            access_size: bytes.u8(offset + 3)?,
+            address: bytes.u64(offset + 4)?,
+        })
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.address == 0
+    }
+
+    pub fn space_name(&self) -> &'static str {
+        match self.address_space {
+            Self::SYSTEM_MEMORY => "memory",
+            Self::SYSTEM_IO => "io",
+            Self::PCI_CONFIG => "pci",
+            _ => "other",
+        }
+    }
+}
@@ -0,0 +1,79 @@
+//! Stack traces from the frame pointer chain
+//!
+//! The kernel is built with frame pointers, so every frame starts with the
+//! caller's RBP followed by the return address. Capturing walks that chain
+//! into a fixed array without allocating, which makes it usable while
+//! holding any lock. Addresses are printed raw; resolve them with
+//! `addr2line -e <kernel>` on the host.
+
+use core::arch::asm;
+use core::fmt;
+
+/// Deepest trace kept
+pub const MAX_FRAMES: usize = 12;
+
+/// Largest frame believed; a bigger jump means the chain is broken
+const MAX_FRAME_SIZE: u64 = 64 * 1024;
+
+/// Return addresses, innermost first
+#[derive(Clone, Copy)]
+pub struct Backtrace {
+    frames: [u64; MAX_FRAMES],
+    len: usize,
+}
+
+impl Backtrace {
+    pub const fn empty() -> Self {
+        Backtrace { frames: [0; MAX_FRAMES], len: 0 }
+    }
+
+    /// Trace of the caller
+    #[inline(always)]
+    pub fn capture() -> Self {
+        let rbp: u64;
+        unsafe { asm!("mov {}, rbp", out(reg) rbp, options(nomem, nostack, preserves_flags)) };
+
+        let mut trace = Self::empty();
+        let mut frame = rbp;
+        while trace.len < MAX_FRAMES && frame != 0 && frame % 8 == 0 {
+            // Safety: `frame` is the current frame or a caller's, each
+            // checked to lie a little above the previous one on the stack;
+            // thread entry frames end the chain with a null RBP
+            let (next, return_address) = unsafe {
+                let slot = frame as *const u64;
+                (*slot, *slot.add(1))
+            };
+            if return_address == 0 {
+                break;
+            }
+            trace.frames[trace.len] = return_address;
+            trace.len += 1;
+
+            if next <= frame || next - frame > MAX_FRAME_SIZE {
+                break;
+            }
+            frame = next;
+        }
+        trace
+    }
+
+    pub fn frames(&self) -> &[u64] {
+        &self.frames[..self.len]
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl fmt::Display for Backtrace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "  <no frames>");
+        }
+        for (depth, address) in self.frames().iter().enumerate() {
+            writeln!(f, "  #{:<2} {:#018x}", depth, address)?;
+        }
+        Ok(())
+    }
+}
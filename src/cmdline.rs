@@ -0,0 +1,27 @@
+//! Boot options
+//!
+//! The bootloader passes no command line, so options are fixed when the
+//! kernel is built, from the `LITHOS_CMDLINE` environment variable:
+//! whitespace-separated `name=value` words, like
+//! `LITHOS_CMDLINE="keymap=de" cargo run`.
+
+/// The kernel command line
+pub const CMDLINE: &str = match option_env!("LITHOS_CMDLINE") {
+    Some(cmdline) => cmdline,
+    None => "",
+};
+
+/// Value of boot option `name`, if given
+pub fn option(name: &str) -> Option<&'static str> {
+    find(CMDLINE, name)
+}
+
+/// Value of option `name` in `cmdline`; the last one wins
+pub fn find<'a>(cmdline: &'a str, name: &str) -> Option<&'a str> {
+    cmdline
+        .split_whitespace()
+        .rev()
+        .filter_map(|word| word.split_once('='))
+        .find(|&(key, _)| key == name)
+        .map(|(_, value)| value)
+}
@@ -0,0 +1,86 @@
+//! CPU identification and feature detection
+
+use core::arch::asm;
+use lazy_static::lazy_static;
+
+/// Raw result of the `cpuid` instruction
+#[derive(Debug, Clone, Copy)]
+pub struct CpuidResult {
+    pub eax: u32,
+    pub ebx: u32,
+    pub ecx: u32,
+    pub edx: u32,
+}
+
+/// Execute `cpuid` for the given leaf and subleaf
+pub fn cpuid(leaf: u32, subleaf: u32) -> CpuidResult {
+    let eax: u32;
+    let ebx: u32;
+    let ecx: u32;
+    let edx: u32;
+    unsafe {
+        // rbx is reserved by LLVM, so it has to be saved by hand
+        asm!(
+            "mov {tmp:r}, rbx",
+            "cpuid",
+            "xchg {tmp:r}, rbx",
+            tmp = out(reg) ebx,
+            inout("eax") leaf => eax,
+            inout("ecx") subleaf => ecx,
+            out("edx") edx,
+            options(nostack, preserves_flags),
+        );
+    }
+    CpuidResult { eax, ebx, ecx, edx }
+}
+
+/// Read the time stamp counter
+pub fn rdtsc() -> u64 {
+    let low: u32;
+    let high: u32;
+    unsafe {
+        asm!("rdtsc", out("eax") low, out("edx") high, options(nomem, nostack, preserves_flags));
+    }
+    ((high as u64) << 32) | low as u64
+}
+
+/// CPU features Lithos cares about
+#[derive(Debug, Clone, Copy)]
+pub struct Features {
+    pub tsc: bool,
+    pub fxsr: bool,
+    pub sse: bool,
+    pub sse2: bool,
+    pub xsave: bool,
+    pub avx: bool,
+    pub rdrand: bool,
+    pub rdseed: bool,
+}
+
+impl Features {
+    fn detect() -> Self {
+        let max_leaf = cpuid(0, 0).eax;
+        let leaf1 = cpuid(1, 0);
+        let leaf7 = if max_leaf >= 7 { cpuid(7, 0) } else { CpuidResult { eax: 0, ebx: 0, ecx: 0, edx: 0 } };
+
+        Features {
+            tsc: leaf1.edx & (1 << 4) != 0,
+            fxsr: leaf1.edx & (1 << 24) != 0,
+            sse: leaf1.edx & (1 << 25) != 0,
+            sse2: leaf1.edx & (1 << 26) != 0,
+            xsave: leaf1.ecx & (1 << 26) != 0,
+            avx: leaf1.ecx & (1 << 28) != 0,
+            rdrand: leaf1.ecx & (1 << 30) != 0,
+            rdseed: leaf7.ebx & (1 << 18) != 0,
+        }
+    }
+}
+
+lazy_static! {
+    static ref FEATURES: Features = Features::detect();
+}
+
+/// Features of the boot CPU
+pub fn features() -> &'static Features {
+    &FEATURES
+}
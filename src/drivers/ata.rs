@@ -1,117 +1,351 @@
+use crate::drivers::block::request::{AsyncBlockDevice, Block, Operation, Request};
 use crate::drivers::block::{BlockDevice, BlockError, BlockResult, BLOCK_SIZE};
+use crate::interrupts::PICS;
+use crate::power::{self, PowerAction};
+use crate::sync::WaitQueue;
+use alloc::sync::Arc;
+use crate::sync::SpinLock;
+use x86_64::instructions::interrupts;
 use x86_64::instructions::port::Port;
-use spin::Mutex;
 
-/// ATA PIO driver for IDE disks
-pub struct AtaDrive {
-    data_port: Mutex<Port<u16>>,
-    error_port: Mutex<Port<u8>>,
-    sector_count_port: Mutex<Port<u8>>,
-    lba_low_port: Mutex<Port<u8>>,
-    lba_mid_port: Mutex<Port<u8>>,
-    lba_high_port: Mutex<Port<u8>>,
-    drive_port: Mutex<Port<u8>>,
-    status_port: Mutex<Port<u8>>,
-    command_port: Mutex<Port<u8>>,
-    is_master: bool,
+const STATUS_ERR: u8 = 0x01;
+const STATUS_DRQ: u8 = 0x08;
+const STATUS_DF: u8 = 0x20;
+const STATUS_BSY: u8 = 0x80;
+
+const COMMAND_READ_SECTORS: u8 = 0x20;
+const COMMAND_WRITE_SECTORS: u8 = 0x30;
+const COMMAND_FLUSH_CACHE: u8 = 0xE7;
+
+/// Largest block reachable with 28-bit LBA
+const MAX_LBA28: u64 = (1 << 28) - 1;
+
+/// IDE channel, each with its own ports and IRQ line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bus {
+    Primary,
+    Secondary,
 }
 
-impl AtaDrive {
-    /// Create a new ATA drive (primary bus, master/slave)
-    pub fn new(is_master: bool) -> Self {
-        AtaDrive {
-            data_port: Mutex::new(Port::new(0x1F0)),
-            error_port: Mutex::new(Port::new(0x1F1)),
-            sector_count_port: Mutex::new(Port::new(0x1F2)),
-            lba_low_port: Mutex::new(Port::new(0x1F3)),
-            lba_mid_port: Mutex::new(Port::new(0x1F4)),
-            lba_high_port: Mutex::new(Port::new(0x1F5)),
-            drive_port: Mutex::new(Port::new(0x1F6)),
-            status_port: Mutex::new(Port::new(0x1F7)),
-            command_port: Mutex::new(Port::new(0x1F7)),
-            is_master,
-        }
-    }
-    
-    /// Wait for drive to be ready
-    fn wait_ready(&self) {
-        unsafe {
-            let mut status_port = self.status_port.lock();
-            loop {
-                let status = status_port.read();
-                if (status & 0x80) == 0 && (status & 0x08) != 0 {
-                    break;
-                }
+impl Bus {
+    /// Legacy IRQ line the channel interrupts on
+    pub fn irq(self) -> u8 {
+        match self {
+            Bus::Primary => 14,
+            Bus::Secondary => 15,
+        }
+    }
+
+    fn channel(self) -> &'static Channel {
+        &CHANNELS[self as usize]
+    }
+}
+
+/// Which interrupt the channel is waiting for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Phase {
+    Idle,
+    /// Data of a read is ready
+    Reading,
+    /// Data of a write reached the drive; flush it next
+    Writing,
+    /// The drive cache is written back
+    Flushing,
+}
+
+struct InFlight {
+    phase: Phase,
+    /// Last request started; kept after it completes so the interrupt
+    /// handler never drops the final reference
+    request: Option<Arc<Request>>,
+}
+
+/// Ports and the request in flight on one channel
+///
+/// A channel runs one request at a time. The submitter issues the command
+/// and the channel's interrupt handler finishes it, so nothing spins while
+/// the drive seeks.
+struct Channel {
+    base: u16,
+    control: u16,
+    /// Locked with interrupts disabled
+    in_flight: SpinLock<InFlight>,
+    /// Submitters waiting for the channel to become idle
+    idle: WaitQueue,
+}
+
+static CHANNELS: [Channel; 2] = [Channel::new(0x1F0, 0x3F6), Channel::new(0x170, 0x376)];
+
+impl Channel {
+    const fn new(base: u16, control: u16) -> Self {
+        Channel {
+            base,
+            control,
+            in_flight: SpinLock::new(InFlight { phase: Phase::Idle, request: None }),
+            idle: WaitQueue::new(),
+        }
+    }
+
+    /// Read the status, acknowledging the drive's interrupt
+    fn status(&self) -> u8 {
+        unsafe { Port::<u8>::new(self.base + 7).read() }
+    }
+
+    /// Read the status without acknowledging anything
+    fn alt_status(&self) -> u8 {
+        unsafe { Port::<u8>::new(self.control).read() }
+    }
+
+    /// Give the drive the 400ns it needs before its status is valid
+    fn delay(&self) {
+        for _ in 0..4 {
+            self.alt_status();
+        }
+    }
+
+    fn command(&self, command: u8) {
+        unsafe { Port::<u8>::new(self.base + 7).write(command) };
+        self.delay();
+    }
+
+    /// Spin while the drive is busy
+    ///
+    /// Only used for the short gaps between phases of a command and when
+    /// interrupts are disabled, never to wait for a whole transfer.
+    fn wait_not_busy(&self) -> u8 {
+        loop {
+            let status = self.alt_status();
+            if status & STATUS_BSY == 0 {
+                return status;
             }
         }
     }
-    
-    /// Read a sector using PIO mode
-    fn read_sector(&self, lba: u32, buf: &mut [u16; 256]) -> BlockResult<()> {
-        self.wait_ready();
-        
+
+    fn read_data(&self, data: &mut Block) {
+        let mut port = Port::<u16>::new(self.base);
+        for bytes in data.as_chunks_mut::<2>().0 {
+            *bytes = unsafe { port.read() }.to_le_bytes();
+        }
+    }
+
+    fn write_data(&self, data: &Block) {
+        let mut port = Port::<u16>::new(self.base);
+        for &bytes in data.as_chunks::<2>().0 {
+            unsafe { port.write(u16::from_le_bytes(bytes)) };
+        }
+    }
+
+    /// Send the command for `request`; returns the interrupt to expect
+    /// (interrupts disabled)
+    fn issue(&self, is_master: bool, request: &Request) -> BlockResult<Phase> {
+        let lba = request.block();
+        if lba > MAX_LBA28 {
+            return Err(BlockError::InvalidBlock);
+        }
+
+        // A floating bus reads as all ones
+        if self.alt_status() == 0xFF {
+            return Err(BlockError::DeviceError);
+        }
+        self.wait_not_busy();
+
         unsafe {
             // Select drive
-            let drive_select = if self.is_master { 0xE0 } else { 0xF0 };
-            self.drive_port.lock().write(drive_select | ((lba >> 24) & 0x0F) as u8);
-            
+            let drive_select = if is_master { 0xE0 } else { 0xF0 };
+            Port::<u8>::new(self.base + 6).write(drive_select | ((lba >> 24) & 0x0F) as u8);
+            self.delay();
+            if self.alt_status() == 0 {
+                return Err(BlockError::DeviceError);
+            }
+
             // Set sector count
-            self.sector_count_port.lock().write(1);
-            
+            Port::<u8>::new(self.base + 2).write(1);
+
             // Set LBA
-            self.lba_low_port.lock().write((lba & 0xFF) as u8);
-            self.lba_mid_port.lock().write(((lba >> 8) & 0xFF) as u8);
-            self.lba_high_port.lock().write(((lba >> 16) & 0xFF) as u8);
-            
-            // Send read command
-            self.command_port.lock().write(0x20); // READ SECTORS
-            
-            self.wait_ready();
-            
-            // Read data
-            let mut data_port = self.data_port.lock();
-            for word in buf.iter_mut() {
-                *word = data_port.read();
+            Port::<u8>::new(self.base + 3).write((lba & 0xFF) as u8);
+            Port::<u8>::new(self.base + 4).write(((lba >> 8) & 0xFF) as u8);
+            Port::<u8>::new(self.base + 5).write(((lba >> 16) & 0xFF) as u8);
+        }
+
+        match request.operation() {
+            Operation::Read => {
+                self.command(COMMAND_READ_SECTORS);
+                Ok(Phase::Reading)
+            }
+            Operation::Write => {
+                // The drive asks for the data right away and interrupts
+                // once it has written it
+                self.command(COMMAND_WRITE_SECTORS);
+                let status = self.wait_not_busy();
+                if status & (STATUS_ERR | STATUS_DF) != 0 || status & STATUS_DRQ == 0 {
+                    return Err(BlockError::IoError);
+                }
+                request.with_data(|data| self.write_data(data));
+                self.delay();
+                Ok(Phase::Writing)
             }
         }
-        
-        Ok(())
     }
-    
-    /// Write a sector using PIO mode
-    fn write_sector(&self, lba: u32, buf: &[u16; 256]) -> BlockResult<()> {
-        self.wait_ready();
-        
-        unsafe {
-            // Select drive
-            let drive_select = if self.is_master { 0xE0 } else { 0xF0 };
-            self.drive_port.lock().write(drive_select | ((lba >> 24) & 0x0F) as u8);
-            
-            // Set sector count
-            self.sector_count_port.lock().write(1);
-            
-            // Set LBA
-            self.lba_low_port.lock().write((lba & 0xFF) as u8);
-            self.lba_mid_port.lock().write(((lba >> 8) & 0xFF) as u8);
-            self.lba_high_port.lock().write(((lba >> 16) & 0xFF) as u8);
-            
-            // Send write command
-            self.command_port.lock().write(0x30); // WRITE SECTORS
-            
-            self.wait_ready();
-            
-            // Write data
-            let mut data_port = self.data_port.lock();
-            for &word in buf.iter() {
-                data_port.write(word);
+
+    /// Start `request` unless another one is in flight (interrupts disabled)
+    fn start(&self, is_master: bool, request: &Arc<Request>) -> BlockResult<bool> {
+        let mut in_flight = self.in_flight.lock();
+        if in_flight.phase != Phase::Idle {
+            return Ok(false);
+        }
+        in_flight.phase = self.issue(is_master, request)?;
+        in_flight.request = Some(request.clone());
+        Ok(true)
+    }
+
+    /// Advance the request in flight after the drive interrupted
+    ///
+    /// Runs in the interrupt handler, so it neither allocates nor frees.
+    fn service(&self) {
+        let status = self.status();
+        let mut in_flight = self.in_flight.lock();
+        let InFlight { phase, request } = &mut *in_flight;
+        let Some(request) = request.as_ref() else {
+            return;
+        };
+        if *phase == Phase::Idle || status & STATUS_BSY != 0 {
+            return;
+        }
+
+        let result = if status & (STATUS_ERR | STATUS_DF) != 0 {
+            Some(Err(BlockError::IoError))
+        } else {
+            match *phase {
+                Phase::Reading if status & STATUS_DRQ != 0 => {
+                    request.with_data(|data| self.read_data(data));
+                    Some(Ok(()))
+                }
+                Phase::Reading => Some(Err(BlockError::IoError)),
+                Phase::Writing => {
+                    self.command(COMMAND_FLUSH_CACHE);
+                    *phase = Phase::Flushing;
+                    None
+                }
+                Phase::Flushing => Some(Ok(())),
+                Phase::Idle => None,
             }
-            
-            // Flush cache
-            self.command_port.lock().write(0xE7); // FLUSH CACHE
-            self.wait_ready();
+        };
+
+        if let Some(result) = result {
+            *phase = Phase::Idle;
+            request.complete(result);
+            drop(in_flight);
+            self.idle.notify_all();
+        }
+    }
+
+    /// Spin until the request in flight is done (interrupts disabled)
+    fn poll_until_idle(&self) {
+        while self.in_flight.lock().phase != Phase::Idle {
+            self.wait_not_busy();
+            self.service();
+        }
+    }
+
+    /// Finish the request in flight and have both drives write back their
+    /// caches (interrupts disabled)
+    fn flush_caches(&self) {
+        self.poll_until_idle();
+        // A floating bus reads as all ones
+        if self.alt_status() == 0xFF {
+            return;
+        }
+        for drive_select in [0xE0, 0xF0] {
+            self.wait_not_busy();
+            unsafe { Port::<u8>::new(self.base + 6).write(drive_select) };
+            self.delay();
+            if self.alt_status() == 0 {
+                continue;
+            }
+            self.command(COMMAND_FLUSH_CACHE);
+            self.wait_not_busy();
+        }
+    }
+
+    /// Run `request` by polling the status port (interrupts disabled)
+    fn run_polled(&self, is_master: bool, request: &Arc<Request>) -> BlockResult<()> {
+        loop {
+            self.poll_until_idle();
+            if self.start(is_master, request)? {
+                break;
+            }
+        }
+        self.poll_until_idle();
+        request.result().unwrap_or(Err(BlockError::DeviceError))
+    }
+}
+
+/// Enable drive interrupts on both channels, unmask IRQ 14 and 15, and
+/// flush the drives on shutdown
+pub fn init() {
+    power::register_shutdown_hook("ata", flush_caches);
+    interrupts::without_interrupts(|| {
+        for channel in &CHANNELS {
+            // Clear nIEN
+            unsafe { Port::<u8>::new(channel.control).write(0) };
+        }
+
+        let mut pics = PICS.lock();
+        unsafe {
+            let [primary, secondary] = pics.read_masks();
+            let lines = (1 << (Bus::Primary.irq() - 8)) | (1 << (Bus::Secondary.irq() - 8));
+            // IRQ 2 cascades the secondary PIC
+            pics.write_masks(primary & !(1 << 2), secondary & !lines);
+        }
+    });
+}
+
+/// Write back every drive's cache, so nothing written is lost when the
+/// power goes
+fn flush_caches(_action: PowerAction) {
+    interrupts::without_interrupts(|| CHANNELS.iter().for_each(Channel::flush_caches));
+}
+
+/// Called by the IRQ handler of `bus`
+pub(crate) fn handle_interrupt(bus: Bus) {
+    bus.channel().service();
+}
+
+/// ATA PIO driver for IDE disks
+///
+/// Requests complete on the channel's interrupt; the blocking
+/// `BlockDevice` methods put the calling thread to sleep until then.
+pub struct AtaDrive {
+    bus: Bus,
+    is_master: bool,
+}
+
+impl AtaDrive {
+    /// Create a new ATA drive (primary bus, master/slave)
+    pub fn new(is_master: bool) -> Self {
+        Self::on_bus(Bus::Primary, is_master)
+    }
+
+    /// Create a new ATA drive on `bus`
+    pub fn on_bus(bus: Bus, is_master: bool) -> Self {
+        AtaDrive { bus, is_master }
+    }
+
+    pub fn bus(&self) -> Bus {
+        self.bus
+    }
+
+    /// Run `request`, sleeping until the drive interrupts
+    ///
+    /// With interrupts disabled nothing would wake us, so this polls the
+    /// status port instead.
+    fn run(&self, request: Arc<Request>) -> BlockResult<Arc<Request>> {
+        if interrupts::are_enabled() {
+            self.submit(request).wait()
+        } else {
+            self.bus.channel().run_polled(self.is_master, &request)?;
+            Ok(request)
         }
-        
-        Ok(())
     }
 }
 
@@ -120,39 +354,30 @@ impl BlockDevice for AtaDrive {
         if buf.len() < BLOCK_SIZE {
             return Err(BlockError::IoError);
         }
-        
-        let mut word_buf = [0u16; 256];
-        self.read_sector(block_num as u32, &mut word_buf)?;
-        
-        // Convert u16 array to u8 array
-        for (i, &word) in word_buf.iter().enumerate() {
-            buf[i * 2] = (word & 0xFF) as u8;
-            buf[i * 2 + 1] = ((word >> 8) & 0xFF) as u8;
-        }
-        
-        Ok(())
+
+        let request = self.run(Request::read(block_num))?;
+        request.copy_to(buf)
     }
-    
+
     fn write_block(&mut self, block_num: u64, buf: &[u8]) -> BlockResult<()> {
-        if buf.len() < BLOCK_SIZE {
-            return Err(BlockError::IoError);
-        }
-        
-        let mut word_buf = [0u16; 256];
-        
-        // Convert u8 array to u16 array
-        for i in 0..256 {
-            word_buf[i] = buf[i * 2] as u16 | ((buf[i * 2 + 1] as u16) << 8);
-        }
-        
-        self.write_sector(block_num as u32, &word_buf)?;
-        
+        let request = Request::write(block_num, buf)?;
+        self.run(request)?;
         Ok(())
     }
-    
+
     fn block_count(&self) -> u64 {
         // For now, return a fixed size (this should be detected from drive)
         // 1GB = 2097152 sectors
         2097152
     }
 }
+
+impl AsyncBlockDevice for AtaDrive {
+    fn start(&self, request: &Arc<Request>) -> BlockResult<bool> {
+        self.bus.channel().start(self.is_master, request)
+    }
+
+    fn idle(&self) -> &WaitQueue {
+        &self.bus.channel().idle
+    }
+}
@@ -1,4 +1,7 @@
 pub mod ramdisk;
+pub mod request;
+
+pub use request::{AsyncBlockDevice, Request, Submit};
 
 use core::fmt;
 
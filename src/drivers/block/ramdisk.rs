@@ -1,11 +1,16 @@
+use super::request::{AsyncBlockDevice, Operation, Request};
 use super::{BlockDevice, BlockError, BlockResult, BLOCK_SIZE};
+use crate::sync::WaitQueue;
+use alloc::sync::Arc;
 use alloc::vec::Vec;
-use spin::Mutex;
+use crate::sync::SpinLock;
 
 /// RAM disk - in-memory block device for testing
 pub struct RamDisk {
-    data: Mutex<Vec<u8>>,
+    data: SpinLock<Vec<u8>>,
     block_count: u64,
+    /// Never waited on: requests finish as soon as they start
+    idle: WaitQueue,
 }
 
 impl RamDisk {
@@ -16,8 +21,9 @@ impl RamDisk {
         data.resize(size, 0);
         
         RamDisk {
-            data: Mutex::new(data),
+            data: SpinLock::new(data),
             block_count,
+            idle: WaitQueue::new(),
         }
     }
     
@@ -26,8 +32,9 @@ impl RamDisk {
         let block_count = (data.len() / BLOCK_SIZE) as u64;
         
         RamDisk {
-            data: Mutex::new(data),
+            data: SpinLock::new(data),
             block_count,
+            idle: WaitQueue::new(),
         }
     }
 }
@@ -50,6 +57,17 @@ impl BlockDevice for RamDisk {
     }
     
     fn write_block(&mut self, block_num: u64, buf: &[u8]) -> BlockResult<()> {
+        self.write_shared(block_num, buf)
+    }
+    
+    fn block_count(&self) -> u64 {
+        self.block_count
+    }
+}
+
+impl RamDisk {
+    /// `write_block` without exclusive access; the data has its own lock
+    fn write_shared(&self, block_num: u64, buf: &[u8]) -> BlockResult<()> {
         if block_num >= self.block_count {
             return Err(BlockError::InvalidBlock);
         }
@@ -64,8 +82,19 @@ impl BlockDevice for RamDisk {
         
         Ok(())
     }
-    
-    fn block_count(&self) -> u64 {
-        self.block_count
+}
+
+impl AsyncBlockDevice for RamDisk {
+    fn start(&self, request: &Arc<Request>) -> BlockResult<bool> {
+        let result = request.with_data(|data| match request.operation() {
+            Operation::Read => self.read_block(request.block(), data),
+            Operation::Write => self.write_shared(request.block(), data),
+        });
+        request.complete(result);
+        Ok(true)
+    }
+
+    fn idle(&self) -> &WaitQueue {
+        &self.idle
     }
 }
@@ -0,0 +1,244 @@
+//! Block requests that complete asynchronously
+//!
+//! A `Request` is shared between its submitter and the device. The device
+//! finishes it with `complete`, usually from its interrupt handler, which
+//! wakes both kernel threads sleeping in `wait` and tasks awaiting `Submit`.
+
+use super::{BlockDevice, BlockError, BlockResult, BLOCK_SIZE};
+use crate::sync::wait_queue::WaitNode;
+use crate::sync::WaitQueue;
+use alloc::sync::Arc;
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicU8, Ordering};
+use core::task::{Context, Poll};
+use crate::sync::SpinLock;
+use x86_64::instructions::interrupts;
+
+/// Contents of one block
+pub type Block = [u8; BLOCK_SIZE];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Read,
+    Write,
+}
+
+const PENDING: u8 = 0;
+const SUCCEEDED: u8 = 1;
+
+fn encode(result: BlockResult<()>) -> u8 {
+    match result {
+        Ok(()) => SUCCEEDED,
+        Err(BlockError::InvalidBlock) => 2,
+        Err(BlockError::IoError) => 3,
+        Err(BlockError::ReadOnly) => 4,
+        Err(BlockError::DeviceError) => 5,
+    }
+}
+
+fn decode(status: u8) -> Option<BlockResult<()>> {
+    match status {
+        PENDING => None,
+        SUCCEEDED => Some(Ok(())),
+        2 => Some(Err(BlockError::InvalidBlock)),
+        3 => Some(Err(BlockError::IoError)),
+        4 => Some(Err(BlockError::ReadOnly)),
+        _ => Some(Err(BlockError::DeviceError)),
+    }
+}
+
+/// One single-block transfer
+pub struct Request {
+    operation: Operation,
+    block: u64,
+    /// Data read or to be written, locked with interrupts disabled
+    data: SpinLock<Block>,
+    status: AtomicU8,
+    waiters: WaitQueue,
+}
+
+impl Request {
+    /// Request to read `block`
+    pub fn read(block: u64) -> Arc<Self> {
+        Arc::new(Self::new(Operation::Read, block, [0; BLOCK_SIZE]))
+    }
+
+    /// Request to write the first `BLOCK_SIZE` bytes of `data` to `block`
+    pub fn write(block: u64, data: &[u8]) -> BlockResult<Arc<Self>> {
+        let data = data.get(..BLOCK_SIZE).ok_or(BlockError::IoError)?;
+        let mut contents = [0; BLOCK_SIZE];
+        contents.copy_from_slice(data);
+        Ok(Arc::new(Self::new(Operation::Write, block, contents)))
+    }
+
+    fn new(operation: Operation, block: u64, data: Block) -> Self {
+        Request {
+            operation,
+            block,
+            data: SpinLock::new(data),
+            status: AtomicU8::new(PENDING),
+            waiters: WaitQueue::new(),
+        }
+    }
+
+    pub fn operation(&self) -> Operation {
+        self.operation
+    }
+
+    pub fn block(&self) -> u64 {
+        self.block
+    }
+
+    /// Result of the request, `None` while it is in flight
+    pub fn result(&self) -> Option<BlockResult<()>> {
+        decode(self.status.load(Ordering::Acquire))
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.result().is_some()
+    }
+
+    /// Run `f` on the request's data
+    pub fn with_data<R>(&self, f: impl FnOnce(&mut Block) -> R) -> R {
+        interrupts::without_interrupts(|| f(&mut self.data.lock()))
+    }
+
+    /// Copy the data into `buf`, which must hold at least a block
+    pub fn copy_to(&self, buf: &mut [u8]) -> BlockResult<()> {
+        let buf = buf.get_mut(..BLOCK_SIZE).ok_or(BlockError::IoError)?;
+        self.with_data(|data| buf.copy_from_slice(data));
+        Ok(())
+    }
+
+    /// Finish the request and wake everyone waiting for it
+    ///
+    /// Safe from interrupt handlers; only the first call has an effect.
+    pub fn complete(&self, result: BlockResult<()>) {
+        let finished = self
+            .status
+            .compare_exchange(PENDING, encode(result), Ordering::AcqRel, Ordering::Acquire)
+            .is_ok();
+        if finished {
+            self.waiters.notify_all();
+        }
+    }
+
+    /// Block the running thread until the request completes
+    pub fn wait(&self) -> BlockResult<()> {
+        self.waiters.wait_until(|| self.is_done());
+        self.result().unwrap_or(Err(BlockError::DeviceError))
+    }
+}
+
+/// A block device that can run `Request`s without blocking the CPU
+pub trait AsyncBlockDevice: BlockDevice {
+    /// Start `request` unless the device is busy with another one
+    ///
+    /// Returns `Ok(false)` when busy; the device notifies `idle` once it
+    /// can take the next request. Called with interrupts disabled.
+    fn start(&self, request: &Arc<Request>) -> BlockResult<bool>;
+
+    /// Notified whenever the device finishes a request
+    fn idle(&self) -> &WaitQueue;
+
+    /// Run `request` on the device
+    ///
+    /// Await the returned future from a task, or call `Submit::wait` from
+    /// a kernel thread.
+    fn submit(&self, request: Arc<Request>) -> Submit<'_, Self>
+    where
+        Self: Sized,
+    {
+        Submit { device: self, request, started: false, node: None }
+    }
+
+    /// Read `block` into a new `Request`
+    fn read_async(&self, block: u64) -> Submit<'_, Self>
+    where
+        Self: Sized,
+    {
+        self.submit(Request::read(block))
+    }
+}
+
+/// Future returned by `AsyncBlockDevice::submit`
+///
+/// The request is started on the first poll. Dropping the future after
+/// that doesn't cancel it; the device still finishes it.
+pub struct Submit<'a, D: AsyncBlockDevice> {
+    device: &'a D,
+    request: Arc<Request>,
+    started: bool,
+    /// Our place in `device.idle()` before starting, in the request's
+    /// waiters after
+    node: Option<Arc<WaitNode>>,
+}
+
+impl<D: AsyncBlockDevice> Submit<'_, D> {
+    pub fn request(&self) -> &Arc<Request> {
+        &self.request
+    }
+
+    /// Block the running thread until the request completes
+    pub fn wait(self) -> BlockResult<Arc<Request>> {
+        if !self.started {
+            let mut error = None;
+            self.device.idle().wait_until(|| match self.device.start(&self.request) {
+                Ok(started) => started,
+                Err(err) => {
+                    error = Some(err);
+                    true
+                }
+            });
+            if let Some(err) = error {
+                return Err(err);
+            }
+        }
+        self.request.wait()?;
+        Ok(self.request.clone())
+    }
+
+    /// Leave the wait queue we are in (interrupts disabled)
+    fn leave(&mut self) {
+        if let Some(node) = self.node.take() {
+            let queue = if self.started { &self.request.waiters } else { self.device.idle() };
+            queue.remove(&node);
+        }
+    }
+}
+
+impl<D: AsyncBlockDevice> Future for Submit<'_, D> {
+    type Output = BlockResult<Arc<Request>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        interrupts::without_interrupts(|| {
+            self.leave();
+            if !self.started {
+                match self.device.start(&self.request) {
+                    Ok(true) => self.started = true,
+                    Ok(false) => {
+                        self.node = Some(self.device.idle().enqueue_task(cx.waker(), 0));
+                        return Poll::Pending;
+                    }
+                    Err(err) => return Poll::Ready(Err(err)),
+                }
+            }
+
+            // Checked after the device could have finished it
+            match self.request.result() {
+                Some(result) => Poll::Ready(result.map(|()| self.request.clone())),
+                None => {
+                    self.node = Some(self.request.waiters.enqueue_task(cx.waker(), 0));
+                    Poll::Pending
+                }
+            }
+        })
+    }
+}
+
+impl<D: AsyncBlockDevice> Drop for Submit<'_, D> {
+    fn drop(&mut self) {
+        interrupts::without_interrupts(|| self.leave());
+    }
+}
@@ -0,0 +1,209 @@
+//! x87/SSE/AVX state management
+//!
+//! The kernel itself is built without SSE, so only threads (and user
+//! programs) touch the extended registers. State is switched lazily:
+//! switching threads sets `CR0.TS`, and the first FPU/SSE instruction the new
+//! thread executes raises `#NM`, where the previous owner's registers are
+//! saved and the new thread's are restored.
+
+use crate::cpu;
+use alloc::alloc::{alloc, dealloc, Layout};
+use core::arch::asm;
+use core::ptr::NonNull;
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+use x86_64::registers::control::{Cr0, Cr0Flags, Cr4, Cr4Flags};
+
+/// XSAVE areas must be 64-byte aligned (FXSAVE needs 16)
+const AREA_ALIGN: usize = 64;
+
+/// Size of the legacy FXSAVE area
+const FXSAVE_SIZE: usize = 512;
+
+/// Largest save area we support; big enough for x87 + SSE + AVX
+const MAX_AREA_SIZE: usize = 4096;
+
+const XCR0_X87: u64 = 1 << 0;
+const XCR0_SSE: u64 = 1 << 1;
+const XCR0_AVX: u64 = 1 << 2;
+
+/// Default MXCSR: all SIMD exceptions masked, round to nearest
+const MXCSR_DEFAULT: u32 = 0x1F80;
+
+static USE_XSAVE: AtomicBool = AtomicBool::new(false);
+static AREA_SIZE: AtomicUsize = AtomicUsize::new(FXSAVE_SIZE);
+
+#[repr(C, align(64))]
+struct StaticArea([u8; MAX_AREA_SIZE]);
+
+/// Register state every new thread starts with
+static mut INITIAL_AREA: StaticArea = StaticArea([0; MAX_AREA_SIZE]);
+
+/// Save area of the context that runs before any thread is scheduled
+static mut BOOT_AREA: StaticArea = StaticArea([0; MAX_AREA_SIZE]);
+
+/// Save area of the running context
+static CURRENT: AtomicPtr<u8> = AtomicPtr::new(core::ptr::null_mut());
+
+/// Save area whose contents are currently loaded in the registers
+static OWNER: AtomicPtr<u8> = AtomicPtr::new(core::ptr::null_mut());
+
+/// Enable x87/SSE (and AVX when available) and capture the initial state
+pub fn init() {
+    let features = cpu::features();
+    assert!(features.fxsr && features.sse && features.sse2, "CPU lacks SSE2");
+
+    unsafe {
+        Cr0::update(|flags| {
+            flags.remove(Cr0Flags::EMULATE_COPROCESSOR | Cr0Flags::TASK_SWITCHED);
+            flags.insert(Cr0Flags::MONITOR_COPROCESSOR | Cr0Flags::NUMERIC_ERROR);
+        });
+        Cr4::update(|flags| {
+            flags.insert(Cr4Flags::OSFXSR | Cr4Flags::OSXMMEXCPT_ENABLE);
+            if features.xsave {
+                flags.insert(Cr4Flags::OSXSAVE);
+            }
+        });
+    }
+
+    if features.xsave {
+        let mut xcr0 = XCR0_X87 | XCR0_SSE;
+        if features.avx {
+            xcr0 |= XCR0_AVX;
+        }
+        unsafe { xsetbv(0, xcr0) };
+
+        // ebx of leaf 0xD reports the area size for the features enabled in XCR0
+        let size = cpu::cpuid(0xD, 0).ebx as usize;
+        assert!(size <= MAX_AREA_SIZE, "XSAVE area too large");
+        AREA_SIZE.store(size, Ordering::Relaxed);
+        USE_XSAVE.store(true, Ordering::Relaxed);
+    }
+
+    unsafe {
+        asm!("fninit", options(nomem, nostack));
+        let mxcsr = MXCSR_DEFAULT;
+        asm!("ldmxcsr [{}]", in(reg) &mxcsr, options(nostack, readonly));
+        // XSAVE only writes the header of a fresh area, which must start zeroed
+        save(&raw mut INITIAL_AREA as *mut u8);
+
+        let boot = &raw mut BOOT_AREA as *mut u8;
+        CURRENT.store(boot, Ordering::Relaxed);
+        OWNER.store(boot, Ordering::Relaxed);
+    }
+}
+
+/// Whether state is saved with XSAVE (otherwise FXSAVE)
+pub fn uses_xsave() -> bool {
+    USE_XSAVE.load(Ordering::Relaxed)
+}
+
+/// Per-thread save area for the FPU/SSE/AVX registers
+pub struct FpuState {
+    area: NonNull<u8>,
+}
+
+// The area is only touched by the thread that owns it or by the #NM handler
+unsafe impl Send for FpuState {}
+unsafe impl Sync for FpuState {}
+
+impl FpuState {
+    /// Create a save area holding the initial register state
+    pub fn new() -> Self {
+        let layout = Self::layout();
+        let area = unsafe {
+            let ptr = alloc(layout);
+            let area = NonNull::new(ptr).expect("failed to allocate FPU state");
+            core::ptr::copy_nonoverlapping(&raw const INITIAL_AREA as *const u8, ptr, layout.size());
+            area
+        };
+        FpuState { area }
+    }
+
+    fn layout() -> Layout {
+        Layout::from_size_align(AREA_SIZE.load(Ordering::Relaxed), AREA_ALIGN).unwrap()
+    }
+
+    /// Raw pointer to the save area, for [`switch_to`]
+    pub fn as_ptr(&self) -> *mut u8 {
+        self.area.as_ptr()
+    }
+}
+
+impl Default for FpuState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for FpuState {
+    fn drop(&mut self) {
+        // Forget the registers if they still belong to this area
+        let _ = OWNER.compare_exchange(
+            self.area.as_ptr(),
+            core::ptr::null_mut(),
+            Ordering::AcqRel,
+            Ordering::Relaxed,
+        );
+        unsafe { dealloc(self.area.as_ptr(), Self::layout()) };
+    }
+}
+
+/// Make `area` the save area of the running context.
+///
+/// Called on every context switch; pass a null pointer to switch back to the
+/// boot context. Sets `CR0.TS` unless the registers already hold `area`.
+pub fn switch_to(area: *mut u8) {
+    let area = if area.is_null() { &raw mut BOOT_AREA as *mut u8 } else { area };
+    CURRENT.store(area, Ordering::Relaxed);
+
+    unsafe {
+        if OWNER.load(Ordering::Relaxed) == area {
+            asm!("clts", options(nomem, nostack));
+        } else {
+            Cr0::update(|flags| flags.insert(Cr0Flags::TASK_SWITCHED));
+        }
+    }
+}
+
+/// Handle `#NM`: hand the registers over to the running context
+pub fn handle_device_not_available() {
+    unsafe {
+        asm!("clts", options(nomem, nostack));
+
+        let current = CURRENT.load(Ordering::Relaxed);
+        let owner = OWNER.load(Ordering::Relaxed);
+        if owner != current {
+            if !owner.is_null() {
+                save(owner);
+            }
+            restore(current);
+            OWNER.store(current, Ordering::Relaxed);
+        }
+    }
+}
+
+unsafe fn save(area: *mut u8) {
+    if uses_xsave() {
+        asm!("xsave64 [{}]", in(reg) area, in("eax") u32::MAX, in("edx") u32::MAX, options(nostack));
+    } else {
+        asm!("fxsave64 [{}]", in(reg) area, options(nostack));
+    }
+}
+
+unsafe fn restore(area: *const u8) {
+    if uses_xsave() {
+        asm!("xrstor64 [{}]", in(reg) area, in("eax") u32::MAX, in("edx") u32::MAX, options(nostack, readonly));
+    } else {
+        asm!("fxrstor64 [{}]", in(reg) area, options(nostack, readonly));
+    }
+}
+
+unsafe fn xsetbv(register: u32, value: u64) {
+    asm!(
+        "xsetbv",
+        in("ecx") register,
+        in("eax") value as u32,
+        in("edx") (value >> 32) as u32,
+        options(nomem, nostack),
+    );
+}
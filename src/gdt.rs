@@ -2,45 +2,95 @@ use x86_64::VirtAddr;
 use x86_64::structures::tss::TaskStateSegment;
 use x86_64::structures::gdt::{GlobalDescriptorTable, Descriptor, SegmentSelector};
 use lazy_static::lazy_static;
+use core::sync::atomic::{AtomicU64, Ordering};
 
 pub const DOUBLE_FAULT_IST_INDEX: u16 = 0;
 
-lazy_static! {
-    static ref TSS: TaskStateSegment = {
-        let mut tss = TaskStateSegment::new();
-        tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] = {
-            const STACK_SIZE: usize = 4096 * 5;
-            static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
-
-            let stack_start = VirtAddr::from_ptr(&raw const STACK);
-            let stack_end = stack_start + STACK_SIZE;
-            stack_end
-        };
-        tss
+/// The TSS is a `static mut` because `privilege_stack_table[0]` (the stack the
+/// CPU switches to on a trap from ring 3) changes with every thread switch.
+static mut TSS: TaskStateSegment = TaskStateSegment::new();
+
+fn init_tss() -> &'static TaskStateSegment {
+    let tss = &raw mut TSS;
+    // Safety: only called once, from the GDT initializer, before the TSS is loaded
+    let tss = unsafe { &mut *tss };
+    tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] = {
+        const STACK_SIZE: usize = 4096 * 5;
+        static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
+
+        let stack_start = VirtAddr::from_ptr(&raw const STACK);
+        let stack_end = stack_start + STACK_SIZE;
+        stack_end
     };
+    tss
 }
 
 lazy_static! {
     static ref GDT: (GlobalDescriptorTable, Selectors) = {
         let mut gdt = GlobalDescriptorTable::new();
+        // The order of these four segments is fixed by SYSCALL/SYSRET: kernel
+        // data must follow kernel code, and user code must follow user data.
         let code_selector = gdt.add_entry(Descriptor::kernel_code_segment());
-        let tss_selector = gdt.add_entry(Descriptor::tss_segment(&TSS));
-        (gdt, Selectors { code_selector, tss_selector })
+        let data_selector = gdt.add_entry(Descriptor::kernel_data_segment());
+        let user_data_selector = gdt.add_entry(Descriptor::user_data_segment());
+        let user_code_selector = gdt.add_entry(Descriptor::user_code_segment());
+        let tss_selector = gdt.add_entry(Descriptor::tss_segment(init_tss()));
+        (gdt, Selectors {
+            code_selector,
+            data_selector,
+            user_code_selector,
+            user_data_selector,
+            tss_selector,
+        })
     };
 }
 
-struct Selectors {
-    code_selector: SegmentSelector,
-    tss_selector: SegmentSelector,
+/// Segment selectors of the kernel GDT
+pub struct Selectors {
+    pub code_selector: SegmentSelector,
+    pub data_selector: SegmentSelector,
+    pub user_code_selector: SegmentSelector,
+    pub user_data_selector: SegmentSelector,
+    pub tss_selector: SegmentSelector,
 }
 
 pub fn init() {
     use x86_64::instructions::tables::load_tss;
-    use x86_64::instructions::segmentation::{CS, Segment};
+    use x86_64::instructions::segmentation::{CS, DS, ES, SS, Segment};
 
     GDT.0.load();
     unsafe {
         CS::set_reg(GDT.1.code_selector);
+        DS::set_reg(GDT.1.data_selector);
+        ES::set_reg(GDT.1.data_selector);
+        SS::set_reg(GDT.1.data_selector);
         load_tss(GDT.1.tss_selector);
     }
 }
+
+/// Get the selectors of the loaded GDT
+pub fn selectors() -> &'static Selectors {
+    &GDT.1
+}
+
+/// Copy of `privilege_stack_table[0]` for the `syscall` entry stub, which
+/// has to switch stacks by itself.
+pub(crate) static KERNEL_STACK_TOP: AtomicU64 = AtomicU64::new(0);
+
+/// Set the stack the CPU switches to when a trap or interrupt arrives in ring 3
+pub fn set_kernel_stack(stack_top: VirtAddr) {
+    let tss = &raw mut TSS;
+    // Safety: the CPU only reads this field while delivering an interrupt,
+    // and we never hold a reference into the TSS across this write
+    unsafe {
+        (*tss).privilege_stack_table[0] = stack_top;
+    }
+    KERNEL_STACK_TOP.store(stack_top.as_u64(), Ordering::Relaxed);
+}
+
+/// Get the stack currently used for traps from ring 3
+pub fn kernel_stack() -> VirtAddr {
+    let tss = &raw const TSS;
+    // Safety: a plain read; writes only happen on thread switches
+    unsafe { (*tss).privilege_stack_table[0] }
+}
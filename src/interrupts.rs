@@ -1,7 +1,9 @@
 use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame};
+use x86_64::{PrivilegeLevel, VirtAddr};
 use crate::println;
 use lazy_static::lazy_static;
 
+use core::sync::atomic::{AtomicUsize, Ordering};
 use pic8259::ChainedPics;
 use spin;
 
@@ -16,6 +18,10 @@ pub static PICS: spin::Mutex<ChainedPics> =
 pub enum InterruptIndex {
     Timer = PIC_1_OFFSET,
     Keyboard,
+    Com2 = PIC_1_OFFSET + 3,
+    Com1,
+    PrimaryAta = PIC_1_OFFSET + 14,
+    SecondaryAta,
 }
 
 impl InterruptIndex {
@@ -32,14 +38,36 @@ lazy_static! {
     static ref IDT: InterruptDescriptorTable = {
         let mut idt = InterruptDescriptorTable::new();
         idt.breakpoint.set_handler_fn(breakpoint_handler);
+        idt.device_not_available.set_handler_fn(device_not_available_handler);
+        idt.simd_floating_point.set_handler_fn(simd_floating_point_handler);
         unsafe {
             idt.double_fault.set_handler_fn(double_fault_handler)
                 .set_stack_index(crate::gdt::DOUBLE_FAULT_IST_INDEX);
         }
-        idt[InterruptIndex::Timer.as_usize()]
-            .set_handler_fn(timer_interrupt_handler);
+        unsafe {
+            let entry = crate::task::context::timer_entry as *const () as u64;
+            idt[InterruptIndex::Timer.as_usize()]
+                .set_handler_addr(VirtAddr::new(entry));
+            let entry = crate::task::context::yield_entry as *const () as u64;
+            idt[crate::task::context::YIELD_VECTOR as usize]
+                .set_handler_addr(VirtAddr::new(entry));
+        }
         idt[InterruptIndex::Keyboard.as_usize()]
             .set_handler_fn(keyboard_interrupt_handler);
+        idt[InterruptIndex::Com1.as_usize()]
+            .set_handler_fn(com1_interrupt_handler);
+        idt[InterruptIndex::Com2.as_usize()]
+            .set_handler_fn(com2_interrupt_handler);
+        idt[InterruptIndex::PrimaryAta.as_usize()]
+            .set_handler_fn(primary_ata_interrupt_handler);
+        idt[InterruptIndex::SecondaryAta.as_usize()]
+            .set_handler_fn(secondary_ata_interrupt_handler);
+        unsafe {
+            let entry = crate::syscall::entry::int80_entry as *const () as u64;
+            idt[crate::syscall::entry::SYSCALL_VECTOR as usize]
+                .set_handler_addr(VirtAddr::new(entry))
+                .set_privilege_level(PrivilegeLevel::Ring3);
+        }
         idt
     };
 }
@@ -48,49 +76,96 @@ pub fn init_idt() {
     IDT.load();
 }
 
+/// Interrupt handlers running, counting nested ones
+static HANDLER_DEPTH: AtomicUsize = AtomicUsize::new(0);
+
+/// Whether the CPU is running an interrupt or exception handler, rather
+/// than the thread it interrupted
+pub fn in_interrupt() -> bool {
+    HANDLER_DEPTH.load(Ordering::Relaxed) > 0
+}
+
+/// Marks a handler as running for as long as it lives
+struct HandlerContext;
+
+impl HandlerContext {
+    fn enter() -> Self {
+        HANDLER_DEPTH.fetch_add(1, Ordering::Relaxed);
+        HandlerContext
+    }
+}
+
+impl Drop for HandlerContext {
+    fn drop(&mut self) {
+        HANDLER_DEPTH.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
 extern "x86-interrupt" fn breakpoint_handler(
     stack_frame: InterruptStackFrame)
 {
+    let _context = HandlerContext::enter();
     println!("EXCEPTION: BREAKPOINT\n{:#?}", stack_frame);
 }
 
+extern "x86-interrupt" fn device_not_available_handler(
+    _stack_frame: InterruptStackFrame)
+{
+    let _context = HandlerContext::enter();
+    crate::fpu::handle_device_not_available();
+}
+
+extern "x86-interrupt" fn simd_floating_point_handler(
+    stack_frame: InterruptStackFrame)
+{
+    let _context = HandlerContext::enter();
+    panic!("EXCEPTION: SIMD FLOATING POINT\n{:#?}", stack_frame);
+}
+
 extern "x86-interrupt" fn double_fault_handler(
     stack_frame: InterruptStackFrame, _error_code: u64) -> !
 {
+    let _context = HandlerContext::enter();
     #[cfg(test)]
     crate::exit_qemu(crate::QemuExitCode::Success);
 
     panic!("EXCEPTION: DOUBLE FAULT\n{:#?}", stack_frame);
 }
 
-extern "x86-interrupt" fn timer_interrupt_handler(
-    _stack_frame: InterruptStackFrame)
-{
-    use core::sync::atomic::{AtomicU64, Ordering};
-    
-    static TICK: AtomicU64 = AtomicU64::new(0);
-    let tick = TICK.fetch_add(1, Ordering::Relaxed);
-    
-    // Trigger thread switch every 10 ticks (~100ms at 18.2Hz)
-    if tick % 10 == 0 {
-        // Schedule next thread (context switch happens here)
-        crate::task::thread_scheduler::schedule_next_thread();
-    }
+/// Called by `task::context::timer_entry` with the interrupted thread's
+/// saved registers at `current_rsp`; returns the saved registers to resume
+pub(crate) extern "C" fn timer_interrupt(current_rsp: u64) -> u64 {
+    let _context = HandlerContext::enter();
+    crate::random::add_interrupt_timing(InterruptIndex::Timer.as_u8());
 
+    // Acknowledge before switching: the next thread may not return here
+    // before re-enabling interrupts
     unsafe {
         PICS.lock()
             .notify_end_of_interrupt(InterruptIndex::Timer.as_u8());
     }
+
+    // Wake expired timers first so their threads can run on this tick
+    crate::time::on_tick();
+    crate::task::thread_scheduler::timer_tick(current_rsp)
+}
+
+/// Called by `task::context::yield_entry` when a thread gives up the CPU
+pub(crate) extern "C" fn yield_interrupt(current_rsp: u64) -> u64 {
+    let _context = HandlerContext::enter();
+    crate::task::thread_scheduler::schedule_next_thread(current_rsp)
 }
 
 extern "x86-interrupt" fn keyboard_interrupt_handler(
     _stack_frame: InterruptStackFrame)
 {
+    let _context = HandlerContext::enter();
     use x86_64::instructions::port::Port;
     let mut port = Port::new(0x60);
 
     let scancode: u8 = unsafe { port.read() };
-    crate::task::keyboard::add_scancode(scancode);
+    crate::random::add_input_event(scancode as u64);
+    crate::keyboard::add_scancode(scancode);
 
     unsafe {
         PICS.lock()
@@ -98,6 +173,58 @@ extern "x86-interrupt" fn keyboard_interrupt_handler(
     }
 }
 
+extern "x86-interrupt" fn com1_interrupt_handler(
+    _stack_frame: InterruptStackFrame)
+{
+    let _context = HandlerContext::enter();
+    crate::random::add_interrupt_timing(InterruptIndex::Com1.as_u8());
+    crate::serial::handle_interrupt(crate::serial::ComPort::Com1);
+
+    unsafe {
+        PICS.lock()
+            .notify_end_of_interrupt(InterruptIndex::Com1.as_u8());
+    }
+}
+
+extern "x86-interrupt" fn com2_interrupt_handler(
+    _stack_frame: InterruptStackFrame)
+{
+    let _context = HandlerContext::enter();
+    crate::random::add_interrupt_timing(InterruptIndex::Com2.as_u8());
+    crate::serial::handle_interrupt(crate::serial::ComPort::Com2);
+
+    unsafe {
+        PICS.lock()
+            .notify_end_of_interrupt(InterruptIndex::Com2.as_u8());
+    }
+}
+
+extern "x86-interrupt" fn primary_ata_interrupt_handler(
+    _stack_frame: InterruptStackFrame)
+{
+    let _context = HandlerContext::enter();
+    crate::random::add_interrupt_timing(InterruptIndex::PrimaryAta.as_u8());
+    crate::drivers::ata::handle_interrupt(crate::drivers::ata::Bus::Primary);
+
+    unsafe {
+        PICS.lock()
+            .notify_end_of_interrupt(InterruptIndex::PrimaryAta.as_u8());
+    }
+}
+
+extern "x86-interrupt" fn secondary_ata_interrupt_handler(
+    _stack_frame: InterruptStackFrame)
+{
+    let _context = HandlerContext::enter();
+    crate::random::add_interrupt_timing(InterruptIndex::SecondaryAta.as_u8());
+    crate::drivers::ata::handle_interrupt(crate::drivers::ata::Bus::Secondary);
+
+    unsafe {
+        PICS.lock()
+            .notify_end_of_interrupt(InterruptIndex::SecondaryAta.as_u8());
+    }
+}
+
 #[test_case]
 fn test_breakpoint_exception() {
     // invoke a breakpoint exception
@@ -0,0 +1,169 @@
+//! Capabilities: handles to kernel objects with access rights
+//!
+//! Every process has a capability table mapping small integer handles to
+//! capabilities, like its file descriptor table maps descriptors to files.
+//! A handle only means something inside its own process; capabilities move
+//! between processes in IPC messages.
+
+use super::{Endpoint, IpcError, IpcResult, Reply};
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use core::fmt;
+use core::num::NonZeroU32;
+use core::ops::BitOr;
+
+/// Handle of a capability in a process's capability table
+///
+/// Handles are never 0, so `Option<CapHandle>` is 0 for "no capability"
+/// in the `#[repr(C)]` message layout.
+#[repr(transparent)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CapHandle(NonZeroU32);
+
+impl CapHandle {
+    pub const fn new(handle: u32) -> Option<Self> {
+        match NonZeroU32::new(handle) {
+            Some(handle) => Some(CapHandle(handle)),
+            None => None,
+        }
+    }
+
+    pub fn as_u32(self) -> u32 {
+        self.0.get()
+    }
+}
+
+impl fmt::Display for CapHandle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// What a capability allows its holder to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rights(u8);
+
+impl Rights {
+    pub const NONE: Rights = Rights(0);
+    /// `send` and `call` on an endpoint
+    pub const SEND: Rights = Rights(1 << 0);
+    /// `recv` on an endpoint
+    pub const RECV: Rights = Rights(1 << 1);
+    /// Attach capabilities to messages sent through an endpoint
+    pub const GRANT: Rights = Rights(1 << 2);
+    pub const ALL: Rights = Rights(0b111);
+
+    pub const fn from_bits(bits: u8) -> Self {
+        Rights(bits & Self::ALL.0)
+    }
+
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+
+    pub const fn contains(self, other: Rights) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub const fn intersection(self, other: Rights) -> Rights {
+        Rights(self.0 & other.0)
+    }
+}
+
+impl BitOr for Rights {
+    type Output = Rights;
+
+    fn bitor(self, other: Rights) -> Rights {
+        Rights(self.0 | other.0)
+    }
+}
+
+/// Kernel object a capability refers to
+#[derive(Clone)]
+pub enum Object {
+    Endpoint(Arc<Endpoint>),
+    /// One-shot right to answer a `call`
+    Reply(Arc<Reply>),
+}
+
+/// Reference to a kernel object plus what may be done with it
+#[derive(Clone)]
+pub struct Capability {
+    pub object: Object,
+    pub rights: Rights,
+}
+
+impl Capability {
+    pub fn endpoint(endpoint: Arc<Endpoint>, rights: Rights) -> Self {
+        Capability { object: Object::Endpoint(endpoint), rights }
+    }
+
+    /// The endpoint, if this refers to one and allows `rights`
+    pub fn as_endpoint(&self, rights: Rights) -> IpcResult<&Arc<Endpoint>> {
+        match &self.object {
+            Object::Endpoint(endpoint) if self.rights.contains(rights) => Ok(endpoint),
+            Object::Endpoint(_) => Err(IpcError::MissingRights),
+            Object::Reply(_) => Err(IpcError::WrongType),
+        }
+    }
+
+    /// Copy with at most `rights`
+    pub fn derive(&self, rights: Rights) -> Self {
+        Capability { object: self.object.clone(), rights: self.rights.intersection(rights) }
+    }
+}
+
+impl fmt::Debug for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = match self.object {
+            Object::Endpoint(_) => "Endpoint",
+            Object::Reply(_) => "Reply",
+        };
+        f.debug_struct("Capability").field("object", &kind).field("rights", &self.rights).finish()
+    }
+}
+
+/// Capability table of a process (see `process::Process`)
+pub struct CapTable {
+    caps: BTreeMap<CapHandle, Capability>,
+    next_handle: u32,
+}
+
+impl Default for CapTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CapTable {
+    pub const fn new() -> Self {
+        CapTable {
+            caps: BTreeMap::new(),
+            next_handle: 1,
+        }
+    }
+
+    /// Store a capability under a new handle
+    pub fn insert(&mut self, cap: Capability) -> CapHandle {
+        let handle = CapHandle::new(self.next_handle).expect("capability handles exhausted");
+        self.next_handle += 1;
+        self.caps.insert(handle, cap);
+        handle
+    }
+
+    pub fn get(&self, handle: CapHandle) -> IpcResult<&Capability> {
+        self.caps.get(&handle).ok_or(IpcError::InvalidHandle)
+    }
+
+    pub fn remove(&mut self, handle: CapHandle) -> IpcResult<Capability> {
+        self.caps.remove(&handle).ok_or(IpcError::InvalidHandle)
+    }
+
+    pub fn len(&self) -> usize {
+        self.caps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.caps.is_empty()
+    }
+}
@@ -0,0 +1,185 @@
+//! Synchronous IPC endpoints
+//!
+//! An endpoint is a rendezvous point without a buffer of its own: senders
+//! queue up in FIFO order and stay blocked until a receiver takes their
+//! message. A `call` additionally stays blocked until the receiver answers
+//! through the `Reply` it got with the message.
+
+use super::{Capability, IpcError, IpcResult, Message};
+use crate::sync::WaitQueue;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicU8, Ordering};
+use crate::sync::SpinLock;
+use x86_64::instructions::interrupts;
+
+/// A message in flight together with the capability it carries
+#[derive(Debug)]
+pub struct Envelope {
+    pub message: Message,
+    pub cap: Option<Capability>,
+}
+
+impl Envelope {
+    pub fn new(message: Message) -> Self {
+        Envelope { message, cap: None }
+    }
+
+    pub fn with_cap(message: Message, cap: Capability) -> Self {
+        Envelope { message, cap: Some(cap) }
+    }
+}
+
+impl From<Message> for Envelope {
+    fn from(message: Message) -> Self {
+        Envelope::new(message)
+    }
+}
+
+const WAITING: u8 = 0;
+const TAKEN: u8 = 1;
+const REPLIED: u8 = 2;
+const ABANDONED: u8 = 3;
+
+/// Where a blocked sender waits for its message to be taken or answered
+struct Slot {
+    state: AtomicU8,
+    reply: SpinLock<Option<Envelope>>,
+    done: WaitQueue,
+}
+
+impl Slot {
+    fn new() -> Arc<Self> {
+        Arc::new(Slot {
+            state: AtomicU8::new(WAITING),
+            reply: SpinLock::new(None),
+            done: WaitQueue::new(),
+        })
+    }
+
+    fn state(&self) -> u8 {
+        self.state.load(Ordering::Acquire)
+    }
+
+    /// Move from `from` to `to` and wake the sender; fails if another
+    /// transition came first
+    fn finish(&self, from: u8, to: u8) -> bool {
+        let changed = self.state
+            .compare_exchange(from, to, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok();
+        if changed {
+            self.done.notify_all();
+        }
+        changed
+    }
+}
+
+struct Pending {
+    envelope: Envelope,
+    slot: Arc<Slot>,
+    wants_reply: bool,
+}
+
+/// Right to answer one `call`
+///
+/// Dropping it without replying fails the call with `IpcError::NoReply`.
+pub struct Reply {
+    slot: Arc<Slot>,
+}
+
+impl Reply {
+    /// Wake the caller with `envelope` as the result of its `call`
+    ///
+    /// Fails if the call was already answered.
+    pub fn send(&self, envelope: impl Into<Envelope>) -> IpcResult<()> {
+        let slot = &self.slot;
+        interrupts::without_interrupts(|| {
+            let mut reply = slot.reply.lock();
+            if slot.state() != TAKEN {
+                return Err(IpcError::NoReply);
+            }
+            *reply = Some(envelope.into());
+            slot.state.store(REPLIED, Ordering::Release);
+            Ok(())
+        })?;
+        slot.done.notify_all();
+        Ok(())
+    }
+}
+
+impl Drop for Reply {
+    fn drop(&mut self) {
+        self.slot.finish(TAKEN, ABANDONED);
+    }
+}
+
+/// Synchronous IPC endpoint
+pub struct Endpoint {
+    senders: SpinLock<VecDeque<Pending>>,
+    receivers: WaitQueue,
+}
+
+impl Default for Endpoint {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Endpoint {
+    pub const fn new() -> Self {
+        Endpoint {
+            senders: SpinLock::new(VecDeque::new()),
+            receivers: WaitQueue::new(),
+        }
+    }
+
+    /// Number of senders blocked on the endpoint
+    pub fn queued(&self) -> usize {
+        interrupts::without_interrupts(|| self.senders.lock().len())
+    }
+
+    /// Send a message, blocking until a receiver takes it
+    pub fn send(&self, envelope: impl Into<Envelope>) {
+        let slot = self.enqueue(envelope.into(), false);
+        slot.done.wait_until(|| slot.state() != WAITING);
+    }
+
+    /// Send a message and block until the receiver replies
+    pub fn call(&self, envelope: impl Into<Envelope>) -> IpcResult<Envelope> {
+        let slot = self.enqueue(envelope.into(), true);
+        slot.done.wait_until(|| matches!(slot.state(), REPLIED | ABANDONED));
+        match slot.state() {
+            REPLIED => Ok(interrupts::without_interrupts(|| slot.reply.lock().take())
+                .expect("replied without a message")),
+            _ => Err(IpcError::NoReply),
+        }
+    }
+
+    /// Take the next message, blocking until there is a sender
+    ///
+    /// The `Reply` is there if the sender used `call`.
+    pub fn recv(&self) -> (Envelope, Option<Reply>) {
+        loop {
+            if let Some(received) = self.try_recv() {
+                return received;
+            }
+            self.receivers.wait_until(|| self.queued() > 0);
+        }
+    }
+
+    /// Take the next message if a sender is waiting
+    pub fn try_recv(&self) -> Option<(Envelope, Option<Reply>)> {
+        let pending = interrupts::without_interrupts(|| self.senders.lock().pop_front())?;
+        let reply = pending.wants_reply.then(|| Reply { slot: pending.slot.clone() });
+        pending.slot.finish(WAITING, TAKEN);
+        Some((pending.envelope, reply))
+    }
+
+    fn enqueue(&self, envelope: Envelope, wants_reply: bool) -> Arc<Slot> {
+        let slot = Slot::new();
+        let pending = Pending { envelope, slot: slot.clone(), wants_reply };
+        interrupts::without_interrupts(|| self.senders.lock().push_back(pending));
+        self.receivers.notify_one();
+        slot
+    }
+}
@@ -0,0 +1,212 @@
+//! Message-passing IPC
+//!
+//! Tasks talk through synchronous [`Endpoint`]s: `send` blocks until a
+//! receiver takes the message, `recv` until a sender arrives, and `call`
+//! sends and then blocks until the receiver answers with `reply`. Messages
+//! are a label plus a few words, small enough to travel in registers, and
+//! can carry one capability.
+//!
+//! Endpoints are named through capability handles in the capability table
+//! of the running process. The functions in this module work on handles and
+//! back the IPC system calls; kernel code that already holds an
+//! `Arc<Endpoint>` can use it directly.
+
+pub mod capability;
+pub mod endpoint;
+
+pub use capability::{CapHandle, CapTable, Capability, Object, Rights};
+pub use endpoint::{Endpoint, Envelope, Reply};
+
+use crate::process::with_current;
+use crate::task::thread;
+use crate::{cpu, time};
+use alloc::sync::Arc;
+use core::fmt;
+use core::time::Duration;
+
+/// Number of data words in a message
+pub const MESSAGE_WORDS: usize = 4;
+
+/// Fixed-size IPC message
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Message {
+    /// Chosen by the protocol, typically an operation or status code
+    pub label: u64,
+    pub words: [u64; MESSAGE_WORDS],
+    /// Capability to pass along; the receiver finds its own handle here
+    pub cap: Option<CapHandle>,
+}
+
+impl Message {
+    pub const fn new(label: u64, words: [u64; MESSAGE_WORDS]) -> Self {
+        Message { label, words, cap: None }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IpcError {
+    /// No capability with this handle
+    InvalidHandle,
+    /// The capability refers to the wrong kind of object
+    WrongType,
+    /// The capability lacks a right the operation needs
+    MissingRights,
+    /// The call was not answered, or was already answered
+    NoReply,
+}
+
+impl fmt::Display for IpcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IpcError::InvalidHandle => write!(f, "Invalid capability handle"),
+            IpcError::WrongType => write!(f, "Wrong capability type"),
+            IpcError::MissingRights => write!(f, "Insufficient capability rights"),
+            IpcError::NoReply => write!(f, "No reply"),
+        }
+    }
+}
+
+pub type IpcResult<T> = Result<T, IpcError>;
+
+/// Create an endpoint, returning a handle with all rights
+pub fn create_endpoint() -> CapHandle {
+    install(Capability::endpoint(Arc::new(Endpoint::new()), Rights::ALL))
+}
+
+/// Give the running process a handle for `cap`
+pub fn install(cap: Capability) -> CapHandle {
+    with_current(|process| process.capabilities.insert(cap))
+}
+
+/// Copy of the capability behind `handle`
+pub fn capability(handle: CapHandle) -> IpcResult<Capability> {
+    with_current(|process| process.capabilities.get(handle).cloned())
+}
+
+/// New handle for the same object with at most `rights`
+pub fn derive(handle: CapHandle, rights: Rights) -> IpcResult<CapHandle> {
+    with_current(|process| {
+        let cap = process.capabilities.get(handle)?.derive(rights);
+        Ok(process.capabilities.insert(cap))
+    })
+}
+
+/// Drop a handle
+pub fn close(handle: CapHandle) -> IpcResult<()> {
+    with_current(|process| process.capabilities.remove(handle).map(drop))
+}
+
+/// Send `message` on the endpoint behind `handle`, blocking until it is
+/// received
+pub fn send(handle: CapHandle, message: Message) -> IpcResult<()> {
+    let (endpoint, envelope) = outgoing(handle, Rights::SEND, message)?;
+    endpoint.send(envelope);
+    Ok(())
+}
+
+/// Send `message` and block until the receiver replies
+pub fn call(handle: CapHandle, message: Message) -> IpcResult<Message> {
+    let (endpoint, envelope) = outgoing(handle, Rights::SEND, message)?;
+    let reply = endpoint.call(envelope)?;
+    Ok(incoming(reply))
+}
+
+/// Receive the next message on the endpoint behind `handle`
+///
+/// Also returns a reply handle if the sender is waiting in `call`.
+pub fn recv(handle: CapHandle) -> IpcResult<(Message, Option<CapHandle>)> {
+    let endpoint = with_current(|process| {
+        process.capabilities.get(handle)?.as_endpoint(Rights::RECV).cloned()
+    })?;
+    let (envelope, reply) = endpoint.recv();
+    let reply = reply.map(|reply| {
+        install(Capability { object: Object::Reply(Arc::new(reply)), rights: Rights::NONE })
+    });
+    Ok((incoming(envelope), reply))
+}
+
+/// Answer a `call` through its reply handle, which is used up
+pub fn reply(handle: CapHandle, mut message: Message) -> IpcResult<()> {
+    let (reply, cap) = with_current(|process| {
+        let table = &mut process.capabilities;
+        let Object::Reply(reply) = &table.get(handle)?.object else {
+            return Err(IpcError::WrongType);
+        };
+        let reply = reply.clone();
+        let cap = message.cap.take().map(|cap| table.get(cap).cloned()).transpose()?;
+        table.remove(handle)?;
+        Ok((reply, cap))
+    })?;
+    reply.send(Envelope { message, cap })
+}
+
+/// Look up the endpoint to send on and the capability to attach
+fn outgoing(handle: CapHandle, rights: Rights, mut message: Message) -> IpcResult<(Arc<Endpoint>, Envelope)> {
+    with_current(|process| {
+        let table = &process.capabilities;
+        let endpoint_cap = table.get(handle)?;
+        let endpoint = endpoint_cap.as_endpoint(rights)?.clone();
+        let cap = match message.cap.take() {
+            Some(_) if !endpoint_cap.rights.contains(Rights::GRANT) => {
+                return Err(IpcError::MissingRights);
+            }
+            Some(cap) => Some(table.get(cap)?.clone()),
+            None => None,
+        };
+        Ok((endpoint, Envelope { message, cap }))
+    })
+}
+
+/// Install the capability a message carries in the running process
+fn incoming(envelope: Envelope) -> Message {
+    let mut message = envelope.message;
+    message.cap = envelope.cap.map(install);
+    message
+}
+
+/// Round-trip latency measured by `benchmark`
+#[derive(Debug, Clone, Copy)]
+pub struct BenchResult {
+    pub round_trips: u32,
+    pub cycles: u64,
+    pub total: Duration,
+}
+
+impl BenchResult {
+    /// Mean time of one `call` and its `reply`
+    pub fn round_trip(&self) -> Duration {
+        self.total / self.round_trips.max(1)
+    }
+
+    /// Mean TSC cycles of one round trip
+    pub fn cycles_per_round_trip(&self) -> u64 {
+        self.cycles / self.round_trips.max(1) as u64
+    }
+}
+
+/// Time `round_trips` calls to an echo server on another kernel thread
+///
+/// Both sides go through capability handles, like user processes do.
+pub fn benchmark(round_trips: u32) -> BenchResult {
+    let endpoint = create_endpoint();
+    let server = thread::Builder::new().name("ipc-bench").spawn(move || {
+        for _ in 0..round_trips {
+            let (message, reply_handle) = recv(endpoint).expect("benchmark endpoint vanished");
+            reply(reply_handle.expect("benchmark message was not a call"), message)
+                .expect("benchmark reply failed");
+        }
+    });
+
+    let mut message = Message::new(0, [0; MESSAGE_WORDS]);
+    let start = cpu::rdtsc();
+    for round in 0..round_trips {
+        message.label = round as u64;
+        message = call(endpoint, message).expect("benchmark call failed");
+    }
+    let cycles = cpu::rdtsc().wrapping_sub(start);
+
+    server.join();
+    let _ = close(endpoint);
+    BenchResult { round_trips, cycles, total: time::cycles_to_duration(cycles) }
+}
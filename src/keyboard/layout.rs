@@ -0,0 +1,127 @@
+//! Keyboard layouts, selectable at runtime
+//!
+//! `pc_keyboard` fixes the layout in the decoder's type, so `Decoder` holds
+//! one decoder per layout behind an enum and is rebuilt when the layout
+//! changes.
+
+use core::fmt;
+use pc_keyboard::{layouts, DecodedKey, HandleControl, KeyEvent, Keyboard, ScancodeSet1};
+
+/// A keyboard layout
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Layout {
+    Us,
+    Uk,
+    De,
+    Azerty,
+    Dvorak,
+    Colemak,
+    Jis,
+}
+
+impl Layout {
+    pub const ALL: [Layout; 7] = [
+        Layout::Us,
+        Layout::Uk,
+        Layout::De,
+        Layout::Azerty,
+        Layout::Dvorak,
+        Layout::Colemak,
+        Layout::Jis,
+    ];
+
+    /// Short name, as used by the `keymap` command and boot option
+    pub fn name(self) -> &'static str {
+        match self {
+            Layout::Us => "us",
+            Layout::Uk => "uk",
+            Layout::De => "de",
+            Layout::Azerty => "azerty",
+            Layout::Dvorak => "dvorak",
+            Layout::Colemak => "colemak",
+            Layout::Jis => "jis",
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Layout::Us => "US 104-key",
+            Layout::Uk => "UK 105-key",
+            Layout::De => "German 105-key",
+            Layout::Azerty => "French AZERTY",
+            Layout::Dvorak => "US Dvorak",
+            Layout::Colemak => "US Colemak",
+            Layout::Jis => "Japanese 109-key",
+        }
+    }
+
+    /// Layout called `name`, ignoring case
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|layout| layout.name().eq_ignore_ascii_case(name))
+    }
+
+    pub(super) fn from_u8(value: u8) -> Self {
+        Self::ALL.get(usize::from(value)).copied().unwrap_or(Layout::Us)
+    }
+}
+
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Scancode set 1 decoder for one layout
+pub(super) enum Decoder {
+    Us(Keyboard<layouts::Us104Key, ScancodeSet1>),
+    Uk(Keyboard<layouts::Uk105Key, ScancodeSet1>),
+    De(Keyboard<layouts::De105Key, ScancodeSet1>),
+    Azerty(Keyboard<layouts::Azerty, ScancodeSet1>),
+    Dvorak(Keyboard<layouts::Dvorak104Key, ScancodeSet1>),
+    Colemak(Keyboard<layouts::Colemak, ScancodeSet1>),
+    Jis(Keyboard<layouts::Jis109Key, ScancodeSet1>),
+}
+
+/// Run `$body` with `$keyboard` bound to the decoder of whichever layout
+macro_rules! with_keyboard {
+    ($decoder:expr, $keyboard:ident => $body:expr) => {
+        match $decoder {
+            Decoder::Us($keyboard) => $body,
+            Decoder::Uk($keyboard) => $body,
+            Decoder::De($keyboard) => $body,
+            Decoder::Azerty($keyboard) => $body,
+            Decoder::Dvorak($keyboard) => $body,
+            Decoder::Colemak($keyboard) => $body,
+            Decoder::Jis($keyboard) => $body,
+        }
+    };
+}
+
+impl Decoder {
+    pub(super) fn new(layout: Layout) -> Self {
+        // Ctrl+letter decodes to the control character, like a terminal
+        let control = HandleControl::MapLettersToUnicode;
+        match layout {
+            Layout::Us => Decoder::Us(Keyboard::new(ScancodeSet1::new(), layouts::Us104Key, control)),
+            Layout::Uk => Decoder::Uk(Keyboard::new(ScancodeSet1::new(), layouts::Uk105Key, control)),
+            Layout::De => Decoder::De(Keyboard::new(ScancodeSet1::new(), layouts::De105Key, control)),
+            Layout::Azerty => Decoder::Azerty(Keyboard::new(ScancodeSet1::new(), layouts::Azerty, control)),
+            Layout::Dvorak => {
+                Decoder::Dvorak(Keyboard::new(ScancodeSet1::new(), layouts::Dvorak104Key, control))
+            }
+            Layout::Colemak => Decoder::Colemak(Keyboard::new(ScancodeSet1::new(), layouts::Colemak, control)),
+            Layout::Jis => Decoder::Jis(Keyboard::new(ScancodeSet1::new(), layouts::Jis109Key, control)),
+        }
+    }
+
+    /// Key event of a finished scancode sequence
+    pub(super) fn add_byte(&mut self, scancode: u8) -> Option<KeyEvent> {
+        with_keyboard!(self, keyboard => keyboard.add_byte(scancode).ok().flatten())
+    }
+
+    /// What `event` types, under this layout
+    pub(super) fn decode(&mut self, event: KeyEvent) -> Option<DecodedKey> {
+        with_keyboard!(self, keyboard => keyboard.process_keyevent(event))
+    }
+}
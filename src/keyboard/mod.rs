@@ -0,0 +1,351 @@
+//! Keyboard
+//!
+//! The interrupt handler queues raw scancodes, which the `run` task decodes
+//! under the current layout into `KeyEvent`s: presses and releases with
+//! the modifier state and, for presses, what the key types. Every event
+//! goes to all subscribers, so the terminal and anything else interested
+//! can read the keyboard at once. Ctrl+Alt+Del reboots.
+//!
+//! The layout is set with `set_layout`, by the shell's `keymap` command or
+//! the `keymap=` boot option.
+
+pub mod layout;
+
+pub use layout::Layout;
+pub use pc_keyboard::{DecodedKey, KeyCode};
+
+use crate::sync::SpinLock;
+use crate::tty::Tty;
+use crate::{cmdline, power, print, println};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use conquer_once::spin::OnceCell;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use core::task::{Context, Poll, Waker};
+use crossbeam_queue::ArrayQueue;
+use futures_util::future;
+use futures_util::stream::{Stream, StreamExt};
+use layout::Decoder;
+use pc_keyboard::KeyState;
+use x86_64::instructions::interrupts;
+
+/// Scancodes the interrupt handler can queue ahead of `run`
+const SCANCODE_QUEUE_SIZE: usize = 100;
+
+/// Events a subscriber can fall behind by before the oldest are dropped
+const SUBSCRIBER_QUEUE_SIZE: usize = 64;
+
+static SCANCODE_QUEUE: OnceCell<ArrayQueue<u8>> = OnceCell::uninit();
+static WAKER: SpinLock<Option<Waker>> = SpinLock::new(None);
+
+/// Called by the keyboard interrupt handler
+///
+/// Must not block or allocate.
+pub(crate) fn add_scancode(scancode: u8) {
+    if let Ok(queue) = SCANCODE_QUEUE.try_get() {
+        if queue.push(scancode).is_err() {
+            println!("WARNING: scancode queue full; dropping keyboard input");
+        } else if let Some(waker) = WAKER.lock().as_ref() {
+            waker.wake_by_ref();
+        }
+    } else {
+        println!("WARNING: scancode queue uninitialized");
+    }
+}
+
+/// Scancodes queued by the interrupt handler
+struct ScancodeStream {
+    queue: &'static ArrayQueue<u8>,
+}
+
+impl Stream for ScancodeStream {
+    type Item = u8;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<u8>> {
+        // fast path
+        if let Some(scancode) = self.queue.pop() {
+            return Poll::Ready(Some(scancode));
+        }
+
+        // The interrupt handler takes this lock too
+        interrupts::without_interrupts(|| {
+            let mut waker = WAKER.lock();
+            match waker.as_ref() {
+                Some(w) if w.will_wake(cx.waker()) => {}
+                _ => *waker = Some(cx.waker().clone()),
+            }
+        });
+
+        match self.queue.pop() {
+            Some(scancode) => Poll::Ready(Some(scancode)),
+            None => Poll::Pending,
+        }
+    }
+}
+
+/// Modifier keys held, and lock keys on, when a key event happened
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+    pub alt_gr: bool,
+    pub caps_lock: bool,
+    pub num_lock: bool,
+}
+
+impl Modifiers {
+    /// Track the key `code` going down or up
+    fn update(&mut self, code: KeyCode, pressed: bool) {
+        match code {
+            KeyCode::LShift | KeyCode::RShift => self.shift = pressed,
+            KeyCode::LControl | KeyCode::RControl => self.ctrl = pressed,
+            KeyCode::LAlt => self.alt = pressed,
+            KeyCode::RAltGr => self.alt_gr = pressed,
+            KeyCode::CapsLock if pressed => self.caps_lock = !self.caps_lock,
+            KeyCode::NumpadLock if pressed => self.num_lock = !self.num_lock,
+            _ => {}
+        }
+    }
+}
+
+/// A key going down or up
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyEvent {
+    /// The physical key, named after what it types on a US keyboard
+    pub code: KeyCode,
+    pub pressed: bool,
+    /// Modifier state, including this key if it is a modifier
+    pub modifiers: Modifiers,
+    /// What the key types under the layout; only for presses
+    pub decoded: Option<DecodedKey>,
+}
+
+impl KeyEvent {
+    /// Whether this is Delete pressed with Ctrl and Alt held
+    pub fn is_ctrl_alt_del(&self) -> bool {
+        self.pressed
+            && matches!(self.code, KeyCode::Delete | KeyCode::NumpadPeriod)
+            && self.modifiers.ctrl
+            && (self.modifiers.alt || self.modifiers.alt_gr)
+    }
+}
+
+/// Turns scancode set 1 bytes into key events under a layout
+pub struct ScancodeDecoder {
+    layout: Layout,
+    decoder: Decoder,
+    modifiers: Modifiers,
+}
+
+impl ScancodeDecoder {
+    pub fn new(layout: Layout) -> Self {
+        ScancodeDecoder { layout, decoder: Decoder::new(layout), modifiers: Modifiers::default() }
+    }
+
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    /// Switch to `layout`
+    ///
+    /// Modifier and lock state start over: keys held during the switch
+    /// only count once pressed again.
+    pub fn set_layout(&mut self, layout: Layout) {
+        if layout != self.layout {
+            *self = ScancodeDecoder::new(layout);
+        }
+    }
+
+    /// Take a scancode byte; returns the event once a key's sequence is
+    /// complete
+    pub fn feed(&mut self, scancode: u8) -> Option<KeyEvent> {
+        let event = self.decoder.add_byte(scancode)?;
+        let code = event.code;
+        let pressed = event.state != KeyState::Up;
+        self.modifiers.update(code, pressed);
+        let decoded = self.decoder.decode(event);
+        Some(KeyEvent { code, pressed, modifiers: self.modifiers, decoded })
+    }
+}
+
+/// Index into `Layout::ALL` of the layout `run` decodes with
+static LAYOUT: AtomicU8 = AtomicU8::new(Layout::Us as u8);
+
+/// The current layout
+pub fn layout() -> Layout {
+    Layout::from_u8(LAYOUT.load(Ordering::Relaxed))
+}
+
+/// Decode keys typed from now on under `layout`
+pub fn set_layout(layout: Layout) {
+    LAYOUT.store(layout as u8, Ordering::Relaxed);
+}
+
+/// Queue of one subscriber, shared with the publisher
+struct Subscriber {
+    events: ArrayQueue<KeyEvent>,
+    waker: SpinLock<Option<Waker>>,
+    dropped: AtomicU64,
+}
+
+/// Locked with interrupts disabled
+static SUBSCRIBERS: SpinLock<Vec<Arc<Subscriber>>> = SpinLock::new(Vec::new());
+
+/// A stream of every key event from the moment of subscribing
+///
+/// A subscriber that falls behind loses its oldest events.
+pub struct Subscription {
+    subscriber: Arc<Subscriber>,
+}
+
+/// Receive key events
+pub fn subscribe() -> Subscription {
+    let subscriber = Arc::new(Subscriber {
+        events: ArrayQueue::new(SUBSCRIBER_QUEUE_SIZE),
+        waker: SpinLock::new(None),
+        dropped: AtomicU64::new(0),
+    });
+    interrupts::without_interrupts(|| SUBSCRIBERS.lock().push(subscriber.clone()));
+    Subscription { subscriber }
+}
+
+impl Subscription {
+    /// The next queued event, without waiting
+    pub fn try_next(&mut self) -> Option<KeyEvent> {
+        self.subscriber.events.pop()
+    }
+
+    /// Events lost because this subscriber fell behind
+    pub fn dropped(&self) -> u64 {
+        self.subscriber.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl Stream for Subscription {
+    type Item = KeyEvent;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<KeyEvent>> {
+        let subscriber = &self.subscriber;
+        if let Some(event) = subscriber.events.pop() {
+            return Poll::Ready(Some(event));
+        }
+        interrupts::without_interrupts(|| {
+            let mut waker = subscriber.waker.lock();
+            match waker.as_ref() {
+                Some(w) if w.will_wake(cx.waker()) => {}
+                _ => *waker = Some(cx.waker().clone()),
+            }
+        });
+        match subscriber.events.pop() {
+            Some(event) => Poll::Ready(Some(event)),
+            None => Poll::Pending,
+        }
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        interrupts::without_interrupts(|| {
+            SUBSCRIBERS.lock().retain(|subscriber| !Arc::ptr_eq(subscriber, &self.subscriber))
+        });
+    }
+}
+
+/// Send `event` to every subscriber
+///
+/// `run` publishes what is typed; anything else can inject keys too.
+pub fn publish(event: KeyEvent) {
+    interrupts::without_interrupts(|| {
+        for subscriber in SUBSCRIBERS.lock().iter() {
+            if subscriber.events.force_push(event.clone()).is_some() {
+                subscriber.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+            if let Some(waker) = subscriber.waker.lock().as_ref() {
+                waker.wake_by_ref();
+            }
+        }
+    });
+}
+
+/// Decode the keyboard and publish its events, forever
+///
+/// Starts with the layout of the `keymap=` boot option. Only one instance
+/// may run.
+pub async fn run() {
+    if SCANCODE_QUEUE.try_init_once(|| ArrayQueue::new(SCANCODE_QUEUE_SIZE)).is_err() {
+        println!("WARNING: keyboard already running");
+        return;
+    }
+    if let Some(name) = cmdline::option("keymap") {
+        match Layout::from_name(name) {
+            Some(layout) => set_layout(layout),
+            None => println!("WARNING: unknown keymap {}; using {}", name, layout()),
+        }
+    }
+
+    let mut scancodes = ScancodeStream { queue: SCANCODE_QUEUE.get().unwrap() };
+    let mut decoder = ScancodeDecoder::new(layout());
+    while let Some(scancode) = scancodes.next().await {
+        decoder.set_layout(layout());
+        let Some(event) = decoder.feed(scancode) else {
+            continue;
+        };
+        if event.is_ctrl_alt_del() {
+            power::reboot();
+        }
+        publish(event);
+    }
+}
+
+/// Keys pressed, as they decode
+pub fn presses() -> impl Stream<Item = DecodedKey> + Unpin {
+    subscribe().filter_map(|event| future::ready(event.decoded))
+}
+
+pub async fn print_keypresses() {
+    let mut keys = presses();
+
+    while let Some(key) = keys.next().await {
+        match key {
+            DecodedKey::Unicode('\u{3}') => print!("^C"),
+            DecodedKey::Unicode(character) => print!("{}", character),
+            DecodedKey::RawKey(key) => print!("{:?}", key),
+        }
+    }
+}
+
+/// What a VT100-style terminal sends for `key`
+///
+/// Backspace sends DEL and Enter a carriage return, like a serial
+/// terminal; keys without a sequence send nothing.
+pub fn key_bytes(key: DecodedKey, buf: &mut [u8; 4]) -> &[u8] {
+    let sequence: &[u8] = match key {
+        DecodedKey::Unicode('\u{8}') => b"\x7f",
+        DecodedKey::Unicode('\n') => b"\r",
+        // The Delete key decodes to DEL
+        DecodedKey::Unicode('\u{7f}') | DecodedKey::RawKey(KeyCode::Delete) => b"\x1b[3~",
+        DecodedKey::Unicode(character) => return character.encode_utf8(buf).as_bytes(),
+        DecodedKey::RawKey(KeyCode::ArrowUp) => b"\x1b[A",
+        DecodedKey::RawKey(KeyCode::ArrowDown) => b"\x1b[B",
+        DecodedKey::RawKey(KeyCode::ArrowRight) => b"\x1b[C",
+        DecodedKey::RawKey(KeyCode::ArrowLeft) => b"\x1b[D",
+        DecodedKey::RawKey(KeyCode::Home) => b"\x1b[H",
+        DecodedKey::RawKey(KeyCode::End) => b"\x1b[F",
+        DecodedKey::RawKey(_) => b"",
+    };
+    let len = sequence.len();
+    buf[..len].copy_from_slice(sequence);
+    &buf[..len]
+}
+
+/// Type keyboard input into `tty`, forever
+pub async fn feed_terminal(tty: &'static Tty) {
+    let mut keys = presses();
+    let mut buf = [0; 4];
+
+    while let Some(key) = keys.next().await {
+        tty.receive(key_bytes(key, &mut buf));
+    }
+}
@@ -20,6 +20,20 @@ pub mod drivers;
 pub mod shell;
 pub mod syscall;
 pub mod elf;
+pub mod usermode;
+pub mod cpu;
+pub mod fpu;
+pub mod random;
+pub mod acpi;
+pub mod power;
+pub mod time;
+pub mod sync;
+pub mod process;
+pub mod ipc;
+pub mod backtrace;
+pub mod tty;
+pub mod cmdline;
+pub mod keyboard;
 
 #[alloc_error_handler]
 fn alloc_error_handler(layout: alloc::alloc::Layout) -> ! {
@@ -29,7 +43,13 @@ fn alloc_error_handler(layout: alloc::alloc::Layout) -> ! {
 pub fn init() {
     gdt::init();
     interrupts::init_idt();
+    syscall::init();
+    fpu::init();
+    random::init();
+    time::init();
     unsafe { interrupts::PICS.lock().initialize() };
+    drivers::ata::init();
+    serial::init();
     x86_64::instructions::interrupts::enable();
 }
 
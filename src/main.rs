@@ -65,10 +65,24 @@ fn kernel_main(boot_info: &'static BootInfo) -> ! {
 
     println!("It did not crash!");
 
+    memory::install(mapper, frame_allocator);
+
     #[cfg(test)]
     test_main();
 
     println!("=== Lithos OS Boot ===\n");
+
+    // Discover firmware tables before anything that needs the platform layout
+    println!("Discovering ACPI tables...");
+    match lithos::acpi::init() {
+        Ok(info) => {
+            println!("  ✓ ACPI {} ({} tables)", info.rsdp.oem_id, info.tables.len());
+            if let Some(madt) = &info.madt {
+                println!("  CPUs: {}, I/O APICs: {}\n", madt.processors.len(), madt.io_apics.len());
+            }
+        }
+        Err(e) => println!("  ✗ ACPI unavailable: {}\n", e),
+    }
     
     // Test block device layer
     println!("Testing Block Device Layer...");
@@ -102,6 +116,32 @@ fn kernel_main(boot_info: &'static BootInfo) -> ! {
     
     println!("  RAM Disk: {} blocks ({} KB)\n", ramdisk.block_count(), ramdisk.block_count() / 2);
 
+    // Run the embedded test program in ring 3, as a child process of init
+    println!("Testing User Mode...");
+    use lithos::process::{self, ExitStatus};
+    use lithos::usermode::{self, test_program};
+
+    let pid = process::spawn("user-test", || match usermode::run_program(test_program::code()) {
+        Ok(code) => code as i32,
+        Err(e) => {
+            println!("  ✗ Could not run ring 3 program: {}", e);
+            -1
+        }
+    });
+    process::set_foreground(Some(pid));
+    let status = process::wait(Some(pid), false);
+    process::set_foreground(None);
+    match status {
+        Ok(Some((pid, ExitStatus::Exited(code)))) if code as i64 == test_program::EXPECTED_EXIT_CODE => {
+            println!("  ✓ Ring 3 program (PID {}) exited with code {}\n", pid, code)
+        }
+        Ok(Some((pid, status))) => {
+            println!("  ✗ Ring 3 program (PID {}) ended unexpectedly: {:?}\n", pid, status)
+        }
+        Ok(None) => {}
+        Err(e) => println!("  ✗ Could not wait for ring 3 program: {}\n", e),
+    }
+
     println!("Initializing Virtual File System...");
     
     use lithos::vfs::{ramfs::RamFs, ops};
@@ -195,6 +235,10 @@ fn kernel_main(boot_info: &'static BootInfo) -> ! {
     for (name, _node) in &dev_nodes {
         println!("    - /dev/{}", name);
     }
+    match ops::mount("/dev", devfs::DevDirectory::new().root_node()) {
+        Ok(_) => println!("  ✓ Mounted device nodes on /dev"),
+        Err(e) => println!("  ✗ Failed to mount /dev: {}", e),
+    }
     
     // Test /dev/zero
     let zero_node = &dev_nodes[1].1;
@@ -222,6 +266,8 @@ fn kernel_main(boot_info: &'static BootInfo) -> ! {
         }
         Err(e) => println!("  /dev/random read failed: {}", e),
     }
+    println!("  Entropy source: {}",
+        lithos::random::hardware_source().unwrap_or("interrupt timing only"));
     
     // Create initial directory structure
     println!("\n=== Creating Initial Directory Structure ===");
@@ -240,52 +286,27 @@ fn kernel_main(boot_info: &'static BootInfo) -> ! {
     println!("Type 'help' for available commands");
     println!();
     
-    use lithos::shell::Shell;
-    let mut shell = Shell::new();
-    
-    // Interactive shell loop
-    loop {
-        print!("lithos$ ");
-        
-        // In a real implementation, we would read from keyboard
-        // For now, we'll demonstrate with predefined commands
-        // This is where keyboard input would be integrated
-        
-        // Since we don't have keyboard input yet, run a demo sequence
-        println!("(Interactive mode not yet implemented - running demo)");
-        println!();
-        
-        let demo_commands = [
-            "help",
-            "pwd",
-            "ls /",
-            "cd /usr",
-            "pwd",
-            "ls /usr",
-            "mkdir /usr/local",
-            "touch /usr/local/test.txt",
-            "ls /usr/local",
-            "cd /",
-            "echo Lithos OS is running!",
-        ];
-        
-        for cmd in &demo_commands {
-            println!("lithos$ {}", cmd);
-            shell.execute(cmd);
-            println!();
-        }
-        
-        println!("\n╔═══════════════════════════════════════════════════════════╗");
-        println!("║  Interactive keyboard input coming soon!                 ║");
-        println!("║  For now, the OS runs in demo mode.                      ║");
-        println!("╚═══════════════════════════════════════════════════════════╝");
-        
-        break; // Exit after demo
+    // The keyboard task publishes key events, which type into the console
+    // like COM1 does; COM2 types into its own terminal, each read by a
+    // shell. All are async tasks sharing a pool of executor threads; shell
+    // commands run on kernel threads of their own, so a long one doesn't
+    // hold up input.
+    use lithos::keyboard;
+    use lithos::serial::{self, ComPort};
+    use lithos::task::executor;
+    use lithos::tty;
+
+    let com2 = ComPort::Com2.is_present();
+    executor::init(2);
+    executor::spawn_named("keyboard", keyboard::run());
+    executor::spawn_named("console-keyboard", keyboard::feed_terminal(tty::console()));
+    executor::spawn_named("console-com1", serial::feed_terminal(ComPort::Com1, tty::console()));
+    executor::spawn_named("shell", lithos::shell::run(tty::console()));
+    if com2 {
+        executor::spawn_named("ttyS1-com2", serial::feed_terminal(ComPort::Com2, tty::com2()));
+        executor::spawn_named("shell-ttyS1", lithos::shell::run(tty::com2()));
     }
-    
-    println!("\nLithos OS demonstration complete!");
-    println!("Press Ctrl+A then X to exit QEMU");
-    
+
     lithos::hlt_loop();
 }
 
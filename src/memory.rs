@@ -1,8 +1,16 @@
 use x86_64::{
-    structures::paging::{PageTable, OffsetPageTable, FrameAllocator, PhysFrame, Size4KiB},
+    structures::paging::{
+        PageTable, OffsetPageTable, FrameAllocator, PhysFrame, Size4KiB, Page, PageTableFlags,
+        Mapper, mapper::MapToError,
+    },
     VirtAddr, PhysAddr,
 };
 use bootloader::bootinfo::{MemoryMap, MemoryRegionType};
+use crate::sync::SpinLock;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// Where the bootloader mapped the complete physical memory
+static PHYSICAL_MEMORY_OFFSET: AtomicU64 = AtomicU64::new(0);
 
 /// Initialize a new OffsetPageTable.
 ///
@@ -11,10 +19,18 @@ use bootloader::bootinfo::{MemoryMap, MemoryRegionType};
 /// `physical_memory_offset`. Also, this function must be only called once
 /// to avoid aliasing `&mut` references (which is undefined behavior).
 pub unsafe fn init(physical_memory_offset: VirtAddr) -> OffsetPageTable<'static> {
+    PHYSICAL_MEMORY_OFFSET.store(physical_memory_offset.as_u64(), Ordering::Relaxed);
     let level_4_table = active_level_4_table(physical_memory_offset);
     OffsetPageTable::new(level_4_table, physical_memory_offset)
 }
 
+/// Translate a physical address through the bootloader's physical memory mapping.
+///
+/// Only valid after [`init`] has been called.
+pub fn phys_to_virt(addr: PhysAddr) -> VirtAddr {
+    VirtAddr::new(PHYSICAL_MEMORY_OFFSET.load(Ordering::Relaxed) + addr.as_u64())
+}
+
 /// Returns a mutable reference to the active level 4 table.
 ///
 /// This function is unsafe because the caller must guarantee that the
@@ -77,3 +93,73 @@ unsafe impl FrameAllocator<Size4KiB> for BootInfoFrameAllocator {
         frame
     }
 }
+
+/// Page table and frame allocator shared by the whole kernel once boot is done.
+pub struct KernelMemory {
+    pub mapper: OffsetPageTable<'static>,
+    pub frame_allocator: BootInfoFrameAllocator,
+}
+
+static KERNEL_MEMORY: SpinLock<Option<KernelMemory>> = SpinLock::new(None);
+
+/// Hand the boot-time mapper and frame allocator over to the kernel.
+///
+/// Must be called after the heap is initialized; afterwards mappings are
+/// created through [`with_kernel_memory`].
+pub fn install(mapper: OffsetPageTable<'static>, frame_allocator: BootInfoFrameAllocator) {
+    *KERNEL_MEMORY.lock() = Some(KernelMemory { mapper, frame_allocator });
+}
+
+/// Run `f` with the kernel page table and frame allocator, if installed.
+pub fn with_kernel_memory<F, R>(f: F) -> Option<R>
+where
+    F: FnOnce(&mut KernelMemory) -> R,
+{
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        KERNEL_MEMORY.lock().as_mut().map(f)
+    })
+}
+
+/// Map `size` bytes starting at `start` as zeroed, user-accessible memory.
+///
+/// Pages that are already mapped are left untouched, so a region can be
+/// mapped again to reuse it.
+pub fn map_user_region(
+    start: VirtAddr,
+    size: u64,
+    flags: PageTableFlags,
+) -> Result<(), MapToError<Size4KiB>> {
+    let flags = flags | PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE;
+    let parent_flags = PageTableFlags::PRESENT
+        | PageTableFlags::WRITABLE
+        | PageTableFlags::USER_ACCESSIBLE;
+
+    let start_page = Page::<Size4KiB>::containing_address(start);
+    let end_page = Page::<Size4KiB>::containing_address(start + size - 1u64);
+
+    with_kernel_memory(|memory| {
+        for page in Page::range_inclusive(start_page, end_page) {
+            if memory.mapper.translate_page(page).is_ok() {
+                continue;
+            }
+            let frame = memory.frame_allocator
+                .allocate_frame()
+                .ok_or(MapToError::FrameAllocationFailed)?;
+            unsafe {
+                memory.mapper
+                    .map_to_with_table_flags(
+                        page,
+                        frame,
+                        flags,
+                        parent_flags,
+                        &mut memory.frame_allocator,
+                    )?
+                    .flush();
+                // Frames come straight from the memory map and may hold old data
+                core::ptr::write_bytes(page.start_address().as_mut_ptr::<u8>(), 0, 4096);
+            }
+        }
+        Ok(())
+    })
+    .unwrap_or(Err(MapToError::FrameAllocationFailed))
+}
@@ -0,0 +1,224 @@
+//! Shutdown and reboot
+//!
+//! Shutdown enters ACPI S5 through the FADT's PM1 control blocks and falls
+//! back to the fixed ports emulators decode. Reboot tries the 8042 reset
+//! line, then the ACPI reset register, then forces a triple fault.
+
+use crate::acpi::{self, GenericAddress};
+use crate::{memory, println, serial_println};
+use crate::sync::SpinLock;
+use x86_64::instructions::{interrupts, port::Port};
+use x86_64::PhysAddr;
+
+/// PM1 control register: SCI_EN, set once the OS owns ACPI
+const PM1_SCI_EN: u16 = 1 << 0;
+
+/// PM1 control register: SLP_TYP field shift
+const PM1_SLP_TYP_SHIFT: u16 = 10;
+
+/// PM1 control register: SLP_EN, starts the sleep transition
+const PM1_SLP_EN: u16 = 1 << 13;
+
+/// Fixed PM1a control ports and values understood by emulators
+const FALLBACK_SHUTDOWN_PORTS: [(u16, u16); 3] = [
+    (0x604, 0x2000),  // QEMU (PIIX4/ICH9 PM base 0x600)
+    (0xB004, 0x2000), // Bochs and older QEMU
+    (0x4004, 0x3400), // VirtualBox
+];
+
+/// 8042 keyboard controller status/command port
+const KBC_COMMAND_PORT: u16 = 0x64;
+
+/// 8042 command pulsing the CPU reset line
+const KBC_PULSE_RESET: u8 = 0xFE;
+
+/// PCI configuration mechanism #1 ports, for reset registers in PCI space
+const PCI_CONFIG_ADDRESS: u16 = 0xCF8;
+const PCI_CONFIG_DATA: u16 = 0xCFC;
+
+/// Why the machine is going down, passed to shutdown hooks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerAction {
+    Shutdown,
+    Reboot,
+}
+
+/// Called before the machine powers off or resets
+pub type ShutdownHook = fn(PowerAction);
+
+/// Hooks that can be registered
+pub const MAX_SHUTDOWN_HOOKS: usize = 16;
+
+/// Registered hooks, in order; a fixed table so drivers can register
+/// before the heap exists. Locked with interrupts disabled.
+static HOOKS: SpinLock<[Option<(&'static str, ShutdownHook)>; MAX_SHUTDOWN_HOOKS]> =
+    SpinLock::new([None; MAX_SHUTDOWN_HOOKS]);
+
+/// Register a hook to run before shutdown or reboot, e.g. to flush caches
+///
+/// Hooks run in registration order, at most once.
+///
+/// # Panics
+/// If `MAX_SHUTDOWN_HOOKS` are registered already.
+pub fn register_shutdown_hook(name: &'static str, hook: ShutdownHook) {
+    interrupts::without_interrupts(|| {
+        let mut hooks = HOOKS.lock();
+        let slot = hooks.iter_mut().find(|slot| slot.is_none()).expect("too many shutdown hooks");
+        *slot = Some((name, hook));
+    });
+}
+
+/// Run and unregister every shutdown hook
+pub fn run_shutdown_hooks(action: PowerAction) {
+    let hooks = interrupts::without_interrupts(|| {
+        core::mem::replace(&mut *HOOKS.lock(), [None; MAX_SHUTDOWN_HOOKS])
+    });
+    for (name, hook) in hooks.into_iter().flatten() {
+        serial_println!("power: running shutdown hook '{}'", name);
+        hook(action);
+    }
+}
+
+/// Power off the machine
+pub fn shutdown() -> ! {
+    println!("Shutting down...");
+    run_shutdown_hooks(PowerAction::Shutdown);
+    interrupts::disable();
+
+    if let Err(e) = acpi_shutdown() {
+        serial_println!("power: ACPI shutdown unavailable: {}", e);
+    }
+    spin_delay();
+
+    for &(port, value) in FALLBACK_SHUTDOWN_PORTS.iter() {
+        unsafe { Port::<u16>::new(port).write(value) };
+        spin_delay();
+    }
+
+    println!("Shutdown failed; it is now safe to turn off your computer.");
+    crate::hlt_loop()
+}
+
+/// Reset the machine
+pub fn reboot() -> ! {
+    println!("Rebooting...");
+    run_shutdown_hooks(PowerAction::Reboot);
+    interrupts::disable();
+
+    keyboard_controller_reset();
+    spin_delay();
+
+    if acpi_reset() {
+        spin_delay();
+    }
+
+    serial_println!("power: reset failed, forcing a triple fault");
+    triple_fault()
+}
+
+/// Enter S5 through the FADT's PM1 control blocks
+fn acpi_shutdown() -> Result<(), &'static str> {
+    let info = acpi::info().ok_or("no ACPI tables")?;
+    let fadt = info.fadt.as_ref().ok_or("no FADT")?;
+    let s5 = info.s5.ok_or("no _S5_ object")?;
+    if fadt.pm1a_control_block == 0 {
+        return Err("no PM1a control block");
+    }
+
+    let mut pm1a = Port::<u16>::new(fadt.pm1a_control_block as u16);
+
+    // Switch from legacy (SMM) mode to ACPI mode if firmware hasn't
+    unsafe {
+        if pm1a.read() & PM1_SCI_EN == 0 && fadt.smi_command_port != 0 && fadt.acpi_enable != 0 {
+            Port::<u8>::new(fadt.smi_command_port as u16).write(fadt.acpi_enable);
+            for _ in 0..1_000_000 {
+                if pm1a.read() & PM1_SCI_EN != 0 {
+                    break;
+                }
+                core::hint::spin_loop();
+            }
+        }
+    }
+
+    let sleep = |slp_typ: u8| ((slp_typ as u16) << PM1_SLP_TYP_SHIFT) | PM1_SLP_EN;
+    unsafe {
+        pm1a.write(sleep(s5.pm1a));
+        if fadt.pm1b_control_block != 0 {
+            Port::<u16>::new(fadt.pm1b_control_block as u16).write(sleep(s5.pm1b));
+        }
+    }
+    Ok(())
+}
+
+/// Pulse the CPU reset line through the 8042 keyboard controller
+fn keyboard_controller_reset() {
+    let has_8042 = acpi::info()
+        .and_then(|info| info.fadt.as_ref())
+        .map(|fadt| fadt.has_8042())
+        .unwrap_or(true);
+    if !has_8042 {
+        return;
+    }
+
+    let mut port = Port::<u8>::new(KBC_COMMAND_PORT);
+    unsafe {
+        // Wait for the input buffer to drain
+        for _ in 0..100_000 {
+            if port.read() & 0x2 == 0 {
+                break;
+            }
+            core::hint::spin_loop();
+        }
+        port.write(KBC_PULSE_RESET);
+    }
+}
+
+/// Write the FADT reset value to the reset register, if there is one
+fn acpi_reset() -> bool {
+    let Some(fadt) = acpi::info().and_then(|info| info.fadt.as_ref()) else {
+        return false;
+    };
+    let Some(reg) = fadt.reset_register.filter(|_| fadt.supports_reset_register()) else {
+        return false;
+    };
+
+    unsafe {
+        match reg.address_space {
+            GenericAddress::SYSTEM_IO => Port::<u8>::new(reg.address as u16).write(fadt.reset_value),
+            GenericAddress::SYSTEM_MEMORY => {
+                let virt = memory::phys_to_virt(PhysAddr::new(reg.address));
+                core::ptr::write_volatile(virt.as_mut_ptr::<u8>(), fadt.reset_value);
+            }
+            GenericAddress::PCI_CONFIG => {
+                // Bus 0; device, function and offset are packed into the address
+                let device = ((reg.address >> 32) & 0x1F) as u32;
+                let function = ((reg.address >> 16) & 0x7) as u32;
+                let offset = (reg.address & 0xFF) as u32;
+                let address = 0x8000_0000 | device << 11 | function << 8 | (offset & 0xFC);
+                Port::<u32>::new(PCI_CONFIG_ADDRESS).write(address);
+                Port::<u8>::new(PCI_CONFIG_DATA + (offset & 0x3) as u16).write(fadt.reset_value);
+            }
+            _ => return false,
+        }
+    }
+    true
+}
+
+/// Load an empty IDT and raise an exception; the CPU can't deliver it and resets
+fn triple_fault() -> ! {
+    use x86_64::structures::DescriptorTablePointer;
+    use x86_64::VirtAddr;
+
+    let empty = DescriptorTablePointer { limit: 0, base: VirtAddr::new(0) };
+    unsafe {
+        x86_64::instructions::tables::lidt(&empty);
+        core::arch::asm!("int3", options(noreturn));
+    }
+}
+
+/// Give the hardware a moment to act on a power request
+fn spin_delay() {
+    for _ in 0..10_000_000 {
+        core::hint::spin_loop();
+    }
+}
@@ -0,0 +1,65 @@
+//! User memory owned by a process
+//!
+//! There is no memory isolation between processes yet. Every process runs
+//! on the kernel's page table, so each one can read and write the user
+//! mappings of all the others, and an `AddressSpace` only records the
+//! regions mapped on its process's behalf. Nothing is unmapped when a
+//! process exits: the frame allocator can't take frames back, and the
+//! next program loaded at the same addresses reuses the mappings.
+
+use crate::memory;
+use alloc::vec::Vec;
+use x86_64::registers::control::Cr3;
+use x86_64::structures::paging::mapper::MapToError;
+use x86_64::structures::paging::{PageTableFlags, PhysFrame, Size4KiB};
+use x86_64::VirtAddr;
+
+/// A user mapping made on behalf of a process
+#[derive(Debug, Clone, Copy)]
+pub struct Region {
+    pub start: VirtAddr,
+    pub size: u64,
+}
+
+/// Page table root and user mappings of a process
+///
+/// The page table is always the kernel's; the regions record which user
+/// mappings the process asked for, not memory only it can reach.
+#[derive(Debug)]
+pub struct AddressSpace {
+    page_table: PhysFrame,
+    regions: Vec<Region>,
+}
+
+impl AddressSpace {
+    /// An address space on the kernel's page table with no user mappings
+    pub fn kernel() -> Self {
+        AddressSpace {
+            page_table: Cr3::read().0,
+            regions: Vec::new(),
+        }
+    }
+
+    /// Root of the page table loaded while the process runs
+    pub fn page_table(&self) -> PhysFrame {
+        self.page_table
+    }
+
+    pub fn regions(&self) -> &[Region] {
+        &self.regions
+    }
+
+    /// Map zeroed user memory and record it (see `memory::map_user_region`)
+    pub fn map_user_region(
+        &mut self,
+        start: VirtAddr,
+        size: u64,
+        flags: PageTableFlags,
+    ) -> Result<(), MapToError<Size4KiB>> {
+        memory::map_user_region(start, size, flags)?;
+        if !self.regions.iter().any(|region| region.start == start && region.size == size) {
+            self.regions.push(Region { start, size });
+        }
+        Ok(())
+    }
+}
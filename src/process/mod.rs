@@ -0,0 +1,487 @@
+//! Processes: PIDs, parent/child links and per-process resources
+//!
+//! A process owns one or more kernel threads, an address space, a file
+//! descriptor table, a capability table, a working directory, credentials
+//! and a controlling terminal. PID 1 (`init`)
+//! is created on first use and owns every thread that was not started in
+//! another process, including the boot thread.
+//!
+//! Processes are not isolated from each other: every address space is the
+//! kernel's page table, and user mappings stay in place after the process
+//! exits (see `address_space`).
+//!
+//! A process becomes a zombie when its last thread exits and stays one
+//! until its parent collects the exit status with `wait`. Children of an
+//! exiting process are re-parented to PID 1, which reaps them as soon as
+//! they exit.
+
+pub mod address_space;
+pub mod signal;
+
+pub use address_space::AddressSpace;
+pub use signal::SignalState;
+
+use crate::ipc::CapTable;
+use crate::sync::WaitQueue;
+use crate::task::{thread, TaskId};
+use crate::tty::{self, Tty};
+use crate::vfs::fd_table::FdTable;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::cell::Cell;
+use core::fmt;
+use crate::sync::SpinLock;
+use x86_64::instructions::interrupts;
+
+/// Process ID
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Pid(u32);
+
+impl Pid {
+    pub const fn new(pid: u32) -> Self {
+        Pid(pid)
+    }
+
+    pub fn as_u32(self) -> u32 {
+        self.0
+    }
+}
+
+impl fmt::Display for Pid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// PID of `init`, which adopts orphans
+pub const INIT_PID: Pid = Pid(1);
+
+/// User and group identity of a process
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Credentials {
+    pub uid: u32,
+    pub gid: u32,
+    pub euid: u32,
+    pub egid: u32,
+}
+
+impl Credentials {
+    pub const fn root() -> Self {
+        Credentials { uid: 0, gid: 0, euid: 0, egid: 0 }
+    }
+}
+
+/// Lifecycle state of a process
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessState {
+    Running,
+    /// Stopped by a signal until `SIGCONT`
+    Stopped,
+    /// Every thread exited; kept until the parent collects the exit status
+    Zombie,
+}
+
+/// How a process ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitStatus {
+    /// Called `exit` or returned from its main function
+    Exited(i32),
+    /// Terminated by a signal
+    Signaled(u8),
+}
+
+impl ExitStatus {
+    /// Status word as stored by `wait4`
+    pub fn wait_status(self) -> i32 {
+        match self {
+            ExitStatus::Exited(code) => (code & 0xff) << 8,
+            ExitStatus::Signaled(signal) => signal as i32 & 0x7f,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessError {
+    NoSuchProcess,
+    /// The caller has no child matching the request
+    NoChildren,
+    InvalidSignal,
+    PermissionDenied,
+}
+
+impl fmt::Display for ProcessError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProcessError::NoSuchProcess => write!(f, "No such process"),
+            ProcessError::NoChildren => write!(f, "No child processes"),
+            ProcessError::InvalidSignal => write!(f, "Invalid signal"),
+            ProcessError::PermissionDenied => write!(f, "Operation not permitted"),
+        }
+    }
+}
+
+pub type ProcessResult<T> = Result<T, ProcessError>;
+
+pub struct Process {
+    pub pid: Pid,
+    /// `None` only for `init`
+    pub parent: Option<Pid>,
+    pub children: Vec<Pid>,
+    pub threads: Vec<TaskId>,
+    pub name: String,
+    pub address_space: AddressSpace,
+    pub fd_table: FdTable,
+    pub capabilities: CapTable,
+    pub cwd: String,
+    pub credentials: Credentials,
+    /// Terminal descriptors 0, 1 and 2 start out open on
+    pub terminal: Option<&'static Tty>,
+    pub signals: SignalState,
+    pub state: ProcessState,
+    /// Set by `exit` or a fatal signal; a process whose threads just
+    /// return exits with 0
+    pub exit_status: Option<ExitStatus>,
+    /// Re-parented to `init`, which reaps it without waiting
+    orphaned: bool,
+}
+
+impl Process {
+    fn new(
+        pid: Pid,
+        parent: Option<Pid>,
+        name: &str,
+        cwd: String,
+        credentials: Credentials,
+        terminal: Option<&'static Tty>,
+    ) -> Self {
+        Process {
+            pid,
+            parent,
+            children: Vec::new(),
+            threads: Vec::new(),
+            name: String::from(name),
+            address_space: AddressSpace::kernel(),
+            fd_table: terminal.map_or_else(FdTable::new, FdTable::with_terminal),
+            capabilities: CapTable::new(),
+            cwd,
+            credentials,
+            terminal,
+            signals: SignalState::new(),
+            state: ProcessState::Running,
+            exit_status: None,
+            orphaned: false,
+        }
+    }
+}
+
+/// Snapshot of a process for debugging
+#[derive(Debug, Clone)]
+pub struct ProcessInfo {
+    pub pid: Pid,
+    pub parent: Option<Pid>,
+    pub name: String,
+    pub state: ProcessState,
+    pub threads: usize,
+    pub children: usize,
+    pub exit_status: Option<ExitStatus>,
+}
+
+impl ProcessInfo {
+    fn new(process: &Process) -> Self {
+        ProcessInfo {
+            pid: process.pid,
+            parent: process.parent,
+            name: process.name.clone(),
+            state: process.state,
+            threads: process.threads.len(),
+            children: process.children.len(),
+            exit_status: process.exit_status,
+        }
+    }
+}
+
+struct ProcessTable {
+    processes: BTreeMap<Pid, Process>,
+    /// Process of every thread started in one; other threads belong to init
+    threads: BTreeMap<TaskId, Pid>,
+    next_pid: u32,
+}
+
+impl ProcessTable {
+    const fn new() -> Self {
+        ProcessTable {
+            processes: BTreeMap::new(),
+            threads: BTreeMap::new(),
+            next_pid: INIT_PID.0 + 1,
+        }
+    }
+
+    fn ensure_init(&mut self) {
+        if self.processes.contains_key(&INIT_PID) {
+            return;
+        }
+        let mut init = Process::new(
+            INIT_PID,
+            None,
+            "init",
+            String::from("/"),
+            Credentials::root(),
+            Some(tty::console()),
+        );
+        if let Some(boot) = thread::current() {
+            init.threads.push(boot);
+            self.threads.insert(boot, INIT_PID);
+        }
+        self.processes.insert(INIT_PID, init);
+    }
+
+    fn pid_of(&self, thread: Option<TaskId>) -> Pid {
+        thread
+            .and_then(|id| self.threads.get(&id).copied())
+            .unwrap_or(INIT_PID)
+    }
+
+    fn attach_thread(&mut self, pid: Pid, thread: TaskId) {
+        if let Some(process) = self.processes.get_mut(&pid) {
+            process.threads.push(thread);
+            self.threads.insert(thread, pid);
+        }
+    }
+
+    /// Remove `thread`; returns true if its process became a zombie
+    fn detach_thread(&mut self, thread: TaskId) -> bool {
+        let Some(pid) = self.threads.remove(&thread) else {
+            return false;
+        };
+        let Some(process) = self.processes.get_mut(&pid) else {
+            return false;
+        };
+        process.threads.retain(|&id| id != thread);
+        if !process.threads.is_empty() || pid == INIT_PID {
+            return false;
+        }
+
+        process.state = ProcessState::Zombie;
+        process.exit_status.get_or_insert(ExitStatus::Exited(0));
+        // Files and capabilities are closed on exit, not when the zombie is
+        // reaped, so pending calls to the process fail right away
+        process.fd_table = FdTable::new();
+        process.capabilities = CapTable::new();
+        let children = core::mem::take(&mut process.children);
+        let orphaned = process.orphaned;
+        let parent = process.parent;
+
+        for child in children {
+            self.reparent_to_init(child);
+        }
+        if orphaned {
+            self.reap(pid);
+        } else if let Some(parent) = parent.and_then(|parent| self.processes.get_mut(&parent)) {
+            signal::raise(parent, signal::SIGCHLD);
+        }
+        true
+    }
+
+    fn reparent_to_init(&mut self, pid: Pid) {
+        let Some(child) = self.processes.get_mut(&pid) else {
+            return;
+        };
+        child.parent = Some(INIT_PID);
+        child.orphaned = true;
+        if child.state == ProcessState::Zombie {
+            self.processes.remove(&pid);
+        } else if let Some(init) = self.processes.get_mut(&INIT_PID) {
+            init.children.push(pid);
+        }
+    }
+
+    /// Free a zombie and unlink it from its parent
+    fn reap(&mut self, pid: Pid) -> Option<Process> {
+        let process = self.processes.remove(&pid)?;
+        if let Some(parent) = process.parent.and_then(|parent| self.processes.get_mut(&parent)) {
+            parent.children.retain(|&child| child != pid);
+        }
+        Some(process)
+    }
+
+    /// Reap a zombie child of `parent` matching `pid` (any child if `None`)
+    fn try_wait(&mut self, parent: Pid, pid: Option<Pid>) -> ProcessResult<Option<(Pid, ExitStatus)>> {
+        let children = &self.processes.get(&parent).ok_or(ProcessError::NoSuchProcess)?.children;
+        let mut candidates = children.iter().filter(|&&child| pid.is_none_or(|pid| pid == child));
+
+        let Some(zombie) = candidates
+            .clone()
+            .copied()
+            .find(|child| self.processes.get(child).is_some_and(|p| p.state == ProcessState::Zombie))
+        else {
+            return match candidates.next() {
+                Some(_) => Ok(None),
+                None => Err(ProcessError::NoChildren),
+            };
+        };
+
+        let process = self.reap(zombie).ok_or(ProcessError::NoSuchProcess)?;
+        Ok(Some((zombie, process.exit_status.unwrap_or(ExitStatus::Exited(0)))))
+    }
+}
+
+/// Locked with interrupts disabled, like the thread scheduler
+static PROCESSES: SpinLock<ProcessTable> = SpinLock::new(ProcessTable::new());
+
+/// Parents blocked in `wait`
+static CHILD_EXITED: WaitQueue = WaitQueue::new();
+
+fn with_table<R>(f: impl FnOnce(&mut ProcessTable) -> R) -> R {
+    interrupts::without_interrupts(|| {
+        let mut table = PROCESSES.lock();
+        table.ensure_init();
+        f(&mut table)
+    })
+}
+
+crate::kernel_thread_local! {
+    /// Process of the running thread, which never changes once it runs
+    static CURRENT_PID: Cell<Option<Pid>> = Cell::new(None);
+}
+
+/// PID of the running thread's process
+pub fn current_pid() -> Pid {
+    CURRENT_PID.with(|pid| {
+        *pid.get().get_or_insert_with(|| {
+            let thread = thread::current();
+            with_table(|table| table.pid_of(thread))
+        })
+    })
+}
+
+/// PID of the process `thread` belongs to
+pub fn thread_pid(thread: TaskId) -> Pid {
+    with_table(|table| table.pid_of(Some(thread)))
+}
+
+/// `getpid`
+pub fn getpid() -> Pid {
+    current_pid()
+}
+
+/// `getppid`; `init` has no parent and gets 0
+pub fn getppid() -> u32 {
+    let thread = thread::current();
+    with_table(|table| {
+        let pid = table.pid_of(thread);
+        table.processes.get(&pid).and_then(|p| p.parent).map_or(0, Pid::as_u32)
+    })
+}
+
+/// Run `f` on the running thread's process
+pub fn with_current<R>(f: impl FnOnce(&mut Process) -> R) -> R {
+    let pid = current_pid();
+    with_table(|table| {
+        let process = table.processes.get_mut(&pid).expect("current process missing");
+        f(process)
+    })
+}
+
+/// Start a child process of the running one with a single thread running
+/// `f`; the process exits with the value `f` returns
+pub fn spawn<F>(name: &str, f: F) -> Pid
+where
+    F: FnOnce() -> i32 + Send + 'static,
+{
+    let thread = thread::current();
+    let pid = with_table(|table| {
+        let parent_pid = table.pid_of(thread);
+        let parent = table.processes.get_mut(&parent_pid).expect("current process missing");
+        let pid = Pid(table.next_pid);
+        parent.children.push(pid);
+        let child = Process::new(
+            pid,
+            Some(parent_pid),
+            name,
+            parent.cwd.clone(),
+            parent.credentials,
+            parent.terminal,
+        );
+
+        table.next_pid += 1;
+        table.processes.insert(pid, child);
+        pid
+    });
+
+    let handle = thread::Builder::new()
+        .name(name)
+        .process(pid)
+        .spawn(move || run_main(f));
+    // Processes are waited for with `wait`, not through their threads
+    drop(handle);
+    pid
+}
+
+fn run_main(f: impl FnOnce() -> i32) {
+    exit(f())
+}
+
+/// Put a new thread in process `pid`, before it first runs
+pub(crate) fn attach_thread(pid: Pid, thread: TaskId) {
+    with_table(|table| table.attach_thread(pid, thread));
+}
+
+/// Take an exiting thread out of its process
+///
+/// Called by `thread::exit`. The last thread to leave turns the process
+/// into a zombie and wakes its parent.
+pub(crate) fn detach_thread(thread: TaskId) {
+    if with_table(|table| table.detach_thread(thread)) {
+        CHILD_EXITED.notify_all();
+    }
+}
+
+/// Record the exit status of the running process, unless already set
+pub fn set_exit_status(status: ExitStatus) {
+    with_current(|process| {
+        process.exit_status.get_or_insert(status);
+    });
+}
+
+/// Exit the running thread, making `status` the process's exit status
+///
+/// The process exits once its last thread has.
+pub fn exit(status: i32) -> ! {
+    set_exit_status(ExitStatus::Exited(status));
+    thread::exit(status as u64)
+}
+
+/// Wait for a child of the running process to exit and reap it
+///
+/// Waits for `pid`, or any child if `None`. Returns the child's PID and
+/// exit status, or `None` if `nohang` is set and no child has exited yet.
+pub fn wait(pid: Option<Pid>, nohang: bool) -> ProcessResult<Option<(Pid, ExitStatus)>> {
+    let parent = current_pid();
+    let mut result = Ok(None);
+    CHILD_EXITED.wait_until(|| {
+        result = with_table(|table| table.try_wait(parent, pid));
+        nohang || !matches!(result, Ok(None))
+    });
+    result
+}
+
+/// Describe one process
+pub fn info(pid: Pid) -> Option<ProcessInfo> {
+    with_table(|table| table.processes.get(&pid).map(ProcessInfo::new))
+}
+
+/// Describe every process
+pub fn list() -> Vec<ProcessInfo> {
+    with_table(|table| table.processes.values().map(ProcessInfo::new).collect())
+}
+
+/// Make `pid` the process that receives Ctrl+C on the console
+pub fn set_foreground(pid: Option<Pid>) {
+    tty::console().set_foreground(pid);
+}
+
+/// Process that receives Ctrl+C on the console
+pub fn foreground() -> Option<Pid> {
+    tty::console().foreground()
+}
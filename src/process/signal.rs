@@ -0,0 +1,428 @@
+//! POSIX-style signals
+//!
+//! Each process has a pending and a blocked signal mask and an action per
+//! signal. Signals are delivered when a thread of the process returns to
+//! user mode, from a system call or from the timer or yield interrupt that
+//! resumes it, so even a loop making no system calls sees them within a
+//! tick: a handler runs on the user stack above a
+//! `SignalFrame` that `rt_sigreturn` restores, while default actions
+//! terminate, stop or continue the process or ignore the signal.
+//!
+//! Threads that never enter user mode are not interrupted; pending signals
+//! wait until the process next returns to user mode.
+
+use super::{
+    with_current, with_table, ExitStatus, Pid, Process, ProcessError, ProcessResult, ProcessState,
+};
+use crate::gdt;
+use crate::sync::WaitQueue;
+use crate::syscall::entry::SyscallFrame;
+use crate::task::context::TaskContext;
+use crate::usermode::is_user_range;
+use core::mem::size_of;
+
+pub const SIGHUP: u8 = 1;
+pub const SIGINT: u8 = 2;
+pub const SIGQUIT: u8 = 3;
+pub const SIGILL: u8 = 4;
+pub const SIGTRAP: u8 = 5;
+pub const SIGABRT: u8 = 6;
+pub const SIGBUS: u8 = 7;
+pub const SIGFPE: u8 = 8;
+pub const SIGKILL: u8 = 9;
+pub const SIGUSR1: u8 = 10;
+pub const SIGSEGV: u8 = 11;
+pub const SIGUSR2: u8 = 12;
+pub const SIGPIPE: u8 = 13;
+pub const SIGALRM: u8 = 14;
+pub const SIGTERM: u8 = 15;
+pub const SIGCHLD: u8 = 17;
+pub const SIGCONT: u8 = 18;
+pub const SIGSTOP: u8 = 19;
+pub const SIGTSTP: u8 = 20;
+pub const SIGTTIN: u8 = 21;
+pub const SIGTTOU: u8 = 22;
+pub const SIGURG: u8 = 23;
+pub const SIGWINCH: u8 = 28;
+
+/// Number of signals; valid signals are 1..=NSIG
+pub const NSIG: u8 = 64;
+
+/// `sa_handler` values with special meaning
+pub const SIG_DFL: u64 = 0;
+pub const SIG_IGN: u64 = 1;
+
+/// Don't block the signal while its handler runs
+pub const SA_NODEFER: u64 = 0x4000_0000;
+/// Reset the action to `SIG_DFL` once the handler is entered
+pub const SA_RESETHAND: u64 = 0x8000_0000;
+/// `sa_restorer` is valid; required for handlers
+pub const SA_RESTORER: u64 = 0x0400_0000;
+
+/// `how` values of `sigprocmask`
+pub const SIG_BLOCK: u64 = 0;
+pub const SIG_UNBLOCK: u64 = 1;
+pub const SIG_SETMASK: u64 = 2;
+
+/// Bytes below the interrupted stack pointer left alone (System V red zone)
+const RED_ZONE: u64 = 128;
+
+/// Action of a signal, laid out like the kernel's `struct sigaction`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SigAction {
+    pub handler: u64,
+    pub flags: u64,
+    /// Where the handler returns to; must call `rt_sigreturn`
+    pub restorer: u64,
+    /// Signals blocked while the handler runs
+    pub mask: u64,
+}
+
+impl SigAction {
+    pub const fn default() -> Self {
+        SigAction { handler: SIG_DFL, flags: 0, restorer: 0, mask: 0 }
+    }
+}
+
+/// What happens to a signal without a handler
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefaultAction {
+    Terminate,
+    Ignore,
+    Stop,
+    Continue,
+}
+
+pub fn default_action(signal: u8) -> DefaultAction {
+    match signal {
+        SIGCHLD | SIGURG | SIGWINCH => DefaultAction::Ignore,
+        SIGSTOP | SIGTSTP | SIGTTIN | SIGTTOU => DefaultAction::Stop,
+        SIGCONT => DefaultAction::Continue,
+        _ => DefaultAction::Terminate,
+    }
+}
+
+/// Signal state of a process
+#[derive(Debug, Clone)]
+pub struct SignalState {
+    /// Bit `n - 1` is set while signal `n` is pending
+    pub pending: u64,
+    pub blocked: u64,
+    pub actions: [SigAction; NSIG as usize],
+}
+
+impl Default for SignalState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SignalState {
+    pub const fn new() -> Self {
+        SignalState {
+            pending: 0,
+            blocked: 0,
+            actions: [SigAction::default(); NSIG as usize],
+        }
+    }
+
+    fn action(&self, signal: u8) -> SigAction {
+        self.actions[signal as usize - 1]
+    }
+
+    /// Lowest pending signal that isn't blocked, removed from `pending`
+    fn take_deliverable(&mut self) -> Option<u8> {
+        let deliverable = self.pending & !self.blocked;
+        if deliverable == 0 {
+            return None;
+        }
+        let signal = deliverable.trailing_zeros() as u8 + 1;
+        self.pending &= !mask(signal);
+        Some(signal)
+    }
+}
+
+pub const fn mask(signal: u8) -> u64 {
+    1 << (signal - 1)
+}
+
+/// Signals that can't be caught, blocked or ignored
+const UNCATCHABLE: u64 = mask(SIGKILL) | mask(SIGSTOP);
+
+const STOP_SIGNALS: u64 = mask(SIGSTOP) | mask(SIGTSTP) | mask(SIGTTIN) | mask(SIGTTOU);
+
+fn valid(signal: u8) -> bool {
+    (1..=NSIG).contains(&signal)
+}
+
+/// Stopped processes waiting for `SIGCONT`
+static CONTINUED: WaitQueue = WaitQueue::new();
+
+/// Make `signal` pending for `process`
+///
+/// Returns whether a stopped process was continued.
+pub(super) fn raise(process: &mut Process, signal: u8) -> bool {
+    let mut continued = false;
+    if (signal == SIGCONT || signal == SIGKILL) && process.state == ProcessState::Stopped {
+        process.state = ProcessState::Running;
+        continued = true;
+    }
+
+    let signals = &mut process.signals;
+    if signal == SIGCONT || signal == SIGKILL {
+        signals.pending &= !STOP_SIGNALS;
+    }
+    if STOP_SIGNALS & mask(signal) != 0 {
+        signals.pending &= !mask(SIGCONT);
+    }
+
+    let action = signals.action(signal);
+    let ignored = action.handler == SIG_IGN
+        || (action.handler == SIG_DFL && default_action(signal) == DefaultAction::Ignore);
+    if !ignored || UNCATCHABLE & mask(signal) != 0 {
+        signals.pending |= mask(signal);
+    }
+    continued
+}
+
+/// Send `signal` to process `pid`; signal 0 only checks that it exists
+pub fn send(pid: Pid, signal: u8) -> ProcessResult<()> {
+    if signal != 0 && !valid(signal) {
+        return Err(ProcessError::InvalidSignal);
+    }
+    let continued = with_table(|table| {
+        let process = table.processes.get_mut(&pid).ok_or(ProcessError::NoSuchProcess)?;
+        if signal == 0 || process.state == ProcessState::Zombie {
+            return Ok(false);
+        }
+        Ok(raise(process, signal))
+    })?;
+
+    if continued {
+        CONTINUED.notify_all();
+    }
+    Ok(())
+}
+
+/// `kill`: like `send`, if the caller may signal `pid`
+pub fn kill(pid: Pid, signal: u8) -> ProcessResult<()> {
+    let sender = with_current(|process| process.credentials);
+    let target = with_table(|table| table.processes.get(&pid).map(|process| process.credentials))
+        .ok_or(ProcessError::NoSuchProcess)?;
+    if sender.euid != 0 && sender.euid != target.uid && sender.uid != target.uid {
+        return Err(ProcessError::PermissionDenied);
+    }
+    send(pid, signal)
+}
+
+/// Change the action of `signal` for the running process, returning the
+/// old one
+pub fn sigaction(signal: u8, action: Option<SigAction>) -> ProcessResult<SigAction> {
+    if !valid(signal) {
+        return Err(ProcessError::InvalidSignal);
+    }
+    if action.is_some() && UNCATCHABLE & mask(signal) != 0 {
+        return Err(ProcessError::InvalidSignal);
+    }
+
+    Ok(with_current(|process| {
+        let signals = &mut process.signals;
+        let old = signals.action(signal);
+        if let Some(action) = action {
+            signals.actions[signal as usize - 1] = action;
+            let ignored = action.handler == SIG_IGN
+                || (action.handler == SIG_DFL && default_action(signal) == DefaultAction::Ignore);
+            if ignored {
+                signals.pending &= !mask(signal);
+            }
+        }
+        old
+    }))
+}
+
+/// Change the blocked mask of the running process, returning the old one
+pub fn sigprocmask(how: u64, set: Option<u64>) -> ProcessResult<u64> {
+    with_current(|process| {
+        let signals = &mut process.signals;
+        let old = signals.blocked;
+        if let Some(set) = set {
+            signals.blocked = match how {
+                SIG_BLOCK => old | set,
+                SIG_UNBLOCK => old & !set,
+                SIG_SETMASK => set,
+                _ => return Err(ProcessError::InvalidSignal),
+            } & !UNCATCHABLE;
+        }
+        Ok(old)
+    })
+}
+
+/// Signals pending for the running process
+pub fn pending() -> u64 {
+    with_current(|process| process.signals.pending)
+}
+
+/// Whether a signal the running process doesn't block is pending, which
+/// ends blocking calls early
+pub fn interrupted() -> bool {
+    with_current(|process| process.signals.pending & !process.signals.blocked != 0)
+}
+
+/// What the user stack holds while a handler runs
+///
+/// The handler's return address comes first, so it returns into the
+/// restorer, which calls `rt_sigreturn` with the stack pointer just above.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SignalFrame {
+    pub restorer: u64,
+    pub signal: u64,
+    /// Registers of the interrupted code
+    pub saved: SyscallFrame,
+    /// Blocked mask to restore
+    pub blocked: u64,
+}
+
+/// Act on the pending signals of the running process before `frame`
+/// returns to user mode
+///
+/// Runs at most one handler, by pointing `frame` at it.
+pub(crate) fn deliver(frame: &mut SyscallFrame) {
+    loop {
+        let next = with_current(|process| {
+            let signal = process.signals.take_deliverable()?;
+            let action = process.signals.action(signal);
+            if action.handler > SIG_IGN && action.flags & SA_RESETHAND != 0 {
+                process.signals.actions[signal as usize - 1] = SigAction::default();
+            }
+            Some((signal, action))
+        });
+        let Some((signal, action)) = next else {
+            return;
+        };
+
+        match action.handler {
+            SIG_IGN => {}
+            SIG_DFL => match default_action(signal) {
+                DefaultAction::Ignore | DefaultAction::Continue => {}
+                DefaultAction::Stop => stop_current(),
+                DefaultAction::Terminate => terminate_current(signal),
+            },
+            handler => {
+                enter_handler(frame, signal, handler, action);
+                return;
+            }
+        }
+    }
+}
+
+/// Deliver pending signals to a thread about to resume in user mode from
+/// a thread-switching interrupt
+///
+/// Called by the entries in `task::context` on the resumed thread's own
+/// stack, once the interrupt is handled, with the `TaskContext` they pop.
+/// It has the layout of `SyscallFrame`.
+pub(crate) extern "C" fn deliver_interrupted(frame: &mut SyscallFrame) {
+    if frame.from_user() {
+        deliver(frame);
+    }
+}
+
+const _: () = assert!(size_of::<TaskContext>() == size_of::<SyscallFrame>());
+
+fn stop_current() {
+    let pid = with_current(|process| {
+        process.state = ProcessState::Stopped;
+        process.pid
+    });
+    CONTINUED.wait_until(|| {
+        with_table(|table| {
+            table.processes.get(&pid).is_none_or(|p| p.state != ProcessState::Stopped)
+        })
+    });
+}
+
+fn terminate_current(signal: u8) -> ! {
+    super::set_exit_status(ExitStatus::Signaled(signal));
+    if crate::usermode::user_program_running() {
+        crate::usermode::exit_user(128 + signal as i64);
+    }
+    super::exit(128 + signal as i32)
+}
+
+/// Build a `SignalFrame` on the user stack and point `frame` at the handler
+///
+/// Kills the process with SIGSEGV if the frame wouldn't lie in user space
+/// or the handler or restorer are outside it.
+fn enter_handler(frame: &mut SyscallFrame, signal: u8, handler: u64, action: SigAction) {
+    // At function entry rsp + 8 is 16-byte aligned, as after a call
+    let rsp = frame
+        .rsp
+        .checked_sub(RED_ZONE + size_of::<SignalFrame>() as u64)
+        .and_then(|top| (top & !0xF).checked_sub(8))
+        .filter(|&rsp| is_user_range(rsp, size_of::<SignalFrame>() as u64));
+    let Some(rsp) = rsp else {
+        terminate_current(SIGSEGV);
+    };
+    if !is_user_range(handler, 1) || !is_user_range(action.restorer, 1) {
+        terminate_current(SIGSEGV);
+    }
+
+    let blocked = with_current(|process| {
+        let old = process.signals.blocked;
+        let mut blocked = old | action.mask;
+        if action.flags & SA_NODEFER == 0 {
+            blocked |= mask(signal);
+        }
+        process.signals.blocked = blocked & !UNCATCHABLE;
+        old
+    });
+
+    let signal_frame = SignalFrame {
+        restorer: action.restorer,
+        signal: signal as u64,
+        saved: *frame,
+        blocked,
+    };
+    // Safety: In user space; we assume the user stack is mapped and writable
+    unsafe { (rsp as *mut SignalFrame).write_unaligned(signal_frame) };
+
+    frame.rip = handler;
+    frame.rsp = rsp;
+    frame.rdi = signal as u64;
+    frame.rsi = 0;
+    frame.rdx = 0;
+}
+
+/// `rt_sigreturn`: resume the code a handler interrupted
+///
+/// The handler's `ret` popped the restorer address, so the rest of the
+/// `SignalFrame` starts right below the current user stack pointer. A
+/// frame outside user space, or one that would resume outside it, kills
+/// the process with SIGSEGV.
+pub(crate) fn sigreturn(frame: &mut SyscallFrame) {
+    let address = frame.rsp.checked_sub(8);
+    let Some(address) = address.filter(|&a| is_user_range(a, size_of::<SignalFrame>() as u64)) else {
+        terminate_current(SIGSEGV);
+    };
+    // Safety: In user space; we assume the user stack is mapped
+    let signal_frame = unsafe { (address as *const SignalFrame).read_unaligned() };
+    let saved = signal_frame.saved;
+    if !is_user_range(saved.rip, 1) || !is_user_range(saved.rsp, 1) {
+        terminate_current(SIGSEGV);
+    }
+
+    with_current(|process| process.signals.blocked = signal_frame.blocked & !UNCATCHABLE);
+
+    // Never let user memory pick kernel selectors or privileged flags
+    const USER_FLAGS: u64 = 0xCD5; // CF, PF, AF, ZF, SF, TF, DF, OF
+    const INTERRUPT_FLAG: u64 = 1 << 9;
+    let selectors = gdt::selectors();
+    *frame = SyscallFrame {
+        cs: selectors.user_code_selector.0 as u64,
+        ss: selectors.user_data_selector.0 as u64,
+        rflags: (saved.rflags & USER_FLAGS) | INTERRUPT_FLAG | 0x2,
+        ..saved
+    };
+}
@@ -0,0 +1,251 @@
+//! Entropy collection and the kernel CSPRNG
+//!
+//! Entropy from RDSEED/RDRAND (when the CPU has them) and from the timing of
+//! interrupts and key presses is folded into a pool. A ChaCha20 generator is
+//! seeded from the pool at boot and reseeded once enough new entropy has
+//! accumulated. `/dev/random`, `/dev/urandom` and `getrandom` all read from it.
+
+pub mod chacha20;
+
+use crate::cpu;
+use core::arch::asm;
+use crate::sync::SpinLock;
+use x86_64::instructions::random::RdRand;
+
+/// Estimated bits of new entropy required before the generator reseeds
+const RESEED_THRESHOLD_BITS: u32 = 256;
+
+/// Output after which the generator reseeds even without new entropy
+const RESEED_INTERVAL_BYTES: usize = 1024 * 1024;
+
+/// Accumulates entropy samples
+struct EntropyPool {
+    state: [u32; 8],
+    index: usize,
+    /// Estimated entropy added since the last extraction, in bits
+    pending_bits: u32,
+    /// Total samples mixed in, used as the stir counter
+    samples: u64,
+    last_tsc: u64,
+}
+
+impl EntropyPool {
+    const fn new() -> Self {
+        EntropyPool {
+            state: [0; 8],
+            index: 0,
+            pending_bits: 0,
+            samples: 0,
+            last_tsc: 0,
+        }
+    }
+
+    /// Fold a sample into the pool, crediting `bits` of entropy
+    fn mix(&mut self, value: u64, bits: u32) {
+        let i = self.index;
+        self.state[i] = self.state[i].rotate_left(7) ^ value as u32;
+        self.state[(i + 1) % 8] = self.state[(i + 1) % 8].rotate_left(13) ^ (value >> 32) as u32;
+        self.index = (i + 2) % 8;
+        self.samples = self.samples.wrapping_add(1);
+        self.pending_bits = self.pending_bits.saturating_add(bits);
+
+        // Diffuse the samples over the whole state after every full pass
+        if self.index == 0 {
+            self.stir();
+        }
+    }
+
+    /// Mix the timestamp of an event; the delta to the previous one carries the jitter
+    fn mix_timing(&mut self, tag: u64, bits: u32) {
+        let tsc = cpu::rdtsc();
+        let delta = tsc.wrapping_sub(self.last_tsc);
+        self.last_tsc = tsc;
+        self.mix(delta ^ tag.rotate_left(48), bits);
+    }
+
+    fn stir(&mut self) {
+        let block = chacha20::block(&self.state, self.samples, 0);
+        self.state = chacha20::key_from_bytes(&block[..32]);
+    }
+
+    /// Derive a 32-byte seed and reset the entropy estimate
+    fn extract(&mut self) -> [u8; 32] {
+        self.stir();
+        let block = chacha20::block(&self.state, self.samples, 1);
+        // Move the pool past the output so it can't be recomputed from the state
+        self.state = chacha20::key_from_bytes(&block[32..]);
+        self.pending_bits = 0;
+
+        let mut seed = [0u8; 32];
+        seed.copy_from_slice(&block[..32]);
+        seed
+    }
+}
+
+/// ChaCha20-based generator with fast key erasure
+struct Csprng {
+    key: [u32; 8],
+    nonce: u64,
+    bytes_since_reseed: usize,
+    seeded: bool,
+}
+
+impl Csprng {
+    const fn new() -> Self {
+        Csprng {
+            key: [0; 8],
+            nonce: 0,
+            bytes_since_reseed: 0,
+            seeded: false,
+        }
+    }
+
+    fn reseed(&mut self, seed: &[u8; 32]) {
+        let mut key = self.key;
+        for (word, extra) in key.iter_mut().zip(chacha20::key_from_bytes(seed)) {
+            *word ^= extra;
+        }
+        let block = chacha20::block(&key, 0, u64::MAX);
+        self.key = chacha20::key_from_bytes(&block[..32]);
+        self.nonce = self.nonce.wrapping_add(1);
+        self.bytes_since_reseed = 0;
+        self.seeded = true;
+    }
+
+    fn fill(&mut self, buf: &mut [u8]) {
+        for (counter, chunk) in (1..).zip(buf.chunks_mut(chacha20::BLOCK_SIZE)) {
+            let block = chacha20::block(&self.key, counter, self.nonce);
+            chunk.copy_from_slice(&block[..chunk.len()]);
+        }
+
+        // Replace the key so earlier output can't be reconstructed
+        let block = chacha20::block(&self.key, 0, self.nonce);
+        self.key = chacha20::key_from_bytes(&block[..32]);
+        self.bytes_since_reseed = self.bytes_since_reseed.saturating_add(buf.len());
+    }
+}
+
+static POOL: SpinLock<EntropyPool> = SpinLock::new(EntropyPool::new());
+static RNG: SpinLock<Csprng> = SpinLock::new(Csprng::new());
+
+/// Read one value from RDSEED, retrying a few times as Intel recommends
+fn rdseed() -> Option<u64> {
+    if !cpu::features().rdseed {
+        return None;
+    }
+    for _ in 0..10 {
+        let value: u64;
+        let ok: u8;
+        unsafe {
+            asm!("rdseed {}", "setc {}", out(reg) value, out(reg_byte) ok, options(nomem, nostack));
+        }
+        if ok != 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+fn rdrand() -> Option<u64> {
+    if !cpu::features().rdrand {
+        return None;
+    }
+    RdRand::new().and_then(|rng| rng.get_u64())
+}
+
+/// Pull entropy from the CPU's hardware generators into the pool
+fn gather_hardware_entropy(pool: &mut EntropyPool) {
+    for _ in 0..4 {
+        if let Some(value) = rdseed() {
+            pool.mix(value, 64);
+        }
+        // RDRAND is a DRBG output, so credit it conservatively
+        if let Some(value) = rdrand() {
+            pool.mix(value, 32);
+        }
+        pool.mix_timing(0, 1);
+    }
+}
+
+/// Seed the generator; call once during boot
+pub fn init() {
+    let seed = x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut pool = POOL.lock();
+        gather_hardware_entropy(&mut pool);
+        pool.extract()
+    });
+    RNG.lock().reseed(&seed);
+}
+
+/// Name of the hardware entropy source in use, if any
+pub fn hardware_source() -> Option<&'static str> {
+    let features = cpu::features();
+    if features.rdseed {
+        Some("RDSEED")
+    } else if features.rdrand {
+        Some("RDRAND")
+    } else {
+        None
+    }
+}
+
+/// Record the timing of an interrupt.
+///
+/// Called from interrupt handlers, so the sample is dropped rather than
+/// waiting when the pool is busy.
+pub fn add_interrupt_timing(vector: u8) {
+    if let Some(mut pool) = POOL.try_lock() {
+        pool.mix_timing(vector as u64, 1);
+    }
+}
+
+/// Record an input event such as a scancode; the timing of human input is
+/// less predictable than that of periodic interrupts.
+pub fn add_input_event(code: u64) {
+    if let Some(mut pool) = POOL.try_lock() {
+        pool.mix_timing(code, 2);
+    }
+}
+
+/// Mix caller-provided bytes into the pool without crediting any entropy
+pub fn add_bytes(bytes: &[u8]) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut pool = POOL.lock();
+        for chunk in bytes.chunks(8) {
+            let mut word = [0u8; 8];
+            word[..chunk.len()].copy_from_slice(chunk);
+            pool.mix(u64::from_le_bytes(word), 0);
+        }
+    });
+}
+
+/// Estimated entropy collected since the last reseed, in bits
+pub fn entropy_estimate() -> u32 {
+    x86_64::instructions::interrupts::without_interrupts(|| POOL.lock().pending_bits)
+}
+
+/// Fill `buf` with cryptographically secure random bytes
+pub fn fill_bytes(buf: &mut [u8]) {
+    let mut rng = RNG.lock();
+
+    let reseed = !rng.seeded
+        || rng.bytes_since_reseed >= RESEED_INTERVAL_BYTES
+        || entropy_estimate() >= RESEED_THRESHOLD_BITS;
+    if reseed {
+        let seed = x86_64::instructions::interrupts::without_interrupts(|| {
+            let mut pool = POOL.lock();
+            gather_hardware_entropy(&mut pool);
+            pool.extract()
+        });
+        rng.reseed(&seed);
+    }
+
+    rng.fill(buf);
+}
+
+/// Get a random `u64`
+pub fn next_u64() -> u64 {
+    let mut bytes = [0u8; 8];
+    fill_bytes(&mut bytes);
+    u64::from_le_bytes(bytes)
+}
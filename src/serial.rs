@@ -1,25 +1,273 @@
+//! Serial ports
+//!
+//! COM1 mirrors the screen and types into the console; COM2 is a second,
+//! independent terminal. Each port interrupts on received bytes, which its
+//! handler buffers without allocating until `feed_terminal` hands them to
+//! a TTY.
+
+use crate::interrupts::PICS;
+use crate::power::{self, PowerAction};
+use crate::sync::WaitQueue;
+use crate::tty::Tty;
+use core::sync::atomic::{AtomicU64, AtomicU8, AtomicUsize, Ordering};
 use uart_16550::SerialPort;
 use spin::Mutex;
 use lazy_static::lazy_static;
+use x86_64::instructions::interrupts;
+use x86_64::instructions::port::Port;
 
 lazy_static! {
     pub static ref SERIAL1: Mutex<SerialPort> = {
-        let mut serial_port = unsafe { SerialPort::new(0x3F8) };
+        let mut serial_port = unsafe { SerialPort::new(ComPort::Com1.base()) };
+        serial_port.init();
+        Mutex::new(serial_port)
+    };
+    pub static ref SERIAL2: Mutex<SerialPort> = {
+        let mut serial_port = unsafe { SerialPort::new(ComPort::Com2.base()) };
         serial_port.init();
         Mutex::new(serial_port)
     };
 }
 
+/// A serial port
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComPort {
+    Com1,
+    Com2,
+}
+
+impl ComPort {
+    pub const fn base(self) -> u16 {
+        match self {
+            ComPort::Com1 => 0x3F8,
+            ComPort::Com2 => 0x2F8,
+        }
+    }
+
+    pub const fn irq(self) -> u8 {
+        match self {
+            ComPort::Com1 => 4,
+            ComPort::Com2 => 3,
+        }
+    }
+
+    fn receiver(self) -> &'static Receiver {
+        &RECEIVERS[self as usize]
+    }
+
+    /// Whether a UART answers at this port
+    ///
+    /// Probed through the scratch register, which a missing port reads
+    /// back as 0xFF.
+    pub fn is_present(self) -> bool {
+        let mut scratch = Port::<u8>::new(self.base() + 7);
+        unsafe {
+            scratch.write(0x5A);
+            scratch.read() == 0x5A
+        }
+    }
+}
+
+// UART registers, as offsets from the base
+const INTERRUPT_ENABLE: u16 = 1;
+const MODEM_CONTROL: u16 = 4;
+const LINE_STATUS: u16 = 5;
+
+/// `INTERRUPT_ENABLE`: interrupt when a byte arrives
+const RECEIVED_DATA_AVAILABLE: u8 = 1 << 0;
+/// `MODEM_CONTROL`: DTR, RTS and OUT2, which gates the IRQ line
+const DTR_RTS_OUT2: u8 = 0x0B;
+/// `LINE_STATUS`: a received byte is waiting
+const DATA_READY: u8 = 1 << 0;
+/// `LINE_STATUS`: every written byte has been sent
+const TRANSMITTER_EMPTY: u8 = 1 << 6;
+
+/// Received bytes kept per port
+pub const RECEIVE_BUFFER_SIZE: usize = 256;
+
+/// Bytes received on one port, filled by its interrupt handler and
+/// drained by a single `feed_terminal`
+struct Receiver {
+    buf: [AtomicU8; RECEIVE_BUFFER_SIZE],
+    /// Bytes ever written; only the interrupt handler advances it
+    head: AtomicUsize,
+    /// Bytes ever read; only the reader advances it
+    tail: AtomicUsize,
+    ready: WaitQueue,
+    /// Bytes lost to a full buffer
+    dropped: AtomicU64,
+}
+
+impl Receiver {
+    const fn new() -> Self {
+        Receiver {
+            buf: [const { AtomicU8::new(0) }; RECEIVE_BUFFER_SIZE],
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            ready: WaitQueue::new(),
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    fn push(&self, byte: u8) {
+        let head = self.head.load(Ordering::Relaxed);
+        if head.wrapping_sub(self.tail.load(Ordering::Acquire)) == RECEIVE_BUFFER_SIZE {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        self.buf[head % RECEIVE_BUFFER_SIZE].store(byte, Ordering::Relaxed);
+        self.head.store(head.wrapping_add(1), Ordering::Release);
+    }
+
+    /// Move buffered bytes into `out`; returns how many
+    fn pop_into(&self, out: &mut [u8]) -> usize {
+        let tail = self.tail.load(Ordering::Relaxed);
+        let available = self.head.load(Ordering::Acquire).wrapping_sub(tail);
+        let count = available.min(out.len());
+        for (index, byte) in out[..count].iter_mut().enumerate() {
+            *byte = self.buf[tail.wrapping_add(index) % RECEIVE_BUFFER_SIZE].load(Ordering::Relaxed);
+        }
+        self.tail.store(tail.wrapping_add(count), Ordering::Release);
+        count
+    }
+}
+
+static RECEIVERS: [Receiver; 2] = [Receiver::new(), Receiver::new()];
+
+/// Enable receive interrupts on every port that is present, and let them
+/// finish sending on shutdown
+pub fn init() {
+    power::register_shutdown_hook("serial", drain);
+    interrupts::without_interrupts(|| {
+        let mut lines = 0;
+        for port in [ComPort::Com1, ComPort::Com2] {
+            if !port.is_present() {
+                continue;
+            }
+            // Initialise the port before its interrupts are unmasked
+            let _ = match port {
+                ComPort::Com1 => SERIAL1.lock(),
+                ComPort::Com2 => SERIAL2.lock(),
+            };
+            unsafe {
+                Port::<u8>::new(port.base() + INTERRUPT_ENABLE).write(RECEIVED_DATA_AVAILABLE);
+                Port::<u8>::new(port.base() + MODEM_CONTROL).write(DTR_RTS_OUT2);
+            }
+            lines |= 1 << port.irq();
+        }
+
+        let mut pics = PICS.lock();
+        unsafe {
+            let [primary, secondary] = pics.read_masks();
+            pics.write_masks(primary & !lines, secondary);
+        }
+    });
+}
+
+/// Wait until the bytes written to each port have gone out
+///
+/// Bounded in case a port never reports an empty transmitter.
+fn drain(_action: PowerAction) {
+    for port in [ComPort::Com1, ComPort::Com2] {
+        if !port.is_present() {
+            continue;
+        }
+        let mut line_status = Port::<u8>::new(port.base() + LINE_STATUS);
+        for _ in 0..1_000_000 {
+            if unsafe { line_status.read() } & TRANSMITTER_EMPTY != 0 {
+                break;
+            }
+            core::hint::spin_loop();
+        }
+    }
+}
+
+/// Called by the IRQ handler of `port`
+///
+/// Buffers every byte the UART holds; never blocks or allocates.
+pub(crate) fn handle_interrupt(port: ComPort) {
+    let receiver = port.receiver();
+    let mut line_status = Port::<u8>::new(port.base() + LINE_STATUS);
+    let mut data = Port::<u8>::new(port.base());
+    // Bounded in case the port vanishes and reads back as all ones
+    for _ in 0..RECEIVE_BUFFER_SIZE {
+        if unsafe { line_status.read() } & DATA_READY == 0 {
+            break;
+        }
+        receiver.push(unsafe { data.read() });
+    }
+    receiver.ready.notify_all();
+}
+
+/// Bytes received on `port` but dropped because nobody read them in time
+pub fn dropped(port: ComPort) -> u64 {
+    port.receiver().dropped.load(Ordering::Relaxed)
+}
+
+/// Type what arrives on `port` into `tty`, forever
+///
+/// Only one task may read each port.
+pub async fn feed_terminal(port: ComPort, tty: &'static Tty) {
+    let receiver = port.receiver();
+    let mut buf = [0; 64];
+    loop {
+        // Registered before checking so no interrupt is missed
+        let wait = receiver.ready.wait_async();
+        let count = receiver.pop_into(&mut buf);
+        if count > 0 {
+            drop(wait);
+            tty.receive(&buf[..count]);
+        } else {
+            wait.await;
+        }
+    }
+}
+
+/// Write `bytes` to COM2
+pub fn write_com2(bytes: &[u8]) {
+    interrupts::without_interrupts(|| {
+        let mut port = SERIAL2.lock();
+        for &byte in bytes {
+            port.send_raw(byte);
+        }
+    });
+}
+
+/// Writes bytes as they are
+///
+/// `SerialPort`'s own `write_str` turns a backspace into backspace, space,
+/// backspace, which would erase what the line editor only steps over.
+struct Raw<'a>(&'a mut SerialPort);
+
+impl core::fmt::Write for Raw<'_> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        for byte in s.bytes() {
+            self.0.send_raw(byte);
+        }
+        Ok(())
+    }
+}
+
 #[doc(hidden)]
 pub fn _print(args: core::fmt::Arguments) {
     use core::fmt::Write;
-    use x86_64::instructions::interrupts;
 
     interrupts::without_interrupts(|| {
-        SERIAL1.lock().write_fmt(args).expect("Printing to serial failed");
+        Raw(&mut SERIAL1.lock()).write_fmt(args).expect("Printing to serial failed");
     });
 }
 
+/// Print without taking `SERIAL1`
+///
+/// For reports about a stuck lock, which may be `SERIAL1` itself. Output
+/// can interleave with a print in progress.
+pub fn print_unlocked(args: core::fmt::Arguments) {
+    use core::fmt::Write;
+
+    let mut port = unsafe { SerialPort::new(ComPort::Com1.base()) };
+    let _ = port.write_fmt(args);
+}
+
 /// Prints to the host through the serial interface.
 #[macro_export]
 macro_rules! serial_print {
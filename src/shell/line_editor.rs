@@ -0,0 +1,375 @@
+//! Line editing for the interactive shell
+//!
+//! The editor keeps the line being typed and redraws it with the terminal
+//! controls both the VGA writer and a serial terminal understand: `\x08`
+//! moves the cursor one column left and a space erases. Lines are kept
+//! shorter than a screen row so they never wrap.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+
+/// Most lines kept in the history
+pub const HISTORY_SIZE: usize = 32;
+
+/// An editing key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    Char(char),
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Home,
+    End,
+    Up,
+    Down,
+    Tab,
+    Enter,
+    /// Ctrl+C: drop the line
+    Interrupt,
+}
+
+/// Turns the bytes a terminal sends back into keys
+///
+/// Characters arrive UTF-8 encoded and cursor keys as VT100 escape
+/// sequences; sequences it doesn't know are dropped.
+pub struct KeyDecoder {
+    pending: [u8; 8],
+    len: usize,
+}
+
+impl KeyDecoder {
+    pub const fn new() -> Self {
+        KeyDecoder { pending: [0; 8], len: 0 }
+    }
+
+    /// Take the next byte; returns a key once one is complete
+    pub fn feed(&mut self, byte: u8) -> Option<Key> {
+        if self.len == 0 {
+            return match byte {
+                b'\r' | b'\n' => Some(Key::Enter),
+                0x7F | 0x08 => Some(Key::Backspace),
+                b'\t' => Some(Key::Tab),
+                0x03 => Some(Key::Interrupt),
+                // Escape or the lead byte of a UTF-8 sequence
+                0x1B | 0xC0.. => {
+                    self.pending[0] = byte;
+                    self.len = 1;
+                    None
+                }
+                0x20..0x7F => Some(Key::Char(byte as char)),
+                _ => None,
+            };
+        }
+
+        if self.pending[0] == 0x1B {
+            return self.escape(byte);
+        }
+        if byte & 0xC0 != 0x80 {
+            // Broken sequence: drop it and start over with this byte
+            self.len = 0;
+            return self.feed(byte);
+        }
+        self.pending[self.len] = byte;
+        self.len += 1;
+        let expected = match self.pending[0] {
+            0xF0.. => 4,
+            0xE0.. => 3,
+            _ => 2,
+        };
+        if self.len < expected {
+            return None;
+        }
+        let key = core::str::from_utf8(&self.pending[..self.len])
+            .ok()
+            .and_then(|text| text.chars().next())
+            .map(Key::Char);
+        self.len = 0;
+        key
+    }
+
+    /// Continue an escape sequence: `ESC [` or `ESC O`, parameters, then a
+    /// final byte
+    fn escape(&mut self, byte: u8) -> Option<Key> {
+        if self.len == 1 && byte != b'[' && byte != b'O' || self.len == self.pending.len() {
+            self.len = 0;
+            return None;
+        }
+        self.pending[self.len] = byte;
+        self.len += 1;
+        if self.len == 2 || !(0x40..=0x7E).contains(&byte) {
+            return None;
+        }
+
+        let key = match &self.pending[2..self.len] {
+            b"A" => Some(Key::Up),
+            b"B" => Some(Key::Down),
+            b"C" => Some(Key::Right),
+            b"D" => Some(Key::Left),
+            b"H" | b"1~" | b"7~" => Some(Key::Home),
+            b"F" | b"4~" | b"8~" => Some(Key::End),
+            b"3~" => Some(Key::Delete),
+            _ => None,
+        };
+        self.len = 0;
+        key
+    }
+}
+
+impl Default for KeyDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Line editor with history and tab completion
+pub struct LineEditor {
+    prompt: String,
+    line: Vec<char>,
+    cursor: usize,
+    max_len: usize,
+    history: Vec<String>,
+    /// History entry shown, `None` while editing a new line
+    browsing: Option<usize>,
+    /// The new line, kept while browsing the history
+    draft: String,
+}
+
+impl LineEditor {
+    /// Editor for lines of at most `max_len` characters
+    pub fn new(max_len: usize) -> Self {
+        LineEditor {
+            prompt: String::new(),
+            line: Vec::new(),
+            cursor: 0,
+            max_len,
+            history: Vec::new(),
+            browsing: None,
+            draft: String::new(),
+        }
+    }
+
+    /// Print `prompt` and start a new line
+    pub fn start(&mut self, prompt: &str, out: &mut impl Write) -> fmt::Result {
+        self.prompt = String::from(prompt);
+        self.line.clear();
+        self.cursor = 0;
+        self.browsing = None;
+        out.write_str(prompt)
+    }
+
+    /// The line typed so far
+    pub fn line(&self) -> String {
+        self.line.iter().collect()
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// Earlier lines, oldest first
+    pub fn history(&self) -> &[String] {
+        &self.history
+    }
+
+    /// Apply `key`, echoing to `out`; returns the line once Enter is pressed
+    ///
+    /// `complete` gets the line up to the cursor and returns candidates for
+    /// its last word.
+    pub fn handle(
+        &mut self,
+        key: Key,
+        complete: impl FnOnce(&str) -> Vec<String>,
+        out: &mut impl Write,
+    ) -> Result<Option<String>, fmt::Error> {
+        match key {
+            Key::Char(character) if !character.is_control() => self.insert(&[character], out)?,
+            Key::Char(_) => {}
+            Key::Backspace if self.cursor > 0 => {
+                out.write_char('\x08')?;
+                self.cursor -= 1;
+                self.remove_at_cursor(out)?;
+            }
+            Key::Delete if self.cursor < self.line.len() => self.remove_at_cursor(out)?,
+            Key::Backspace | Key::Delete => {}
+            Key::Left if self.cursor > 0 => {
+                out.write_char('\x08')?;
+                self.cursor -= 1;
+            }
+            Key::Right if self.cursor < self.line.len() => {
+                out.write_char(self.line[self.cursor])?;
+                self.cursor += 1;
+            }
+            Key::Left | Key::Right => {}
+            Key::Home => self.move_to(0, out)?,
+            Key::End => self.move_to(self.line.len(), out)?,
+            Key::Up => self.browse_back(out)?,
+            Key::Down => self.browse_forward(out)?,
+            Key::Tab => {
+                let before: String = self.line[..self.cursor].iter().collect();
+                self.complete(&before, complete(&before), out)?;
+            }
+            Key::Enter => {
+                self.move_to(self.line.len(), out)?;
+                out.write_char('\n')?;
+                let line = self.line();
+                self.remember(&line);
+                self.line.clear();
+                self.cursor = 0;
+                self.browsing = None;
+                return Ok(Some(line));
+            }
+            Key::Interrupt => {
+                self.move_to(self.line.len(), out)?;
+                out.write_str("^C\n")?;
+                self.line.clear();
+                self.cursor = 0;
+                self.browsing = None;
+                out.write_str(&self.prompt)?;
+            }
+        }
+        Ok(None)
+    }
+
+    /// Insert `text` at the cursor, as far as it fits
+    fn insert(&mut self, text: &[char], out: &mut impl Write) -> fmt::Result {
+        let room = self.max_len.saturating_sub(self.line.len());
+        let text = &text[..text.len().min(room)];
+        if text.is_empty() {
+            return Ok(());
+        }
+        let from = self.cursor;
+        let old_len = self.line.len();
+        self.line.splice(from..from, text.iter().copied());
+        self.cursor += text.len();
+        self.redraw(from, old_len, out)
+    }
+
+    /// Remove the character under the cursor
+    fn remove_at_cursor(&mut self, out: &mut impl Write) -> fmt::Result {
+        let old_len = self.line.len();
+        self.line.remove(self.cursor);
+        self.redraw(self.cursor, old_len, out)
+    }
+
+    /// Redraw the line from column `from`, where the screen cursor is, over
+    /// a line that was `old_len` long; leaves the screen cursor at `cursor`
+    fn redraw(&self, from: usize, old_len: usize, out: &mut impl Write) -> fmt::Result {
+        for &character in &self.line[from..] {
+            out.write_char(character)?;
+        }
+        let erased = old_len.saturating_sub(self.line.len());
+        for _ in 0..erased {
+            out.write_char(' ')?;
+        }
+        for _ in self.cursor..self.line.len() + erased {
+            out.write_char('\x08')?;
+        }
+        Ok(())
+    }
+
+    fn move_to(&mut self, position: usize, out: &mut impl Write) -> fmt::Result {
+        for _ in position..self.cursor {
+            out.write_char('\x08')?;
+        }
+        for &character in self.line.get(self.cursor..position).unwrap_or(&[]) {
+            out.write_char(character)?;
+        }
+        self.cursor = position;
+        Ok(())
+    }
+
+    /// Show `text` instead of the current line
+    fn replace(&mut self, text: &str, out: &mut impl Write) -> fmt::Result {
+        self.move_to(0, out)?;
+        let old_len = self.line.len();
+        self.line = text.chars().take(self.max_len).collect();
+        self.cursor = self.line.len();
+        self.redraw(0, old_len, out)
+    }
+
+    fn browse_back(&mut self, out: &mut impl Write) -> fmt::Result {
+        let index = match self.browsing {
+            Some(0) => return Ok(()),
+            Some(index) => index - 1,
+            None if self.history.is_empty() => return Ok(()),
+            None => {
+                self.draft = self.line();
+                self.history.len() - 1
+            }
+        };
+        self.browsing = Some(index);
+        let entry = self.history[index].clone();
+        self.replace(&entry, out)
+    }
+
+    fn browse_forward(&mut self, out: &mut impl Write) -> fmt::Result {
+        let Some(index) = self.browsing else {
+            return Ok(());
+        };
+        if index + 1 < self.history.len() {
+            self.browsing = Some(index + 1);
+            let entry = self.history[index + 1].clone();
+            self.replace(&entry, out)
+        } else {
+            self.browsing = None;
+            let draft = core::mem::take(&mut self.draft);
+            self.replace(&draft, out)
+        }
+    }
+
+    fn remember(&mut self, line: &str) {
+        if line.trim().is_empty() || self.history.last().is_some_and(|last| last == line) {
+            return;
+        }
+        if self.history.len() == HISTORY_SIZE {
+            self.history.remove(0);
+        }
+        self.history.push(String::from(line));
+    }
+
+    /// Complete the last word of `before` from `candidates`
+    ///
+    /// A single candidate is filled in; several are filled in as far as
+    /// they agree and listed if that adds nothing.
+    fn complete(&mut self, before: &str, candidates: Vec<String>, out: &mut impl Write) -> fmt::Result {
+        let word = before.rsplit(' ').next().unwrap_or("");
+        let Some(first) = candidates.first() else {
+            return Ok(());
+        };
+
+        let common = candidates.iter().fold(first.as_str(), |common, candidate| {
+            let shared = common
+                .char_indices()
+                .zip(candidate.chars())
+                .find(|((_, a), b)| a != b)
+                .map_or(common.len().min(candidate.len()), |((index, _), _)| index);
+            &common[..shared]
+        });
+        let mut addition: Vec<char> = common.strip_prefix(word).unwrap_or("").chars().collect();
+        if candidates.len() == 1 && !common.ends_with('/') {
+            addition.push(' ');
+        }
+        if !addition.is_empty() {
+            return self.insert(&addition, out);
+        }
+        if candidates.len() == 1 {
+            return Ok(());
+        }
+
+        // Nothing to add: list the choices and draw the line again below
+        self.move_to(self.line.len(), out)?;
+        out.write_char('\n')?;
+        for candidate in &candidates {
+            let shown = candidate.rsplit('/').find(|part| !part.is_empty()).unwrap_or(candidate);
+            write!(out, "{}{}  ", shown, if candidate.ends_with('/') { "/" } else { "" })?;
+        }
+        out.write_char('\n')?;
+        out.write_str(&self.prompt)?;
+        let cursor = self.cursor;
+        self.cursor = 0;
+        self.move_to(self.line.len(), out)?;
+        self.move_to(cursor, out)
+    }
+}
@@ -1,6 +1,27 @@
-use crate::{println, vfs::ops};
+pub mod line_editor;
+
+use crate::{acpi, ipc, keyboard, power, println, time, vfs::ops};
+use crate::process;
+use crate::sync::{spinlock, SpinLock, WaitQueue};
+use crate::task::{executor, thread, thread_scheduler};
+use crate::task::thread_scheduler::{CpuTimes, ThreadInfo};
+use crate::tty::{self, SetWhen, Tty, TtyWriter};
+use crate::vfs::FileType;
+use alloc::format;
 use alloc::string::String;
+use alloc::sync::Arc;
 use alloc::vec::Vec;
+use core::time::Duration;
+use line_editor::{KeyDecoder, LineEditor};
+use x86_64::instructions::interrupts;
+
+pub const PROMPT: &str = "lithos$ ";
+
+/// Commands `execute` knows, for tab completion
+pub const COMMANDS: &[&str] = &[
+    "help", "ls", "pwd", "cd", "mkdir", "touch", "echo", "clear", "acpi", "threads", "uptime",
+    "tasks", "cancel", "ipcbench", "ps", "top", "lockstat", "keymap", "shutdown", "reboot",
+];
 
 /// Simple shell for Lithos OS
 pub struct Shell {
@@ -14,6 +35,50 @@ impl Shell {
         }
     }
     
+    /// Candidates for the last word of `line`: commands for the first
+    /// word, VFS paths after it
+    ///
+    /// Candidates replace the whole word; directories end in `/`.
+    pub fn complete(&self, line: &str) -> Vec<String> {
+        let word = line.rsplit(' ').next().unwrap_or("");
+        if !line.trim_start().contains(' ') {
+            return COMMANDS
+                .iter()
+                .filter(|command| command.starts_with(word))
+                .map(|&command| String::from(command))
+                .collect();
+        }
+
+        let (directory, prefix) = match word.rfind('/') {
+            Some(slash) => word.split_at(slash + 1),
+            None => ("", word),
+        };
+        let path = self.absolute(directory);
+        let Ok(entries) = ops::vfs_readdir(&path) else {
+            return Vec::new();
+        };
+        entries
+            .into_iter()
+            .filter(|name| name.starts_with(prefix))
+            .map(|name| {
+                let is_directory = ops::resolve_path(&self.absolute(&format!("{}{}", directory, name)))
+                    .is_ok_and(|node| node.lock().file_type() == FileType::Directory);
+                format!("{}{}{}", directory, name, if is_directory { "/" } else { "" })
+            })
+            .collect()
+    }
+
+    /// `path` relative to the working directory
+    fn absolute(&self, path: &str) -> String {
+        if path.starts_with('/') {
+            String::from(path)
+        } else if self.cwd.ends_with('/') {
+            format!("{}{}", self.cwd, path)
+        } else {
+            format!("{}/{}", self.cwd, path)
+        }
+    }
+
     /// Execute a command
     pub fn execute(&mut self, line: &str) {
         let parts: Vec<&str> = line.trim().split_whitespace().collect();
@@ -31,6 +96,18 @@ impl Shell {
             "touch" => self.cmd_touch(parts.get(1).copied()),
             "echo" => self.cmd_echo(&parts[1..]),
             "clear" => self.cmd_clear(),
+            "acpi" => self.cmd_acpi(),
+            "threads" => self.cmd_threads(),
+            "uptime" => self.cmd_uptime(),
+            "tasks" => self.cmd_tasks(),
+            "cancel" => self.cmd_cancel(parts.get(1).copied()),
+            "ipcbench" => self.cmd_ipcbench(parts.get(1).copied()),
+            "ps" => self.cmd_ps(),
+            "top" => self.cmd_top(parts.get(1).copied()),
+            "lockstat" => self.cmd_lockstat(parts.get(1).copied()),
+            "keymap" => self.cmd_keymap(parts.get(1).copied()),
+            "shutdown" => power::shutdown(),
+            "reboot" => power::reboot(),
             "" => {},
             cmd => println!("Unknown command: {}. Type 'help' for available commands.", cmd),
         }
@@ -46,6 +123,20 @@ impl Shell {
         println!("  touch <path>  - Create empty file");
         println!("  echo <text>   - Print text");
         println!("  clear         - Clear screen");
+        println!("  acpi          - Show ACPI tables and platform info");
+        println!("  threads       - List kernel threads");
+        println!("  uptime        - Show time since boot");
+        println!("  tasks         - List async tasks");
+        println!("  cancel <id>   - Cancel an async task");
+        println!("  ipcbench [n]  - Measure IPC call/reply round trips");
+        println!("  ps            - List threads and tasks with CPU time");
+        println!("  top [n]       - Show CPU share per thread, refreshed n times");
+        println!("  lockstat [n]  - Show the n most contended spinlocks");
+        println!("  keymap [name] - List keyboard layouts or switch to one");
+        println!("  shutdown      - Power off the machine");
+        println!("  reboot        - Restart the machine");
+        println!();
+        println!("Left/Right/Home/End move, Up/Down recall history, Tab completes.");
     }
     
     fn cmd_ls(&self, path: Option<&str>) {
@@ -116,4 +207,437 @@ impl Shell {
             println!();
         }
     }
+    
+    fn cmd_acpi(&self) {
+        let info = match acpi::info() {
+            Some(info) => info,
+            None => {
+                println!("acpi: no ACPI tables found");
+                return;
+            }
+        };
+
+        println!(
+            "RSDP at {:#x}: revision {}, OEM '{}', root table {}",
+            info.rsdp.address,
+            info.rsdp.revision,
+            info.rsdp.oem_id,
+            if info.uses_xsdt { "XSDT" } else { "RSDT" }
+        );
+
+        println!("Tables:");
+        for table in &info.tables {
+            println!(
+                "  {} at {:#010x} len {:5} rev {} {:6} {:8}{}",
+                table.signature,
+                table.address,
+                table.length,
+                table.revision,
+                table.oem_id,
+                table.oem_table_id,
+                if table.valid { "" } else { " (bad checksum)" }
+            );
+        }
+
+        if let Some(madt) = &info.madt {
+            println!("MADT: local APIC at {:#x}, legacy PICs: {}", madt.local_apic_address, madt.has_8259());
+            for cpu in &madt.processors {
+                println!(
+                    "  CPU uid {} APIC id {}{}{}",
+                    cpu.processor_uid,
+                    cpu.apic_id,
+                    if cpu.enabled { "" } else { " (disabled)" },
+                    if cpu.x2apic { " x2APIC" } else { "" }
+                );
+            }
+            for io_apic in &madt.io_apics {
+                println!(
+                    "  I/O APIC id {} at {:#x}, GSI base {}",
+                    io_apic.id, io_apic.address, io_apic.gsi_base
+                );
+            }
+            for o in &madt.overrides {
+                println!(
+                    "  IRQ {} -> GSI {} (polarity {}, trigger {})",
+                    o.source,
+                    o.gsi,
+                    o.polarity(),
+                    o.trigger_mode()
+                );
+            }
+        }
+
+        if let Some(fadt) = &info.fadt {
+            println!(
+                "FADT: SCI IRQ {}, PM1a control {:#x}, PM timer {:#x}, DSDT at {:#x}",
+                fadt.sci_interrupt, fadt.pm1a_control_block, fadt.pm_timer_block, fadt.dsdt_address
+            );
+            if let Some(reset) = fadt.reset_register.filter(|_| fadt.supports_reset_register()) {
+                println!(
+                    "  Reset register: {} {:#x} value {:#x}",
+                    reset.space_name(),
+                    reset.address,
+                    fadt.reset_value
+                );
+            }
+        }
+
+        if let Some(hpet) = &info.hpet {
+            println!(
+                "HPET: {} at {:#x}, {} comparators, {}-bit counter, min tick {}",
+                hpet.hpet_number,
+                hpet.base_address.address,
+                hpet.comparator_count(),
+                if hpet.counter_is_64bit() { 64 } else { 32 },
+                hpet.minimum_tick
+            );
+        }
+
+        if let Some(s5) = info.s5 {
+            println!("S5 sleep type: PM1a {} PM1b {}", s5.pm1a, s5.pm1b);
+        }
+
+        if let Some(mcfg) = &info.mcfg {
+            for entry in &mcfg.entries {
+                println!(
+                    "MCFG: segment {} buses {}-{} at {:#x}",
+                    entry.segment_group, entry.start_bus, entry.end_bus, entry.base_address
+                );
+            }
+        }
+    }
+    
+    fn cmd_threads(&self) {
+        println!("Scheduler: {}, {} context switches", thread_scheduler::policy_name(), thread_scheduler::context_switches());
+        println!("  ID  NAME          STATE     PRI  STACK   TICKS  SWITCHES  EXIT");
+        for thread in thread::list() {
+            let exit = match thread.exit_value {
+                Some(value) => format!("{}", value),
+                None => String::from("-"),
+            };
+            println!(
+                "{} {:3}  {:12}  {:8}  {:>3}  {:>4}K  {:>6}  {:>8}  {}",
+                if thread.is_current { '*' } else { ' ' },
+                thread.id.as_u64(),
+                thread.name.as_deref().unwrap_or("-"),
+                format!("{:?}", thread.state),
+                priority(&thread),
+                thread.stack_size / 1024,
+                thread.cpu_ticks,
+                thread.switches,
+                exit
+            );
+        }
+    }
+    
+    fn cmd_uptime(&self) {
+        let uptime = time::uptime();
+        println!(
+            "up {}.{:02}s ({} ticks at {} Hz)",
+            uptime.as_secs(),
+            uptime.subsec_millis() / 10,
+            time::ticks(),
+            time::TICK_HZ
+        );
+    }
+    
+    fn cmd_tasks(&self) {
+        let tasks = executor::list();
+        println!("{} async tasks", tasks.len());
+        println!("  ID  NAME          STATE     POLLS  POLL TIME");
+        for task in tasks {
+            println!(
+                "  {:3}  {:12}  {:8}  {:>5}  {}us",
+                task.id.as_u64(),
+                task.name.as_deref().unwrap_or("-"),
+                format!("{:?}", task.state),
+                task.polls,
+                task.poll_time.as_micros()
+            );
+        }
+    }
+    
+    fn cmd_cancel(&self, id: Option<&str>) {
+        let Some(id) = id.and_then(|id| id.parse::<u64>().ok()) else {
+            println!("cancel: usage: cancel <task id>");
+            return;
+        };
+        match executor::list().into_iter().find(|task| task.id.as_u64() == id) {
+            Some(task) if executor::cancel(task.id) => println!("Cancelled task {}", id),
+            _ => println!("cancel: no task {}", id),
+        }
+    }
+
+    fn cmd_ipcbench(&self, rounds: Option<&str>) {
+        let rounds = match rounds.map(str::parse::<u32>) {
+            None => 10_000,
+            Some(Ok(rounds)) if rounds > 0 => rounds,
+            Some(_) => {
+                println!("ipcbench: usage: ipcbench [round trips]");
+                return;
+            }
+        };
+        let result = ipc::benchmark(rounds);
+        let round_trip = result.round_trip();
+        println!(
+            "{} round trips in {} ms: {} ns ({} cycles) per call/reply",
+            result.round_trips,
+            result.total.as_millis(),
+            round_trip.as_nanos(),
+            result.cycles_per_round_trip()
+        );
+    }
+
+    fn cmd_ps(&self) {
+        let times = thread_scheduler::cpu_times();
+        println!(
+            "CPU: {} ms busy, {} ms idle ({} busy)",
+            times.busy.as_millis(),
+            times.idle.as_millis(),
+            Percent::of(times.busy, times.total())
+        );
+
+        let mut threads: Vec<_> = thread::list()
+            .into_iter()
+            .map(|thread| (process::thread_pid(thread.id), thread))
+            .collect();
+        threads.sort_by_key(|(pid, thread)| (*pid, thread.id));
+        println!("  PID  TID  NAME          STATE     PRI      TIME  WAKEUPS  SWITCHES");
+        for (pid, thread) in threads {
+            println!(
+                "{:5}  {:3}  {:12}  {:8}  {:>3}  {:>6}ms  {:>7}  {:>8}",
+                pid.as_u32(),
+                thread.id.as_u64(),
+                thread.name.as_deref().unwrap_or("-"),
+                format!("{:?}", thread.state),
+                priority(&thread),
+                thread.cpu_time.as_millis(),
+                thread.wakeups,
+                thread.switches
+            );
+        }
+
+        let tasks = executor::list();
+        if tasks.is_empty() {
+            return;
+        }
+        println!();
+        println!(" TASK  NAME          STATE     POLLS      TIME  WAKEUPS");
+        for task in tasks {
+            println!(
+                "{:5}  {:12}  {:8}  {:>5}  {:>6}us  {:>7}",
+                task.id.as_u64(),
+                task.name.as_deref().unwrap_or("-"),
+                format!("{:?}", task.state),
+                task.polls,
+                task.poll_time.as_micros(),
+                task.wakeups
+            );
+        }
+    }
+
+    fn cmd_top(&self, refreshes: Option<&str>) {
+        let refreshes = match refreshes.map(str::parse::<u32>) {
+            None => 5,
+            Some(Ok(refreshes)) if refreshes > 0 => refreshes,
+            Some(_) => {
+                println!("top: usage: top [refreshes]");
+                return;
+            }
+        };
+
+        let mut previous = (thread_scheduler::cpu_times(), thread::list());
+        for _ in 0..refreshes {
+            thread::sleep(Duration::from_secs(1));
+            let current = (thread_scheduler::cpu_times(), thread::list());
+            self.cmd_clear();
+            print_top(&previous, &current);
+            previous = current;
+        }
+    }
+
+    fn cmd_lockstat(&self, count: Option<&str>) {
+        let count = match count.map(str::parse::<usize>) {
+            None => 10,
+            Some(Ok(count)) if count > 0 => count,
+            Some(_) => {
+                println!("lockstat: usage: lockstat [count]");
+                return;
+            }
+        };
+        if !spinlock::ENABLED {
+            println!("lockstat: spinlocks are not instrumented; build with --features lock-debug");
+            return;
+        }
+
+        let mut stats = spinlock::stats();
+        stats.sort_by(|a, b| {
+            b.contended.cmp(&a.contended).then(b.acquisitions.cmp(&a.acquisitions))
+        });
+        println!("  ACQUIRED  CONTENDED  AVG SPIN  MAX SPIN  MAX HOLD  STUCK  CREATED AT");
+        for stat in stats.iter().take(count) {
+            let average_spin = stat.spin_cycles.checked_div(stat.contended).unwrap_or(0);
+            println!(
+                "  {:>8}  {:>9}  {:>6}us  {:>6}us  {:>6}us  {:>5}  {}:{}",
+                stat.acquisitions,
+                stat.contended,
+                time::cycles_to_duration(average_spin).as_micros(),
+                time::cycles_to_duration(stat.max_spin_cycles).as_micros(),
+                time::cycles_to_duration(stat.max_hold_cycles).as_micros(),
+                stat.reports,
+                stat.site.file(),
+                stat.site.line()
+            );
+        }
+    }
+
+    fn cmd_keymap(&self, name: Option<&str>) {
+        let Some(name) = name else {
+            let current = keyboard::layout();
+            for layout in keyboard::Layout::ALL {
+                let marker = if layout == current { '*' } else { ' ' };
+                println!("{} {:<8} {}", marker, layout.name(), layout.description());
+            }
+            return;
+        };
+        match keyboard::Layout::from_name(name) {
+            Some(layout) => {
+                keyboard::set_layout(layout);
+                println!("Keyboard layout: {}", layout.description());
+            }
+            None => println!("keymap: unknown layout {}", name),
+        }
+    }
+}
+
+/// Run a shell on `tty`, forever
+///
+/// The terminal is read in raw mode and its bytes decoded into keys, which
+/// a `LineEditor` edits into lines. Each finished line runs as a command
+/// on a kernel thread of its own, printing to `tty` and with the
+/// terminal's settings restored so Ctrl+C reaches the foreground process.
+pub async fn run(tty: &'static Tty) {
+    let cooked = tty.termios();
+    let mut shell = Shell::new();
+    let columns = usize::from(tty.winsize().columns);
+    let mut editor = LineEditor::new(columns - PROMPT.len() - 1);
+    let mut decoder = KeyDecoder::new();
+    let mut out = TtyWriter(tty);
+    let mut buf = [0; 16];
+
+    tty.set_termios(cooked.raw(), SetWhen::Now);
+    let _ = editor.start(PROMPT, &mut out);
+    loop {
+        let count = tty.read_async(&mut buf).await;
+        for &byte in &buf[..count] {
+            let Some(key) = decoder.feed(byte) else {
+                continue;
+            };
+            if let Ok(Some(line)) = editor.handle(key, |line| shell.complete(line), &mut out) {
+                tty.set_termios(cooked, SetWhen::Now);
+                shell = execute_on_thread(shell, line, tty).await;
+                tty.set_termios(cooked.raw(), SetWhen::Now);
+                let _ = editor.start(PROMPT, &mut out);
+            }
+        }
+    }
+}
+
+/// A command running on its own thread, and the shell it hands back
+struct Command {
+    /// Locked with interrupts disabled
+    shell: SpinLock<Option<Shell>>,
+    done: WaitQueue,
+}
+
+/// Run `line` on a new kernel thread and wait for it to finish
+///
+/// Commands like `top` and `ipcbench` block for a long time; run on the
+/// shell's executor thread they would stall every task queued on it.
+async fn execute_on_thread(shell: Shell, line: String, tty: &'static Tty) -> Shell {
+    let command = Arc::new(Command { shell: SpinLock::new(None), done: WaitQueue::new() });
+    let finished = command.clone();
+    // Detached: reaped once it exits
+    drop(thread::Builder::new().name("shell-command").spawn(move || {
+        let mut shell = shell;
+        tty::with_output(tty, || shell.execute(&line));
+        interrupts::without_interrupts(|| *finished.shell.lock() = Some(shell));
+        finished.done.notify_all();
+    }));
+
+    loop {
+        // Registered before checking so the notification isn't missed
+        let wait = command.done.wait_async();
+        if let Some(shell) = interrupts::without_interrupts(|| command.shell.lock().take()) {
+            return shell;
+        }
+        wait.await;
+    }
+}
+
+/// One `top` screen: CPU use between two samples
+fn print_top(previous: &(CpuTimes, Vec<ThreadInfo>), current: &(CpuTimes, Vec<ThreadInfo>)) {
+    let (before, old_threads) = previous;
+    let (now, threads) = current;
+    let busy = now.busy.saturating_sub(before.busy);
+    let elapsed = now.total().saturating_sub(before.total());
+
+    let uptime = time::uptime();
+    println!(
+        "top - up {}s, {} threads, {} tasks, CPU {} busy, {} context switches",
+        uptime.as_secs(),
+        threads.len(),
+        executor::list().len(),
+        Percent::of(busy, elapsed),
+        thread_scheduler::context_switches()
+    );
+    println!();
+
+    let mut rows: Vec<(Duration, &ThreadInfo)> = threads
+        .iter()
+        .map(|thread| {
+            let old = old_threads.iter().find(|old| old.id == thread.id);
+            let ran = thread.cpu_time.saturating_sub(old.map_or(Duration::ZERO, |old| old.cpu_time));
+            (ran, thread)
+        })
+        .collect();
+    rows.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.id.cmp(&b.1.id)));
+
+    println!("  TID  NAME          STATE     PRI   %CPU      TIME");
+    for (ran, thread) in rows {
+        println!(
+            "  {:3}  {:12}  {:8}  {:>3}  {:>6}  {:>6}ms",
+            thread.id.as_u64(),
+            thread.name.as_deref().unwrap_or("-"),
+            format!("{:?}", thread.state),
+            priority(thread),
+            format!("{}", Percent::of(ran, elapsed)),
+            thread.cpu_time.as_millis()
+        );
+    }
+}
+
+fn priority(thread: &ThreadInfo) -> String {
+    match thread.priority {
+        Some(priority) => format!("{}", priority),
+        None => String::from("-"),
+    }
+}
+
+/// Share of a duration, shown with one decimal
+struct Percent(u128);
+
+impl Percent {
+    fn of(part: Duration, whole: Duration) -> Self {
+        match whole.as_nanos() {
+            0 => Percent(0),
+            whole => Percent((part.as_nanos() * 1000 / whole).min(1000)),
+        }
+    }
+}
+
+impl core::fmt::Display for Percent {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "{}.{}%", self.0 / 10, self.0 % 10)
+    }
 }
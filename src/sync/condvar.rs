@@ -0,0 +1,65 @@
+//! Condition variable for the sleeping `Mutex`
+
+use super::mutex::MutexGuard;
+use super::wait_queue::{self, WaitQueue};
+use x86_64::instructions::interrupts;
+
+/// Condition variable: wait for a change to data protected by a `Mutex`
+pub struct Condvar {
+    waiters: WaitQueue,
+}
+
+impl Default for Condvar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Condvar {
+    pub const fn new() -> Self {
+        Condvar { waiters: WaitQueue::new() }
+    }
+
+    /// Unlock `guard`, block until notified, then lock again
+    pub fn wait<'a, T: ?Sized>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        let mutex = guard.mutex();
+        interrupts::without_interrupts(|| {
+            // Queued before unlocking so a notification can't slip in between
+            let node = self.waiters.enqueue_current(0);
+            drop(guard);
+            wait_queue::park(&node);
+        });
+        mutex.lock()
+    }
+
+    /// Wait until `condition` returns false for the protected data
+    pub fn wait_while<'a, T: ?Sized>(
+        &self,
+        mut guard: MutexGuard<'a, T>,
+        mut condition: impl FnMut(&mut T) -> bool,
+    ) -> MutexGuard<'a, T> {
+        while condition(&mut guard) {
+            guard = self.wait(guard);
+        }
+        guard
+    }
+
+    /// `wait` for async tasks
+    pub async fn wait_async<'a, T: ?Sized>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        let mutex = guard.mutex();
+        let notified = self.waiters.wait_async();
+        drop(guard);
+        notified.await;
+        mutex.lock_async().await
+    }
+
+    /// Wake the longest waiting waiter; returns whether there was one
+    pub fn notify_one(&self) -> bool {
+        self.waiters.notify_one()
+    }
+
+    /// Wake every waiter; returns how many there were
+    pub fn notify_all(&self) -> usize {
+        self.waiters.notify_all()
+    }
+}
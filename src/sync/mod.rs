@@ -0,0 +1,23 @@
+//! Blocking synchronization primitives
+//!
+//! Unlike `spin::Mutex`, these park the waiting kernel thread in the
+//! scheduler (or the waiting task in the executor) and wake waiters in FIFO
+//! order. Locks are handed directly to the next waiter on unlock, so a
+//! thread that unlocks and immediately locks again can't overtake it.
+//!
+//! Waiting is only allowed in thread or task context; `WaitQueue::notify_*`
+//! and `Semaphore::release` may also be used from interrupt handlers.
+
+pub mod condvar;
+pub mod mutex;
+pub mod rwlock;
+pub mod semaphore;
+pub mod spinlock;
+pub mod wait_queue;
+
+pub use condvar::Condvar;
+pub use mutex::{Mutex, MutexGuard};
+pub use rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+pub use semaphore::Semaphore;
+pub use spinlock::{SpinLock, SpinLockGuard};
+pub use wait_queue::WaitQueue;
@@ -0,0 +1,134 @@
+//! Sleeping mutual exclusion lock
+
+use super::wait_queue::{self, Acquire, Handoff, WaitQueue};
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicBool, Ordering};
+use x86_64::instructions::interrupts;
+
+/// Mutual exclusion lock that parks waiters instead of spinning
+///
+/// Waiters get the lock in the order they asked for it. Use `spin::Mutex`
+/// for data touched by interrupt handlers; this lock may block.
+pub struct Mutex<T: ?Sized> {
+    locked: AtomicBool,
+    waiters: WaitQueue,
+    data: UnsafeCell<T>,
+}
+
+// Safety: the lock hands out access to `data` to one owner at a time
+unsafe impl<T: ?Sized + Send> Send for Mutex<T> {}
+unsafe impl<T: ?Sized + Send> Sync for Mutex<T> {}
+
+impl<T> Mutex<T> {
+    pub const fn new(data: T) -> Self {
+        Mutex {
+            locked: AtomicBool::new(false),
+            waiters: WaitQueue::new(),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+
+impl<T: ?Sized> Mutex<T> {
+    /// Block the running thread until the lock is free, then take it
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        wait_queue::acquire(self, 0);
+        MutexGuard { mutex: self }
+    }
+
+    /// Take the lock if it is free
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        interrupts::without_interrupts(|| self.try_acquire(0))
+            .then(|| MutexGuard { mutex: self })
+    }
+
+    /// Wait for the lock without blocking the executor
+    pub async fn lock_async(&self) -> MutexGuard<'_, T> {
+        Acquire::new(self, 0).await;
+        MutexGuard { mutex: self }
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.locked.load(Ordering::Relaxed)
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+
+    fn unlock(&self) {
+        interrupts::without_interrupts(|| self.release(0));
+    }
+}
+
+impl<T: ?Sized> Handoff for Mutex<T> {
+    fn waiters(&self) -> &WaitQueue {
+        &self.waiters
+    }
+
+    fn try_acquire(&self, _tag: usize) -> bool {
+        !self.locked.swap(true, Ordering::Acquire)
+    }
+
+    fn release(&self, _tag: usize) {
+        // The lock stays held for the waiter we wake
+        if !self.waiters.notify_one() {
+            self.locked.store(false, Ordering::Release);
+        }
+    }
+}
+
+impl<T: Default> Default for Mutex<T> {
+    fn default() -> Self {
+        Mutex::new(T::default())
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for Mutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.try_lock() {
+            Some(guard) => f.debug_struct("Mutex").field("data", &&*guard).finish(),
+            None => f.write_str("Mutex { <locked> }"),
+        }
+    }
+}
+
+/// Access to the data of a locked `Mutex`; unlocks when dropped
+pub struct MutexGuard<'a, T: ?Sized> {
+    mutex: &'a Mutex<T>,
+}
+
+impl<'a, T: ?Sized> MutexGuard<'a, T> {
+    /// The mutex this guard locks
+    pub(crate) fn mutex(&self) -> &'a Mutex<T> {
+        self.mutex
+    }
+}
+
+impl<T: ?Sized> Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // Safety: the guard owns the lock
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // Safety: the guard owns the lock
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.unlock();
+    }
+}
@@ -0,0 +1,192 @@
+//! Sleeping reader-writer lock
+
+use super::wait_queue::{self, Acquire, Handoff, WaitQueue};
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+const READ: usize = 0;
+const WRITE: usize = 1;
+
+struct State {
+    readers: usize,
+    writer: bool,
+}
+
+/// Reader-writer lock that parks waiters instead of spinning
+///
+/// Waiters are served in order: a reader arriving after a waiting writer
+/// waits behind it, so writers can't be starved. When a writer unlocks,
+/// every reader at the front of the queue is let in together.
+pub struct RwLock<T: ?Sized> {
+    /// Locked with interrupts disabled
+    state: Mutex<State>,
+    waiters: WaitQueue,
+    data: UnsafeCell<T>,
+}
+
+// Safety: readers share `&T` (needs Sync), a writer gets `&mut T` (needs Send)
+unsafe impl<T: ?Sized + Send> Send for RwLock<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for RwLock<T> {}
+
+impl<T> RwLock<T> {
+    pub const fn new(data: T) -> Self {
+        RwLock {
+            state: Mutex::new(State { readers: 0, writer: false }),
+            waiters: WaitQueue::new(),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+
+impl<T: ?Sized> RwLock<T> {
+    /// Block the running thread until it may read
+    pub fn read(&self) -> RwLockReadGuard<'_, T> {
+        wait_queue::acquire(self, READ);
+        RwLockReadGuard { lock: self }
+    }
+
+    /// Block the running thread until it may write
+    pub fn write(&self) -> RwLockWriteGuard<'_, T> {
+        wait_queue::acquire(self, WRITE);
+        RwLockWriteGuard { lock: self }
+    }
+
+    pub fn try_read(&self) -> Option<RwLockReadGuard<'_, T>> {
+        interrupts::without_interrupts(|| self.try_acquire(READ))
+            .then(|| RwLockReadGuard { lock: self })
+    }
+
+    pub fn try_write(&self) -> Option<RwLockWriteGuard<'_, T>> {
+        interrupts::without_interrupts(|| self.try_acquire(WRITE))
+            .then(|| RwLockWriteGuard { lock: self })
+    }
+
+    /// Wait to read without blocking the executor
+    pub async fn read_async(&self) -> RwLockReadGuard<'_, T> {
+        Acquire::new(self, READ).await;
+        RwLockReadGuard { lock: self }
+    }
+
+    /// Wait to write without blocking the executor
+    pub async fn write_async(&self) -> RwLockWriteGuard<'_, T> {
+        Acquire::new(self, WRITE).await;
+        RwLockWriteGuard { lock: self }
+    }
+
+    /// Number of active readers
+    pub fn reader_count(&self) -> usize {
+        interrupts::without_interrupts(|| self.state.lock().readers)
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+
+    fn unlock(&self, tag: usize) {
+        interrupts::without_interrupts(|| self.release(tag));
+    }
+}
+
+impl<T: ?Sized> Handoff for RwLock<T> {
+    fn waiters(&self) -> &WaitQueue {
+        &self.waiters
+    }
+
+    fn try_acquire(&self, tag: usize) -> bool {
+        let mut state = self.state.lock();
+        if state.writer || !self.waiters.is_empty() {
+            return false;
+        }
+        match tag {
+            READ => state.readers += 1,
+            _ if state.readers > 0 => return false,
+            _ => state.writer = true,
+        }
+        true
+    }
+
+    fn release(&self, tag: usize) {
+        let mut state = self.state.lock();
+        if tag == READ {
+            state.readers -= 1;
+            if state.readers > 0 {
+                return;
+            }
+        } else {
+            state.writer = false;
+        }
+
+        // Hand the lock to the next writer, or to every reader up front
+        match self.waiters.front_tag() {
+            Some(WRITE) => {
+                state.writer = true;
+                self.waiters.notify_one();
+            }
+            _ => {
+                while self.waiters.front_tag() == Some(READ) {
+                    state.readers += 1;
+                    self.waiters.notify_one();
+                }
+            }
+        }
+    }
+}
+
+impl<T: Default> Default for RwLock<T> {
+    fn default() -> Self {
+        RwLock::new(T::default())
+    }
+}
+
+/// Shared access to the data of an `RwLock`
+pub struct RwLockReadGuard<'a, T: ?Sized> {
+    lock: &'a RwLock<T>,
+}
+
+impl<T: ?Sized> Deref for RwLockReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // Safety: no writer while a read guard exists
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for RwLockReadGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.unlock(READ);
+    }
+}
+
+/// Exclusive access to the data of an `RwLock`
+pub struct RwLockWriteGuard<'a, T: ?Sized> {
+    lock: &'a RwLock<T>,
+}
+
+impl<T: ?Sized> Deref for RwLockWriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // Safety: the guard owns the lock exclusively
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for RwLockWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // Safety: the guard owns the lock exclusively
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for RwLockWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.unlock(WRITE);
+    }
+}
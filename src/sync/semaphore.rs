@@ -0,0 +1,69 @@
+//! Counting semaphore
+
+use super::wait_queue::{self, Acquire, Handoff, WaitQueue};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use x86_64::instructions::interrupts;
+
+/// Counting semaphore with FIFO waiters
+///
+/// `release` may be called from interrupt handlers.
+pub struct Semaphore {
+    /// Only changed with interrupts disabled
+    permits: AtomicUsize,
+    waiters: WaitQueue,
+}
+
+impl Semaphore {
+    pub const fn new(permits: usize) -> Self {
+        Semaphore {
+            permits: AtomicUsize::new(permits),
+            waiters: WaitQueue::new(),
+        }
+    }
+
+    /// Block the running thread until a permit is available, then take it
+    pub fn acquire(&self) {
+        wait_queue::acquire(self, 0);
+    }
+
+    /// Take a permit if one is available
+    pub fn try_acquire(&self) -> bool {
+        interrupts::without_interrupts(|| Handoff::try_acquire(self, 0))
+    }
+
+    /// Wait for a permit without blocking the executor
+    pub async fn acquire_async(&self) {
+        Acquire::new(self, 0).await
+    }
+
+    /// Return a permit, handing it to the longest waiter if there is one
+    pub fn release(&self) {
+        interrupts::without_interrupts(|| Handoff::release(self, 0));
+    }
+
+    /// Permits not currently taken
+    pub fn available(&self) -> usize {
+        self.permits.load(Ordering::Relaxed)
+    }
+}
+
+impl Handoff for Semaphore {
+    fn waiters(&self) -> &WaitQueue {
+        &self.waiters
+    }
+
+    fn try_acquire(&self, _tag: usize) -> bool {
+        let permits = self.permits.load(Ordering::Relaxed);
+        if permits == 0 || !self.waiters.is_empty() {
+            return false;
+        }
+        self.permits.store(permits - 1, Ordering::Relaxed);
+        true
+    }
+
+    fn release(&self, _tag: usize) {
+        if !self.waiters.notify_one() {
+            self.permits.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
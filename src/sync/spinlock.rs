@@ -0,0 +1,340 @@
+//! Spinlock with optional deadlock and contention diagnostics
+//!
+//! `SpinLock` is plain `spin::Mutex` unless the kernel is built with the
+//! `lock-debug` feature. Then every lock remembers which thread holds it,
+//! where it was acquired and the holder's stack; a lock spun on for longer
+//! than `REPORT_AFTER_CYCLES` is reported on the serial port with both the
+//! waiter's and the holder's stack. Counters are kept per lock class, i.e.
+//! per source location the lock was created at, so all locks of one kind
+//! (every ramfs node, say) share a line in `stats()`.
+//!
+//! Diagnostics never allocate or take another instrumented lock, so even
+//! the scheduler and heap paths can use them.
+
+use core::panic::Location;
+
+/// Whether locks are instrumented in this build
+pub const ENABLED: bool = cfg!(feature = "lock-debug");
+
+/// Counters of one lock class
+#[derive(Debug, Clone, Copy)]
+pub struct LockStat {
+    /// Where the locks of this class are created
+    pub site: &'static Location<'static>,
+    pub acquisitions: u64,
+    /// Acquisitions that found the lock taken
+    pub contended: u64,
+    /// TSC cycles spent spinning, in total and at most at once
+    pub spin_cycles: u64,
+    pub max_spin_cycles: u64,
+    /// TSC cycles the lock was held, in total and at most at once
+    pub hold_cycles: u64,
+    pub max_hold_cycles: u64,
+    /// Spins reported as possible deadlocks
+    pub reports: u64,
+}
+
+#[cfg(not(feature = "lock-debug"))]
+pub use spin::{Mutex as SpinLock, MutexGuard as SpinLockGuard};
+
+#[cfg(feature = "lock-debug")]
+pub use instrumented::{SpinLock, SpinLockGuard, REPORT_AFTER_CYCLES};
+
+/// Counters of every lock class used so far
+#[cfg(feature = "lock-debug")]
+pub use instrumented::stats;
+
+/// Counters of every lock class used so far, always empty without
+/// `lock-debug`
+#[cfg(not(feature = "lock-debug"))]
+pub fn stats() -> alloc::vec::Vec<LockStat> {
+    alloc::vec::Vec::new()
+}
+
+#[cfg(feature = "lock-debug")]
+mod instrumented {
+    use super::LockStat;
+    use crate::backtrace::Backtrace;
+    use crate::cpu;
+    use crate::task::{thread_scheduler, TaskId};
+    use alloc::vec::Vec;
+    use core::fmt;
+    use core::ops::{Deref, DerefMut};
+    use core::panic::Location;
+    use core::ptr;
+    use core::sync::atomic::{AtomicPtr, AtomicU64, Ordering};
+    use x86_64::instructions::interrupts;
+
+    /// Cycles a lock may be spun on before it is reported, about a second
+    pub const REPORT_AFTER_CYCLES: u64 = 1 << 31;
+
+    const MAX_CLASSES: usize = 128;
+
+    /// Counters shared by every lock created at one site
+    struct LockClass {
+        site: AtomicPtr<Location<'static>>,
+        acquisitions: AtomicU64,
+        contended: AtomicU64,
+        spin_cycles: AtomicU64,
+        max_spin_cycles: AtomicU64,
+        hold_cycles: AtomicU64,
+        max_hold_cycles: AtomicU64,
+        reports: AtomicU64,
+    }
+
+    impl LockClass {
+        const fn new() -> Self {
+            LockClass {
+                site: AtomicPtr::new(ptr::null_mut()),
+                acquisitions: AtomicU64::new(0),
+                contended: AtomicU64::new(0),
+                spin_cycles: AtomicU64::new(0),
+                max_spin_cycles: AtomicU64::new(0),
+                hold_cycles: AtomicU64::new(0),
+                max_hold_cycles: AtomicU64::new(0),
+                reports: AtomicU64::new(0),
+            }
+        }
+
+        fn site(&self) -> Option<&'static Location<'static>> {
+            // Safety: only ever set from a `&'static Location`
+            unsafe { self.site.load(Ordering::Acquire).as_ref() }
+        }
+
+        fn stat(&self) -> Option<LockStat> {
+            Some(LockStat {
+                site: self.site()?,
+                acquisitions: self.acquisitions.load(Ordering::Relaxed),
+                contended: self.contended.load(Ordering::Relaxed),
+                spin_cycles: self.spin_cycles.load(Ordering::Relaxed),
+                max_spin_cycles: self.max_spin_cycles.load(Ordering::Relaxed),
+                hold_cycles: self.hold_cycles.load(Ordering::Relaxed),
+                max_hold_cycles: self.max_hold_cycles.load(Ordering::Relaxed),
+                reports: self.reports.load(Ordering::Relaxed),
+            })
+        }
+    }
+
+    /// Registered classes; the last one also takes everything that
+    /// doesn't fit
+    static CLASSES: [LockClass; MAX_CLASSES] = [const { LockClass::new() }; MAX_CLASSES];
+
+    /// Class of locks created at `site`, registered on first use
+    fn class_of(site: &'static Location<'static>) -> &'static LockClass {
+        interrupts::without_interrupts(|| {
+            for class in &CLASSES {
+                match class.site() {
+                    Some(known) if known == site => return class,
+                    Some(_) => {}
+                    None => {
+                        class.site.store(ptr::from_ref(site).cast_mut(), Ordering::Release);
+                        return class;
+                    }
+                }
+            }
+            &CLASSES[MAX_CLASSES - 1]
+        })
+    }
+
+    pub fn stats() -> Vec<LockStat> {
+        CLASSES.iter().map_while(LockClass::stat).collect()
+    }
+
+    /// Who holds a lock, for reports
+    #[derive(Clone, Copy)]
+    struct Holder {
+        thread: Option<TaskId>,
+        site: Option<&'static Location<'static>>,
+        since: u64,
+        trace: Backtrace,
+    }
+
+    /// `spin::Mutex` that records its holder and keeps contention counters
+    pub struct SpinLock<T: ?Sized> {
+        site: &'static Location<'static>,
+        class: AtomicPtr<LockClass>,
+        /// Written by the holder, read by reports; never waited on
+        holder: spin::Mutex<Holder>,
+        inner: spin::Mutex<T>,
+    }
+
+    impl<T> SpinLock<T> {
+        #[track_caller]
+        pub const fn new(value: T) -> Self {
+            SpinLock {
+                site: Location::caller(),
+                class: AtomicPtr::new(ptr::null_mut()),
+                holder: spin::Mutex::new(Holder {
+                    thread: None,
+                    site: None,
+                    since: 0,
+                    trace: Backtrace::empty(),
+                }),
+                inner: spin::Mutex::new(value),
+            }
+        }
+
+        pub fn into_inner(self) -> T {
+            self.inner.into_inner()
+        }
+    }
+
+    impl<T: ?Sized> SpinLock<T> {
+        #[track_caller]
+        pub fn lock(&self) -> SpinLockGuard<'_, T> {
+            let site = Location::caller();
+            let class = self.class();
+            let guard = match self.inner.try_lock() {
+                Some(guard) => guard,
+                None => self.spin(class, site),
+            };
+            self.acquired(class, site, guard)
+        }
+
+        #[track_caller]
+        pub fn try_lock(&self) -> Option<SpinLockGuard<'_, T>> {
+            let site = Location::caller();
+            let guard = self.inner.try_lock()?;
+            Some(self.acquired(self.class(), site, guard))
+        }
+
+        pub fn is_locked(&self) -> bool {
+            self.inner.is_locked()
+        }
+
+        pub fn get_mut(&mut self) -> &mut T {
+            self.inner.get_mut()
+        }
+
+        fn class(&self) -> &'static LockClass {
+            // Safety: only ever set to an element of `CLASSES`
+            if let Some(class) = unsafe { self.class.load(Ordering::Relaxed).as_ref() } {
+                return class;
+            }
+            let class = class_of(self.site);
+            self.class.store(ptr::from_ref(class).cast_mut(), Ordering::Relaxed);
+            class
+        }
+
+        #[cold]
+        fn spin(
+            &self,
+            class: &LockClass,
+            site: &'static Location<'static>,
+        ) -> spin::MutexGuard<'_, T> {
+            class.contended.fetch_add(1, Ordering::Relaxed);
+            let start = cpu::rdtsc();
+            let mut reported = false;
+            loop {
+                if let Some(guard) = self.inner.try_lock() {
+                    let spun = cpu::rdtsc().wrapping_sub(start);
+                    class.spin_cycles.fetch_add(spun, Ordering::Relaxed);
+                    class.max_spin_cycles.fetch_max(spun, Ordering::Relaxed);
+                    return guard;
+                }
+                core::hint::spin_loop();
+
+                if !reported && cpu::rdtsc().wrapping_sub(start) > REPORT_AFTER_CYCLES {
+                    reported = true;
+                    class.reports.fetch_add(1, Ordering::Relaxed);
+                    self.report(site, &Backtrace::capture());
+                }
+            }
+        }
+
+        fn acquired<'a>(
+            &'a self,
+            class: &'static LockClass,
+            site: &'static Location<'static>,
+            guard: spin::MutexGuard<'a, T>,
+        ) -> SpinLockGuard<'a, T> {
+            class.acquisitions.fetch_add(1, Ordering::Relaxed);
+            let since = cpu::rdtsc();
+            // Busy only while a report copies it out
+            if let Some(mut holder) = self.holder.try_lock() {
+                *holder = Holder {
+                    thread: thread_scheduler::running_thread(),
+                    site: Some(site),
+                    since,
+                    trace: Backtrace::capture(),
+                };
+            }
+            SpinLockGuard { lock: self, class, since, guard }
+        }
+
+        /// Print a possible deadlock, bypassing every lock on the way
+        #[cold]
+        fn report(&self, site: &'static Location<'static>, waiter: &Backtrace) {
+            let holder = self.holder.try_lock().map(|holder| *holder);
+            crate::serial::print_unlocked(format_args!(
+                "\nlock-debug: lock created at {} spun on for over {} cycles\n\
+                 waiter: thread {:?} at {}\n{}",
+                self.site,
+                REPORT_AFTER_CYCLES,
+                thread_scheduler::running_thread(),
+                site,
+                waiter,
+            ));
+            match holder {
+                Some(Holder { thread, site: Some(site), since, trace }) => {
+                    crate::serial::print_unlocked(format_args!(
+                        "holder: thread {:?} at {}, for {} cycles\n{}",
+                        thread,
+                        site,
+                        cpu::rdtsc().wrapping_sub(since),
+                        trace,
+                    ));
+                }
+                _ => crate::serial::print_unlocked(format_args!("holder: unknown\n")),
+            }
+        }
+    }
+
+    impl<T: Default> Default for SpinLock<T> {
+        #[track_caller]
+        fn default() -> Self {
+            Self::new(T::default())
+        }
+    }
+
+    impl<T: ?Sized + fmt::Debug> fmt::Debug for SpinLock<T> {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            self.inner.fmt(f)
+        }
+    }
+
+    /// Guard of a `SpinLock`; records the hold time when dropped
+    pub struct SpinLockGuard<'a, T: ?Sized> {
+        lock: &'a SpinLock<T>,
+        class: &'static LockClass,
+        since: u64,
+        guard: spin::MutexGuard<'a, T>,
+    }
+
+    impl<T: ?Sized> Deref for SpinLockGuard<'_, T> {
+        type Target = T;
+
+        fn deref(&self) -> &T {
+            &self.guard
+        }
+    }
+
+    impl<T: ?Sized> DerefMut for SpinLockGuard<'_, T> {
+        fn deref_mut(&mut self) -> &mut T {
+            &mut self.guard
+        }
+    }
+
+    impl<T: ?Sized> Drop for SpinLockGuard<'_, T> {
+        fn drop(&mut self) {
+            let held = cpu::rdtsc().wrapping_sub(self.since);
+            self.class.hold_cycles.fetch_add(held, Ordering::Relaxed);
+            self.class.max_hold_cycles.fetch_max(held, Ordering::Relaxed);
+            if let Some(mut holder) = self.lock.holder.try_lock() {
+                holder.thread = None;
+                holder.site = None;
+            }
+            // `guard` unlocks after this
+        }
+    }
+}
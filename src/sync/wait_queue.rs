@@ -0,0 +1,326 @@
+//! FIFO queue of parked kernel threads and async tasks
+
+use crate::task::thread_scheduler::{self, with_scheduler};
+use crate::task::{thread, TaskId};
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::{Context, Poll, Waker};
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+/// What to wake when a waiter is notified
+enum Parked {
+    Thread(TaskId),
+    Task(Waker),
+    /// Registered but not polled yet, or waiting before threading starts;
+    /// nothing to wake, the waiter checks `notified` itself
+    Nothing,
+}
+
+/// One waiter, shared between the queue and the waiting thread or future
+pub(crate) struct WaitNode {
+    parked: Mutex<Parked>,
+    notified: AtomicBool,
+    /// Kind of waiter, for primitives that treat waiters differently
+    tag: usize,
+}
+
+impl WaitNode {
+    pub(crate) fn notified(&self) -> bool {
+        self.notified.load(Ordering::Acquire)
+    }
+
+    fn wake(&self) {
+        self.notified.store(true, Ordering::Release);
+        match &*self.parked.lock() {
+            Parked::Thread(id) => thread_scheduler::wake(*id),
+            Parked::Task(waker) => waker.wake_by_ref(),
+            Parked::Nothing => {}
+        }
+    }
+
+    /// Make the next notification wake the task polling with `waker`
+    fn set_waker(&self, waker: &Waker) {
+        let old = interrupts::without_interrupts(|| {
+            let mut parked = self.parked.lock();
+            match &*parked {
+                Parked::Task(old) if old.will_wake(waker) => None,
+                _ => Some(core::mem::replace(&mut *parked, Parked::Task(waker.clone()))),
+            }
+        });
+        drop(old);
+    }
+}
+
+/// Waiters woken in the order they started waiting
+///
+/// Notifying is safe from interrupt handlers: it neither allocates nor
+/// frees. Waiting must happen in thread or task context.
+pub struct WaitQueue {
+    /// Locked with interrupts disabled
+    waiters: Mutex<VecDeque<Arc<WaitNode>>>,
+}
+
+impl Default for WaitQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WaitQueue {
+    pub const fn new() -> Self {
+        WaitQueue { waiters: Mutex::new(VecDeque::new()) }
+    }
+
+    /// Number of waiters
+    pub fn len(&self) -> usize {
+        interrupts::without_interrupts(|| self.waiters.lock().len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Block the running thread until it is notified
+    pub fn wait(&self) {
+        interrupts::without_interrupts(|| {
+            let node = self.enqueue_current(0);
+            park(&node);
+        });
+    }
+
+    /// Block the running thread until `condition` holds
+    ///
+    /// `condition` runs with interrupts disabled and is rechecked after
+    /// every notification.
+    pub fn wait_until(&self, mut condition: impl FnMut() -> bool) {
+        interrupts::without_interrupts(|| {
+            while !condition() {
+                let node = self.enqueue_current(0);
+                park(&node);
+            }
+        });
+    }
+
+    /// Future that completes once it is notified
+    ///
+    /// The waiter joins the queue when the future is created, not when it
+    /// is first polled, so no notification sent in between is missed.
+    pub fn wait_async(&self) -> Wait<'_> {
+        let node = interrupts::without_interrupts(|| self.enqueue(Parked::Nothing, 0));
+        Wait { queue: self, node: Some(node) }
+    }
+
+    /// Wake the longest waiting waiter; returns whether there was one
+    pub fn notify_one(&self) -> bool {
+        interrupts::without_interrupts(|| {
+            // The waiter holds its own reference, so this never frees
+            match self.waiters.lock().pop_front() {
+                Some(node) => {
+                    node.wake();
+                    true
+                }
+                None => false,
+            }
+        })
+    }
+
+    /// Wake every waiter; returns how many there were
+    pub fn notify_all(&self) -> usize {
+        let mut woken = 0;
+        while self.notify_one() {
+            woken += 1;
+        }
+        woken
+    }
+
+    /// Queue the running thread (interrupts must be disabled)
+    pub(crate) fn enqueue_current(&self, tag: usize) -> Arc<WaitNode> {
+        let parked = match thread::current() {
+            Some(id) => Parked::Thread(id),
+            None => Parked::Nothing,
+        };
+        self.enqueue(parked, tag)
+    }
+
+    fn enqueue(&self, parked: Parked, tag: usize) -> Arc<WaitNode> {
+        let node = Arc::new(WaitNode {
+            parked: Mutex::new(parked),
+            notified: AtomicBool::new(false),
+            tag,
+        });
+        self.waiters.lock().push_back(node.clone());
+        node
+    }
+
+    /// Queue an async waiter (interrupts must be disabled)
+    pub(crate) fn enqueue_task(&self, waker: &Waker, tag: usize) -> Arc<WaitNode> {
+        self.enqueue(Parked::Task(waker.clone()), tag)
+    }
+
+    /// Tag of the longest waiting waiter (interrupts must be disabled)
+    pub(crate) fn front_tag(&self) -> Option<usize> {
+        self.waiters.lock().front().map(|node| node.tag)
+    }
+
+    /// Take `node` out of the queue; returns false if it was already
+    /// notified (interrupts must be disabled)
+    pub(crate) fn remove(&self, node: &Arc<WaitNode>) -> bool {
+        let mut waiters = self.waiters.lock();
+        match waiters.iter().position(|queued| Arc::ptr_eq(queued, node)) {
+            Some(index) => {
+                waiters.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Block until `node` is notified (interrupts must be disabled)
+///
+/// Before threading starts there is nothing to switch to, so this waits
+/// for an interrupt handler to notify the node instead.
+pub(crate) fn park(node: &WaitNode) {
+    while !node.notified() {
+        if thread::current().is_some() {
+            with_scheduler(|scheduler| scheduler.block_current());
+            thread::yield_now();
+        } else {
+            interrupts::enable_and_hlt();
+            interrupts::disable();
+        }
+    }
+}
+
+/// Future returned by `WaitQueue::wait_async`
+pub struct Wait<'a> {
+    queue: &'a WaitQueue,
+    node: Option<Arc<WaitNode>>,
+}
+
+impl Future for Wait<'_> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        let Some(node) = self.node.as_ref() else {
+            return Poll::Ready(());
+        };
+        node.set_waker(cx.waker());
+
+        // Checked after registering the waker so a notification between the
+        // two still wakes us
+        if node.notified() {
+            self.node = None;
+            return Poll::Ready(());
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for Wait<'_> {
+    fn drop(&mut self) {
+        let Some(node) = self.node.take() else {
+            return;
+        };
+        let removed = interrupts::without_interrupts(|| self.queue.remove(&node));
+
+        // Pass on a notification we will never act on
+        if !removed {
+            self.queue.notify_one();
+        }
+    }
+}
+
+/// A primitive whose ownership is handed directly to the next waiter
+///
+/// `release` hands ownership to a waiter instead of freeing it when there
+/// is one, so waiters are served in order and can't be overtaken.
+pub(crate) trait Handoff {
+    fn waiters(&self) -> &WaitQueue;
+
+    /// Take ownership of kind `tag` if it is free and nobody is waiting
+    /// (interrupts disabled)
+    fn try_acquire(&self, tag: usize) -> bool;
+
+    /// Give up ownership of kind `tag` (interrupts disabled)
+    fn release(&self, tag: usize);
+}
+
+/// Block the running thread until it owns `primitive`
+pub(crate) fn acquire<H: Handoff + ?Sized>(primitive: &H, tag: usize) {
+    interrupts::without_interrupts(|| {
+        if !primitive.try_acquire(tag) {
+            let node = primitive.waiters().enqueue_current(tag);
+            park(&node);
+        }
+    });
+}
+
+/// Future that completes once the task owns the primitive
+pub(crate) struct Acquire<'a, H: Handoff + ?Sized> {
+    primitive: &'a H,
+    tag: usize,
+    node: Option<Arc<WaitNode>>,
+    done: bool,
+}
+
+impl<'a, H: Handoff + ?Sized> Acquire<'a, H> {
+    pub(crate) fn new(primitive: &'a H, tag: usize) -> Self {
+        Acquire { primitive, tag, node: None, done: false }
+    }
+}
+
+impl<H: Handoff + ?Sized> Future for Acquire<'_, H> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        if self.done {
+            return Poll::Ready(());
+        }
+
+        let ready = match self.node.as_ref() {
+            Some(node) => {
+                node.set_waker(cx.waker());
+                node.notified()
+            }
+            None => {
+                let (primitive, tag) = (self.primitive, self.tag);
+                interrupts::without_interrupts(|| {
+                    if primitive.try_acquire(tag) {
+                        true
+                    } else {
+                        self.node = Some(primitive.waiters().enqueue_task(cx.waker(), tag));
+                        false
+                    }
+                })
+            }
+        };
+
+        if ready {
+            self.node = None;
+            self.done = true;
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl<H: Handoff + ?Sized> Drop for Acquire<'_, H> {
+    fn drop(&mut self) {
+        let Some(node) = self.node.take() else {
+            return;
+        };
+        let (primitive, tag) = (self.primitive, self.tag);
+        interrupts::without_interrupts(|| {
+            // Ownership was handed to us after all; pass it on
+            if !primitive.waiters().remove(&node) {
+                primitive.release(tag);
+            }
+        });
+    }
+}
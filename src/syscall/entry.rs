@@ -0,0 +1,221 @@
+//! Trap entry for system calls issued with `syscall` or `int 0x80`
+//!
+//! Both entry stubs save the user registers into a [`SyscallFrame`] on the
+//! kernel stack and call the dispatcher with the six argument registers.
+//! `syscall` returns with `sysretq` when it is safe to, `int 0x80` with `iretq`.
+
+use super::{syscall_handler, Syscall};
+use crate::process::signal;
+use crate::gdt;
+use core::sync::atomic::{AtomicU64, Ordering};
+use x86_64::VirtAddr;
+use x86_64::registers::model_specific::{Efer, EferFlags, LStar, SFMask, Star};
+use x86_64::registers::rflags::RFlags;
+
+/// Interrupt vector used for `int 0x80` system calls
+pub const SYSCALL_VECTOR: u8 = 0x80;
+
+/// Registers saved on the kernel stack when a system call traps
+///
+/// The layout matches the push order of the entry stubs, followed by the
+/// interrupt frame (pushed by the CPU for `int 0x80`, built by hand for
+/// `syscall`).
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SyscallFrame {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rbp: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rdx: u64,
+    pub rcx: u64,
+    pub rbx: u64,
+    pub rax: u64,
+
+    // Interrupt frame
+    pub rip: u64,
+    pub cs: u64,
+    pub rflags: u64,
+    pub rsp: u64,
+    pub ss: u64,
+}
+
+impl SyscallFrame {
+    /// Whether the system call came from ring 3
+    pub fn from_user(&self) -> bool {
+        self.cs & 0x3 == 0x3
+    }
+}
+
+/// User stack pointer, parked while `syscall_entry` switches stacks
+static USER_RSP_SCRATCH: AtomicU64 = AtomicU64::new(0);
+
+/// User selectors pushed into the frame built by `syscall_entry`
+static USER_CS: AtomicU64 = AtomicU64::new(0);
+static USER_SS: AtomicU64 = AtomicU64::new(0);
+
+/// Program the MSRs used by the `syscall` instruction
+pub fn init() {
+    let selectors = gdt::selectors();
+    USER_CS.store(selectors.user_code_selector.0 as u64, Ordering::Relaxed);
+    USER_SS.store(selectors.user_data_selector.0 as u64, Ordering::Relaxed);
+
+    Star::write(
+        selectors.user_code_selector,
+        selectors.user_data_selector,
+        selectors.code_selector,
+        selectors.data_selector,
+    )
+    .expect("GDT layout is not usable for SYSCALL/SYSRET");
+    LStar::write(VirtAddr::new(syscall_entry as *const () as u64));
+    // Enter the kernel with interrupts off and a clean direction flag
+    SFMask::write(
+        RFlags::INTERRUPT_FLAG
+            | RFlags::TRAP_FLAG
+            | RFlags::DIRECTION_FLAG
+            | RFlags::ALIGNMENT_CHECK,
+    );
+    unsafe {
+        Efer::update(|flags| *flags |= EferFlags::SYSTEM_CALL_EXTENSIONS);
+    }
+}
+
+macro_rules! save_registers {
+    () => {
+        "push rax
+        push rbx
+        push rcx
+        push rdx
+        push rsi
+        push rdi
+        push rbp
+        push r8
+        push r9
+        push r10
+        push r11
+        push r12
+        push r13
+        push r14
+        push r15"
+    };
+}
+
+macro_rules! restore_registers {
+    () => {
+        "pop r15
+        pop r14
+        pop r13
+        pop r12
+        pop r11
+        pop r10
+        pop r9
+        pop r8
+        pop rbp
+        pop rdi
+        pop rsi
+        pop rdx
+        pop rcx
+        pop rbx
+        pop rax"
+    };
+}
+
+/// Entry point of the `int 0x80` gate
+///
+/// # Safety
+/// Only to be installed in the IDT; it expects the CPU's interrupt frame
+/// on the stack and must not be called.
+#[unsafe(naked)]
+pub unsafe extern "C" fn int80_entry() {
+    core::arch::naked_asm!(
+        save_registers!(),
+        // The CPU frame plus 15 registers keep the stack 16-byte aligned
+        "mov rdi, rsp",
+        "call {dispatch}",
+        restore_registers!(),
+        "iretq",
+        dispatch = sym dispatch_syscall,
+    )
+}
+
+/// Target of the `syscall` instruction (`LSTAR`)
+///
+/// On entry rcx holds the user rip, r11 the user rflags, and rsp is still
+/// the user stack.
+///
+/// # Safety
+/// Only to be loaded into `LSTAR`; it must not be called.
+#[unsafe(naked)]
+pub unsafe extern "C" fn syscall_entry() {
+    core::arch::naked_asm!(
+        "mov [rip + {user_rsp}], rsp",
+        "mov rsp, [rip + {kernel_stack}]",
+
+        // Build the same interrupt frame `int 0x80` gets from the CPU
+        "push qword ptr [rip + {user_ss}]",
+        "push qword ptr [rip + {user_rsp}]",
+        "push r11",
+        "push qword ptr [rip + {user_cs}]",
+        "push rcx",
+
+        save_registers!(),
+        "mov rdi, rsp",
+        "call {dispatch}",
+        "test al, al",
+        restore_registers!(),
+        "jz 2f",
+
+        // sysretq reloads rip from rcx and rflags from r11
+        "mov rcx, [rsp]",
+        "mov r11, [rsp + 16]",
+        "mov rsp, [rsp + 24]",
+        "sysretq",
+
+        // The frame was changed in a way sysretq can't express
+        "2:",
+        "iretq",
+        user_rsp = sym USER_RSP_SCRATCH,
+        kernel_stack = sym gdt::KERNEL_STACK_TOP,
+        user_ss = sym USER_SS,
+        user_cs = sym USER_CS,
+        dispatch = sym dispatch_syscall,
+    )
+}
+
+/// Decode the saved registers and run the dispatcher; the result goes to rax.
+/// Pending signals are delivered before returning to user mode.
+///
+/// Returns whether the frame can be restored with `sysretq`.
+extern "C" fn dispatch_syscall(frame: &mut SyscallFrame) -> bool {
+    if frame.rax == Syscall::RtSigreturn as u64 && frame.from_user() {
+        // The restored registers include rcx and r11, which sysretq clobbers
+        signal::sigreturn(frame);
+        signal::deliver(frame);
+        return false;
+    }
+
+    let result = syscall_handler(
+        frame.rax,
+        frame.rdi,
+        frame.rsi,
+        frame.rdx,
+        frame.r10,
+        frame.r8,
+        frame.r9,
+    );
+    frame.rax = result as u64;
+
+    if frame.from_user() {
+        signal::deliver(frame);
+    }
+
+    // sysretq faults in ring 0 on a non-canonical rip, so fall back to iretq
+    frame.from_user() && VirtAddr::try_new(frame.rip).is_ok()
+}
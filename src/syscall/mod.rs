@@ -1,5 +1,22 @@
+use crate::ipc::{self, CapHandle, Message, Rights};
+use crate::process::signal::{self, SigAction};
+use crate::process::{self, ExitStatus, Pid};
+use crate::task::thread;
+use crate::tty::termios::{
+    Termios, Winsize, TCGETS, TCSETS, TCSETSF, TCSETSW, TIOCGPGRP, TIOCGWINSZ, TIOCSPGRP,
+};
+use crate::tty::SetWhen;
+use crate::usermode::{is_user_range, USER_SPACE_END};
 use crate::vfs::{fd_table::FileDescriptor, ops};
-use crate::{println, print};
+use x86_64::VirtAddr;
+use crate::println;
+
+pub mod entry;
+
+/// Set up the `syscall` instruction; the `int 0x80` gate lives in the IDT
+pub fn init() {
+    entry::init();
+}
 
 /// System call numbers (Linux-compatible)
 #[repr(u64)]
@@ -9,10 +26,28 @@ pub enum Syscall {
     Write = 1,
     Open = 2,
     Close = 3,
+    RtSigaction = 13,
+    RtSigprocmask = 14,
+    RtSigreturn = 15,
+    Ioctl = 16,
+    GetPid = 39,
     Exit = 60,
     Fork = 57,
     Exec = 59,
-    Wait = 61,
+    Wait4 = 61,
+    Kill = 62,
+    GetPpid = 110,
+    ArchPrctl = 158,
+    GetRandom = 318,
+
+    // Lithos-specific, above the Linux range
+    IpcEndpoint = 1000,
+    IpcSend = 1001,
+    IpcRecv = 1002,
+    IpcCall = 1003,
+    IpcReply = 1004,
+    CapDerive = 1005,
+    CapClose = 1006,
 }
 
 impl Syscall {
@@ -22,10 +57,26 @@ impl Syscall {
             1 => Some(Syscall::Write),
             2 => Some(Syscall::Open),
             3 => Some(Syscall::Close),
+            13 => Some(Syscall::RtSigaction),
+            14 => Some(Syscall::RtSigprocmask),
+            15 => Some(Syscall::RtSigreturn),
+            16 => Some(Syscall::Ioctl),
+            39 => Some(Syscall::GetPid),
             60 => Some(Syscall::Exit),
             57 => Some(Syscall::Fork),
             59 => Some(Syscall::Exec),
-            61 => Some(Syscall::Wait),
+            61 => Some(Syscall::Wait4),
+            62 => Some(Syscall::Kill),
+            110 => Some(Syscall::GetPpid),
+            158 => Some(Syscall::ArchPrctl),
+            318 => Some(Syscall::GetRandom),
+            1000 => Some(Syscall::IpcEndpoint),
+            1001 => Some(Syscall::IpcSend),
+            1002 => Some(Syscall::IpcRecv),
+            1003 => Some(Syscall::IpcCall),
+            1004 => Some(Syscall::IpcReply),
+            1005 => Some(Syscall::CapDerive),
+            1006 => Some(Syscall::CapClose),
             _ => None,
         }
     }
@@ -59,10 +110,29 @@ pub fn syscall_handler(
         Syscall::Write => sys_write(arg1 as i32, arg2 as *const u8, arg3 as usize),
         Syscall::Open => sys_open(arg1 as *const u8, arg2 as i32),
         Syscall::Close => sys_close(arg1 as i32),
+        Syscall::RtSigaction => {
+            sys_rt_sigaction(arg1, arg2 as *const SigAction, arg3 as *mut SigAction)
+        }
+        Syscall::RtSigprocmask => sys_rt_sigprocmask(arg1, arg2 as *const u64, arg3 as *mut u64),
+        // Needs the trap frame, so the entry stub handles it
+        Syscall::RtSigreturn => -1, // EINVAL
+        Syscall::Ioctl => sys_ioctl(arg1 as i32, arg2, arg3),
+        Syscall::GetPid => sys_getpid(),
         Syscall::Exit => sys_exit(arg1 as i32),
         Syscall::Fork => sys_fork(),
         Syscall::Exec => sys_exec(arg1 as *const u8),
-        Syscall::Wait => sys_wait(arg1 as *mut i32),
+        Syscall::Wait4 => sys_wait4(arg1 as i64, arg2 as *mut i32, arg3),
+        Syscall::Kill => sys_kill(arg1 as i64, arg2 as i64),
+        Syscall::GetPpid => sys_getppid(),
+        Syscall::ArchPrctl => sys_arch_prctl(arg1, arg2),
+        Syscall::GetRandom => sys_getrandom(arg1 as *mut u8, arg2 as usize, arg3 as u32),
+        Syscall::IpcEndpoint => ipc::create_endpoint().as_u32() as i64,
+        Syscall::IpcSend => sys_ipc_send(arg1, arg2 as *const Message),
+        Syscall::IpcRecv => sys_ipc_recv(arg1, arg2 as *mut Message),
+        Syscall::IpcCall => sys_ipc_call(arg1, arg2 as *mut Message),
+        Syscall::IpcReply => sys_ipc_reply(arg1, arg2 as *const Message),
+        Syscall::CapDerive => sys_cap_derive(arg1, arg2),
+        Syscall::CapClose => sys_cap_close(arg1),
     }
 }
 
@@ -90,15 +160,6 @@ fn sys_write(fd: i32, buf: *const u8, count: usize) -> i64 {
     // Safety: We assume the buffer is valid
     let buffer = unsafe { core::slice::from_raw_parts(buf, count) };
     
-    // Special handling for stdout/stderr
-    if fd == 1 || fd == 2 {
-        // Write to console
-        for &byte in buffer {
-            print!("{}", byte as char);
-        }
-        return count as i64;
-    }
-    
     match ops::vfs_write(FileDescriptor(fd as usize), buffer) {
         Ok(n) => n as i64,
         Err(_) => -1,
@@ -136,12 +197,102 @@ fn sys_close(fd: i32) -> i64 {
     }
 }
 
+/// Control a terminal with the `termios` requests of Linux
+fn sys_ioctl(fd: i32, request: u64, arg: u64) -> i64 {
+    let Ok(tty) = ops::vfs_tty(FileDescriptor(fd as usize)) else {
+        return -1; // ENOTTY
+    };
+    let size = match request {
+        TCGETS | TCSETS | TCSETSW | TCSETSF => size_of::<Termios>(),
+        TIOCGPGRP | TIOCSPGRP => size_of::<i32>(),
+        TIOCGWINSZ => size_of::<Winsize>(),
+        _ => return -1, // ENOTTY
+    };
+    if arg == 0 || !is_user_range(arg, size as u64) {
+        return -1; // EFAULT
+    }
+
+    // Safety: In user space; we assume the pointer is valid
+    match request {
+        TCGETS => unsafe { (arg as *mut Termios).write(tty.termios()) },
+        TCSETS | TCSETSW | TCSETSF => {
+            let termios = unsafe { (arg as *const Termios).read() };
+            let when = if request == TCSETSF { SetWhen::Flush } else { SetWhen::Now };
+            tty.set_termios(termios, when);
+        }
+        TIOCGPGRP => {
+            let pid = tty.foreground().map_or(0, Pid::as_u32);
+            unsafe { (arg as *mut i32).write(pid as i32) };
+        }
+        TIOCSPGRP => {
+            let pid = Pid::new(unsafe { (arg as *const i32).read() } as u32);
+            if process::info(pid).is_none() {
+                return -1; // ESRCH
+            }
+            tty.set_foreground(Some(pid));
+        }
+        TIOCGWINSZ => unsafe { (arg as *mut Winsize).write(tty.winsize()) },
+        _ => unreachable!(),
+    }
+    0
+}
+
 /// Exit process
+///
+/// Ends the calling thread; the process exits with `code` once its last
+/// thread has exited.
 fn sys_exit(code: i32) -> i64 {
-    println!("Process exited with code: {}", code);
-    // In a real OS, this would terminate the current process
-    // For now, we just log it
-    code as i64
+    process::set_exit_status(ExitStatus::Exited(code));
+    if crate::usermode::user_program_running() {
+        crate::usermode::exit_user(code as i64);
+    }
+    process::exit(code)
+}
+
+/// PID of the calling process
+fn sys_getpid() -> i64 {
+    process::getpid().as_u32() as i64
+}
+
+/// PID of the calling process's parent
+fn sys_getppid() -> i64 {
+    process::getppid() as i64
+}
+
+/// `arch_prctl` codes
+const ARCH_SET_GS: u64 = 0x1001;
+const ARCH_SET_FS: u64 = 0x1002;
+const ARCH_GET_FS: u64 = 0x1003;
+const ARCH_GET_GS: u64 = 0x1004;
+
+/// Set or get the FS base of the calling thread, for user TLS
+///
+/// GS base holds the kernel's thread-local area, so it can't be changed.
+fn sys_arch_prctl(code: u64, addr: u64) -> i64 {
+    match code {
+        ARCH_SET_FS => {
+            // Only canonical user addresses; a kernel FS base would leak
+            // kernel memory layout and fault on the first TLS access
+            match VirtAddr::try_new(addr) {
+                Ok(base) if addr < USER_SPACE_END => {
+                    thread::set_fs_base(base);
+                    0
+                }
+                _ => -1, // EPERM
+            }
+        }
+        ARCH_GET_FS => {
+            let out = addr as *mut u64;
+            if out.is_null() || !is_user_range(addr, 8) {
+                return -1; // EFAULT
+            }
+            // Safety: In user space; we assume the pointer is valid
+            unsafe { out.write(thread::fs_base().as_u64()) };
+            0
+        }
+        ARCH_SET_GS | ARCH_GET_GS => -1, // EPERM
+        _ => -1,                         // EINVAL
+    }
 }
 
 /// Fork process (not implemented yet)
@@ -156,8 +307,222 @@ fn sys_exec(_path: *const u8) -> i64 {
     -1 // ENOSYS
 }
 
-/// Wait for child process (not implemented yet)
-fn sys_wait(_status: *mut i32) -> i64 {
-    println!("wait() not yet implemented");
-    -1 // ENOSYS
+/// Don't block if no child has exited
+const WNOHANG: u64 = 1;
+
+/// Wait for a child process to exit and reap it
+///
+/// `pid` is a child's PID, or -1 for any child. Process groups are not
+/// supported. The status is encoded like Linux's, for `WIFEXITED` and
+/// `WIFSIGNALED`.
+fn sys_wait4(pid: i64, status: *mut i32, options: u64) -> i64 {
+    let pid = match pid {
+        -1 => None,
+        pid if pid > 0 => Some(Pid::new(pid as u32)),
+        _ => return -1, // EINVAL
+    };
+    // Checked before reaping, so a bad pointer doesn't lose the status
+    if !is_user_ptr(status) {
+        return -1; // EFAULT
+    }
+
+    match process::wait(pid, options & WNOHANG != 0) {
+        Ok(Some((pid, exit_status))) => {
+            if !status.is_null() {
+                // Safety: In user space; we assume the pointer is valid
+                unsafe { status.write(exit_status.wait_status()) };
+            }
+            pid.as_u32() as i64
+        }
+        Ok(None) => 0,
+        Err(_) => -1, // ECHILD
+    }
+}
+
+/// Send a signal to a process
+///
+/// Only single processes can be signalled; process groups and broadcast
+/// (`pid <= 0`) are not supported.
+fn sys_kill(pid: i64, sig: i64) -> i64 {
+    if pid <= 0 || !(0..=signal::NSIG as i64).contains(&sig) {
+        return -1; // EINVAL
+    }
+    match signal::kill(Pid::new(pid as u32), sig as u8) {
+        Ok(()) => 0,
+        Err(_) => -1, // ESRCH or EPERM
+    }
+}
+
+/// Whether optional argument `ptr` is null or has room for a `T` in user
+/// space
+fn is_user_ptr<T>(ptr: *const T) -> bool {
+    ptr.is_null() || is_user_range(ptr as u64, size_of::<T>() as u64)
+}
+
+/// Examine and change the action of a signal
+fn sys_rt_sigaction(sig: u64, act: *const SigAction, oldact: *mut SigAction) -> i64 {
+    if sig > signal::NSIG as u64 {
+        return -1; // EINVAL
+    }
+    if !is_user_ptr(act) || !is_user_ptr(oldact) {
+        return -1; // EFAULT
+    }
+    // Safety: In user space; we assume the pointer is valid
+    let action = (!act.is_null()).then(|| unsafe { act.read() });
+    if action.is_some_and(|a| a.handler > signal::SIG_IGN && a.flags & signal::SA_RESTORER == 0) {
+        return -1; // EINVAL, handlers need a restorer
+    }
+    // Handlers and restorers run in ring 3, so must be user addresses
+    let outside = |address| !is_user_range(address, 1);
+    if action.is_some_and(|a| a.handler > signal::SIG_IGN && (outside(a.handler) || outside(a.restorer))) {
+        return -1; // EFAULT
+    }
+
+    match signal::sigaction(sig as u8, action) {
+        Ok(old) => {
+            if !oldact.is_null() {
+                // Safety: In user space; we assume the pointer is valid
+                unsafe { oldact.write(old) };
+            }
+            0
+        }
+        Err(_) => -1, // EINVAL
+    }
+}
+
+/// Examine and change the blocked signal mask
+fn sys_rt_sigprocmask(how: u64, set: *const u64, oldset: *mut u64) -> i64 {
+    if !is_user_ptr(set) || !is_user_ptr(oldset) {
+        return -1; // EFAULT
+    }
+    // Safety: In user space; we assume the pointer is valid
+    let set = (!set.is_null()).then(|| unsafe { set.read() });
+    match signal::sigprocmask(how, set) {
+        Ok(old) => {
+            if !oldset.is_null() {
+                // Safety: In user space; we assume the pointer is valid
+                unsafe { oldset.write(old) };
+            }
+            0
+        }
+        Err(_) => -1, // EINVAL
+    }
+}
+
+/// Fill a buffer with random bytes from the kernel CSPRNG
+fn sys_getrandom(buf: *mut u8, count: usize, _flags: u32) -> i64 {
+    if buf.is_null() || !is_user_range(buf as u64, count as u64) {
+        return -1; // EFAULT
+    }
+    if count == 0 {
+        return 0;
+    }
+
+    // The generator is seeded during boot, so GRND_RANDOM and GRND_NONBLOCK
+    // never have to wait and need no special handling
+    // Safety: In user space; we assume the pointer is valid
+    let buffer = unsafe { core::slice::from_raw_parts_mut(buf, count) };
+    crate::random::fill_bytes(buffer);
+    count as i64
+}
+
+/// Capability handle from a system call argument
+fn cap_handle(handle: u64) -> Option<CapHandle> {
+    CapHandle::new(u32::try_from(handle).ok()?)
+}
+
+/// Whether a `Message` fits at user address `msg`
+fn is_message(msg: u64) -> bool {
+    msg != 0 && is_user_range(msg, size_of::<Message>() as u64)
+}
+
+/// Send the message at `msg` on an endpoint
+fn sys_ipc_send(handle: u64, msg: *const Message) -> i64 {
+    let Some(handle) = cap_handle(handle) else {
+        return -1; // EBADF
+    };
+    if !is_message(msg as u64) {
+        return -1; // EFAULT
+    }
+    // Safety: In user space; we assume the pointer is valid
+    let message = unsafe { msg.read() };
+    match ipc::send(handle, message) {
+        Ok(()) => 0,
+        Err(_) => -1,
+    }
+}
+
+/// Receive a message into `msg`
+///
+/// Returns the reply handle if the sender is waiting for a reply, else 0.
+fn sys_ipc_recv(handle: u64, msg: *mut Message) -> i64 {
+    let Some(handle) = cap_handle(handle) else {
+        return -1; // EBADF
+    };
+    if !is_message(msg as u64) {
+        return -1; // EFAULT
+    }
+    match ipc::recv(handle) {
+        Ok((message, reply)) => {
+            // Safety: We assume the pointer is valid
+            unsafe { msg.write(message) };
+            reply.map_or(0, |reply| reply.as_u32() as i64)
+        }
+        Err(_) => -1,
+    }
+}
+
+/// Send the message at `msg` and overwrite it with the reply
+fn sys_ipc_call(handle: u64, msg: *mut Message) -> i64 {
+    let Some(handle) = cap_handle(handle) else {
+        return -1; // EBADF
+    };
+    if !is_message(msg as u64) {
+        return -1; // EFAULT
+    }
+    // Safety: In user space; we assume the pointer is valid
+    let message = unsafe { msg.read() };
+    match ipc::call(handle, message) {
+        Ok(reply) => {
+            // Safety: We assume the pointer is valid
+            unsafe { msg.write(reply) };
+            0
+        }
+        Err(_) => -1,
+    }
+}
+
+/// Answer a call with the message at `msg`
+fn sys_ipc_reply(handle: u64, msg: *const Message) -> i64 {
+    let Some(handle) = cap_handle(handle) else {
+        return -1; // EBADF
+    };
+    if !is_message(msg as u64) {
+        return -1; // EFAULT
+    }
+    // Safety: In user space; we assume the pointer is valid
+    let message = unsafe { msg.read() };
+    match ipc::reply(handle, message) {
+        Ok(()) => 0,
+        Err(_) => -1,
+    }
+}
+
+/// New handle for a capability with at most `rights`
+fn sys_cap_derive(handle: u64, rights: u64) -> i64 {
+    let Some(handle) = cap_handle(handle) else {
+        return -1; // EBADF
+    };
+    match ipc::derive(handle, Rights::from_bits(rights as u8)) {
+        Ok(handle) => handle.as_u32() as i64,
+        Err(_) => -1,
+    }
+}
+
+/// Drop a capability handle
+fn sys_cap_close(handle: u64) -> i64 {
+    match cap_handle(handle).map(ipc::close) {
+        Some(Ok(())) => 0,
+        _ => -1, // EBADF
+    }
 }
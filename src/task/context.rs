@@ -1,22 +1,38 @@
-/// CPU context for task switching
+/// Full CPU state saved on a thread's stack when it is preempted
+///
+/// `timer_entry` pushes the general purpose registers below the frame the
+/// CPU pushes on interrupt entry, so a pointer to the saved state is just
+/// the thread's stack pointer at that moment.
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
 pub struct TaskContext {
-    // Callee-saved registers (preserved across function calls)
     pub r15: u64,
     pub r14: u64,
     pub r13: u64,
     pub r12: u64,
-    pub rbx: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
     pub rbp: u64,
-    
-    // Stack pointer
-    pub rsp: u64,
-    
-    // Instruction pointer (return address)
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rdx: u64,
+    pub rcx: u64,
+    pub rbx: u64,
+    pub rax: u64,
+
+    // Pushed by the CPU
     pub rip: u64,
+    pub cs: u64,
+    pub rflags: u64,
+    pub rsp: u64,
+    pub ss: u64,
 }
 
+/// RFLAGS for a new thread: interrupts enabled, reserved bit 1 set
+const INITIAL_RFLAGS: u64 = 0x202;
+
 impl TaskContext {
     /// Create a new empty context
     pub const fn new() -> Self {
@@ -25,83 +41,129 @@ impl TaskContext {
             r14: 0,
             r13: 0,
             r12: 0,
-            rbx: 0,
+            r11: 0,
+            r10: 0,
+            r9: 0,
+            r8: 0,
             rbp: 0,
-            rsp: 0,
+            rdi: 0,
+            rsi: 0,
+            rdx: 0,
+            rcx: 0,
+            rbx: 0,
+            rax: 0,
             rip: 0,
+            cs: 0,
+            rflags: 0,
+            rsp: 0,
+            ss: 0,
         }
     }
 
-    /// Initialize a context for a new task
+    /// Initialize a context for a new kernel task
     pub fn init(entry_point: u64, stack_top: u64) -> Self {
+        let selectors = crate::gdt::selectors();
         TaskContext {
-            r15: 0,
-            r14: 0,
-            r13: 0,
-            r12: 0,
-            rbx: 0,
-            rbp: 0,
-            rsp: stack_top,
             rip: entry_point,
+            cs: selectors.code_selector.0 as u64,
+            rflags: INITIAL_RFLAGS,
+            rsp: stack_top,
+            ss: selectors.data_selector.0 as u64,
+            ..TaskContext::new()
         }
     }
 }
 
-/// Switch from the current context to a new context
-/// 
-/// # Safety
-/// This function is unsafe because it directly manipulates CPU registers
-/// and the stack pointer. The caller must ensure that:
-/// - `old_context` points to valid memory where the current context can be saved
-/// - `new_context` points to a valid, initialized context
-/// - The new context's stack is valid and properly aligned
-#[unsafe(naked)]
-pub unsafe extern "C" fn switch_context(old_context: *mut TaskContext, new_context: *const TaskContext) {
-    core::arch::naked_asm!(
-        // Save current context to old_context
-        "mov [rdi + 0x00], r15",
-        "mov [rdi + 0x08], r14",
-        "mov [rdi + 0x10], r13",
-        "mov [rdi + 0x18], r12",
-        "mov [rdi + 0x20], rbx",
-        "mov [rdi + 0x28], rbp",
-        "mov [rdi + 0x30], rsp",
-        
-        // Save return address (rip)
-        "lea rax, [rip + 1f]",
-        "mov [rdi + 0x38], rax",
-        
-        // Load new context from new_context
-        "mov r15, [rsi + 0x00]",
-        "mov r14, [rsi + 0x08]",
-        "mov r13, [rsi + 0x10]",
-        "mov r12, [rsi + 0x18]",
-        "mov rbx, [rsi + 0x20]",
-        "mov rbp, [rsi + 0x28]",
-        "mov rsp, [rsi + 0x30]",
-        
-        // Jump to new task (load rip)
-        "jmp [rsi + 0x38]",
-        
-        // Return point for when we switch back
-        "1:",
-        "ret",
-    )
+/// Software interrupt vector threads use to give up the CPU
+pub const YIELD_VECTOR: u8 = 0x81;
+
+/// Define an interrupt entry that may switch threads
+///
+/// The entry saves the interrupted thread's registers on its stack and hands
+/// the resulting stack pointer to `$handler`, which returns the stack pointer
+/// of the thread to resume. On that thread's stack, pending signals are
+/// delivered if it resumes in ring 3; its registers are then popped and
+/// `iretq` restores RIP, CS, RFLAGS, RSP and SS.
+macro_rules! switching_entry {
+    ($(#[$attr:meta])* $name:ident => $handler:path) => {
+        $(#[$attr])*
+        ///
+        /// # Safety
+        /// Only to be installed in the IDT; it expects the CPU's interrupt
+        /// frame on the stack and must not be called.
+        #[unsafe(naked)]
+        pub unsafe extern "C" fn $name() {
+            core::arch::naked_asm!(
+                "push rax",
+                "push rbx",
+                "push rcx",
+                "push rdx",
+                "push rsi",
+                "push rdi",
+                "push rbp",
+                "push r8",
+                "push r9",
+                "push r10",
+                "push r11",
+                "push r12",
+                "push r13",
+                "push r14",
+                "push r15",
+
+                // The CPU aligned the stack before pushing its 5-word frame, and
+                // we pushed 15 more, so rsp is 16-byte aligned for the call
+                "cld",
+                "mov rdi, rsp",
+                "call {handler}",
+                "mov rsp, rax",
+                "mov rdi, rsp",
+                "call {deliver}",
+
+                "pop r15",
+                "pop r14",
+                "pop r13",
+                "pop r12",
+                "pop r11",
+                "pop r10",
+                "pop r9",
+                "pop r8",
+                "pop rbp",
+                "pop rdi",
+                "pop rsi",
+                "pop rdx",
+                "pop rcx",
+                "pop rbx",
+                "pop rax",
+                "iretq",
+                handler = sym $handler,
+                deliver = sym crate::process::signal::deliver_interrupted,
+            )
+        }
+    };
 }
 
+switching_entry!(
+    /// Timer interrupt entry; preempts the running thread
+    timer_entry => crate::interrupts::timer_interrupt
+);
+
+switching_entry!(
+    /// `int YIELD_VECTOR` entry; the running thread gives up the CPU
+    yield_entry => crate::interrupts::yield_interrupt
+);
+
 /// Entry point wrapper for new tasks
-/// This is called when a task is first scheduled
+///
+/// Calls the entry function in r15 with the argument in r14, then exits
+/// the thread with its return value.
 #[unsafe(naked)]
 pub unsafe extern "C" fn task_entry_wrapper() {
     core::arch::naked_asm!(
-        // The task function pointer is in r15 (we'll set this up when creating tasks)
-        // Call the task function
+        "mov rdi, r14",
         "call r15",
-        
-        // If the task returns, we should mark it as completed
-        // For now, just loop forever (we'll improve this later)
-        "2:",
-        "hlt",
-        "jmp 2b",
+        "mov rdi, rax",
+        "call {exit}",
+        "ud2",
+        exit = sym crate::task::thread::exit_current,
     )
 }
@@ -1,106 +1,398 @@
-use super::{Task, TaskId};
-use alloc::{collections::BTreeMap, sync::Arc, task::Wake};
-use core::task::{Context, Poll, Waker};
-use crossbeam_queue::ArrayQueue;
+//! Async executor running on kernel threads
+//!
+//! An `Executor` is a set of tasks shared by one or more worker threads.
+//! Any thread can poll tasks by calling `run`, or `start` spawns dedicated
+//! worker threads. Idle workers park in the thread scheduler until a task is
+//! woken, so the executor costs no CPU time while nothing is ready.
+//!
+//! Tasks are spawned through a cloneable `Spawner`, from any thread or,
+//! with `Spawner::spawn_task`, from interrupt handlers. The ready queue has
+//! room for every live task, so waking never fails or allocates.
+
+use super::{thread, Task, TaskId};
+use crate::sync::WaitQueue;
+use crate::{cpu, time};
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use alloc::{sync::Arc, task::Wake};
 use conquer_once::spin::OnceCell;
-use spin::Mutex;
+use core::cell::Cell;
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
+use core::task::{Context, Waker};
+use core::time::Duration;
+use crossbeam_queue::ArrayQueue;
+use crate::sync::SpinLock;
+use x86_64::instructions::interrupts;
 
-static EXECUTOR: OnceCell<Mutex<Executor>> = OnceCell::uninit();
+/// Tasks spawned from interrupt handlers that no worker has picked up yet
+const INJECT_QUEUE_CAPACITY: usize = 64;
 
-pub struct Executor {
-    tasks: BTreeMap<TaskId, Task>,
-    task_queue: Arc<ArrayQueue<TaskId>>,
-    waker_cache: BTreeMap<TaskId, Waker>,
+// Task states; a task is in the ready queue only while SCHEDULED
+const IDLE: u8 = 0;
+const SCHEDULED: u8 = 1;
+const RUNNING: u8 = 2;
+/// Woken while being polled; polled again right after
+const NOTIFIED: u8 = 3;
+const DONE: u8 = 4;
+
+crate::kernel_thread_local! {
+    /// Task the worker running on this thread is polling
+    static CURRENT_TASK: Cell<Option<TaskId>> = Cell::new(None);
 }
 
-impl Executor {
-    pub fn new() -> Self {
-        Executor {
-            tasks: BTreeMap::new(),
-            task_queue: Arc::new(ArrayQueue::new(100)),
-            waker_cache: BTreeMap::new(),
+type BoxedFuture = Pin<alloc::boxed::Box<dyn Future<Output = ()> + Send>>;
+
+/// Scheduling state of a task, for debugging
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskState {
+    /// Waiting to be woken
+    Idle,
+    /// Woken, waiting for a worker
+    Ready,
+    /// Being polled
+    Running,
+}
+
+/// Snapshot of a task for debugging
+#[derive(Debug, Clone)]
+pub struct TaskInfo {
+    pub id: TaskId,
+    pub name: Option<String>,
+    pub state: TaskState,
+    /// Times the task was polled
+    pub polls: u64,
+    /// Total time spent in `poll`
+    pub poll_time: Duration,
+    /// Times the task was woken
+    pub wakeups: u64,
+}
+
+struct TaskCell {
+    id: TaskId,
+    name: Option<String>,
+    state: AtomicU8,
+    /// Set by `Spawner::cancel`; the next worker to see it drops the future
+    cancelled: AtomicBool,
+    polls: AtomicU64,
+    /// TSC cycles spent polling
+    poll_cycles: AtomicU64,
+    wakeups: AtomicU64,
+    /// Only locked by the worker that moved the task to RUNNING
+    future: SpinLock<Option<BoxedFuture>>,
+}
+
+impl TaskCell {
+    fn info(&self) -> TaskInfo {
+        let state = match self.state.load(Ordering::Relaxed) {
+            SCHEDULED => TaskState::Ready,
+            RUNNING | NOTIFIED => TaskState::Running,
+            _ => TaskState::Idle,
+        };
+        TaskInfo {
+            id: self.id,
+            name: self.name.clone(),
+            state,
+            polls: self.polls.load(Ordering::Relaxed),
+            poll_time: time::cycles_to_duration(self.poll_cycles.load(Ordering::Relaxed)),
+            wakeups: self.wakeups.load(Ordering::Relaxed),
         }
     }
+}
+
+struct Shared {
+    /// Locked with interrupts disabled
+    tasks: SpinLock<BTreeMap<TaskId, (Arc<TaskCell>, Waker)>>,
+    /// Locked with interrupts disabled; room for every task in `tasks` is
+    /// reserved on insertion, so pushing never allocates
+    ready: SpinLock<VecDeque<Arc<TaskCell>>>,
+    /// Spawned from interrupt context, inserted into `tasks` by a worker
+    injected: ArrayQueue<Task>,
+    /// Workers with nothing to do
+    idle_workers: WaitQueue,
+}
+
+impl Shared {
+    fn has_work(&self) -> bool {
+        let ready = interrupts::without_interrupts(|| !self.ready.lock().is_empty());
+        ready || !self.injected.is_empty()
+    }
+
+    /// Make a task known to the workers and schedule its first poll
+    fn insert(self: &Arc<Self>, task: Task) {
+        let cell = Arc::new(TaskCell {
+            id: task.id,
+            name: task.name,
+            state: AtomicU8::new(SCHEDULED),
+            cancelled: AtomicBool::new(false),
+            polls: AtomicU64::new(0),
+            poll_cycles: AtomicU64::new(0),
+            wakeups: AtomicU64::new(0),
+            future: SpinLock::new(Some(task.future)),
+        });
+        let waker = Waker::from(Arc::new(TaskWaker {
+            cell: cell.clone(),
+            shared: self.clone(),
+        }));
+
+        let previous = interrupts::without_interrupts(|| {
+            let mut tasks = self.tasks.lock();
+            let previous = tasks.insert(cell.id, (cell.clone(), waker));
+            let mut ready = self.ready.lock();
+            let unused = tasks.len().saturating_sub(ready.len());
+            ready.reserve(unused);
+            previous
+        });
+        assert!(previous.is_none(), "task with same ID already in tasks");
+        self.schedule(cell);
+    }
 
-    pub fn spawn(&mut self, task: Task) {
-        let task_id = task.id;
-        if self.tasks.insert(task.id, task).is_some() {
-            panic!("task with same ID already in tasks");
+    /// Queue a task that just became SCHEDULED; safe in interrupt handlers
+    fn schedule(&self, cell: Arc<TaskCell>) {
+        interrupts::without_interrupts(|| self.ready.lock().push_back(cell));
+        self.idle_workers.notify_one();
+    }
+
+    fn pop_ready(&self) -> Option<Arc<TaskCell>> {
+        interrupts::without_interrupts(|| self.ready.lock().pop_front())
+    }
+
+    /// Move tasks spawned by interrupt handlers into the task map
+    fn take_injected(self: &Arc<Self>) {
+        while let Some(task) = self.injected.pop() {
+            self.insert(task);
         }
-        self.task_queue.push(task_id).expect("queue full");
-        super::scheduler::add_task(task_id);
     }
 
-    pub fn run(&mut self) -> ! {
-        loop {
-            self.run_ready_tasks();
-            self.sleep_if_idle();
+    /// Poll every ready task once
+    fn run_ready_tasks(self: &Arc<Self>) {
+        self.take_injected();
+        while let Some(cell) = self.pop_ready() {
+            self.poll(&cell);
         }
     }
 
-    fn run_ready_tasks(&mut self) {
-        // destructure `self` to avoid borrow checker errors
-        let Self {
-            tasks,
-            task_queue,
-            waker_cache,
-        } = self;
+    fn poll(&self, cell: &Arc<TaskCell>) {
+        let waker = interrupts::without_interrupts(|| {
+            self.tasks.lock().get(&cell.id).map(|(_, waker)| waker.clone())
+        });
+        let Some(waker) = waker else {
+            return;
+        };
+
+        loop {
+            cell.state.store(RUNNING, Ordering::Release);
+            let mut context = Context::from_waker(&waker);
+            let mut future = cell.future.lock();
+            let Some(running) = future.as_mut() else {
+                return;
+            };
 
-        while let Some(task_id) = task_queue.pop() {
-            let task = match tasks.get_mut(&task_id) {
-                Some(task) => task,
-                None => continue, // task no longer exists
+            let finished = cell.cancelled.load(Ordering::Acquire) || {
+                let start = cpu::rdtsc();
+                CURRENT_TASK.with(|current| current.set(Some(cell.id)));
+                let poll = running.as_mut().poll(&mut context);
+                CURRENT_TASK.with(|current| current.set(None));
+                cell.polls.fetch_add(1, Ordering::Relaxed);
+                cell.poll_cycles.fetch_add(cpu::rdtsc().wrapping_sub(start), Ordering::Relaxed);
+                poll.is_ready()
             };
-            let waker = waker_cache
-                .entry(task_id)
-                .or_insert_with(|| TaskWaker::new(task_id, task_queue.clone()));
-            let mut context = Context::from_waker(waker);
-            match task.poll(&mut context) {
-                Poll::Ready(()) => {
-                    // task done -> remove it and its cached waker
-                    tasks.remove(&task_id);
-                    waker_cache.remove(&task_id);
-                    super::scheduler::mark_completed();
-                }
-                Poll::Pending => {}
+
+            if finished {
+                cell.state.store(DONE, Ordering::Release);
+                // The future and the map entry are freed outside any lock
+                let finished = future.take();
+                drop(future);
+                drop(finished);
+                let entry = interrupts::without_interrupts(|| self.tasks.lock().remove(&cell.id));
+                drop(entry);
+                return;
+            }
+            drop(future);
+
+            // Woken while it ran: poll again instead of requeueing
+            if cell
+                .state
+                .compare_exchange(RUNNING, IDLE, Ordering::AcqRel, Ordering::Acquire)
+                .is_ok()
+            {
+                return;
             }
         }
     }
+}
 
-    fn sleep_if_idle(&mut self) {
-        use x86_64::instructions::interrupts::{self, enable_and_hlt};
+pub struct Executor {
+    shared: Arc<Shared>,
+}
+
+impl Default for Executor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
-        interrupts::disable();
-        if self.task_queue.is_empty() {
-            enable_and_hlt();
-        } else {
-            interrupts::enable();
+impl Executor {
+    pub fn new() -> Self {
+        Executor {
+            shared: Arc::new(Shared {
+                tasks: SpinLock::new(BTreeMap::new()),
+                ready: SpinLock::new(VecDeque::new()),
+                injected: ArrayQueue::new(INJECT_QUEUE_CAPACITY),
+                idle_workers: WaitQueue::new(),
+            }),
         }
     }
 
-    /// Wake a specific task
-    pub fn wake_task(&mut self, task_id: TaskId) {
-        if self.tasks.contains_key(&task_id) {
-            self.task_queue.push(task_id).ok();
+    pub fn spawn(&self, task: Task) {
+        self.shared.insert(task);
+    }
+
+    /// Handle for spawning tasks onto this executor
+    pub fn spawner(&self) -> Spawner {
+        Spawner { shared: self.shared.clone() }
+    }
+
+    /// Number of tasks that have not finished
+    pub fn task_count(&self) -> usize {
+        interrupts::without_interrupts(|| self.shared.tasks.lock().len())
+    }
+
+    /// Describe every live task
+    pub fn list(&self) -> Vec<TaskInfo> {
+        self.spawner().list()
+    }
+
+    /// Poll tasks on the running thread forever
+    pub fn run(&self) -> ! {
+        let shared = &self.shared;
+        loop {
+            shared.run_ready_tasks();
+            shared.idle_workers.wait_until(|| shared.has_work());
+        }
+    }
+
+    /// Run the executor on `workers` new kernel threads
+    pub fn start(self, workers: usize) -> Spawner {
+        let spawner = self.spawner();
+        let executor = Arc::new(self);
+        for n in 0..workers.max(1) {
+            let executor = executor.clone();
+            let handle = thread::Builder::new()
+                .name(&format!("executor-{}", n))
+                .spawn(move || run_worker(&executor));
+            // Workers never exit; nobody joins them
+            drop(handle);
         }
+        spawner
     }
 }
 
-struct TaskWaker {
-    task_id: TaskId,
-    task_queue: Arc<ArrayQueue<TaskId>>,
+fn run_worker(executor: &Executor) {
+    executor.run()
 }
 
-impl TaskWaker {
-    fn new(task_id: TaskId, task_queue: Arc<ArrayQueue<TaskId>>) -> Waker {
-        Waker::from(Arc::new(TaskWaker {
-            task_id,
-            task_queue,
-        }))
+/// Cloneable handle for spawning tasks onto an `Executor`
+#[derive(Clone)]
+pub struct Spawner {
+    shared: Arc<Shared>,
+}
+
+impl Spawner {
+    /// Spawn a future as a new task
+    ///
+    /// Allocates, so it must not be used in interrupt handlers; see
+    /// `spawn_task`.
+    pub fn spawn(&self, future: impl Future<Output = ()> + Send + 'static) -> TaskId {
+        let task = Task::new(future);
+        let id = task.id;
+        self.shared.insert(task);
+        id
+    }
+
+    /// Like `spawn`, with a name shown in task listings
+    pub fn spawn_named(&self, name: &str, future: impl Future<Output = ()> + Send + 'static) -> TaskId {
+        let task = Task::named(name, future);
+        let id = task.id;
+        self.shared.insert(task);
+        id
+    }
+
+    /// Spawn an already allocated task
+    ///
+    /// Safe in interrupt handlers: the task is queued without allocating
+    /// and picked up by a worker. Gives the task back if the queue is full.
+    pub fn spawn_task(&self, task: Task) -> Result<TaskId, Task> {
+        let id = task.id;
+        self.shared.injected.push(task)?;
+        self.shared.idle_workers.notify_one();
+        Ok(id)
+    }
+
+    /// Stop a task: its future is dropped instead of being polled again
+    ///
+    /// Returns false if there is no such live task.
+    pub fn cancel(&self, id: TaskId) -> bool {
+        let waker = interrupts::without_interrupts(|| {
+            self.shared.tasks.lock().get(&id).map(|(cell, waker)| {
+                cell.cancelled.store(true, Ordering::Release);
+                waker.clone()
+            })
+        });
+        match waker {
+            Some(waker) => {
+                waker.wake();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Describe one live task
+    pub fn info(&self, id: TaskId) -> Option<TaskInfo> {
+        let cell = interrupts::without_interrupts(|| {
+            self.shared.tasks.lock().get(&id).map(|(cell, _)| cell.clone())
+        });
+        cell.map(|cell| cell.info())
+    }
+
+    /// Describe every live task
+    pub fn list(&self) -> Vec<TaskInfo> {
+        let cells: Vec<Arc<TaskCell>> = interrupts::without_interrupts(|| {
+            self.shared.tasks.lock().values().map(|(cell, _)| cell.clone()).collect()
+        });
+        cells.iter().map(|cell| cell.info()).collect()
     }
+}
+
+struct TaskWaker {
+    cell: Arc<TaskCell>,
+    shared: Arc<Shared>,
+}
 
+impl TaskWaker {
+    /// Never allocates, so tasks may be woken from interrupt handlers
     fn wake_task(&self) {
-        self.task_queue.push(self.task_id).expect("task_queue full");
+        let state = &self.cell.state;
+        let mut current = state.load(Ordering::Acquire);
+        loop {
+            let next = match current {
+                IDLE => SCHEDULED,
+                RUNNING => NOTIFIED,
+                _ => return,
+            };
+            match state.compare_exchange(current, next, Ordering::AcqRel, Ordering::Acquire) {
+                Ok(_) => break,
+                Err(actual) => current = actual,
+            }
+        }
+        self.cell.wakeups.fetch_add(1, Ordering::Relaxed);
+        if current == IDLE {
+            self.shared.schedule(self.cell.clone());
+        }
     }
 }
 
@@ -114,19 +406,44 @@ impl Wake for TaskWaker {
     }
 }
 
-/// Initialize the global executor
-pub fn init(executor: Executor) {
-    EXECUTOR.init_once(|| Mutex::new(executor));
+static SPAWNER: OnceCell<Spawner> = OnceCell::uninit();
+
+/// Start the global executor on `workers` kernel threads
+pub fn init(workers: usize) {
+    SPAWNER.init_once(|| Executor::new().start(workers));
 }
 
-/// Get a reference to the global executor
-pub fn get_executor() -> Option<&'static Mutex<Executor>> {
-    EXECUTOR.try_get().ok()
+/// Spawner of the global executor, `None` before `init`
+pub fn spawner() -> Option<&'static Spawner> {
+    SPAWNER.try_get().ok()
 }
 
-/// Wake a task from an interrupt handler
-pub fn wake_task_from_interrupt(task_id: TaskId) {
-    if let Some(executor) = get_executor() {
-        executor.lock().wake_task(task_id);
-    }
+/// Spawn a future on the global executor
+pub fn spawn(future: impl Future<Output = ()> + Send + 'static) -> TaskId {
+    spawner().expect("executor not initialized").spawn(future)
+}
+
+/// Spawn a named future on the global executor
+pub fn spawn_named(name: &str, future: impl Future<Output = ()> + Send + 'static) -> TaskId {
+    spawner().expect("executor not initialized").spawn_named(name, future)
+}
+
+/// Task being polled on the running thread, of any executor
+pub fn current_task() -> Option<TaskId> {
+    CURRENT_TASK.with(Cell::get)
+}
+
+/// Describe a live task of the global executor
+pub fn info(id: TaskId) -> Option<TaskInfo> {
+    spawner().and_then(|spawner| spawner.info(id))
+}
+
+/// Describe every live task of the global executor
+pub fn list() -> Vec<TaskInfo> {
+    spawner().map(Spawner::list).unwrap_or_default()
+}
+
+/// Cancel a task of the global executor
+pub fn cancel(id: TaskId) -> bool {
+    spawner().is_some_and(|spawner| spawner.cancel(id))
 }
@@ -1,47 +1,178 @@
-use super::{TaskId, context::TaskContext};
+use super::{TaskId, context::TaskContext, local::{self, LocalStorage}};
+use crate::fpu::FpuState;
+use alloc::boxed::Box;
+use alloc::string::String;
 use alloc::vec::Vec;
+use core::mem::size_of;
+use x86_64::registers::model_specific::FsBase;
+use x86_64::VirtAddr;
 
-const KERNEL_STACK_SIZE: usize = 4096 * 4; // 16 KiB stack per task
+/// Default stack size of a kernel thread
+pub const KERNEL_STACK_SIZE: usize = 4096 * 4; // 16 KiB stack per task
+
+/// Smallest stack a kernel thread may be given
+pub const MIN_STACK_SIZE: usize = 4096;
+
+/// Priority of threads that don't ask for one (0 is highest)
+pub const DEFAULT_PRIORITY: u8 = 1;
+
+/// Lifecycle state of a kernel thread
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThreadState {
+    /// Waiting for its turn on the CPU
+    Ready,
+    /// Currently on the CPU
+    Running,
+    /// Waiting for another thread (e.g. in `JoinHandle::join`)
+    Blocked,
+    /// Waiting for a timer (`thread::sleep`)
+    Sleeping,
+    /// Exited; kept until its exit value is collected or it is detached
+    Zombie,
+}
 
 /// A kernel thread that uses context switching (not async/await)
 pub struct KernelThread {
     pub id: TaskId,
-    pub context: TaskContext,
-    stack: Vec<u8>,
+    /// Optional name, for debugging
+    pub name: Option<String>,
+    pub state: ThreadState,
+    /// Base priority handed to the scheduling policy (0 is highest)
+    pub priority: u8,
+    /// Timer ticks spent running
+    pub cpu_ticks: u64,
+    /// Times the thread was switched to
+    pub switches: u64,
+    /// TSC cycles spent running, up to its last switch away
+    pub run_cycles: u64,
+    /// Times the thread was woken from `Blocked` or `Sleeping`
+    pub wakeups: u64,
+    /// Stack pointer at which the thread's `TaskContext` is saved
+    pub saved_rsp: u64,
+    /// Stack the CPU switches to on a trap from ring 3
+    pub kernel_stack_top: VirtAddr,
+    /// Saved x87/SSE/AVX registers; `None` for the boot thread, which
+    /// uses the FPU module's boot area
+    pub fpu: Option<FpuState>,
+    /// FS base, the TLS pointer user code sets with `arch_prctl`
+    pub fs_base: VirtAddr,
+    /// Kernel thread-locals, reached through GS base while the thread runs
+    locals: Box<LocalStorage>,
+    /// Value passed to `thread::exit`, set once the thread is a zombie
+    pub exit_value: Option<u64>,
+    /// Thread blocked in `join` on this one
+    pub joiner: Option<TaskId>,
+    /// No `JoinHandle` is left, so the thread is reaped as soon as it exits
+    pub detached: bool,
+    /// `None` for the boot thread, which runs on the bootloader's stack
+    stack: Option<Vec<u8>>,
 }
 
 impl KernelThread {
     /// Create a new kernel thread
     pub fn new(entry_point: extern "C" fn()) -> Self {
-        let mut stack = Vec::with_capacity(KERNEL_STACK_SIZE);
-        stack.resize(KERNEL_STACK_SIZE, 0);
-        
+        Self::with_argument(run_void, entry_point as usize as u64)
+    }
+
+    /// Create a thread that runs `entry(argument)` and exits with its result
+    pub fn with_argument(entry: extern "C" fn(u64) -> u64, argument: u64) -> Self {
+        Self::with_stack_size(entry, argument, KERNEL_STACK_SIZE)
+    }
+
+    /// Like `with_argument`, with a stack of `stack_size` bytes
+    pub fn with_stack_size(entry: extern "C" fn(u64) -> u64, argument: u64, stack_size: usize) -> Self {
+        let stack_size = stack_size.max(MIN_STACK_SIZE);
+        let mut stack = Vec::with_capacity(stack_size);
+        stack.resize(stack_size, 0);
+
         // Stack grows downward, so the top is at the end
-        let stack_top = stack.as_ptr() as u64 + KERNEL_STACK_SIZE as u64;
-        
-        // Initialize context with entry point
+        let stack_top = (stack.as_ptr() as u64 + stack_size as u64) & !0xF;
+
+        // Start in task_entry_wrapper, which calls r15 with r14 as argument
         let mut context = TaskContext::init(
-            entry_point as u64,
+            super::context::task_entry_wrapper as *const () as u64,
             stack_top
         );
-        
-        // Set r15 to the entry point for task_entry_wrapper
-        context.r15 = entry_point as u64;
-        
-        // Set rip to task_entry_wrapper
-        context.rip = super::context::task_entry_wrapper as *const () as u64;
-        
+        context.r15 = entry as u64;
+        context.r14 = argument;
+
+        // Place the context where timer_entry would have saved it, so the
+        // first switch to this thread looks like returning from an interrupt
+        let saved_rsp = stack_top - size_of::<TaskContext>() as u64;
+        unsafe {
+            (saved_rsp as *mut TaskContext).write(context);
+        }
+
         KernelThread {
             id: TaskId::new(),
-            context,
-            stack, // Stack is kept alive for the lifetime of the thread
+            name: None,
+            state: ThreadState::Ready,
+            priority: DEFAULT_PRIORITY,
+            cpu_ticks: 0,
+            switches: 0,
+            run_cycles: 0,
+            wakeups: 0,
+            saved_rsp,
+            kernel_stack_top: VirtAddr::new(stack_top),
+            fpu: Some(FpuState::new()),
+            fs_base: VirtAddr::zero(),
+            locals: Box::new(LocalStorage::new()),
+            exit_value: None,
+            joiner: None,
+            detached: false,
+            stack: Some(stack), // Stack is kept alive for the lifetime of the thread
         }
     }
-    
+
+    /// The context `kernel_main` runs in, adopted when the first thread is added
+    pub(crate) fn boot() -> Self {
+        KernelThread {
+            id: TaskId::new(),
+            name: Some(String::from("boot")),
+            state: ThreadState::Running,
+            priority: DEFAULT_PRIORITY,
+            cpu_ticks: 0,
+            switches: 0,
+            run_cycles: 0,
+            wakeups: 0,
+            saved_rsp: 0,
+            kernel_stack_top: VirtAddr::zero(),
+            fpu: None,
+            fs_base: FsBase::read(),
+            locals: local::take_boot_storage(),
+            exit_value: None,
+            joiner: None,
+            detached: true,
+            stack: None,
+        }
+    }
+
     /// Get the task ID
     pub fn id(&self) -> TaskId {
         self.id
     }
+
+    /// Value for GS base while the thread runs
+    pub(crate) fn locals_base(&self) -> VirtAddr {
+        self.locals.base()
+    }
+
+    /// Size of the thread's own stack (0 for the boot thread)
+    pub fn stack_size(&self) -> usize {
+        self.stack.as_ref().map_or(0, |stack| stack.len())
+    }
+
+    /// Save area to hand to `fpu::switch_to`
+    pub(crate) fn fpu_area(&self) -> *mut u8 {
+        self.fpu.as_ref().map_or(core::ptr::null_mut(), |fpu| fpu.as_ptr())
+    }
+}
+
+/// Adapter for entry points that take no argument and return nothing
+extern "C" fn run_void(entry: u64) -> u64 {
+    let entry: extern "C" fn() = unsafe { core::mem::transmute(entry as usize) };
+    entry();
+    0
 }
 
 // Implement new() for TaskId to make it accessible from kernel_thread
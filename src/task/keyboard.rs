@@ -1,86 +0,0 @@
-use crate::print;
-use core::{
-    pin::Pin,
-    task::{Context, Poll},
-};
-use futures_util::stream::{Stream, StreamExt};
-use pc_keyboard::{layouts, DecodedKey, HandleControl, Keyboard, ScancodeSet1};
-use crossbeam_queue::ArrayQueue;
-use crate::println;
-use conquer_once::spin::OnceCell;
-use core::task::Waker;
-use spin::Mutex;
-
-static SCANCODE_QUEUE: OnceCell<ArrayQueue<u8>> = OnceCell::uninit();
-static WAKER: Mutex<Option<Waker>> = Mutex::new(None);
-
-/// Called by the keyboard interrupt handler
-///
-/// Must not block or allocate.
-pub(crate) fn add_scancode(scancode: u8) {
-    if let Ok(queue) = SCANCODE_QUEUE.try_get() {
-        if let Err(_) = queue.push(scancode) {
-            println!("WARNING: scancode queue full; dropping keyboard input");
-        } else {
-            if let Some(waker) = WAKER.lock().as_ref() {
-                waker.wake_by_ref();
-            }
-        }
-    } else {
-        println!("WARNING: scancode queue uninitialized");
-    }
-}
-
-pub struct ScancodeStream {
-    _private: (),
-}
-
-impl ScancodeStream {
-    pub fn new() -> Self {
-        SCANCODE_QUEUE.try_init_once(|| ArrayQueue::new(100))
-            .expect("ScancodeStream::new should only be called once");
-        ScancodeStream { _private: () }
-    }
-}
-
-impl Stream for ScancodeStream {
-    type Item = u8;
-
-    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<u8>> {
-        let queue = SCANCODE_QUEUE.try_get().expect("not initialized");
-
-        // fast path
-        if let Some(scancode) = queue.pop() {
-            return Poll::Ready(Some(scancode));
-        }
-
-        let mut waker = WAKER.lock();
-        match waker.as_ref() {
-            Some(w) if w.will_wake(cx.waker()) => {}
-            _ => *waker = Some(cx.waker().clone()),
-        }
-
-        if let Some(scancode) = queue.pop() {
-            Poll::Ready(Some(scancode))
-        } else {
-            Poll::Pending
-        }
-    }
-}
-
-pub async fn print_keypresses() {
-    let mut scancodes = ScancodeStream::new();
-    let mut keyboard = Keyboard::new(ScancodeSet1::new(), layouts::Us104Key,
-        HandleControl::MapLettersToUnicode);
-
-    while let Some(scancode) = scancodes.next().await {
-        if let Ok(Some(key_event)) = keyboard.add_byte(scancode) {
-            if let Some(key) = keyboard.process_keyevent(key_event) {
-                match key {
-                    DecodedKey::Unicode(character) => print!("{}", character),
-                    DecodedKey::RawKey(key) => print!("{:?}", key),
-                }
-            }
-        }
-    }
-}
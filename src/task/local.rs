@@ -0,0 +1,176 @@
+//! Per-thread storage for kernel threads
+//!
+//! Every kernel thread owns a `LocalStorage` area whose address the context
+//! switch loads into the GS base register, so finding the running thread's
+//! data is a single MSR read with no scheduler lock. Values are declared
+//! with [`kernel_thread_local!`](crate::kernel_thread_local) and reached
+//! through their [`LocalKey`]:
+//!
+//! ```ignore
+//! kernel_thread_local! {
+//!     static COUNTER: Cell<u32> = Cell::new(0);
+//! }
+//!
+//! COUNTER.with(|counter| counter.set(counter.get() + 1));
+//! ```
+//!
+//! Each thread's value is created on its first access and dropped when the
+//! thread is reaped, on whichever thread reaps it, hence the `Send` bound.
+//! The first access allocates, so interrupt handlers must only touch
+//! values the interrupted thread has already initialised.
+//!
+//! User threads set FS for their own TLS with `arch_prctl`; GS belongs to
+//! the kernel. Ring 3 code can still clobber the GS base by loading a GS
+//! selector, so the context switch also records the base, and accesses
+//! restore it from there if it has changed since.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::any::Any;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use x86_64::instructions::interrupts;
+use x86_64::registers::model_specific::GsBase;
+use x86_64::VirtAddr;
+
+/// Thread-local values of one kernel thread, indexed by key slot
+#[derive(Default)]
+pub struct LocalStorage {
+    values: Vec<Option<Box<dyn Any + Send>>>,
+}
+
+impl LocalStorage {
+    pub const fn new() -> Self {
+        LocalStorage { values: Vec::new() }
+    }
+
+    /// Address loaded into GS base while the owning thread runs
+    pub(crate) fn base(&self) -> VirtAddr {
+        VirtAddr::from_ptr(self)
+    }
+
+    fn get(&self, slot: usize) -> Option<&(dyn Any + Send)> {
+        self.values.get(slot)?.as_deref()
+    }
+}
+
+/// GS base of the running thread, 0 until threading starts
+static CURRENT_BASE: AtomicU64 = AtomicU64::new(0);
+
+/// Load the storage at `base` for the thread being switched to
+pub(crate) fn switch_to(base: VirtAddr) {
+    assert!(!base.is_null(), "kernel thread without thread-local storage");
+    CURRENT_BASE.store(base.as_u64(), Ordering::Relaxed);
+    GsBase::write(base);
+}
+
+/// Storage of the running thread
+///
+/// Before the scheduler adopts the boot thread, the boot context gets a
+/// storage area of its own, which `take_boot_storage` hands over. Once
+/// threads run, the base the context switch recorded is authoritative.
+fn current_storage() -> *mut LocalStorage {
+    interrupts::without_interrupts(|| {
+        let base = GsBase::read();
+        let current = CURRENT_BASE.load(Ordering::Relaxed);
+        if current != 0 {
+            if base.as_u64() != current {
+                // Clobbered by a GS load in ring 3
+                GsBase::write(VirtAddr::new(current));
+            }
+            return current as *mut LocalStorage;
+        }
+        if !base.is_null() {
+            return base.as_mut_ptr();
+        }
+        let storage = Box::into_raw(Box::new(LocalStorage::new()));
+        GsBase::write(VirtAddr::from_ptr(storage));
+        storage
+    })
+}
+
+/// Storage of the boot context, for the boot thread to own
+pub(crate) fn take_boot_storage() -> Box<LocalStorage> {
+    // Safety: A non-null GS base before threading is the area leaked by
+    // `current_storage`, and only the boot thread ever adopts it
+    unsafe { Box::from_raw(current_storage()) }
+}
+
+/// Slots handed out to keys; 0 means "not assigned yet"
+static NEXT_SLOT: AtomicUsize = AtomicUsize::new(1);
+
+/// Handle to a kernel thread-local value, see [`kernel_thread_local!`](crate::kernel_thread_local)
+pub struct LocalKey<T: Send + 'static> {
+    slot: AtomicUsize,
+    init: fn() -> T,
+}
+
+impl<T: Send + 'static> LocalKey<T> {
+    #[doc(hidden)]
+    pub const fn new(init: fn() -> T) -> Self {
+        LocalKey { slot: AtomicUsize::new(0), init }
+    }
+
+    fn slot(&self) -> usize {
+        let slot = self.slot.load(Ordering::Acquire);
+        if slot != 0 {
+            return slot - 1;
+        }
+        let new = NEXT_SLOT.fetch_add(1, Ordering::Relaxed);
+        match self.slot.compare_exchange(0, new, Ordering::AcqRel, Ordering::Acquire) {
+            Ok(_) => new - 1,
+            Err(existing) => existing - 1,
+        }
+    }
+
+    /// Run `f` with the running thread's value, creating it if needed
+    ///
+    /// Like `std::thread::LocalKey`, this only hands out shared references;
+    /// use `Cell` or `RefCell` for values that change.
+    pub fn with<R>(&'static self, f: impl FnOnce(&T) -> R) -> R {
+        let slot = self.slot();
+        let storage = current_storage();
+
+        // Safety: Only the running thread uses its storage. Values are
+        // boxed, so references to them stay valid while `values` grows,
+        // and they are only dropped when the thread is reaped.
+        let existing = unsafe { (*storage).get(slot).map(|value| value as *const (dyn Any + Send)) };
+        let value: *const (dyn Any + Send) = match existing {
+            Some(value) => value,
+            None => {
+                // `init` may itself use thread-locals, so no borrow of the
+                // storage is held while it runs
+                let value: Box<dyn Any + Send> = Box::new((self.init)());
+                interrupts::without_interrupts(|| unsafe {
+                    let values = &mut (*storage).values;
+                    if values.len() <= slot {
+                        values.resize_with(slot + 1, || None);
+                    }
+                    values[slot].get_or_insert(value).as_ref() as *const _
+                })
+            }
+        };
+
+        // Safety: See above; the slot of this key only ever holds a `T`
+        let value = unsafe { &*value }.downcast_ref::<T>().expect("thread-local slot holds another type");
+        f(value)
+    }
+}
+
+/// Declare kernel thread-local values
+///
+/// Works like `std::thread_local!`: each static becomes a [`LocalKey`]
+/// and every kernel thread sees its own value, created on first use.
+#[macro_export]
+macro_rules! kernel_thread_local {
+    ($($(#[$attr:meta])* $vis:vis static $name:ident: $ty:ty = $init:expr;)+) => {
+        $(
+            $(#[$attr])*
+            $vis static $name: $crate::task::local::LocalKey<$ty> = {
+                fn init() -> $ty {
+                    $init
+                }
+                $crate::task::local::LocalKey::new(init)
+            };
+        )+
+    };
+}
@@ -1,17 +1,20 @@
 use core::{future::Future, pin::Pin};
 use alloc::boxed::Box;
-use core::task::{Context, Poll};
+use alloc::string::String;
 
 pub mod executor;
-pub mod keyboard;
-pub mod scheduler;
 pub mod context;
+pub mod local;
 pub mod kernel_thread;
 pub mod thread_scheduler;
+pub mod thread;
+pub mod policy;
 pub mod test_threads;
 
 pub struct Task {
     pub id: TaskId,
+    /// Optional name, for debugging
+    pub name: Option<String>,
     future: Pin<Box<dyn Future<Output = ()> + Send>>,
 }
 
@@ -19,14 +22,26 @@ impl Task {
     pub fn new(future: impl Future<Output = ()> + Send + 'static) -> Task {
         Task {
             id: TaskId::new(),
+            name: None,
             future: Box::pin(future),
         }
     }
 
-    pub(crate) fn poll(&mut self, context: &mut Context) -> Poll<()> {
-        self.future.as_mut().poll(context)
+    /// Create a task shown as `name` in task listings
+    pub fn named(name: &str, future: impl Future<Output = ()> + Send + 'static) -> Task {
+        Task {
+            name: Some(String::from(name)),
+            ..Task::new(future)
+        }
     }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct TaskId(u64);
+
+impl TaskId {
+    /// The raw numeric ID
+    pub fn as_u64(self) -> u64 {
+        self.0
+    }
+}
@@ -0,0 +1,141 @@
+//! Multilevel feedback queue
+//!
+//! Threads start at their base priority level. Using up a level's time
+//! slice demotes a thread one level; giving up the CPU early does not reset
+//! the slice, so a thread can't stay on top by yielding just before it
+//! expires. Every `BOOST_INTERVAL` ticks all threads return to their base
+//! level so CPU-bound threads cannot starve.
+
+use super::{ReadyReason, SchedulingPolicy};
+use crate::task::TaskId;
+use alloc::collections::{BTreeMap, VecDeque};
+
+/// Number of priority levels; 0 is the highest
+pub const LEVELS: usize = 4;
+
+/// Timer ticks a thread may run at each level before it is demoted
+const TIME_SLICES: [u64; LEVELS] = [1, 2, 4, 8];
+
+/// Timer ticks between priority boosts
+const BOOST_INTERVAL: u64 = 50;
+
+struct Entry {
+    base: u8,
+    level: u8,
+    /// Ticks used at the current level
+    used: u64,
+    queued: bool,
+}
+
+pub struct Mlfq {
+    queues: [VecDeque<TaskId>; LEVELS],
+    entries: BTreeMap<TaskId, Entry>,
+    since_boost: u64,
+}
+
+impl Mlfq {
+    pub fn new() -> Self {
+        Mlfq {
+            queues: Default::default(),
+            entries: BTreeMap::new(),
+            since_boost: 0,
+        }
+    }
+
+    /// Move every thread back to its base level
+    fn boost(&mut self) {
+        for entry in self.entries.values_mut() {
+            entry.level = entry.base;
+            entry.used = 0;
+        }
+
+        // Rotating each queue through the target queues keeps FIFO order;
+        // capacity for every thread is reserved in `add`
+        for level in 0..LEVELS {
+            for _ in 0..self.queues[level].len() {
+                let id = self.queues[level].pop_front().unwrap();
+                let target = self.entries.get(&id).map_or(level, |e| e.level as usize);
+                self.queues[target].push_back(id);
+            }
+        }
+    }
+}
+
+impl Default for Mlfq {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SchedulingPolicy for Mlfq {
+    fn name(&self) -> &'static str {
+        "mlfq"
+    }
+
+    fn add(&mut self, id: TaskId, priority: u8) {
+        let base = priority.min(LEVELS as u8 - 1);
+        self.entries.insert(id, Entry { base, level: base, used: 0, queued: false });
+
+        let threads = self.entries.len();
+        for queue in self.queues.iter_mut() {
+            queue.reserve(threads.saturating_sub(queue.len()));
+        }
+    }
+
+    fn remove(&mut self, id: TaskId) {
+        if let Some(entry) = self.entries.remove(&id) {
+            if entry.queued {
+                self.queues[entry.level as usize].retain(|&queued| queued != id);
+            }
+        }
+    }
+
+    fn make_ready(&mut self, id: TaskId, _reason: ReadyReason) {
+        let Some(entry) = self.entries.get_mut(&id) else {
+            return;
+        };
+        if !entry.queued {
+            entry.queued = true;
+            self.queues[entry.level as usize].push_back(id);
+        }
+    }
+
+    fn pick_next(&mut self) -> Option<TaskId> {
+        let id = self.queues.iter_mut().find_map(|queue| queue.pop_front())?;
+        if let Some(entry) = self.entries.get_mut(&id) {
+            entry.queued = false;
+        }
+        Some(id)
+    }
+
+    fn has_ready(&self) -> bool {
+        self.queues.iter().any(|queue| !queue.is_empty())
+    }
+
+    fn tick(&mut self, current: TaskId) -> bool {
+        self.since_boost += 1;
+        if self.since_boost >= BOOST_INTERVAL {
+            self.since_boost = 0;
+            self.boost();
+        }
+
+        let Some(entry) = self.entries.get_mut(&current) else {
+            return true;
+        };
+        let level = entry.level as usize;
+
+        entry.used += 1;
+        if entry.used >= TIME_SLICES[level] {
+            entry.level = (level + 1).min(LEVELS - 1) as u8;
+            entry.used = 0;
+            return true;
+        }
+
+        // A higher priority thread became ready
+        self.queues[..level].iter().any(|queue| !queue.is_empty())
+    }
+
+    fn priority(&self, id: TaskId) -> Option<u8> {
+        self.entries.get(&id).map(|entry| entry.level)
+    }
+}
@@ -0,0 +1,56 @@
+//! Pluggable thread scheduling policies
+//!
+//! `ThreadScheduler` owns thread state and does the switching; a policy only
+//! decides which ready thread runs next and when the running one is
+//! preempted. Most methods run in interrupt context with the scheduler
+//! locked, so only `add` and `remove` may allocate or free memory.
+
+pub mod mlfq;
+pub mod round_robin;
+
+use super::TaskId;
+
+pub use mlfq::Mlfq;
+pub use round_robin::RoundRobin;
+
+/// Why a thread was handed to `SchedulingPolicy::make_ready`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadyReason {
+    /// Just spawned
+    New,
+    /// Preempted by the timer
+    Preempted,
+    /// Gave up the CPU voluntarily
+    Yielded,
+    /// Woken from blocking or sleeping
+    Woken,
+}
+
+pub trait SchedulingPolicy: Send {
+    fn name(&self) -> &'static str;
+
+    /// Start tracking a thread with the given base priority (0 is highest)
+    ///
+    /// Called outside interrupt context; may allocate, and must reserve
+    /// whatever `make_ready` needs so it never has to.
+    fn add(&mut self, id: TaskId, priority: u8);
+
+    /// Stop tracking a thread, dropping it from the ready queues
+    fn remove(&mut self, id: TaskId);
+
+    /// Queue a thread that can run
+    fn make_ready(&mut self, id: TaskId, reason: ReadyReason);
+
+    /// Take the next thread to run off the ready queues
+    fn pick_next(&mut self) -> Option<TaskId>;
+
+    /// Whether any thread is queued
+    fn has_ready(&self) -> bool;
+
+    /// The running thread used up a timer tick; returns whether it should
+    /// be preempted
+    fn tick(&mut self, current: TaskId) -> bool;
+
+    /// Current priority of a thread, for display
+    fn priority(&self, id: TaskId) -> Option<u8>;
+}
@@ -0,0 +1,70 @@
+//! Plain round robin with a fixed time slice
+
+use super::{ReadyReason, SchedulingPolicy};
+use crate::task::TaskId;
+use alloc::collections::VecDeque;
+
+/// Timer ticks a thread runs before it is preempted
+const TIME_SLICE: u64 = 2;
+
+pub struct RoundRobin {
+    queue: VecDeque<TaskId>,
+    threads: usize,
+    used: u64,
+}
+
+impl RoundRobin {
+    pub fn new() -> Self {
+        RoundRobin {
+            queue: VecDeque::new(),
+            threads: 0,
+            used: 0,
+        }
+    }
+}
+
+impl Default for RoundRobin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SchedulingPolicy for RoundRobin {
+    fn name(&self) -> &'static str {
+        "round-robin"
+    }
+
+    fn add(&mut self, _id: TaskId, _priority: u8) {
+        self.threads += 1;
+        self.queue.reserve(self.threads.saturating_sub(self.queue.len()));
+    }
+
+    fn remove(&mut self, id: TaskId) {
+        self.threads = self.threads.saturating_sub(1);
+        self.queue.retain(|&queued| queued != id);
+    }
+
+    fn make_ready(&mut self, id: TaskId, _reason: ReadyReason) {
+        if !self.queue.contains(&id) {
+            self.queue.push_back(id);
+        }
+    }
+
+    fn pick_next(&mut self) -> Option<TaskId> {
+        self.used = 0;
+        self.queue.pop_front()
+    }
+
+    fn has_ready(&self) -> bool {
+        !self.queue.is_empty()
+    }
+
+    fn tick(&mut self, _current: TaskId) -> bool {
+        self.used += 1;
+        self.used >= TIME_SLICE
+    }
+
+    fn priority(&self, _id: TaskId) -> Option<u8> {
+        Some(0)
+    }
+}
@@ -1,34 +1,29 @@
+use super::thread::{self, JoinHandle};
 use crate::println;
 
-/// Test kernel thread A - counts and prints periodically
-pub extern "C" fn thread_a() {
-    let mut counter = 0u64;
-    loop {
-        counter = counter.wrapping_add(1);
-        if counter % 1_000_000 == 0 {
-            println!("Thread A: {}", counter);
-        }
-    }
-}
+/// Number of progress lines each demo counter prints before exiting
+const REPORTS: u64 = 10;
 
-/// Test kernel thread B - counts and prints periodically
-pub extern "C" fn thread_b() {
-    let mut counter = 0u64;
-    loop {
-        counter = counter.wrapping_add(1);
-        if counter % 1_500_000 == 0 {
-            println!("Thread B: {}", counter);
+/// Demo kernel thread that counts, printing every `interval` iterations,
+/// and exits with its final count
+pub fn spawn_counter(name: &'static str, interval: u64) -> JoinHandle {
+    thread::Builder::new().name(name).spawn(move || {
+        let mut counter = 0u64;
+        while counter < interval * REPORTS {
+            counter += 1;
+            if counter.is_multiple_of(interval) {
+                println!("{}: {}", name, counter);
+            }
         }
-    }
+        counter
+    })
 }
 
-/// Test kernel thread C - counts and prints periodically
-pub extern "C" fn thread_c() {
-    let mut counter = 0u64;
-    loop {
-        counter = counter.wrapping_add(1);
-        if counter % 2_000_000 == 0 {
-            println!("Thread C: {}", counter);
-        }
-    }
+/// Spawn the three demo counters
+pub fn spawn_demo_threads() -> [JoinHandle; 3] {
+    [
+        spawn_counter("Thread A", 1_000_000),
+        spawn_counter("Thread B", 1_500_000),
+        spawn_counter("Thread C", 2_000_000),
+    ]
 }
@@ -0,0 +1,265 @@
+//! Kernel thread lifecycle: spawn, yield, exit and join
+
+use super::context::YIELD_VECTOR;
+use super::kernel_thread::{KernelThread, ThreadState, DEFAULT_PRIORITY, KERNEL_STACK_SIZE};
+use super::thread_scheduler::{self, with_scheduler, ThreadInfo};
+use super::TaskId;
+use crate::process::{self, Pid};
+use crate::time::{self, timer::Waiter};
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::arch::asm;
+use core::time::Duration;
+use x86_64::instructions::interrupts;
+use x86_64::registers::model_specific::FsBase;
+use x86_64::VirtAddr;
+
+/// Owned permission to join a thread
+///
+/// Dropping the handle detaches the thread: it is reaped as soon as it exits.
+#[derive(Debug)]
+pub struct JoinHandle {
+    id: TaskId,
+}
+
+impl JoinHandle {
+    pub fn id(&self) -> TaskId {
+        self.id
+    }
+
+    /// Whether the thread has exited
+    pub fn is_finished(&self) -> bool {
+        with_scheduler(|scheduler| scheduler.state(self.id)) == Some(ThreadState::Zombie)
+    }
+
+    /// Wait for the thread to exit, free it, and return its exit value
+    ///
+    /// # Panics
+    /// If the scheduler no longer knows the thread, which the handle's
+    /// ownership should rule out.
+    pub fn join(self) -> u64 {
+        let id = self.id;
+        core::mem::forget(self);
+
+        loop {
+            match with_scheduler(|scheduler| scheduler.try_join(id)) {
+                // Dropped here, outside the scheduler lock
+                Ok(Some(thread)) => return thread.exit_value.expect("zombie without exit value"),
+                Ok(None) => yield_now(),
+                Err(error) => panic!("joining thread {:?}: {}", id, error),
+            }
+        }
+    }
+}
+
+impl Drop for JoinHandle {
+    fn drop(&mut self) {
+        let id = self.id;
+        drop(with_scheduler(|scheduler| scheduler.detach(id)));
+    }
+}
+
+/// Values a thread's closure may return, converted to its exit value
+pub trait ExitValue {
+    fn into_exit_value(self) -> u64;
+}
+
+impl ExitValue for () {
+    fn into_exit_value(self) -> u64 {
+        0
+    }
+}
+
+impl ExitValue for u64 {
+    fn into_exit_value(self) -> u64 {
+        self
+    }
+}
+
+/// Start a kernel thread running `f`; its return value is the exit value
+pub fn spawn<F, T>(f: F) -> JoinHandle
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: ExitValue,
+{
+    Builder::new().spawn(f)
+}
+
+/// Thread configuration: name, stack size and priority
+#[derive(Debug, Default)]
+pub struct Builder {
+    name: Option<String>,
+    stack_size: Option<usize>,
+    priority: Option<u8>,
+    /// Process to start the thread in instead of the caller's
+    process: Option<Pid>,
+}
+
+impl Builder {
+    pub fn new() -> Self {
+        Builder::default()
+    }
+
+    /// Name shown in thread listings
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = Some(String::from(name));
+        self
+    }
+
+    /// Stack size in bytes (at least `MIN_STACK_SIZE`)
+    pub fn stack_size(mut self, size: usize) -> Self {
+        self.stack_size = Some(size);
+        self
+    }
+
+    /// Base scheduling priority, 0 being the highest
+    pub fn priority(mut self, priority: u8) -> Self {
+        self.priority = Some(priority);
+        self
+    }
+
+    pub(crate) fn process(mut self, pid: Pid) -> Self {
+        self.process = Some(pid);
+        self
+    }
+
+    /// Start a kernel thread running `f`
+    pub fn spawn<F, T>(self, f: F) -> JoinHandle
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: ExitValue,
+    {
+        // Double boxed so the trampoline gets a thin pointer
+        let closure: Box<ThreadMain> = Box::new(Box::new(move || f().into_exit_value()));
+        let argument = Box::into_raw(closure) as u64;
+
+        let stack_size = self.stack_size.unwrap_or(KERNEL_STACK_SIZE);
+        let mut thread = KernelThread::with_stack_size(run_closure, argument, stack_size);
+        thread.name = self.name;
+        thread.priority = self.priority.unwrap_or(DEFAULT_PRIORITY);
+        let pid = self.process.unwrap_or_else(process::current_pid);
+        spawn_thread_in(thread, pid)
+    }
+}
+
+type ThreadMain = Box<dyn FnOnce() -> u64 + Send>;
+
+extern "C" fn run_closure(closure: u64) -> u64 {
+    let closure = unsafe { Box::from_raw(closure as *mut ThreadMain) };
+    closure()
+}
+
+/// Hand a prepared thread to the scheduler, in the caller's process
+pub fn spawn_thread(thread: KernelThread) -> JoinHandle {
+    spawn_thread_in(thread, process::current_pid())
+}
+
+fn spawn_thread_in(thread: KernelThread, pid: Pid) -> JoinHandle {
+    reap();
+    let id = thread.id();
+    // Before the thread can run, so it never sees the wrong process
+    process::attach_thread(pid, id);
+    thread_scheduler::add_kernel_thread(thread);
+    JoinHandle { id }
+}
+
+/// Give up the CPU to the next ready thread
+pub fn yield_now() {
+    unsafe {
+        asm!("int {vector}", vector = const YIELD_VECTOR);
+    }
+}
+
+/// Block the running thread for at least `duration`
+///
+/// The thread wakes on the first timer tick after the deadline, so it
+/// oversleeps by less than one tick (`time::tick_duration`).
+pub fn sleep(duration: Duration) {
+    if duration.is_zero() {
+        yield_now();
+        return;
+    }
+    sleep_until(time::deadline_after(duration));
+}
+
+/// Block the running thread until the tick counter reaches `deadline`
+pub fn sleep_until(deadline: u64) {
+    let Some(id) = current() else {
+        // Nothing to switch to before threading starts
+        while time::ticks() < deadline {
+            interrupts::enable_and_hlt();
+        }
+        return;
+    };
+
+    // Interrupts stay off until the yield so the timer can't fire or switch
+    // away between going to sleep and giving up the CPU
+    let enabled = interrupts::are_enabled();
+    interrupts::disable();
+    let timer = time::timer::register(deadline, Waiter::Thread(id));
+    while !time::timer::fired(timer) {
+        with_scheduler(|scheduler| scheduler.sleep_current());
+        yield_now();
+    }
+    time::timer::cancel(timer);
+    if enabled {
+        interrupts::enable();
+    }
+}
+
+/// Exit the running thread with `value`
+pub fn exit(value: u64) -> ! {
+    if let Some(id) = current() {
+        process::detach_thread(id);
+    }
+
+    interrupts::disable();
+    with_scheduler(|scheduler| scheduler.exit_current(value));
+
+    // A zombie is only resumed when nothing else can run
+    loop {
+        yield_now();
+        interrupts::enable_and_hlt();
+        interrupts::disable();
+    }
+}
+
+/// Called by `context::task_entry_wrapper` when a thread's entry returns
+pub(crate) extern "C" fn exit_current(value: u64) -> ! {
+    exit(value)
+}
+
+/// ID of the running thread, `None` before threading starts
+pub fn current() -> Option<TaskId> {
+    thread_scheduler::current_thread()
+}
+
+/// Set FS base of the running thread, the TLS pointer of user code
+///
+/// The value follows the thread across context switches.
+pub fn set_fs_base(base: VirtAddr) {
+    thread_scheduler::set_current_fs_base(base);
+}
+
+/// FS base of the running thread
+pub fn fs_base() -> VirtAddr {
+    FsBase::read()
+}
+
+/// Free every exited thread that has no `JoinHandle` left
+pub fn reap() {
+    // The stacks are freed after the scheduler lock is released
+    drop(with_scheduler(|scheduler| scheduler.take_reapable()));
+}
+
+/// Describe one thread, for debugging
+pub fn info(id: TaskId) -> Option<ThreadInfo> {
+    with_scheduler(|scheduler| scheduler.thread_info(id))
+}
+
+/// Describe every thread, for debugging
+pub fn list() -> Vec<ThreadInfo> {
+    reap();
+    with_scheduler(|scheduler| scheduler.list())
+}
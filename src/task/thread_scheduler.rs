@@ -1,12 +1,92 @@
-use super::{TaskId, kernel_thread::KernelThread, context::{TaskContext, switch_context}};
+use super::{TaskId, kernel_thread::{KernelThread, ThreadState}};
+use super::policy::{Mlfq, ReadyReason, SchedulingPolicy};
+use crate::{cpu, time};
+use alloc::boxed::Box;
 use alloc::collections::BTreeMap;
-use spin::Mutex;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+use crate::sync::SpinLock;
+use x86_64::instructions::interrupts;
+use x86_64::registers::model_specific::FsBase;
+use x86_64::VirtAddr;
 
 /// Thread-aware scheduler with context switching support
+///
+/// The scheduler owns the threads and performs the switches; which ready
+/// thread runs next is up to its `SchedulingPolicy` (MLFQ by default).
+///
+/// The context `kernel_main` runs in is adopted as the "boot thread" when
+/// the first kernel thread is added, together with an idle thread that
+/// runs whenever nothing else is ready. Until then `current_thread` is
+/// `None` and nothing is switched.
 pub struct ThreadScheduler {
     threads: BTreeMap<TaskId, KernelThread>,
     current_thread: Option<TaskId>,
-    idle_context: TaskContext,
+    idle_thread: Option<TaskId>,
+    /// Set when the first thread is added
+    policy: Option<Box<dyn SchedulingPolicy>>,
+    /// A thread was woken since the last tick, so the running thread
+    /// should make way for it without waiting out its time slice
+    wake_pending: bool,
+    /// Ring 3 trap stack set before any thread existed
+    idle_kernel_stack: VirtAddr,
+    /// TSC when threading started and when the running thread got the CPU
+    started_at: u64,
+    switched_in_at: u64,
+}
+
+/// The thread to join doesn't exist, or was already joined or detached
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NoSuchThread;
+
+impl fmt::Display for NoSuchThread {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "No such thread")
+    }
+}
+
+/// CPU time since threading started, split by whether the idle thread ran
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CpuTimes {
+    pub busy: Duration,
+    pub idle: Duration,
+}
+
+impl CpuTimes {
+    pub fn total(&self) -> Duration {
+        self.busy + self.idle
+    }
+}
+
+/// Number of thread switches performed so far
+static CONTEXT_SWITCHES: AtomicU64 = AtomicU64::new(0);
+
+/// ID of `current_thread` plus one, 0 before threading starts
+static RUNNING_THREAD: AtomicU64 = AtomicU64::new(0);
+
+/// Snapshot of a thread for debugging
+#[derive(Debug, Clone)]
+pub struct ThreadInfo {
+    pub id: TaskId,
+    pub name: Option<String>,
+    pub state: ThreadState,
+    /// Current priority level in the scheduling policy (0 is highest)
+    pub priority: Option<u8>,
+    pub stack_size: usize,
+    /// Timer ticks spent running
+    pub cpu_ticks: u64,
+    /// Times the thread was switched to
+    pub switches: u64,
+    /// Time spent running, measured with the TSC
+    pub cpu_time: Duration,
+    /// Times the thread was woken after blocking or sleeping
+    pub wakeups: u64,
+    pub exit_value: Option<u64>,
+    pub is_current: bool,
+    pub is_idle: bool,
 }
 
 impl ThreadScheduler {
@@ -14,97 +94,437 @@ impl ThreadScheduler {
         ThreadScheduler {
             threads: BTreeMap::new(),
             current_thread: None,
-            idle_context: TaskContext::new(),
+            idle_thread: None,
+            policy: None,
+            wake_pending: false,
+            idle_kernel_stack: VirtAddr::zero(),
+            started_at: 0,
+            switched_in_at: 0,
+        }
+    }
+
+    /// Replace the scheduling policy, handing it every existing thread
+    pub fn set_policy(&mut self, mut policy: Box<dyn SchedulingPolicy>) {
+        for thread in self.threads.values() {
+            if Some(thread.id) == self.idle_thread || thread.state == ThreadState::Zombie {
+                continue;
+            }
+            policy.add(thread.id, thread.priority);
+            if thread.state == ThreadState::Ready {
+                policy.make_ready(thread.id, ReadyReason::Woken);
+            }
         }
+        self.policy = Some(policy);
+    }
+
+    /// Name of the scheduling policy in use
+    pub fn policy_name(&self) -> &'static str {
+        self.policy.as_ref().map_or("none", |policy| policy.name())
     }
 
     /// Add a kernel thread to the scheduler
     pub fn add_thread(&mut self, thread: KernelThread) {
+        if self.current_thread.is_none() {
+            self.start_threading();
+        }
+
         let thread_id = thread.id();
+        let policy = self.policy.as_mut().unwrap();
+        policy.add(thread_id, thread.priority);
+        policy.make_ready(thread_id, ReadyReason::New);
         self.threads.insert(thread_id, thread);
     }
 
-    /// Switch to the next thread (round-robin)
-    pub fn schedule_next(&mut self) {
-        // Get the next thread to run
-        let next_thread_id = self.get_next_thread();
-        
-        if let Some(next_id) = next_thread_id {
-            if Some(next_id) != self.current_thread {
-                self.switch_to(next_id);
+    /// Adopt the boot context as a thread and create the idle thread
+    fn start_threading(&mut self) {
+        let policy = self.policy.get_or_insert_with(|| Box::new(Mlfq::new()));
+
+        self.started_at = cpu::rdtsc();
+        self.switched_in_at = self.started_at;
+
+        let mut boot = KernelThread::boot();
+        boot.kernel_stack_top = self.idle_kernel_stack;
+        policy.add(boot.id(), boot.priority);
+        self.current_thread = Some(boot.id());
+        RUNNING_THREAD.store(boot.id().as_u64() + 1, Ordering::Relaxed);
+        self.threads.insert(boot.id(), boot);
+
+        // Never queued in the policy; picked only when nothing is ready
+        let mut idle = KernelThread::with_argument(idle_main, 0);
+        idle.name = Some(String::from("idle"));
+        self.idle_thread = Some(idle.id());
+        self.threads.insert(idle.id(), idle);
+    }
+
+    /// Account a timer tick to the running thread and preempt it if the
+    /// policy says so or a thread was woken since the last tick
+    ///
+    /// Runs in interrupt context, so it must not allocate or free.
+    pub fn tick(&mut self, current_rsp: u64) -> u64 {
+        let Some(current_id) = self.current_thread else {
+            return current_rsp;
+        };
+        let Some(policy) = self.policy.as_mut() else {
+            return current_rsp;
+        };
+        let woken = core::mem::take(&mut self.wake_pending);
+
+        let running = match self.threads.get_mut(&current_id) {
+            Some(current) => {
+                current.cpu_ticks += 1;
+                current.state == ThreadState::Running
             }
+            None => false,
+        };
+
+        let preempt = if !running {
+            true
+        } else if Some(current_id) == self.idle_thread {
+            policy.has_ready()
+        } else {
+            // Always charge the tick, even if a wakeup preempts anyway
+            policy.tick(current_id) || woken
+        };
+
+        if preempt {
+            self.schedule_next(current_rsp, ReadyReason::Preempted)
+        } else {
+            current_rsp
         }
     }
 
-    /// Get the next thread ID to run (round-robin)
-    fn get_next_thread(&self) -> Option<TaskId> {
-        if self.threads.is_empty() {
-            return None;
-        }
+    /// Save the running thread's stack pointer and switch to the thread the
+    /// policy picks, returning the stack pointer to resume
+    ///
+    /// Runs in interrupt context, so it must not allocate or free.
+    pub fn schedule_next(&mut self, current_rsp: u64, reason: ReadyReason) -> u64 {
+        let Some(current_id) = self.current_thread else {
+            return current_rsp;
+        };
+        let Some(policy) = self.policy.as_mut() else {
+            return current_rsp;
+        };
+        self.wake_pending = false;
 
-        // If there's a current thread, get the next one after it
-        if let Some(current_id) = self.current_thread {
-            let mut found_current = false;
-            for (&thread_id, _) in &self.threads {
-                if found_current {
-                    return Some(thread_id);
-                }
-                if thread_id == current_id {
-                    found_current = true;
-                }
+        if let Some(current) = self.threads.get_mut(&current_id) {
+            current.saved_rsp = current_rsp;
+            if current.state == ThreadState::Running && Some(current_id) != self.idle_thread {
+                current.state = ThreadState::Ready;
+                policy.make_ready(current_id, reason);
             }
         }
 
-        // Wrap around to the first thread
-        self.threads.keys().next().copied()
+        let next_id = policy
+            .pick_next()
+            .or(self.idle_thread)
+            .unwrap_or(current_id);
+        self.switch_to(current_id, next_id);
+
+        self.threads
+            .get(&next_id)
+            .map_or(current_rsp, |thread| thread.saved_rsp)
     }
 
-    /// Switch to a specific thread
-    fn switch_to(&mut self, new_thread_id: TaskId) {
-        let old_context = if let Some(current_id) = self.current_thread {
-            // Get mutable reference to current thread's context
-            if let Some(current_thread) = self.threads.get_mut(&current_id) {
-                &mut current_thread.context as *mut TaskContext
-            } else {
-                &mut self.idle_context as *mut TaskContext
+    /// Make `next_id` the current thread and load its per-thread CPU state
+    fn switch_to(&mut self, current_id: TaskId, next_id: TaskId) {
+        if next_id != current_id {
+            let now = cpu::rdtsc();
+            let ran = now.wrapping_sub(self.switched_in_at);
+            self.switched_in_at = now;
+            if let Some(current) = self.threads.get_mut(&current_id) {
+                current.run_cycles += ran;
+                if current.state == ThreadState::Running {
+                    current.state = ThreadState::Ready;
+                }
             }
-        } else {
-            &mut self.idle_context as *mut TaskContext
+        }
+
+        let Some(next) = self.threads.get_mut(&next_id) else {
+            return;
         };
+        next.state = ThreadState::Running;
+        if next_id == current_id {
+            return;
+        }
 
-        // Get the new thread's context
-        if let Some(new_thread) = self.threads.get(&new_thread_id) {
-            let new_context = &new_thread.context as *const TaskContext;
-            
-            // Update current thread
-            self.current_thread = Some(new_thread_id);
-            
-            // Perform the context switch
-            unsafe {
-                switch_context(old_context, new_context);
-            }
+        next.switches += 1;
+        if !next.kernel_stack_top.is_null() {
+            crate::gdt::set_kernel_stack(next.kernel_stack_top);
         }
+        crate::fpu::switch_to(next.fpu_area());
+        crate::task::local::switch_to(next.locals_base());
+        FsBase::write(next.fs_base);
+
+        self.current_thread = Some(next_id);
+        RUNNING_THREAD.store(next_id.as_u64() + 1, Ordering::Relaxed);
+        CONTEXT_SWITCHES.fetch_add(1, Ordering::Relaxed);
     }
 
     /// Get the currently running thread ID
     pub fn current_thread(&self) -> Option<TaskId> {
         self.current_thread
     }
+
+    /// Record the ring 3 trap stack of the running thread and load it into the TSS
+    pub fn set_current_kernel_stack(&mut self, stack_top: VirtAddr) {
+        let slot = match self.current_thread.and_then(|id| self.threads.get_mut(&id)) {
+            Some(thread) => &mut thread.kernel_stack_top,
+            None => &mut self.idle_kernel_stack,
+        };
+        *slot = stack_top;
+        crate::gdt::set_kernel_stack(stack_top);
+    }
+
+    /// Set FS base for the running thread and load it
+    pub fn set_current_fs_base(&mut self, base: VirtAddr) {
+        if let Some(thread) = self.current_thread.and_then(|id| self.threads.get_mut(&id)) {
+            thread.fs_base = base;
+        }
+        FsBase::write(base);
+    }
+
+    /// Turn the running thread into a zombie and wake its joiner
+    ///
+    /// The caller must switch away afterwards; a zombie is never scheduled.
+    pub fn exit_current(&mut self, value: u64) {
+        let Some(current_id) = self.current_thread else {
+            return;
+        };
+        let Some(current) = self.threads.get_mut(&current_id) else {
+            return;
+        };
+        current.state = ThreadState::Zombie;
+        current.exit_value = Some(value);
+        let joiner = current.joiner.take();
+
+        if let Some(policy) = self.policy.as_mut() {
+            policy.remove(current_id);
+        }
+        if let Some(joiner) = joiner {
+            self.wake(joiner);
+        }
+    }
+
+    /// Block the running thread until `wake` is called for it
+    pub fn block_current(&mut self) {
+        if let Some(current) = self.current_thread.and_then(|id| self.threads.get_mut(&id)) {
+            current.state = ThreadState::Blocked;
+        }
+    }
+
+    /// Put the running thread to sleep until `wake` is called for it,
+    /// normally by a timer (see `time::timer`)
+    pub fn sleep_current(&mut self) {
+        if let Some(current) = self.current_thread.and_then(|id| self.threads.get_mut(&id)) {
+            current.state = ThreadState::Sleeping;
+        }
+    }
+
+    /// Make a blocked or sleeping thread ready again
+    pub fn wake(&mut self, id: TaskId) {
+        let Some(thread) = self.threads.get_mut(&id) else {
+            return;
+        };
+        if matches!(thread.state, ThreadState::Blocked | ThreadState::Sleeping) {
+            thread.state = ThreadState::Ready;
+            thread.wakeups += 1;
+            self.wake_pending = true;
+            if let Some(policy) = self.policy.as_mut() {
+                policy.make_ready(id, ReadyReason::Woken);
+            }
+        }
+    }
+
+    /// Collect the thread `id` if it has exited; otherwise block the
+    /// running thread until it does
+    ///
+    /// Fails if there is no such thread.
+    pub fn try_join(&mut self, id: TaskId) -> Result<Option<KernelThread>, NoSuchThread> {
+        let current = self.current_thread;
+        let target = self.threads.get_mut(&id).ok_or(NoSuchThread)?;
+
+        if target.state == ThreadState::Zombie {
+            return Ok(self.threads.remove(&id));
+        }
+
+        target.joiner = current;
+        self.block_current();
+        Ok(None)
+    }
+
+    /// Give up the right to join `id`; returns the thread if it can be freed now
+    pub fn detach(&mut self, id: TaskId) -> Option<KernelThread> {
+        let thread = self.threads.get_mut(&id)?;
+        thread.detached = true;
+        if thread.state == ThreadState::Zombie {
+            self.threads.remove(&id)
+        } else {
+            None
+        }
+    }
+
+    /// Remove exited threads nobody will join
+    pub fn take_reapable(&mut self) -> Vec<KernelThread> {
+        let current = self.current_thread;
+        let reapable: Vec<TaskId> = self
+            .threads
+            .values()
+            .filter(|t| t.state == ThreadState::Zombie && t.detached && Some(t.id) != current)
+            .map(|t| t.id)
+            .collect();
+
+        reapable
+            .into_iter()
+            .filter_map(|id| self.threads.remove(&id))
+            .collect()
+    }
+
+    /// Look at a thread's state
+    pub fn state(&self, id: TaskId) -> Option<ThreadState> {
+        self.threads.get(&id).map(|thread| thread.state)
+    }
+
+    /// TSC cycles `thread` has run, including its current time slice
+    fn run_cycles(&self, thread: &KernelThread) -> u64 {
+        if Some(thread.id) == self.current_thread {
+            thread.run_cycles + cpu::rdtsc().wrapping_sub(self.switched_in_at)
+        } else {
+            thread.run_cycles
+        }
+    }
+
+    fn info(&self, thread: &KernelThread) -> ThreadInfo {
+        ThreadInfo {
+            id: thread.id,
+            name: thread.name.clone(),
+            state: thread.state,
+            priority: self.policy.as_ref().and_then(|policy| policy.priority(thread.id)),
+            stack_size: thread.stack_size(),
+            cpu_ticks: thread.cpu_ticks,
+            switches: thread.switches,
+            cpu_time: time::cycles_to_duration(self.run_cycles(thread)),
+            wakeups: thread.wakeups,
+            exit_value: thread.exit_value,
+            is_current: Some(thread.id) == self.current_thread,
+            is_idle: Some(thread.id) == self.idle_thread,
+        }
+    }
+
+    /// Describe one thread
+    pub fn thread_info(&self, id: TaskId) -> Option<ThreadInfo> {
+        self.threads.get(&id).map(|thread| self.info(thread))
+    }
+
+    /// Describe every thread
+    pub fn list(&self) -> Vec<ThreadInfo> {
+        self.threads.values().map(|thread| self.info(thread)).collect()
+    }
+
+    /// Busy and idle time since threading started
+    pub fn cpu_times(&self) -> CpuTimes {
+        if self.current_thread.is_none() {
+            return CpuTimes::default();
+        }
+        let total = cpu::rdtsc().wrapping_sub(self.started_at);
+        let idle = self.idle_thread
+            .and_then(|id| self.threads.get(&id))
+            .map_or(0, |idle| self.run_cycles(idle))
+            .min(total);
+        CpuTimes {
+            busy: time::cycles_to_duration(total - idle),
+            idle: time::cycles_to_duration(idle),
+        }
+    }
 }
 
-static THREAD_SCHEDULER: Mutex<ThreadScheduler> = Mutex::new(ThreadScheduler::new());
+/// Runs when no other thread is ready
+extern "C" fn idle_main(_: u64) -> u64 {
+    loop {
+        super::thread::reap();
+        interrupts::enable_and_hlt();
+    }
+}
+
+/// Locked from the timer interrupt, so every other user must disable
+/// interrupts while holding it.
+static THREAD_SCHEDULER: SpinLock<ThreadScheduler> = SpinLock::new(ThreadScheduler::new());
+
+/// Run `f` on the global scheduler with interrupts disabled
+pub fn with_scheduler<R>(f: impl FnOnce(&mut ThreadScheduler) -> R) -> R {
+    interrupts::without_interrupts(|| f(&mut THREAD_SCHEDULER.lock()))
+}
 
 /// Add a kernel thread to the global scheduler
 pub fn add_kernel_thread(thread: KernelThread) {
-    THREAD_SCHEDULER.lock().add_thread(thread);
+    with_scheduler(|scheduler| scheduler.add_thread(thread));
+}
+
+/// Replace the global scheduling policy
+pub fn set_policy(policy: Box<dyn SchedulingPolicy>) {
+    // The old policy is freed outside the lock
+    let old = with_scheduler(|scheduler| {
+        let old = scheduler.policy.take();
+        scheduler.set_policy(policy);
+        old
+    });
+    drop(old);
 }
 
-/// Schedule the next thread
-pub fn schedule_next_thread() {
-    THREAD_SCHEDULER.lock().schedule_next();
+/// Name of the global scheduling policy
+pub fn policy_name() -> &'static str {
+    with_scheduler(|scheduler| scheduler.policy_name())
+}
+
+/// Handle a timer tick
+///
+/// Called from the timer interrupt with the interrupted thread's saved
+/// context at `current_rsp`; returns the saved context to resume.
+pub fn timer_tick(current_rsp: u64) -> u64 {
+    THREAD_SCHEDULER.lock().tick(current_rsp)
+}
+
+/// Switch away from a thread that gave up the CPU
+///
+/// Called from the yield interrupt; see `timer_tick`.
+pub fn schedule_next_thread(current_rsp: u64) -> u64 {
+    THREAD_SCHEDULER.lock().schedule_next(current_rsp, ReadyReason::Yielded)
+}
+
+/// Make a blocked or sleeping thread ready again
+///
+/// Safe to call from interrupt handlers.
+pub fn wake(id: TaskId) {
+    with_scheduler(|scheduler| scheduler.wake(id));
 }
 
 /// Get the currently running thread
 pub fn current_thread() -> Option<TaskId> {
-    THREAD_SCHEDULER.lock().current_thread()
+    with_scheduler(|scheduler| scheduler.current_thread())
+}
+
+/// Get the currently running thread without taking the scheduler lock
+///
+/// For code the scheduler lock itself depends on, like lock diagnostics.
+pub fn running_thread() -> Option<TaskId> {
+    RUNNING_THREAD.load(Ordering::Relaxed).checked_sub(1).map(TaskId)
+}
+
+/// Busy and idle CPU time since threading started
+pub fn cpu_times() -> CpuTimes {
+    with_scheduler(|scheduler| scheduler.cpu_times())
+}
+
+/// Number of thread switches performed since boot
+pub fn context_switches() -> u64 {
+    CONTEXT_SWITCHES.load(Ordering::Relaxed)
+}
+
+/// Set the stack traps from ring 3 use while the current thread runs
+pub fn set_current_kernel_stack(stack_top: VirtAddr) {
+    with_scheduler(|scheduler| scheduler.set_current_kernel_stack(stack_top));
+}
+
+/// Set the FS base of the current thread, kept across context switches
+pub fn set_current_fs_base(base: VirtAddr) {
+    with_scheduler(|scheduler| scheduler.set_current_fs_base(base));
 }
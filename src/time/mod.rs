@@ -0,0 +1,94 @@
+//! System tick, uptime and timers
+//!
+//! The PIT interrupts `TICK_HZ` times per second. Every tick advances the
+//! tick counter, fires expired timers, and drives preemption.
+
+pub mod pit;
+pub mod timer;
+
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+
+pub use timer::Timer;
+
+/// Configured timer interrupt frequency
+pub const TICK_HZ: u64 = 100;
+
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+
+static TICKS: AtomicU64 = AtomicU64::new(0);
+
+/// TSC at the first tick and at the latest one, for calibrating the TSC
+static FIRST_TICK_TSC: AtomicU64 = AtomicU64::new(0);
+static LAST_TICK_TSC: AtomicU64 = AtomicU64::new(0);
+
+/// Program the PIT for `TICK_HZ`
+pub fn init() {
+    pit::set_frequency(TICK_HZ as u32);
+}
+
+/// Timer ticks since boot
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+/// Length of one tick
+pub fn tick_duration() -> Duration {
+    Duration::from_nanos((NANOS_PER_SEC / TICK_HZ as u128) as u64)
+}
+
+/// Time since boot, to tick resolution
+pub fn uptime() -> Duration {
+    ticks_to_duration(ticks())
+}
+
+/// Number of whole ticks covering `duration`, rounded up
+pub fn duration_to_ticks(duration: Duration) -> u64 {
+    let nanos = duration.as_nanos() * TICK_HZ as u128;
+    nanos.div_ceil(NANOS_PER_SEC) as u64
+}
+
+pub fn ticks_to_duration(ticks: u64) -> Duration {
+    let nanos = ticks as u128 * NANOS_PER_SEC / TICK_HZ as u128;
+    Duration::from_nanos(nanos as u64)
+}
+
+/// First tick at which at least `duration` has passed from now
+///
+/// The current tick is already partly over, so one extra tick is added.
+pub fn deadline_after(duration: Duration) -> u64 {
+    ticks() + duration_to_ticks(duration) + 1
+}
+
+/// TSC frequency measured against the timer, `None` until two ticks passed
+pub fn tsc_hz() -> Option<u64> {
+    let last = LAST_TICK_TSC.load(Ordering::Relaxed);
+    let first = FIRST_TICK_TSC.load(Ordering::Relaxed);
+    let elapsed_ticks = ticks().checked_sub(1).filter(|&ticks| ticks > 0)?;
+    let hz = (last.wrapping_sub(first) as u128 * TICK_HZ as u128 / elapsed_ticks as u128) as u64;
+    (hz > 0).then_some(hz)
+}
+
+/// Convert a TSC cycle count to time, zero before the TSC is calibrated
+pub fn cycles_to_duration(cycles: u64) -> Duration {
+    match tsc_hz() {
+        Some(hz) => Duration::from_nanos((cycles as u128 * NANOS_PER_SEC / hz as u128) as u64),
+        None => Duration::ZERO,
+    }
+}
+
+/// Async sleep: `time::sleep(duration).await`
+pub fn sleep(duration: Duration) -> Timer {
+    Timer::after(duration)
+}
+
+/// Called from the timer interrupt
+pub(crate) fn on_tick() {
+    let tsc = crate::cpu::rdtsc();
+    let now = TICKS.fetch_add(1, Ordering::Relaxed) + 1;
+    if now == 1 {
+        FIRST_TICK_TSC.store(tsc, Ordering::Relaxed);
+    }
+    LAST_TICK_TSC.store(tsc, Ordering::Relaxed);
+    timer::fire_expired(now);
+}
@@ -0,0 +1,30 @@
+//! 8253/8254 Programmable Interval Timer
+
+use x86_64::instructions::port::Port;
+
+/// Input clock of the PIT
+pub const BASE_FREQUENCY: u32 = 1_193_182;
+
+const CHANNEL0_DATA: u16 = 0x40;
+const COMMAND: u16 = 0x43;
+
+/// Channel 0, lobyte/hibyte access, mode 2 (rate generator), binary
+const CHANNEL0_RATE_GENERATOR: u8 = 0b0011_0100;
+
+/// Program channel 0 (IRQ 0) to fire `hz` times per second
+///
+/// Returns the frequency actually configured, which differs slightly from
+/// `hz` because the divisor is an integer.
+pub fn set_frequency(hz: u32) -> u32 {
+    let divisor = (BASE_FREQUENCY / hz).clamp(1, u16::MAX as u32) as u16;
+
+    let mut command = Port::<u8>::new(COMMAND);
+    let mut data = Port::<u8>::new(CHANNEL0_DATA);
+    unsafe {
+        command.write(CHANNEL0_RATE_GENERATOR);
+        data.write(divisor as u8);
+        data.write((divisor >> 8) as u8);
+    }
+
+    BASE_FREQUENCY / divisor as u32
+}
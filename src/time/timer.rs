@@ -0,0 +1,173 @@
+//! Deadline queue shared by sleeping threads and async timers
+//!
+//! Deadlines live in a min-heap checked from the timer interrupt. The
+//! interrupt must not allocate or free, so it only pops the heap and marks
+//! entries as fired; each entry is removed by its owner (the sleeping thread
+//! or the `Timer` future) in normal context.
+
+use super::ticks;
+use crate::task::{thread_scheduler, TaskId};
+use alloc::collections::{BTreeMap, BinaryHeap};
+use core::cmp::Reverse;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+use core::time::Duration;
+use crate::sync::SpinLock;
+use x86_64::instructions::interrupts;
+
+/// Handle to a registered deadline
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TimerId(u64);
+
+/// What to wake when a deadline passes
+pub enum Waiter {
+    Thread(TaskId),
+    Task(Waker),
+}
+
+struct Entry {
+    waiter: Waiter,
+    fired: bool,
+}
+
+struct TimerQueue {
+    /// (deadline tick, timer) ordered by earliest deadline
+    deadlines: BinaryHeap<Reverse<(u64, TimerId)>>,
+    entries: BTreeMap<TimerId, Entry>,
+    next_id: u64,
+}
+
+/// Locked from the timer interrupt; other users must disable interrupts.
+static TIMERS: SpinLock<TimerQueue> = SpinLock::new(TimerQueue {
+    deadlines: BinaryHeap::new(),
+    entries: BTreeMap::new(),
+    next_id: 0,
+});
+
+/// Wake `waiter` once the tick counter reaches `deadline`
+pub fn register(deadline: u64, waiter: Waiter) -> TimerId {
+    interrupts::without_interrupts(|| {
+        let mut timers = TIMERS.lock();
+        let id = TimerId(timers.next_id);
+        timers.next_id += 1;
+        timers.entries.insert(id, Entry { waiter, fired: false });
+        timers.deadlines.push(Reverse((deadline, id)));
+        id
+    })
+}
+
+/// Forget a timer, fired or not
+pub fn cancel(id: TimerId) {
+    // Dropped after the lock is released
+    let entry = interrupts::without_interrupts(|| TIMERS.lock().entries.remove(&id));
+    drop(entry);
+}
+
+/// Whether the deadline of `id` has passed
+pub fn fired(id: TimerId) -> bool {
+    interrupts::without_interrupts(|| {
+        TIMERS.lock().entries.get(&id).is_none_or(|entry| entry.fired)
+    })
+}
+
+/// Replace the waker of an async timer if it changed since the last poll
+fn update_waker(id: TimerId, waker: &Waker) {
+    let old = interrupts::without_interrupts(|| {
+        let mut timers = TIMERS.lock();
+        match timers.entries.get_mut(&id) {
+            Some(Entry { waiter: Waiter::Task(old), .. }) if !old.will_wake(waker) => {
+                Some(core::mem::replace(old, waker.clone()))
+            }
+            _ => None,
+        }
+    });
+    drop(old);
+}
+
+/// Wake everything whose deadline is `now` or earlier
+///
+/// Called from the timer interrupt.
+pub(crate) fn fire_expired(now: u64) {
+    let mut timers = TIMERS.lock();
+    let TimerQueue { deadlines, entries, .. } = &mut *timers;
+
+    while let Some(&Reverse((deadline, id))) = deadlines.peek() {
+        if deadline > now {
+            break;
+        }
+        deadlines.pop();
+
+        // Cancelled timers have no entry left
+        let Some(entry) = entries.get_mut(&id) else {
+            continue;
+        };
+        entry.fired = true;
+        match &entry.waiter {
+            Waiter::Thread(thread) => thread_scheduler::wake(*thread),
+            Waiter::Task(waker) => waker.wake_by_ref(),
+        }
+    }
+}
+
+/// Future that completes once a deadline has passed
+#[derive(Debug)]
+pub struct Timer {
+    deadline: u64,
+    id: Option<TimerId>,
+}
+
+impl Timer {
+    /// Complete once the tick counter reaches `deadline`
+    pub fn at(deadline: u64) -> Self {
+        Timer { deadline, id: None }
+    }
+
+    /// Complete after at least `duration`
+    pub fn after(duration: Duration) -> Self {
+        Timer::at(super::deadline_after(duration))
+    }
+
+    /// Tick at which the timer completes
+    pub fn deadline(&self) -> u64 {
+        self.deadline
+    }
+
+    fn cancel(&mut self) {
+        if let Some(id) = self.id.take() {
+            cancel(id);
+        }
+    }
+}
+
+impl Future for Timer {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        if ticks() >= self.deadline {
+            self.cancel();
+            return Poll::Ready(());
+        }
+
+        match self.id {
+            Some(id) => update_waker(id, cx.waker()),
+            None => {
+                let id = register(self.deadline, Waiter::Task(cx.waker().clone()));
+                self.id = Some(id);
+            }
+        }
+
+        // The deadline may have passed while we registered
+        if ticks() >= self.deadline {
+            self.cancel();
+            return Poll::Ready(());
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
@@ -0,0 +1,282 @@
+//! Line discipline: turns typed bytes into what `read` returns
+//!
+//! In canonical mode bytes collect in a line that the erase and kill
+//! characters edit, and only finished lines can be read. In raw mode bytes
+//! are readable as soon as they arrive. Buffers are fixed-size so typing
+//! never allocates.
+
+use super::termios::{
+    Termios, ECHO, ECHOCTL, ECHOE, ECHOK, ICRNL, ISIG, VEOF, VERASE, VINTR, VKILL, VMIN, VQUIT,
+    VSUSP,
+};
+use crate::process::signal::{SIGINT, SIGQUIT, SIGTSTP};
+
+/// Longest line in canonical mode, including its newline
+pub const MAX_CANON: usize = 255;
+
+/// Bytes typed ahead of the reader
+pub const INPUT_SIZE: usize = 1024;
+
+/// Ends a line finished with the end-of-file character; outside the byte
+/// range so it can't be typed
+const EOF_MARK: u16 = 0x100;
+
+/// Typed input of one terminal
+pub struct LineDiscipline {
+    /// Line being edited in canonical mode
+    line: [u8; MAX_CANON],
+    line_len: usize,
+    /// Readable bytes, as a ring
+    queue: [u16; INPUT_SIZE],
+    head: usize,
+    len: usize,
+    /// Finished lines in `queue`
+    lines: usize,
+}
+
+impl LineDiscipline {
+    pub const fn new() -> Self {
+        LineDiscipline {
+            line: [0; MAX_CANON],
+            line_len: 0,
+            queue: [0; INPUT_SIZE],
+            head: 0,
+            len: 0,
+            lines: 0,
+        }
+    }
+
+    /// The line being edited
+    pub fn line(&self) -> &[u8] {
+        &self.line[..self.line_len]
+    }
+
+    /// Take a typed byte, echoing through `echo`
+    ///
+    /// Returns the signal to send to the foreground process if `byte` is a
+    /// signal character.
+    pub fn receive(&mut self, byte: u8, termios: &Termios, echo: &mut impl FnMut(&[u8])) -> Option<u8> {
+        let byte = if byte == b'\r' && termios.iflag & ICRNL != 0 { b'\n' } else { byte };
+        let echoing = termios.has(ECHO);
+
+        if termios.has(ISIG) {
+            let signal = match byte {
+                _ if byte == termios.cc[VINTR] => Some(SIGINT),
+                _ if byte == termios.cc[VQUIT] => Some(SIGQUIT),
+                _ if byte == termios.cc[VSUSP] => Some(SIGTSTP),
+                _ => None,
+            };
+            if signal.is_some() {
+                if echoing {
+                    echo_byte(byte, termios, echo);
+                    echo(b"\n");
+                }
+                self.flush();
+                return signal;
+            }
+        }
+
+        if !termios.is_canonical() {
+            self.push(u16::from(byte));
+            if echoing {
+                echo_byte(byte, termios, echo);
+            }
+            return None;
+        }
+
+        match byte {
+            _ if byte == termios.cc[VERASE] => {
+                if self.erase() && echoing {
+                    echo_erase(1, termios, byte, echo);
+                }
+            }
+            _ if byte == termios.cc[VKILL] => {
+                let erased = core::iter::from_fn(|| self.erase().then_some(())).count();
+                if echoing {
+                    echo_erase(erased, termios, byte, echo);
+                }
+            }
+            _ if byte == termios.cc[VEOF] => self.finish_line(Some(EOF_MARK)),
+            b'\n' => {
+                if echoing {
+                    echo(b"\n");
+                }
+                self.finish_line(Some(u16::from(b'\n')));
+            }
+            // One byte is kept for the newline
+            _ if self.line_len < MAX_CANON - 1 => {
+                self.line[self.line_len] = byte;
+                self.line_len += 1;
+                if echoing {
+                    echo_byte(byte, termios, echo);
+                }
+            }
+            _ => {}
+        }
+        None
+    }
+
+    /// Whether `read` would return something
+    pub fn readable(&self, termios: &Termios) -> bool {
+        if termios.is_canonical() {
+            self.lines > 0
+        } else {
+            self.len >= usize::from(termios.cc[VMIN]).max(1)
+        }
+    }
+
+    /// Read typed input into `buf`, or `None` if the reader has to wait
+    ///
+    /// In canonical mode at most one line is returned; a line ended with
+    /// the end-of-file character comes without terminator, so an empty one
+    /// reads as end of file.
+    pub fn read(&mut self, buf: &mut [u8], termios: &Termios) -> Option<usize> {
+        let canonical = termios.is_canonical();
+        let ready = if canonical {
+            self.lines > 0
+        } else {
+            self.len >= usize::from(termios.cc[VMIN]).min(buf.len()).max(1)
+        };
+        if !ready {
+            return None;
+        }
+
+        let mut count = 0;
+        while count < buf.len() {
+            let Some(entry) = self.pop() else {
+                break;
+            };
+            if entry == EOF_MARK {
+                if canonical {
+                    self.lines -= 1;
+                    break;
+                }
+                continue;
+            }
+            buf[count] = entry as u8;
+            count += 1;
+            if canonical && entry == u16::from(b'\n') {
+                self.lines -= 1;
+                break;
+            }
+        }
+        Some(count)
+    }
+
+    /// Carry input over a switch between canonical and raw mode
+    ///
+    /// A half-typed line becomes readable in raw mode. In canonical mode
+    /// queued bytes after the last line end become the line being edited
+    /// again; as much of it as fits, with the rest readable as a line of
+    /// its own.
+    pub fn set_canonical(&mut self, canonical: bool) {
+        if !canonical {
+            self.finish_line(None);
+            return;
+        }
+        let is_end = |entry| entry == EOF_MARK || entry == u16::from(b'\n');
+        let entries = (0..self.len).map(|index| self.queue[(self.head + index) % INPUT_SIZE]);
+        let lines = entries.clone().filter(|&entry| is_end(entry)).count();
+        let trailing = entries.rev().take_while(|&entry| !is_end(entry)).count();
+
+        // One byte is kept for the newline
+        let kept = trailing.min(MAX_CANON - 1);
+        let start = self.len - kept;
+        for index in 0..kept {
+            self.line[index] = self.queue[(self.head + start + index) % INPUT_SIZE] as u8;
+        }
+        self.line_len = kept;
+        self.len = start;
+        self.lines = lines;
+        if trailing > kept {
+            self.push(EOF_MARK);
+            self.lines += 1;
+        }
+    }
+
+    /// Drop all typed input
+    pub fn flush(&mut self) {
+        self.line_len = 0;
+        self.head = 0;
+        self.len = 0;
+        self.lines = 0;
+    }
+
+    /// Remove the last character of the line; returns whether there was one
+    fn erase(&mut self) -> bool {
+        if self.line_len == 0 {
+            return false;
+        }
+        // A whole UTF-8 sequence: continuation bytes, then its lead byte
+        while self.line_len > 1 && self.line[self.line_len - 1] & 0xC0 == 0x80 {
+            self.line_len -= 1;
+        }
+        self.line_len -= 1;
+        true
+    }
+
+    /// Make the line readable, ended with `terminator`
+    ///
+    /// A line that doesn't fit in the queue is dropped.
+    fn finish_line(&mut self, terminator: Option<u16>) {
+        let needed = self.line_len + usize::from(terminator.is_some());
+        if needed == 0 {
+            return;
+        }
+        if INPUT_SIZE - self.len >= needed {
+            for index in 0..self.line_len {
+                self.push(u16::from(self.line[index]));
+            }
+            if let Some(terminator) = terminator {
+                self.push(terminator);
+                self.lines += 1;
+            }
+        }
+        self.line_len = 0;
+    }
+
+    fn push(&mut self, entry: u16) {
+        if self.len < INPUT_SIZE {
+            self.queue[(self.head + self.len) % INPUT_SIZE] = entry;
+            self.len += 1;
+        }
+    }
+
+    fn pop(&mut self) -> Option<u16> {
+        if self.len == 0 {
+            return None;
+        }
+        let entry = self.queue[self.head];
+        self.head = (self.head + 1) % INPUT_SIZE;
+        self.len -= 1;
+        Some(entry)
+    }
+}
+
+impl Default for LineDiscipline {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Echo `byte`, showing control characters as `^X` if asked to
+fn echo_byte(byte: u8, termios: &Termios, echo: &mut impl FnMut(&[u8])) {
+    let control = byte < 0x20 && byte != b'\n' && byte != b'\t' || byte == 0x7F;
+    if control && termios.has(ECHOCTL) {
+        echo(&[b'^', byte ^ 0x40]);
+    } else {
+        echo(&[byte]);
+    }
+}
+
+/// Echo erasing `count` characters with the erase or kill character `byte`
+fn echo_erase(count: usize, termios: &Termios, byte: u8, echo: &mut impl FnMut(&[u8])) {
+    let visual = if byte == termios.cc[VKILL] { ECHOK } else { ECHOE };
+    if termios.has(visual) {
+        for _ in 0..count {
+            echo(b"\x08 \x08");
+        }
+    } else {
+        echo_byte(byte, termios, echo);
+    }
+}
@@ -0,0 +1,302 @@
+//! Terminals
+//!
+//! A `Tty` sits between an input source, which hands it typed bytes with
+//! `receive`, and the processes reading and writing it. Typed bytes go
+//! through the terminal's line discipline, which edits lines, echoes and
+//! turns the interrupt, quit and suspend characters into signals for the
+//! terminal's foreground process. Output goes straight to the device.
+//!
+//! The console is the screen and first serial port, with the keyboard and
+//! COM1 as input. It is the controlling terminal of `init`, and every
+//! process inherits its parent's, with descriptors 0, 1 and 2 open on it.
+//! COM2 is a second terminal, `ttyS1`.
+
+pub mod line_discipline;
+pub mod termios;
+
+use crate::process::{self, signal, Pid};
+use crate::sync::{SpinLock, WaitQueue};
+use crate::task::thread_scheduler;
+use core::fmt;
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, AtomicU32, AtomicU64, Ordering};
+use line_discipline::LineDiscipline;
+use termios::{Termios, Winsize};
+use x86_64::instructions::interrupts;
+
+/// Terminal errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TtyError {
+    /// A signal arrived while waiting for input
+    Interrupted,
+}
+
+impl fmt::Display for TtyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TtyError::Interrupted => write!(f, "Interrupted system call"),
+        }
+    }
+}
+
+pub type TtyResult<T> = Result<T, TtyError>;
+
+/// When new settings take effect
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetWhen {
+    Now,
+    /// After discarding typed input
+    Flush,
+}
+
+struct TtyState {
+    termios: Termios,
+    discipline: LineDiscipline,
+}
+
+/// A terminal
+pub struct Tty {
+    name: &'static str,
+    /// Locked with interrupts disabled
+    state: SpinLock<TtyState>,
+    /// Readers waiting for input
+    readable: WaitQueue,
+    /// Process that receives signal characters, 0 if none
+    foreground: AtomicU32,
+    winsize: Winsize,
+    output: fn(&[u8]),
+}
+
+impl Tty {
+    /// Terminal of `rows` by `columns` that writes through `output`
+    pub const fn new(name: &'static str, rows: u16, columns: u16, output: fn(&[u8])) -> Self {
+        Tty {
+            name,
+            state: SpinLock::new(TtyState {
+                termios: Termios::cooked(),
+                discipline: LineDiscipline::new(),
+            }),
+            readable: WaitQueue::new(),
+            foreground: AtomicU32::new(0),
+            winsize: Winsize { rows, columns, x_pixels: 0, y_pixels: 0 },
+            output,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn winsize(&self) -> Winsize {
+        self.winsize
+    }
+
+    pub fn termios(&self) -> Termios {
+        self.with_state(|state| state.termios)
+    }
+
+    /// Change the settings; returns the old ones
+    pub fn set_termios(&self, termios: Termios, when: SetWhen) -> Termios {
+        let old = self.with_state(|state| {
+            if when == SetWhen::Flush {
+                state.discipline.flush();
+            }
+            if termios.is_canonical() != state.termios.is_canonical() {
+                state.discipline.set_canonical(termios.is_canonical());
+            }
+            core::mem::replace(&mut state.termios, termios)
+        });
+        // Input may have become readable in the new mode
+        self.readable.notify_all();
+        old
+    }
+
+    /// Process that receives signal characters
+    pub fn foreground(&self) -> Option<Pid> {
+        match self.foreground.load(Ordering::Relaxed) {
+            0 => None,
+            pid => Some(Pid::new(pid)),
+        }
+    }
+
+    pub fn set_foreground(&self, pid: Option<Pid>) {
+        self.foreground.store(pid.map_or(0, Pid::as_u32), Ordering::Relaxed);
+    }
+
+    /// Take typed bytes from the input device
+    ///
+    /// Runs in thread or task context: signals take the process table
+    /// lock.
+    pub fn receive(&self, bytes: &[u8]) {
+        for &byte in bytes {
+            let signal = self.with_state(|state| {
+                let termios = state.termios;
+                state.discipline.receive(byte, &termios, &mut |echo| (self.output)(echo))
+            });
+            if let Some(signal) = signal {
+                if let Some(pid) = self.foreground() {
+                    let _ = signal::send(pid, signal);
+                }
+            }
+        }
+        // Also wakes readers to notice a signal
+        self.readable.notify_all();
+    }
+
+    /// Write `bytes` to the terminal
+    pub fn write(&self, bytes: &[u8]) -> usize {
+        (self.output)(bytes);
+        bytes.len()
+    }
+
+    pub fn write_fmt(&'static self, args: fmt::Arguments) {
+        let _ = fmt::Write::write_fmt(&mut TtyWriter(self), args);
+    }
+
+    /// Read typed input, blocking the running thread until there is some
+    ///
+    /// Fails once a signal is pending for the calling process, so Ctrl+C
+    /// ends a blocked read.
+    pub fn read(&self, buf: &mut [u8]) -> TtyResult<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let mut result = Err(TtyError::Interrupted);
+        self.readable.wait_until(|| {
+            if let Some(count) = self.try_read(buf) {
+                result = Ok(count);
+                return true;
+            }
+            signal::interrupted()
+        });
+        result
+    }
+
+    /// Read typed input from an async task
+    pub async fn read_async(&self, buf: &mut [u8]) -> usize {
+        if buf.is_empty() {
+            return 0;
+        }
+        loop {
+            // Registered before checking so no input is missed
+            let wait = self.readable.wait_async();
+            if let Some(count) = self.try_read(buf) {
+                return count;
+            }
+            wait.await;
+        }
+    }
+
+    fn try_read(&self, buf: &mut [u8]) -> Option<usize> {
+        self.with_state(|state| {
+            let termios = state.termios;
+            state.discipline.read(buf, &termios)
+        })
+    }
+
+    fn with_state<R>(&self, f: impl FnOnce(&mut TtyState) -> R) -> R {
+        interrupts::without_interrupts(|| f(&mut self.state.lock()))
+    }
+}
+
+impl fmt::Debug for Tty {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Tty").field("name", &self.name).finish()
+    }
+}
+
+/// `fmt::Write` to a terminal
+pub struct TtyWriter(pub &'static Tty);
+
+impl fmt::Write for TtyWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0.write(s.as_bytes());
+        Ok(())
+    }
+}
+
+/// Screen and first serial port
+fn console_output(bytes: &[u8]) {
+    for chunk in bytes.utf8_chunks() {
+        crate::vga_buffer::print_console(format_args!("{}", chunk.valid()));
+    }
+}
+
+static CONSOLE: Tty = Tty::new(
+    "console",
+    crate::vga_buffer::BUFFER_HEIGHT as u16,
+    crate::vga_buffer::BUFFER_WIDTH as u16,
+    console_output,
+);
+
+static COM2: Tty = Tty::new("ttyS1", 24, 80, crate::serial::write_com2);
+
+/// The system console
+pub fn console() -> &'static Tty {
+    &CONSOLE
+}
+
+/// The terminal on the second serial port
+pub fn com2() -> &'static Tty {
+    &COM2
+}
+
+/// Most threads printing to a terminal of their own at once
+const MAX_REDIRECTS: usize = 8;
+
+/// A thread whose `print!`s go to a terminal
+///
+/// Only the thread itself reads its slot, so claiming it and setting the
+/// terminal needn't be atomic together.
+struct Redirect {
+    /// Thread ID + 1, 0 while free
+    thread: AtomicU64,
+    tty: AtomicPtr<Tty>,
+}
+
+static REDIRECTS: [Redirect; MAX_REDIRECTS] = [const {
+    Redirect { thread: AtomicU64::new(0), tty: AtomicPtr::new(ptr::null_mut()) }
+}; MAX_REDIRECTS];
+
+/// Run `f` with the running thread's `print!`s going to `tty`
+///
+/// For work done on behalf of a terminal other than the console, like a
+/// shell command typed on COM2. Prints go to the console as usual if
+/// threading hasn't started or too many threads are redirected, and so do
+/// those of interrupt handlers that run meanwhile.
+pub fn with_output<R>(tty: &'static Tty, f: impl FnOnce() -> R) -> R {
+    let Some(thread) = thread_scheduler::running_thread().map(|id| id.as_u64() + 1) else {
+        return f();
+    };
+    let slot = REDIRECTS.iter().find(|slot| slot.thread.load(Ordering::Relaxed) == thread).or_else(|| {
+        REDIRECTS.iter().find(|slot| {
+            slot.thread.compare_exchange(0, thread, Ordering::Acquire, Ordering::Relaxed).is_ok()
+        })
+    });
+    let Some(slot) = slot else {
+        return f();
+    };
+
+    let previous = slot.tty.swap(ptr::from_ref(tty).cast_mut(), Ordering::Relaxed);
+    let result = f();
+    slot.tty.store(previous, Ordering::Relaxed);
+    if previous.is_null() {
+        slot.thread.store(0, Ordering::Release);
+    }
+    result
+}
+
+/// Terminal the running thread's `print!`s go to instead of the console
+///
+/// Lock-free and allocation-free, for `print!`.
+pub fn redirected() -> Option<&'static Tty> {
+    let thread = thread_scheduler::running_thread()?.as_u64() + 1;
+    let slot = REDIRECTS.iter().find(|slot| slot.thread.load(Ordering::Relaxed) == thread)?;
+    // Safety: only ever set from a `&'static Tty`
+    unsafe { slot.tty.load(Ordering::Relaxed).as_ref() }
+}
+
+/// Controlling terminal of the running process
+pub fn controlling() -> Option<&'static Tty> {
+    process::with_current(|process| process.terminal)
+}
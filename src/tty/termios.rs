@@ -0,0 +1,117 @@
+//! Terminal settings, laid out like Linux's kernel `struct termios`
+//!
+//! Only the flags the line discipline acts on are defined; the others are
+//! stored and handed back unchanged, so programs that save and restore
+//! settings keep working.
+
+/// Number of control characters
+pub const NCCS: usize = 19;
+
+// Input flags
+/// Translate carriage return to newline
+pub const ICRNL: u32 = 0o400;
+
+// Output flags
+pub const OPOST: u32 = 0o1;
+
+// Local flags
+/// Generate signals for the interrupt, quit and suspend characters
+pub const ISIG: u32 = 0o1;
+/// Canonical mode: input is read a line at a time and can be edited
+pub const ICANON: u32 = 0o2;
+pub const ECHO: u32 = 0o10;
+/// Echo the erase character as backspace, space, backspace
+pub const ECHOE: u32 = 0o20;
+/// Erase the line on screen on the kill character
+pub const ECHOK: u32 = 0o40;
+/// Echo control characters as `^X`
+pub const ECHOCTL: u32 = 0o1000;
+
+// Control character indices
+pub const VINTR: usize = 0;
+pub const VQUIT: usize = 1;
+pub const VERASE: usize = 2;
+pub const VKILL: usize = 3;
+pub const VEOF: usize = 4;
+/// Non-canonical reads wait for at least this many bytes
+pub const VMIN: usize = 6;
+pub const VSUSP: usize = 10;
+
+/// Terminal settings
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Termios {
+    pub iflag: u32,
+    pub oflag: u32,
+    pub cflag: u32,
+    pub lflag: u32,
+    pub line: u8,
+    pub cc: [u8; NCCS],
+}
+
+impl Termios {
+    /// Cooked mode: line editing, echo and signal characters
+    pub const fn cooked() -> Self {
+        let mut cc = [0; NCCS];
+        cc[VINTR] = 0x03; // ^C
+        cc[VQUIT] = 0x1C; // ^\
+        cc[VERASE] = 0x7F;
+        cc[VKILL] = 0x15; // ^U
+        cc[VEOF] = 0x04; // ^D
+        cc[VMIN] = 1;
+        cc[VSUSP] = 0x1A; // ^Z
+        Termios {
+            iflag: ICRNL,
+            oflag: OPOST,
+            cflag: 0,
+            lflag: ISIG | ICANON | ECHO | ECHOE | ECHOK | ECHOCTL,
+            line: 0,
+            cc,
+        }
+    }
+
+    /// These settings with every byte passed through as typed, like
+    /// `cfmakeraw`
+    pub const fn raw(mut self) -> Self {
+        self.iflag &= !ICRNL;
+        self.lflag &= !(ISIG | ICANON | ECHO | ECHOE | ECHOK | ECHOCTL);
+        self.cc[VMIN] = 1;
+        self
+    }
+
+    pub fn is_canonical(&self) -> bool {
+        self.lflag & ICANON != 0
+    }
+
+    pub fn has(&self, lflag: u32) -> bool {
+        self.lflag & lflag != 0
+    }
+}
+
+impl Default for Termios {
+    fn default() -> Self {
+        Self::cooked()
+    }
+}
+
+/// Terminal size, as in `TIOCGWINSZ`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Winsize {
+    pub rows: u16,
+    pub columns: u16,
+    pub x_pixels: u16,
+    pub y_pixels: u16,
+}
+
+// ioctl requests, with Linux's numbers
+pub const TCGETS: u64 = 0x5401;
+/// Set now
+pub const TCSETS: u64 = 0x5402;
+/// Set after output drains; output never waits, so the same as `TCSETS`
+pub const TCSETSW: u64 = 0x5403;
+/// Set and discard pending input
+pub const TCSETSF: u64 = 0x5404;
+pub const TIOCGPGRP: u64 = 0x540F;
+pub const TIOCSPGRP: u64 = 0x5410;
+pub const TIOCGWINSZ: u64 = 0x5413;
@@ -0,0 +1,158 @@
+//! Running code in ring 3
+
+use crate::{gdt, process};
+use core::sync::atomic::{AtomicU64, Ordering};
+use x86_64::VirtAddr;
+use x86_64::structures::paging::PageTableFlags;
+
+pub mod test_program;
+
+/// End of the lower canonical half, which user addresses must stay below
+pub const USER_SPACE_END: u64 = 0x0000_8000_0000_0000;
+
+/// Whether the `len` bytes at `address` lie wholly in user space
+///
+/// User addresses are canonical, so this also rules out addresses that
+/// would fault in ring 0.
+pub fn is_user_range(address: u64, len: u64) -> bool {
+    address.checked_add(len).is_some_and(|end| end <= USER_SPACE_END)
+}
+
+/// Where user programs are loaded
+pub const USER_CODE_BASE: u64 = 0x0000_1000_0000_0000;
+
+/// Top of the user stack (the stack grows down from here)
+pub const USER_STACK_TOP: u64 = 0x0000_1000_0080_0000;
+
+/// Size of the user stack
+pub const USER_STACK_SIZE: u64 = 4096 * 4; // 16 KiB
+
+/// Kernel stack pointer to return to when the running user program exits,
+/// or 0 if no program entered through `enter_user` is running.
+static USER_RETURN_RSP: AtomicU64 = AtomicU64::new(0);
+
+/// Switch to ring 3 at `entry` with the given user stack.
+///
+/// Returns the exit code once the program calls `exit`. Traps from ring 3
+/// land on the current kernel stack, just below the frame saved here.
+///
+/// # Safety
+/// `entry` and `user_stack` must point into user-accessible mappings that
+/// contain the program and a writable stack.
+pub unsafe fn enter_user(entry: VirtAddr, user_stack: VirtAddr) -> i64 {
+    let selectors = gdt::selectors();
+    enter_user_asm(
+        entry.as_u64(),
+        user_stack.as_u64(),
+        selectors.user_code_selector.0 as u64,
+        selectors.user_data_selector.0 as u64,
+    )
+}
+
+/// Whether a program started by [`enter_user`] is currently running
+pub fn user_program_running() -> bool {
+    USER_RETURN_RSP.load(Ordering::Acquire) != 0
+}
+
+/// Leave ring 3 for good and make the pending `enter_user` return `code`.
+///
+/// # Panics
+/// Panics if no program entered through `enter_user` is running.
+pub fn exit_user(code: i64) -> ! {
+    let rsp = USER_RETURN_RSP.swap(0, Ordering::AcqRel);
+    assert!(rsp != 0, "exit_user called without a running user program");
+    unsafe { exit_user_asm(rsp, code) }
+}
+
+/// Remember the kernel stack that `enter_user_asm` should come back to and
+/// point the TSS at it so traps from ring 3 land below the saved frame.
+extern "C" fn set_user_return_stack(rsp: u64) {
+    USER_RETURN_RSP.store(rsp, Ordering::Release);
+    crate::task::thread_scheduler::set_current_kernel_stack(VirtAddr::new(rsp));
+}
+
+#[unsafe(naked)]
+unsafe extern "C" fn enter_user_asm(entry: u64, user_stack: u64, user_cs: u64, user_ss: u64) -> i64 {
+    core::arch::naked_asm!(
+        // Save callee-saved registers and flags for `exit_user_asm`
+        "pushfq",
+        "push rbp",
+        "push rbx",
+        "push r12",
+        "push r13",
+        "push r14",
+        "push r15",
+
+        "mov r12, rdi",
+        "mov r13, rsi",
+        "mov r14, rdx",
+        "mov r15, rcx",
+
+        "mov rdi, rsp",
+        "call {set_return_stack}",
+
+        // Build the frame for iretq: ss, rsp, rflags (IF set), cs, rip
+        "push r15",
+        "push r13",
+        "push 0x202",
+        "push r14",
+        "push r12",
+
+        // Don't leak kernel values into ring 3
+        "xor eax, eax",
+        "xor ebx, ebx",
+        "xor ecx, ecx",
+        "xor edx, edx",
+        "xor esi, esi",
+        "xor edi, edi",
+        "xor ebp, ebp",
+        "xor r8d, r8d",
+        "xor r9d, r9d",
+        "xor r10d, r10d",
+        "xor r11d, r11d",
+        "xor r12d, r12d",
+        "xor r13d, r13d",
+        "xor r14d, r14d",
+        "xor r15d, r15d",
+        "iretq",
+        set_return_stack = sym set_user_return_stack,
+    )
+}
+
+#[unsafe(naked)]
+unsafe extern "C" fn exit_user_asm(rsp: u64, code: i64) -> ! {
+    core::arch::naked_asm!(
+        "mov rsp, rdi",
+        "mov rax, rsi",
+        "pop r15",
+        "pop r14",
+        "pop r13",
+        "pop r12",
+        "pop rbx",
+        "pop rbp",
+        "popfq",
+        "ret",
+    )
+}
+
+/// Map `code` at [`USER_CODE_BASE`] with a fresh stack in the current
+/// process's address space and run it in ring 3.
+///
+/// Returns the program's exit code.
+pub fn run_program(code: &[u8]) -> Result<i64, &'static str> {
+    let code_start = VirtAddr::new(USER_CODE_BASE);
+    let stack_top = VirtAddr::new(USER_STACK_TOP);
+
+    process::with_current(|process| {
+        let memory = &mut process.address_space;
+        memory.map_user_region(code_start, code.len() as u64, PageTableFlags::WRITABLE)
+            .map_err(|_| "failed to map user code")?;
+        memory.map_user_region(stack_top - USER_STACK_SIZE, USER_STACK_SIZE, PageTableFlags::WRITABLE)
+            .map_err(|_| "failed to map user stack")
+    })?;
+
+    unsafe {
+        core::ptr::copy_nonoverlapping(code.as_ptr(), code_start.as_mut_ptr::<u8>(), code.len());
+        Ok(enter_user(code_start, stack_top))
+    }
+}
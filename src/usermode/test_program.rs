@@ -0,0 +1,42 @@
+//! A tiny position-independent program used to exercise ring 3.
+//!
+//! It writes a greeting to stdout with `int 0x80` and exits with the number
+//! of bytes the kernel reported as written.
+
+core::arch::global_asm!(
+    ".pushsection .rodata.lithos_user_test, \"a\"",
+    ".balign 16",
+    ".global lithos_user_test_start",
+    ".global lithos_user_test_end",
+    "lithos_user_test_start:",
+    "    mov rax, 1",                            // write
+    "    mov rdi, 1",                            // stdout
+    "    lea rsi, [rip + lithos_user_test_msg]",
+    "    mov rdx, 19",                           // message length
+    "    int 0x80",
+    "    mov rdi, rax",                          // exit(bytes written)
+    "    mov rax, 60",
+    "    int 0x80",
+    "    ud2",
+    "lithos_user_test_msg:",
+    "    .ascii \"Hello from ring 3!\\n\"",
+    "lithos_user_test_end:",
+    ".popsection",
+);
+
+extern "C" {
+    static lithos_user_test_start: u8;
+    static lithos_user_test_end: u8;
+}
+
+/// Length of the greeting, which is also the expected exit code
+pub const EXPECTED_EXIT_CODE: i64 = 19;
+
+/// Machine code of the test program
+pub fn code() -> &'static [u8] {
+    unsafe {
+        let start = &raw const lithos_user_test_start;
+        let end = &raw const lithos_user_test_end;
+        core::slice::from_raw_parts(start, end as usize - start as usize)
+    }
+}
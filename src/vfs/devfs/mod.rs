@@ -1,12 +1,19 @@
 use super::{VfsNode, VfsNodeRef, FileType, Permissions, VfsResult, VfsError};
 use alloc::{string::String, vec::Vec, vec, sync::Arc};
-use spin::Mutex;
+use crate::sync::SpinLock;
+use crate::tty::{self, Tty};
 
 /// Device file types
 pub enum DeviceNode {
     Null,
     Zero,
     Random,
+    URandom,
+    /// Controlling terminal of whoever opens it
+    Tty,
+    Console,
+    /// COM2
+    TtyS1,
 }
 
 impl VfsNode for DeviceNode {
@@ -32,19 +39,14 @@ impl VfsNode for DeviceNode {
                 }
                 Ok(buf.len())
             }
-            DeviceNode::Random => {
-                // Simple pseudo-random (not cryptographically secure)
-                use core::sync::atomic::{AtomicU64, Ordering};
-                static SEED: AtomicU64 = AtomicU64::new(0x123456789ABCDEF0);
-                
-                for byte in buf.iter_mut() {
-                    let seed = SEED.load(Ordering::Relaxed);
-                    let next = seed.wrapping_mul(1103515245).wrapping_add(12345);
-                    SEED.store(next, Ordering::Relaxed);
-                    *byte = (next >> 16) as u8;
-                }
+            DeviceNode::Random | DeviceNode::URandom => {
+                // Both are backed by the ChaCha20 CSPRNG, which is seeded at boot
+                crate::random::fill_bytes(buf);
                 Ok(buf.len())
             }
+            // Terminal reads block, so they go through the descriptor
+            // without holding the node lock
+            DeviceNode::Tty | DeviceNode::Console | DeviceNode::TtyS1 => Err(VfsError::IoError),
         }
     }
     
@@ -52,7 +54,14 @@ impl VfsNode for DeviceNode {
         match self {
             DeviceNode::Null => Ok(buf.len()), // Discard all writes
             DeviceNode::Zero => Err(VfsError::PermissionDenied), // Can't write to /dev/zero
-            DeviceNode::Random => Err(VfsError::PermissionDenied), // Can't write to /dev/random
+            DeviceNode::Random | DeviceNode::URandom => {
+                // Mixed into the entropy pool, but not credited
+                crate::random::add_bytes(buf);
+                Ok(buf.len())
+            }
+            DeviceNode::Tty | DeviceNode::Console | DeviceNode::TtyS1 => {
+                self.tty().map(|tty| tty.write(buf)).ok_or(VfsError::IoError)
+            }
         }
     }
     
@@ -67,13 +76,86 @@ impl VfsNode for DeviceNode {
     fn create(&mut self, _name: &str, _file_type: FileType) -> VfsResult<VfsNodeRef> {
         Err(VfsError::NotADirectory)
     }
+
+    fn tty(&self) -> Option<&'static Tty> {
+        match self {
+            DeviceNode::Tty => tty::controlling(),
+            DeviceNode::Console => Some(tty::console()),
+            DeviceNode::TtyS1 => Some(tty::com2()),
+            _ => None,
+        }
+    }
 }
 
 /// Create /dev filesystem nodes
 pub fn create_dev_nodes() -> Vec<(&'static str, VfsNodeRef)> {
     vec![
-        ("null", Arc::new(Mutex::new(DeviceNode::Null)) as VfsNodeRef),
-        ("zero", Arc::new(Mutex::new(DeviceNode::Zero)) as VfsNodeRef),
-        ("random", Arc::new(Mutex::new(DeviceNode::Random)) as VfsNodeRef),
+        ("null", Arc::new(SpinLock::new(DeviceNode::Null)) as VfsNodeRef),
+        ("zero", Arc::new(SpinLock::new(DeviceNode::Zero)) as VfsNodeRef),
+        ("random", Arc::new(SpinLock::new(DeviceNode::Random)) as VfsNodeRef),
+        ("urandom", Arc::new(SpinLock::new(DeviceNode::URandom)) as VfsNodeRef),
+        ("tty", Arc::new(SpinLock::new(DeviceNode::Tty)) as VfsNodeRef),
+        ("console", Arc::new(SpinLock::new(DeviceNode::Console)) as VfsNodeRef),
+        ("ttyS1", Arc::new(SpinLock::new(DeviceNode::TtyS1)) as VfsNodeRef),
     ]
 }
+
+/// Directory of the device nodes, to mount at /dev
+pub struct DevDirectory {
+    entries: Vec<(&'static str, VfsNodeRef)>,
+}
+
+impl DevDirectory {
+    pub fn new() -> Self {
+        DevDirectory { entries: create_dev_nodes() }
+    }
+
+    pub fn root_node(self) -> VfsNodeRef {
+        Arc::new(SpinLock::new(self)) as VfsNodeRef
+    }
+}
+
+impl Default for DevDirectory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VfsNode for DevDirectory {
+    fn file_type(&self) -> FileType {
+        FileType::Directory
+    }
+
+    fn size(&self) -> usize {
+        0
+    }
+
+    fn permissions(&self) -> Permissions {
+        Permissions::new(0o755)
+    }
+
+    fn read_at(&self, _offset: usize, _buf: &mut [u8]) -> VfsResult<usize> {
+        Err(VfsError::IsADirectory)
+    }
+
+    fn write_at(&mut self, _offset: usize, _buf: &[u8]) -> VfsResult<usize> {
+        Err(VfsError::IsADirectory)
+    }
+
+    fn readdir(&self) -> VfsResult<Vec<String>> {
+        Ok(self.entries.iter().map(|(name, _)| String::from(*name)).collect())
+    }
+
+    fn lookup(&self, name: &str) -> VfsResult<VfsNodeRef> {
+        self.entries
+            .iter()
+            .find(|(entry, _)| *entry == name)
+            .map(|(_, node)| node.clone())
+            .ok_or(VfsError::NotFound)
+    }
+
+    fn create(&mut self, _name: &str, _file_type: FileType) -> VfsResult<VfsNodeRef> {
+        // The set of devices is fixed
+        Err(VfsError::PermissionDenied)
+    }
+}
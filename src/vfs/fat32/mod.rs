@@ -5,19 +5,19 @@ use crate::drivers::block::BlockDevice;
 use alloc::string::String;
 use alloc::vec::Vec;
 use alloc::sync::Arc;
-use spin::Mutex;
+use crate::sync::SpinLock;
 
 pub use structs::*;
 
 /// FAT32 File System
 pub struct Fat32Fs {
-    device: Arc<Mutex<dyn BlockDevice>>,
+    device: Arc<SpinLock<dyn BlockDevice>>,
     boot_sector: BootSector,
 }
 
 impl Fat32Fs {
     /// Mount a FAT32 filesystem from a block device
-    pub fn mount(device: Arc<Mutex<dyn BlockDevice>>) -> VfsResult<Self> {
+    pub fn mount(device: Arc<SpinLock<dyn BlockDevice>>) -> VfsResult<Self> {
         // Read boot sector
         let mut boot_buf = [0u8; 512];
         device.lock().read_block(0, &mut boot_buf)
@@ -1,6 +1,6 @@
 use super::{VfsResult, VfsError};
+use crate::tty::Tty;
 use alloc::collections::BTreeMap;
-use spin::Mutex;
 
 /// File descriptor
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
@@ -12,6 +12,9 @@ pub struct OpenFile {
     // For now, we'll use a simple offset tracker
     pub offset: usize,
     pub flags: OpenFlags,
+    /// Terminal the descriptor is open on; read and written without
+    /// holding a node lock, since reads block
+    pub tty: Option<&'static Tty>,
 }
 
 /// File open flags
@@ -52,7 +55,7 @@ impl OpenFlags {
     }
 }
 
-/// File descriptor table
+/// File descriptor table of a process (see `process::Process`)
 pub struct FdTable {
     files: BTreeMap<FileDescriptor, OpenFile>,
     next_fd: usize,
@@ -66,14 +69,25 @@ impl FdTable {
         }
     }
 
-    /// Allocate a new file descriptor
-    pub fn alloc(&mut self, flags: OpenFlags) -> FileDescriptor {
+    /// Table with stdin, stdout and stderr open on `tty`
+    pub fn with_terminal(tty: &'static Tty) -> Self {
+        let mut table = Self::new();
+        let standard = [OpenFlags::read_only(), OpenFlags::write_only(), OpenFlags::write_only()];
+        for (fd, flags) in standard.into_iter().enumerate() {
+            table.files.insert(FileDescriptor(fd), OpenFile { offset: 0, flags, tty: Some(tty) });
+        }
+        table
+    }
+
+    /// Allocate a new file descriptor, on `tty` if it names a terminal
+    pub fn alloc(&mut self, flags: OpenFlags, tty: Option<&'static Tty>) -> FileDescriptor {
         let fd = FileDescriptor(self.next_fd);
         self.next_fd += 1;
         
         self.files.insert(fd, OpenFile {
             offset: 0,
             flags,
+            tty,
         });
         
         fd
@@ -95,10 +109,3 @@ impl FdTable {
         Ok(())
     }
 }
-
-static GLOBAL_FD_TABLE: Mutex<FdTable> = Mutex::new(FdTable::new());
-
-/// Get the global file descriptor table
-pub fn global_fd_table() -> &'static Mutex<FdTable> {
-    &GLOBAL_FD_TABLE
-}
@@ -8,7 +8,8 @@ pub mod devfs;
 use alloc::string::String;
 use alloc::vec::Vec;
 use alloc::sync::Arc;
-use spin::Mutex;
+use crate::sync::SpinLock;
+use crate::tty::Tty;
 use core::fmt;
 
 /// File types
@@ -55,6 +56,9 @@ pub enum VfsError {
     InvalidPath,
     IoError,
     NoSpace,
+    /// A signal arrived while waiting
+    Interrupted,
+    NotATerminal,
 }
 
 impl fmt::Display for VfsError {
@@ -68,6 +72,8 @@ impl fmt::Display for VfsError {
             VfsError::InvalidPath => write!(f, "Invalid path"),
             VfsError::IoError => write!(f, "I/O error"),
             VfsError::NoSpace => write!(f, "No space left"),
+            VfsError::Interrupted => write!(f, "Interrupted system call"),
+            VfsError::NotATerminal => write!(f, "Not a terminal"),
         }
     }
 }
@@ -75,7 +81,7 @@ impl fmt::Display for VfsError {
 pub type VfsResult<T> = Result<T, VfsError>;
 
 /// Type alias for VFS node references
-pub type VfsNodeRef = Arc<Mutex<dyn VfsNode>>;
+pub type VfsNodeRef = Arc<SpinLock<dyn VfsNode>>;
 
 /// VFS node trait - represents files, directories, and devices
 pub trait VfsNode: Send + Sync {
@@ -102,4 +108,9 @@ pub trait VfsNode: Send + Sync {
     
     /// Create a new file in this directory
     fn create(&mut self, name: &str, file_type: FileType) -> VfsResult<VfsNodeRef>;
+
+    /// Terminal this node opens, for terminal devices
+    fn tty(&self) -> Option<&'static Tty> {
+        None
+    }
 }
@@ -1,13 +1,47 @@
-use super::{VfsNodeRef, VfsResult, VfsError, FileType, fd_table::{FileDescriptor, OpenFlags, global_fd_table}};
+use super::{VfsNodeRef, VfsResult, VfsError, FileType, fd_table::{FileDescriptor, OpenFlags}};
+use crate::process;
+use crate::tty::Tty;
 use alloc::string::String;
 use alloc::vec::Vec;
-use spin::Mutex;
+use crate::sync::SpinLock;
 
-static ROOT_FS: Mutex<Option<VfsNodeRef>> = Mutex::new(None);
+static ROOT_FS: SpinLock<Option<VfsNodeRef>> = SpinLock::new(None);
+
+/// Filesystems mounted over directories, by path without trailing `/`
+static MOUNTS: SpinLock<Vec<(String, VfsNodeRef)>> = SpinLock::new(Vec::new());
 
 /// Initialize the VFS with a root filesystem
 pub fn init(root: VfsNodeRef) {
     *ROOT_FS.lock() = Some(root);
+    MOUNTS.lock().clear();
+}
+
+/// Mount the filesystem `root` over the directory at `path`
+pub fn mount(path: &str, root: VfsNodeRef) -> VfsResult<()> {
+    if resolve_path(path)?.lock().file_type() != FileType::Directory {
+        return Err(VfsError::NotADirectory);
+    }
+    let mut normalized = String::new();
+    for component in path.split('/').filter(|s| !s.is_empty()) {
+        normalized.push('/');
+        normalized.push_str(component);
+    }
+    if normalized.is_empty() {
+        return Err(VfsError::InvalidPath);
+    }
+
+    let mut mounts = MOUNTS.lock();
+    mounts.retain(|(mounted, _)| *mounted != normalized);
+    mounts.push((normalized, root));
+    Ok(())
+}
+
+/// Root of the filesystem mounted at `path`, if any
+fn mounted(path: &str) -> Option<VfsNodeRef> {
+    MOUNTS.lock()
+        .iter()
+        .find(|(mounted, _)| mounted == path)
+        .map(|(_, root)| root.clone())
 }
 
 /// Get the root filesystem node
@@ -34,9 +68,12 @@ pub fn resolve_path(path: &str) -> VfsResult<VfsNodeRef> {
     // Split path and traverse
     let components: Vec<&str> = path[1..].split('/').filter(|s| !s.is_empty()).collect();
 
+    let mut walked = String::new();
     for component in components {
+        walked.push('/');
+        walked.push_str(component);
         let node = current.lock().lookup(component)?;
-        current = node;
+        current = mounted(&walked).unwrap_or(node);
     }
 
     Ok(current)
@@ -44,22 +81,25 @@ pub fn resolve_path(path: &str) -> VfsResult<VfsNodeRef> {
 
 /// Open a file and return a file descriptor
 pub fn vfs_open(path: &str, flags: OpenFlags) -> VfsResult<FileDescriptor> {
-    let _node = resolve_path(path)?;
+    let node = resolve_path(path)?;
+    let tty = node.lock().tty();
     
-    // Allocate file descriptor
-    let fd = global_fd_table().lock().alloc(flags);
+    // Allocate file descriptor in the caller's process
+    let fd = process::with_current(|process| process.fd_table.alloc(flags, tty));
     
     Ok(fd)
 }
 
 /// Read from a file descriptor
-pub fn vfs_read(fd: FileDescriptor, _buf: &mut [u8]) -> VfsResult<usize> {
-    let mut fd_table = global_fd_table().lock();
-    let open_file = fd_table.get_mut(fd).ok_or(VfsError::NotFound)?;
+pub fn vfs_read(fd: FileDescriptor, buf: &mut [u8]) -> VfsResult<usize> {
+    let (flags, tty) = open_file(fd)?;
     
-    if !open_file.flags.read {
+    if !flags.read {
         return Err(VfsError::PermissionDenied);
     }
+    if let Some(tty) = tty {
+        return tty.read(buf).map_err(|_| VfsError::Interrupted);
+    }
 
     // In a real implementation, we'd read from the actual file node
     // For now, just return 0 (EOF)
@@ -68,21 +108,34 @@ pub fn vfs_read(fd: FileDescriptor, _buf: &mut [u8]) -> VfsResult<usize> {
 
 /// Write to a file descriptor
 pub fn vfs_write(fd: FileDescriptor, buf: &[u8]) -> VfsResult<usize> {
-    let mut fd_table = global_fd_table().lock();
-    let open_file = fd_table.get_mut(fd).ok_or(VfsError::NotFound)?;
+    let (flags, tty) = open_file(fd)?;
     
-    if !open_file.flags.write {
+    if !flags.write {
         return Err(VfsError::PermissionDenied);
     }
+    if let Some(tty) = tty {
+        return Ok(tty.write(buf));
+    }
 
     // In a real implementation, we'd write to the actual file node
     // For now, just return the buffer length
     Ok(buf.len())
 }
 
+/// Flags and terminal of an open file of the caller's process
+fn open_file(fd: FileDescriptor) -> VfsResult<(OpenFlags, Option<&'static Tty>)> {
+    process::with_current(|process| process.fd_table.get(fd).map(|file| (file.flags, file.tty)))
+        .ok_or(VfsError::NotFound)
+}
+
+/// Terminal an open file of the caller's process is on
+pub fn vfs_tty(fd: FileDescriptor) -> VfsResult<&'static Tty> {
+    open_file(fd)?.1.ok_or(VfsError::NotATerminal)
+}
+
 /// Close a file descriptor
 pub fn vfs_close(fd: FileDescriptor) -> VfsResult<()> {
-    global_fd_table().lock().close(fd)
+    process::with_current(|process| process.fd_table.close(fd))
 }
 
 /// Create a directory
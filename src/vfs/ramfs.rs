@@ -3,7 +3,7 @@ use alloc::string::String;
 use alloc::vec::Vec;
 use alloc::collections::BTreeMap;
 use alloc::sync::Arc;
-use spin::Mutex;
+use crate::sync::SpinLock;
 use core::sync::atomic::{AtomicU64, Ordering};
 
 /// In-memory file system node
@@ -30,7 +30,7 @@ impl RamFile {
 /// In-memory directory
 pub struct RamDirectory {
     inode: Inode,
-    entries: BTreeMap<String, Arc<Mutex<RamFsNode>>>,
+    entries: BTreeMap<String, Arc<SpinLock<RamFsNode>>>,
 }
 
 impl RamDirectory {
@@ -41,11 +41,11 @@ impl RamDirectory {
         }
     }
 
-    pub fn insert(&mut self, name: String, node: Arc<Mutex<RamFsNode>>) {
+    pub fn insert(&mut self, name: String, node: Arc<SpinLock<RamFsNode>>) {
         self.entries.insert(name, node);
     }
 
-    pub fn get(&self, name: &str) -> Option<&Arc<Mutex<RamFsNode>>> {
+    pub fn get(&self, name: &str) -> Option<&Arc<SpinLock<RamFsNode>>> {
         self.entries.get(name)
     }
 
@@ -140,7 +140,7 @@ impl VfsNode for RamFsNode {
                     _ => return Err(VfsError::IoError),
                 };
 
-                let node_ref = Arc::new(Mutex::new(node));
+                let node_ref = Arc::new(SpinLock::new(node));
                 d.insert(name.into(), Arc::clone(&node_ref));
                 Ok(node_ref as VfsNodeRef)
             }
@@ -151,12 +151,12 @@ impl VfsNode for RamFsNode {
 
 /// RamFS file system
 pub struct RamFs {
-    root: Arc<Mutex<RamFsNode>>,
+    root: Arc<SpinLock<RamFsNode>>,
 }
 
 impl RamFs {
     pub fn new() -> Self {
-        let root = Arc::new(Mutex::new(
+        let root = Arc::new(SpinLock::new(
             RamFsNode::Directory(RamDirectory::new(0))
         ));
         RamFs { root }
@@ -40,8 +40,8 @@ struct ScreenChar {
     color_code: ColorCode,
 }
 
-const BUFFER_HEIGHT: usize = 25;
-const BUFFER_WIDTH: usize = 80;
+pub const BUFFER_HEIGHT: usize = 25;
+pub const BUFFER_WIDTH: usize = 80;
 
 #[repr(transparent)]
 struct Buffer {
@@ -58,6 +58,8 @@ impl Writer {
     pub fn write_byte(&mut self, byte: u8) {
         match byte {
             b'\n' => self.new_line(),
+            // Backspace moves the cursor left without erasing
+            0x08 => self.column_position = self.column_position.saturating_sub(1),
             byte => {
                 if self.column_position >= BUFFER_WIDTH {
                     self.new_line();
@@ -97,18 +99,56 @@ impl Writer {
         }
     }
 
+    /// Write `s` one cell per character, like the line editor counts them
     pub fn write_string(&mut self, s: &str) {
-        for byte in s.bytes() {
-            match byte {
-                // printable ASCII byte or newline
-                0x20..=0x7e | b'\n' => self.write_byte(byte),
-                // not part of printable ASCII range
-                _ => self.write_byte(0xfe),
-            }
+        for character in s.chars() {
+            self.write_byte(to_cp437(character));
+        }
+        self.move_cursor();
+    }
+
+    /// Put the blinking hardware cursor where the next character goes
+    fn move_cursor(&self) {
+        use x86_64::instructions::port::Port;
+
+        let column = self.column_position.min(BUFFER_WIDTH - 1);
+        let position = ((BUFFER_HEIGHT - 1) * BUFFER_WIDTH + column) as u16;
+        let mut index = Port::<u8>::new(0x3D4);
+        let mut data = Port::<u8>::new(0x3D5);
+        unsafe {
+            index.write(0x0F);
+            data.write((position & 0xFF) as u8);
+            index.write(0x0E);
+            data.write((position >> 8) as u8);
         }
     }
 }
 
+/// Upper half of code page 437, the VGA text mode character set
+const CP437_HIGH: [char; 128] = [
+    'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç', 'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å',
+    'É', 'æ', 'Æ', 'ô', 'ö', 'ò', 'û', 'ù', 'ÿ', 'Ö', 'Ü', '¢', '£', '¥', '₧', 'ƒ',
+    'á', 'í', 'ó', 'ú', 'ñ', 'Ñ', 'ª', 'º', '¿', '⌐', '¬', '½', '¼', '¡', '«', '»',
+    '░', '▒', '▓', '│', '┤', '╡', '╢', '╖', '╕', '╣', '║', '╗', '╝', '╜', '╛', '┐',
+    '└', '┴', '┬', '├', '─', '┼', '╞', '╟', '╚', '╔', '╩', '╦', '╠', '═', '╬', '╧',
+    '╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫', '╪', '┘', '┌', '█', '▄', '▌', '▐', '▀',
+    'α', 'ß', 'Γ', 'π', 'Σ', 'σ', 'µ', 'τ', 'Φ', 'Θ', 'Ω', 'δ', '∞', 'φ', 'ε', '∩',
+    '≡', '±', '≥', '≤', '⌠', '⌡', '÷', '≈', '°', '∙', '·', '√', 'ⁿ', '²', '■', '\u{a0}',
+];
+
+/// The VGA cell value showing `character`: printable ASCII, newline and
+/// backspace as themselves, the rest of code page 437 by table, and 0xfe
+/// (■) for anything else
+pub fn to_cp437(character: char) -> u8 {
+    match character {
+        ' '..='~' | '\n' | '\u{8}' => character as u8,
+        _ => CP437_HIGH
+            .iter()
+            .position(|&c| c == character)
+            .map_or(0xfe, |index| 0x80 + index as u8),
+    }
+}
+
 impl fmt::Write for Writer {
     fn write_str(&mut self, s: &str) -> fmt::Result {
         self.write_string(s);
@@ -117,10 +157,10 @@ impl fmt::Write for Writer {
 }
 
 use lazy_static::lazy_static;
-use spin::Mutex;
+use crate::sync::SpinLock;
 
 lazy_static! {
-    pub static ref WRITER: Mutex<Writer> = Mutex::new(Writer {
+    pub static ref WRITER: SpinLock<Writer> = SpinLock::new(Writer {
         column_position: 0,
         color_code: ColorCode::new(Color::Yellow, Color::Black),
         buffer: unsafe { &mut *(0xb8000 as *mut Buffer) },
@@ -140,6 +180,19 @@ macro_rules! println {
 
 #[doc(hidden)]
 pub fn _print(args: fmt::Arguments) {
+    // Handlers print on the system's behalf, not the interrupted thread's
+    if crate::interrupts::in_interrupt() {
+        return print_console(args);
+    }
+    match crate::tty::redirected() {
+        Some(tty) => tty.write_fmt(args),
+        None => print_console(args),
+    }
+}
+
+/// Print to the screen and COM1, even if the running thread's prints go
+/// to another terminal
+pub fn print_console(args: fmt::Arguments) {
     use core::fmt::Write;
     use x86_64::instructions::interrupts;
 
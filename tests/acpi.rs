@@ -0,0 +1,67 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(lithos::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use lithos::acpi::{self, AcpiError};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use lithos::allocator;
+    use lithos::memory::{self, BootInfoFrameAllocator};
+    use x86_64::VirtAddr;
+
+    lithos::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+    memory::install(mapper, frame_allocator);
+
+    acpi::init().expect("ACPI discovery failed");
+
+    test_main();
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    lithos::test_panic_handler(info)
+}
+
+#[test_case]
+fn test_tables_are_found() {
+    let info = acpi::info().expect("ACPI info missing");
+    assert!(info.tables.iter().all(|t| t.valid));
+    assert!(info.tables.iter().any(|t| t.signature == "APIC"));
+    assert!(info.tables.iter().any(|t| t.signature == "FACP"));
+    assert!(info.tables.iter().any(|t| t.signature == "DSDT"));
+}
+
+#[test_case]
+fn test_madt_lists_boot_cpu() {
+    let madt = acpi::info().unwrap().madt.as_ref().expect("no MADT");
+    assert!(madt.processors.iter().any(|cpu| cpu.enabled));
+    assert!(!madt.io_apics.is_empty());
+    assert_ne!(madt.local_apic_address, 0);
+}
+
+#[test_case]
+fn test_fadt_has_pm1a_control() {
+    let fadt = acpi::info().unwrap().fadt.as_ref().expect("no FADT");
+    assert_ne!(fadt.pm1a_control_block, 0);
+    assert_ne!(fadt.dsdt_address, 0);
+}
+
+#[test_case]
+fn test_find_table_and_double_init() {
+    let table = acpi::find_table(b"APIC").expect("MADT not found");
+    assert_eq!(&table.header.signature, b"APIC");
+    assert!(matches!(acpi::init(), Err(AcpiError::AlreadyInitialized)));
+}
@@ -23,3 +23,15 @@ fn panic(info: &PanicInfo) -> ! {
 fn test_println() {
     println!("test_println output");
 }
+
+#[test_case]
+fn test_one_cell_per_character() {
+    use lithos::vga_buffer::to_cp437;
+
+    assert_eq!(to_cp437('a'), b'a');
+    assert_eq!(to_cp437('é'), 0x82);
+    assert_eq!(to_cp437('£'), 0x9c);
+    assert_eq!(to_cp437('ß'), 0xe1);
+    assert_eq!(to_cp437('═'), 0xcd);
+    assert_eq!(to_cp437('€'), 0xfe);
+}
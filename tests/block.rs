@@ -0,0 +1,160 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(lithos::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::sync::Arc;
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use lithos::drivers::ata::AtaDrive;
+use lithos::drivers::block::ramdisk::RamDisk;
+use lithos::drivers::block::{AsyncBlockDevice, BlockDevice, BlockError, Request, BLOCK_SIZE};
+use lithos::task::executor::Executor;
+use lithos::task::{thread, Task};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use lithos::allocator;
+    use lithos::memory::{self, BootInfoFrameAllocator};
+    use x86_64::VirtAddr;
+
+    lithos::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+    memory::install(mapper, frame_allocator);
+
+    test_main();
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    lithos::test_panic_handler(info)
+}
+
+fn wait_for(flag: &AtomicBool) {
+    while !flag.load(Ordering::SeqCst) {
+        thread::yield_now();
+    }
+}
+
+/// The boot image is attached as the primary master
+fn boot_disk() -> AtaDrive {
+    AtaDrive::new(true)
+}
+
+fn assert_boot_sector(data: &[u8]) {
+    assert_eq!(&data[510..512], &[0x55, 0xAA]);
+}
+
+#[test_case]
+fn test_request_completes_once() {
+    let request = Request::read(3);
+    assert_eq!(request.result(), None);
+    request.complete(Err(BlockError::IoError));
+    request.complete(Ok(()));
+    assert_eq!(request.result(), Some(Err(BlockError::IoError)));
+    assert_eq!(request.wait(), Err(BlockError::IoError));
+}
+
+#[test_case]
+fn test_ramdisk_requests() {
+    let disk = RamDisk::new(4);
+    let mut data = [0u8; BLOCK_SIZE];
+    data[0] = 0x42;
+    let write = Request::write(2, &data).unwrap();
+    assert!(disk.submit(write).wait().is_ok());
+
+    let read = disk.read_async(2).wait().unwrap();
+    read.with_data(|block| assert_eq!(block[0], 0x42));
+
+    assert_eq!(disk.read_async(4).wait().err(), Some(BlockError::InvalidBlock));
+    assert_eq!(Request::write(0, &data[..10]).err(), Some(BlockError::IoError));
+}
+
+#[test_case]
+fn test_ata_blocking_read() {
+    let disk = boot_disk();
+    let mut buf = [0u8; BLOCK_SIZE];
+    disk.read_block(0, &mut buf).unwrap();
+    assert_boot_sector(&buf);
+}
+
+#[test_case]
+fn test_ata_polled_read() {
+    let disk = boot_disk();
+    let mut buf = [0u8; BLOCK_SIZE];
+    x86_64::instructions::interrupts::without_interrupts(|| disk.read_block(0, &mut buf)).unwrap();
+    assert_boot_sector(&buf);
+}
+
+#[test_case]
+fn test_ata_write_back() {
+    let mut disk = boot_disk();
+    let mut original = [0u8; BLOCK_SIZE];
+    disk.read_block(1, &mut original).unwrap();
+    disk.write_block(1, &original).unwrap();
+
+    let mut again = [0u8; BLOCK_SIZE];
+    disk.read_block(1, &mut again).unwrap();
+    assert_eq!(original, again);
+}
+
+#[test_case]
+fn test_ata_read_from_task() {
+    let executor = Executor::new();
+    let done = Arc::new(AtomicBool::new(false));
+    let task_done = done.clone();
+    executor.spawn(Task::new(async move {
+        let disk = boot_disk();
+        let request = disk.read_async(0).await.unwrap();
+        request.with_data(|block| assert_boot_sector(block));
+        task_done.store(true, Ordering::SeqCst);
+    }));
+    executor.start(1);
+    wait_for(&done);
+}
+
+#[test_case]
+fn test_threads_run_during_disk_io() {
+    let reading = Arc::new(AtomicBool::new(true));
+    let spins = Arc::new(AtomicU64::new(0));
+    let counter = {
+        let (reading, spins) = (reading.clone(), spins.clone());
+        thread::spawn(move || {
+            while reading.load(Ordering::SeqCst) {
+                spins.fetch_add(1, Ordering::SeqCst);
+                thread::yield_now();
+            }
+            0
+        })
+    };
+
+    let readers: alloc::vec::Vec<_> = (0..4)
+        .map(|reader| {
+            thread::spawn(move || {
+                let disk = boot_disk();
+                let mut buf = [0u8; BLOCK_SIZE];
+                for block in 0..8 {
+                    disk.read_block(block * 4 + reader, &mut buf).unwrap();
+                }
+                disk.read_block(0, &mut buf).unwrap();
+                assert_boot_sector(&buf);
+                0
+            })
+        })
+        .collect();
+    for reader in readers {
+        reader.join();
+    }
+    reading.store(false, Ordering::SeqCst);
+    counter.join();
+    assert!(spins.load(Ordering::SeqCst) > 0);
+}
@@ -0,0 +1,231 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(lithos::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use bootloader::{entry_point, BootInfo};
+use core::future::Future;
+use core::panic::PanicInfo;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::task::{Context, Poll};
+use lithos::task::executor::{self, Executor};
+use lithos::task::{thread, Task};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use lithos::allocator;
+    use lithos::memory::{self, BootInfoFrameAllocator};
+    use x86_64::VirtAddr;
+
+    lithos::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+    memory::install(mapper, frame_allocator);
+
+    executor::init(2);
+    test_main();
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    lithos::test_panic_handler(info)
+}
+
+fn wait_for(counter: &AtomicU64, value: u64) {
+    while counter.load(Ordering::SeqCst) < value {
+        thread::yield_now();
+    }
+}
+
+/// Returns `Pending` `n` times, waking itself each time
+struct YieldTimes(u64);
+
+impl Future for YieldTimes {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        if self.0 == 0 {
+            return Poll::Ready(());
+        }
+        self.0 -= 1;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+#[test_case]
+fn test_global_spawn() {
+    let done = Arc::new(AtomicU64::new(0));
+    for _ in 0..10 {
+        let done = done.clone();
+        executor::spawn(async move {
+            YieldTimes(5).await;
+            done.fetch_add(1, Ordering::SeqCst);
+        });
+    }
+    wait_for(&done, 10);
+}
+
+#[test_case]
+fn test_spawner_from_many_threads() {
+    let done = Arc::new(AtomicU64::new(0));
+    let spawner = executor::spawner().unwrap().clone();
+
+    let handles: Vec<_> = (0..4)
+        .map(|_| {
+            let (spawner, done) = (spawner.clone(), done.clone());
+            thread::spawn(move || {
+                for _ in 0..5 {
+                    let done = done.clone();
+                    spawner.spawn(async move {
+                        done.fetch_add(1, Ordering::SeqCst);
+                    });
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join();
+    }
+    wait_for(&done, 20);
+}
+
+#[test_case]
+fn test_spawn_task_without_allocating() {
+    let done = Arc::new(AtomicU64::new(0));
+    let task = {
+        let done = done.clone();
+        Task::new(async move {
+            done.fetch_add(1, Ordering::SeqCst);
+        })
+    };
+
+    // What an interrupt handler would do
+    let spawner = executor::spawner().unwrap();
+    let id = x86_64::instructions::interrupts::without_interrupts(|| spawner.spawn_task(task));
+    assert!(id.is_ok());
+    wait_for(&done, 1);
+}
+
+#[test_case]
+fn test_private_executor_on_threads() {
+    let executor = Executor::new();
+    let done = Arc::new(AtomicU64::new(0));
+    for _ in 0..3 {
+        let done = done.clone();
+        executor.spawn(Task::new(async move {
+            YieldTimes(100).await;
+            done.fetch_add(1, Ordering::SeqCst);
+        }));
+    }
+
+    let spawner = executor.start(3);
+    wait_for(&done, 3);
+
+    let done_later = done.clone();
+    spawner.spawn(async move {
+        done_later.fetch_add(1, Ordering::SeqCst);
+    });
+    wait_for(&done, 4);
+
+    let workers = thread::list()
+        .into_iter()
+        .filter(|t| t.name.as_deref().is_some_and(|name| name.starts_with("executor-")))
+        .count();
+    assert_eq!(workers, 2 + 3);
+}
+
+#[test_case]
+fn test_ready_queue_grows() {
+    let done = Arc::new(AtomicU64::new(0));
+    for _ in 0..1200 {
+        let done = done.clone();
+        executor::spawn(async move {
+            YieldTimes(2).await;
+            done.fetch_add(1, Ordering::SeqCst);
+        });
+    }
+    wait_for(&done, 1200);
+}
+
+#[test_case]
+fn test_task_listing_and_stats() {
+    let release = Arc::new(lithos::sync::Semaphore::new(0));
+    let id = {
+        let release = release.clone();
+        executor::spawn_named("lister", async move {
+            YieldTimes(3).await;
+            release.acquire_async().await;
+        })
+    };
+
+    // Wait until it has yielded its way to the semaphore
+    let info = loop {
+        let info = executor::list().into_iter().find(|t| t.id == id).unwrap();
+        if info.polls >= 4 {
+            break info;
+        }
+        thread::yield_now();
+    };
+    assert_eq!(info.name.as_deref(), Some("lister"));
+    assert!(info.wakeups >= 3, "self-wakes not counted");
+    assert_eq!(executor::info(id).map(|info| info.id), Some(id));
+
+    release.release();
+    while executor::list().iter().any(|t| t.id == id) {
+        thread::yield_now();
+    }
+}
+
+#[test_case]
+fn test_cancel_task() {
+    struct SetOnDrop(Arc<AtomicU64>);
+    impl Drop for SetOnDrop {
+        fn drop(&mut self) {
+            self.0.store(1, Ordering::SeqCst);
+        }
+    }
+
+    let dropped = Arc::new(AtomicU64::new(0));
+    let guard = SetOnDrop(dropped.clone());
+    let id = executor::spawn_named("forever", async move {
+        let _guard = guard;
+        core::future::pending::<()>().await;
+    });
+
+    assert!(executor::cancel(id));
+    wait_for(&dropped, 1);
+    while executor::list().iter().any(|t| t.id == id) {
+        thread::yield_now();
+    }
+    assert!(!executor::cancel(id));
+}
+
+#[test_case]
+fn test_current_task() {
+    assert_eq!(executor::current_task(), None);
+
+    let seen = Arc::new(AtomicU64::new(0));
+    let id = {
+        let seen = seen.clone();
+        executor::spawn(async move {
+            let id = executor::current_task().unwrap();
+            YieldTimes(1).await;
+            assert_eq!(executor::current_task(), Some(id));
+            seen.store(id.as_u64() + 1, Ordering::SeqCst);
+        })
+    };
+    wait_for(&seen, 1);
+    assert_eq!(seen.load(Ordering::SeqCst), id.as_u64() + 1);
+    assert_eq!(executor::current_task(), None);
+}
@@ -0,0 +1,89 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use bootloader::{entry_point, BootInfo};
+use core::arch::asm;
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use lithos::serial_print;
+use lithos::task::{kernel_thread::KernelThread, thread_scheduler};
+
+entry_point!(main);
+
+/// Additions per thread; each one is followed by a delay loop so the timer
+/// preempts the threads in the middle of their SSE work many times.
+const ITERATIONS: u64 = 20_000;
+
+static FINISHED: AtomicUsize = AtomicUsize::new(0);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use lithos::allocator;
+    use lithos::memory::{self, BootInfoFrameAllocator};
+    use x86_64::VirtAddr;
+
+    serial_print!("fpu_threads::two_threads_keep_sse_state... ");
+
+    lithos::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+
+    thread_scheduler::add_kernel_thread(KernelThread::new(thread_one));
+    thread_scheduler::add_kernel_thread(KernelThread::new(thread_two));
+
+    lithos::hlt_loop();
+}
+
+/// Add `step` to xmm1 `ITERATIONS` times and return the sum
+fn accumulate(step: f64) -> f64 {
+    let result: u64;
+    unsafe {
+        // The kernel is soft-float, so xmm registers are only touched here
+        asm!(
+            "movq xmm0, {step}",
+            "pxor xmm1, xmm1",
+            "2:",
+            "addsd xmm1, xmm0",
+            "mov {delay}, 2000",
+            "3:",
+            "dec {delay}",
+            "jnz 3b",
+            "dec {count}",
+            "jnz 2b",
+            "movq {result}, xmm1",
+            step = in(reg) step.to_bits(),
+            count = inout(reg) ITERATIONS => _,
+            delay = out(reg) _,
+            result = out(reg) result,
+        );
+    }
+    f64::from_bits(result)
+}
+
+fn check(step: f64) {
+    let sum = accumulate(step);
+    // Small integers are exact in f64, so any lost state shows up here
+    assert!(sum == step * ITERATIONS as f64, "SSE state corrupted: got {}", sum);
+
+    if FINISHED.fetch_add(1, Ordering::SeqCst) == 1 {
+        lithos::serial_println!("[ok]");
+        lithos::exit_qemu(lithos::QemuExitCode::Success);
+    }
+    lithos::hlt_loop();
+}
+
+extern "C" fn thread_one() {
+    check(3.0);
+}
+
+extern "C" fn thread_two() {
+    check(7.0);
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    lithos::test_panic_handler(info)
+}
@@ -0,0 +1,232 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(lithos::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::sync::Arc;
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use lithos::ipc::{self, Capability, Endpoint, Envelope, IpcError, Message, Rights};
+use lithos::process::{self, ExitStatus};
+use lithos::syscall::syscall_handler;
+use lithos::task::thread;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use lithos::allocator;
+    use lithos::memory::{self, BootInfoFrameAllocator};
+    use x86_64::VirtAddr;
+
+    lithos::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+    memory::install(mapper, frame_allocator);
+
+    test_main();
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    lithos::test_panic_handler(info)
+}
+
+const SYS_IPC_ENDPOINT: u64 = 1000;
+const SYS_IPC_SEND: u64 = 1001;
+const SYS_IPC_RECV: u64 = 1002;
+const SYS_IPC_CALL: u64 = 1003;
+const SYS_IPC_REPLY: u64 = 1004;
+const SYS_CAP_DERIVE: u64 = 1005;
+const SYS_CAP_CLOSE: u64 = 1006;
+
+#[test_case]
+fn test_send_and_recv_in_order() {
+    let endpoint = Arc::new(Endpoint::new());
+    let sender = {
+        let endpoint = endpoint.clone();
+        thread::spawn(move || {
+            for label in 0..3 {
+                endpoint.send(Message::new(label, [label * 10; ipc::MESSAGE_WORDS]));
+            }
+        })
+    };
+
+    for label in 0..3 {
+        let (envelope, reply) = endpoint.recv();
+        assert_eq!(envelope.message.label, label, "messages arrive in order");
+        assert_eq!(envelope.message.words, [label * 10; ipc::MESSAGE_WORDS]);
+        assert!(reply.is_none());
+    }
+    sender.join();
+    assert!(endpoint.try_recv().is_none());
+}
+
+#[test_case]
+fn test_call_and_reply() {
+    let endpoint = Arc::new(Endpoint::new());
+    let server = {
+        let endpoint = endpoint.clone();
+        thread::spawn(move || {
+            let (envelope, reply) = endpoint.recv();
+            let mut answer = envelope.message;
+            answer.label += 1;
+            reply.unwrap().send(answer).unwrap();
+        })
+    };
+
+    let answer = endpoint.call(Message::new(41, [1, 2, 3, 4])).unwrap();
+    assert_eq!(answer.message, Message::new(42, [1, 2, 3, 4]));
+    server.join();
+}
+
+#[test_case]
+fn test_dropped_reply_fails_call() {
+    let endpoint = Arc::new(Endpoint::new());
+    let server = {
+        let endpoint = endpoint.clone();
+        thread::spawn(move || {
+            let (_, reply) = endpoint.recv();
+            drop(reply);
+        })
+    };
+    assert_eq!(endpoint.call(Message::default()).map(|e| e.message), Err(IpcError::NoReply));
+    server.join();
+}
+
+#[test_case]
+fn test_reply_only_once() {
+    let endpoint = Arc::new(Endpoint::new());
+    let server = {
+        let endpoint = endpoint.clone();
+        thread::spawn(move || {
+            let (_, reply) = endpoint.recv();
+            let reply = reply.unwrap();
+            reply.send(Message::new(1, [0; 4])).unwrap();
+            assert_eq!(reply.send(Message::new(2, [0; 4])), Err(IpcError::NoReply));
+        })
+    };
+    assert_eq!(endpoint.call(Message::default()).unwrap().message.label, 1);
+    server.join();
+}
+
+#[test_case]
+fn test_rights_are_enforced() {
+    let endpoint = ipc::create_endpoint();
+    let send_only = ipc::derive(endpoint, Rights::SEND).unwrap();
+    assert_eq!(ipc::recv(send_only).map(|_| ()), Err(IpcError::MissingRights));
+
+    let message = Message { cap: Some(endpoint), ..Message::default() };
+    assert_eq!(ipc::send(send_only, message), Err(IpcError::MissingRights));
+
+    let widened = ipc::derive(send_only, Rights::ALL).unwrap();
+    assert_eq!(ipc::capability(widened).unwrap().rights, Rights::SEND, "rights never grow");
+
+    ipc::close(widened).unwrap();
+    ipc::close(send_only).unwrap();
+    ipc::close(endpoint).unwrap();
+    assert_eq!(ipc::close(endpoint), Err(IpcError::InvalidHandle));
+}
+
+#[test_case]
+fn test_handles_across_processes() {
+    let server_endpoint = ipc::create_endpoint();
+    let client_cap = ipc::capability(server_endpoint).unwrap().derive(Rights::SEND | Rights::GRANT);
+
+    // The client hands the server an endpoint of its own to talk back on
+    let client = process::spawn("ipc-client", move || {
+        let server = ipc::install(client_cap);
+        let back = ipc::create_endpoint();
+        let mut message = Message::new(7, [0; ipc::MESSAGE_WORDS]);
+        message.cap = Some(back);
+        let answer = ipc::call(server, message).unwrap();
+        assert_eq!(answer.label, 8);
+
+        let (message, reply) = ipc::recv(back).unwrap();
+        assert!(reply.is_none());
+        message.label as i32
+    });
+
+    let (message, reply) = ipc::recv(server_endpoint).unwrap();
+    assert_eq!(message.label, 7);
+    let back = message.cap.expect("capability was not transferred");
+    assert_eq!(ipc::capability(back).unwrap().rights, Rights::ALL);
+    ipc::reply(reply.unwrap(), Message::new(8, [0; ipc::MESSAGE_WORDS])).unwrap();
+    ipc::send(back, Message::new(99, [0; ipc::MESSAGE_WORDS])).unwrap();
+
+    assert_eq!(process::wait(Some(client), false), Ok(Some((client, ExitStatus::Exited(99)))));
+    ipc::close(back).unwrap();
+    ipc::close(server_endpoint).unwrap();
+}
+
+#[test_case]
+fn test_exit_drops_reply_capabilities() {
+    let endpoint = Arc::new(Endpoint::new());
+    let cap = Capability::endpoint(endpoint.clone(), Rights::RECV);
+    let server = process::spawn("ipc-server", move || {
+        let handle = ipc::install(cap);
+        let (_, reply) = ipc::recv(handle).unwrap();
+        assert!(reply.is_some());
+        // Exit without replying
+        0
+    });
+
+    let answer = endpoint.call(Envelope::new(Message::default()));
+    assert_eq!(answer.map(|e| e.message), Err(IpcError::NoReply));
+    assert_eq!(process::wait(Some(server), false), Ok(Some((server, ExitStatus::Exited(0)))));
+}
+
+#[test_case]
+fn test_ipc_syscalls() {
+    let endpoint = syscall_handler(SYS_IPC_ENDPOINT, 0, 0, 0, 0, 0, 0);
+    assert!(endpoint > 0);
+    let endpoint = endpoint as u64;
+
+    let server = thread::spawn(move || {
+        let mut message = Message::default();
+        let ptr = &mut message as *mut Message as u64;
+        let reply = syscall_handler(SYS_IPC_RECV, endpoint, ptr, 0, 0, 0, 0);
+        assert!(reply > 0, "call did not come with a reply handle");
+        message.words[0] *= 2;
+        assert_eq!(syscall_handler(SYS_IPC_REPLY, reply as u64, ptr, 0, 0, 0, 0), 0);
+
+        assert_eq!(syscall_handler(SYS_IPC_RECV, endpoint, ptr, 0, 0, 0, 0), 0);
+        assert_eq!(message.label, 2);
+    });
+
+    let mut message = Message::new(1, [21, 0, 0, 0]);
+    let ptr = &mut message as *mut Message as u64;
+    assert_eq!(syscall_handler(SYS_IPC_CALL, endpoint, ptr, 0, 0, 0, 0), 0);
+    assert_eq!(message.words[0], 42);
+    let message = Message::new(2, [0; ipc::MESSAGE_WORDS]);
+    let ptr = &message as *const Message as u64;
+    assert_eq!(syscall_handler(SYS_IPC_SEND, endpoint, ptr, 0, 0, 0, 0), 0);
+    server.join();
+
+    // Kernel addresses fail before the endpoint is touched, so nothing blocks
+    let kernel = 0xFFFF_8000_0000_0000;
+    for number in [SYS_IPC_SEND, SYS_IPC_RECV, SYS_IPC_CALL] {
+        assert_eq!(syscall_handler(number, endpoint, kernel, 0, 0, 0, 0), -1);
+    }
+
+    let derived = syscall_handler(SYS_CAP_DERIVE, endpoint, Rights::RECV.bits() as u64, 0, 0, 0, 0);
+    assert!(derived > 0);
+    assert_eq!(syscall_handler(SYS_IPC_SEND, derived as u64, ptr, 0, 0, 0, 0), -1);
+    assert_eq!(syscall_handler(SYS_IPC_SEND, 0, ptr, 0, 0, 0, 0), -1);
+    assert_eq!(syscall_handler(SYS_CAP_CLOSE, derived as u64, 0, 0, 0, 0, 0), 0);
+    assert_eq!(syscall_handler(SYS_CAP_CLOSE, endpoint, 0, 0, 0, 0, 0), 0);
+    assert_eq!(syscall_handler(SYS_CAP_CLOSE, endpoint, 0, 0, 0, 0, 0), -1);
+}
+
+#[test_case]
+fn test_round_trip_benchmark() {
+    let result = ipc::benchmark(200);
+    assert_eq!(result.round_trips, 200);
+    assert!(result.cycles > 0);
+    assert!(result.round_trip() <= result.total);
+}
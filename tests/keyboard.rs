@@ -0,0 +1,157 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(lithos::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use lithos::cmdline;
+use lithos::keyboard::{self, DecodedKey, KeyCode, KeyEvent, Layout, Modifiers, ScancodeDecoder};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use lithos::allocator;
+    use lithos::memory::{self, BootInfoFrameAllocator};
+    use x86_64::VirtAddr;
+
+    lithos::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+    memory::install(mapper, frame_allocator);
+
+    test_main();
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    lithos::test_panic_handler(info)
+}
+
+/// Feed `scancodes`, returning the last event
+fn feed(decoder: &mut ScancodeDecoder, scancodes: &[u8]) -> Option<KeyEvent> {
+    scancodes.iter().fold(None, |_, &scancode| decoder.feed(scancode))
+}
+
+#[test_case]
+fn test_layout_names() {
+    for layout in Layout::ALL {
+        assert_eq!(Layout::from_name(layout.name()), Some(layout));
+    }
+    assert_eq!(Layout::from_name("DVORAK"), Some(Layout::Dvorak));
+    assert_eq!(Layout::from_name("qwertz"), None);
+}
+
+#[test_case]
+fn test_boot_options() {
+    assert_eq!(cmdline::find("quiet keymap=de", "keymap"), Some("de"));
+    assert_eq!(cmdline::find("keymap=de keymap=uk", "keymap"), Some("uk"));
+    assert_eq!(cmdline::find("keymaps=de", "keymap"), None);
+    assert_eq!(cmdline::find("", "keymap"), None);
+}
+
+#[test_case]
+fn test_press_and_release() {
+    let mut decoder = ScancodeDecoder::new(Layout::Us);
+    let press = decoder.feed(0x1E).unwrap();
+    assert_eq!(press.code, KeyCode::A);
+    assert!(press.pressed);
+    assert_eq!(press.decoded, Some(DecodedKey::Unicode('a')));
+
+    let release = decoder.feed(0x9E).unwrap();
+    assert!(!release.pressed);
+    assert_eq!(release.decoded, None);
+}
+
+#[test_case]
+fn test_modifiers() {
+    let mut decoder = ScancodeDecoder::new(Layout::Us);
+    let shift = decoder.feed(0x2A).unwrap();
+    assert!(shift.modifiers.shift);
+    let press = decoder.feed(0x1E).unwrap();
+    assert_eq!(press.decoded, Some(DecodedKey::Unicode('A')));
+    assert!(press.modifiers.shift);
+
+    let released = feed(&mut decoder, &[0xAA, 0x9E]).unwrap();
+    assert_eq!(released.modifiers, Modifiers::default());
+}
+
+#[test_case]
+fn test_layouts_decode_differently() {
+    let typed = |layout, scancode| ScancodeDecoder::new(layout).feed(scancode).unwrap().decoded;
+    // The key labelled Q, S, H and Z on a US keyboard
+    assert_eq!(typed(Layout::Us, 0x10), Some(DecodedKey::Unicode('q')));
+    assert_eq!(typed(Layout::Azerty, 0x10), Some(DecodedKey::Unicode('a')));
+    assert_eq!(typed(Layout::Colemak, 0x1F), Some(DecodedKey::Unicode('r')));
+    assert_eq!(typed(Layout::Dvorak, 0x23), Some(DecodedKey::Unicode('d')));
+    assert_eq!(typed(Layout::De, 0x2C), Some(DecodedKey::Unicode('y')));
+
+    let mut decoder = ScancodeDecoder::new(Layout::Us);
+    decoder.set_layout(Layout::Azerty);
+    assert_eq!(decoder.layout(), Layout::Azerty);
+    assert_eq!(decoder.feed(0x10).unwrap().decoded, Some(DecodedKey::Unicode('a')));
+}
+
+#[test_case]
+fn test_ctrl_alt_del() {
+    let mut decoder = ScancodeDecoder::new(Layout::Us);
+    let event = feed(&mut decoder, &[0x1D, 0x38, 0xE0, 0x53]).unwrap();
+    assert_eq!(event.code, KeyCode::Delete);
+    assert!(event.is_ctrl_alt_del());
+
+    // Delete alone, or once Alt is released
+    let mut decoder = ScancodeDecoder::new(Layout::Us);
+    assert!(!feed(&mut decoder, &[0xE0, 0x53]).unwrap().is_ctrl_alt_del());
+    assert!(!feed(&mut decoder, &[0x1D, 0x38, 0xB8, 0xE0, 0x53]).unwrap().is_ctrl_alt_del());
+}
+
+fn key(character: char) -> KeyEvent {
+    KeyEvent {
+        code: KeyCode::A,
+        pressed: true,
+        modifiers: Modifiers::default(),
+        decoded: Some(DecodedKey::Unicode(character)),
+    }
+}
+
+#[test_case]
+fn test_every_subscriber_gets_every_event() {
+    let mut first = keyboard::subscribe();
+    let mut second = keyboard::subscribe();
+    keyboard::publish(key('x'));
+    assert_eq!(first.try_next(), Some(key('x')));
+    assert_eq!(second.try_next(), Some(key('x')));
+    assert_eq!(first.try_next(), None);
+
+    // Gone once dropped; the rest still receive
+    drop(second);
+    keyboard::publish(key('y'));
+    assert_eq!(first.try_next(), Some(key('y')));
+}
+
+#[test_case]
+fn test_slow_subscriber_loses_oldest_events() {
+    let mut subscription = keyboard::subscribe();
+    for _ in 0..100 {
+        keyboard::publish(key('a'));
+    }
+    keyboard::publish(key('z'));
+    assert_eq!(subscription.dropped(), 37);
+    let last = core::iter::from_fn(|| subscription.try_next()).last();
+    assert_eq!(last, Some(key('z')));
+}
+
+#[test_case]
+fn test_key_bytes() {
+    let mut buf = [0; 4];
+    assert_eq!(keyboard::key_bytes(DecodedKey::Unicode('é'), &mut buf), "é".as_bytes());
+    assert_eq!(keyboard::key_bytes(DecodedKey::Unicode('\n'), &mut buf), b"\r");
+    assert_eq!(keyboard::key_bytes(DecodedKey::RawKey(KeyCode::ArrowUp), &mut buf), b"\x1b[A");
+    assert_eq!(keyboard::key_bytes(DecodedKey::RawKey(KeyCode::F1), &mut buf), b"");
+}
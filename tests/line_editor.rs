@@ -0,0 +1,207 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(lithos::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use lithos::shell::line_editor::{Key, KeyDecoder, LineEditor, HISTORY_SIZE};
+use lithos::shell::Shell;
+use lithos::vfs::{ops, ramfs::RamFs};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use lithos::allocator;
+    use lithos::memory::{self, BootInfoFrameAllocator};
+    use x86_64::VirtAddr;
+
+    lithos::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+    memory::install(mapper, frame_allocator);
+
+    test_main();
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    lithos::test_panic_handler(info)
+}
+
+fn no_completion(_: &str) -> Vec<String> {
+    Vec::new()
+}
+
+/// Feed `keys`, returning the last finished line and everything echoed
+fn feed(editor: &mut LineEditor, keys: &[Key]) -> (Option<String>, String) {
+    let mut out = String::new();
+    let mut finished = None;
+    for &key in keys {
+        if let Some(line) = editor.handle(key, no_completion, &mut out).unwrap() {
+            finished = Some(line);
+        }
+    }
+    (finished, out)
+}
+
+fn type_text(text: &str) -> Vec<Key> {
+    text.chars().map(Key::Char).collect()
+}
+
+#[test_case]
+fn test_typing_and_enter() {
+    let mut editor = LineEditor::new(40);
+    let mut out = String::new();
+    editor.start("$ ", &mut out).unwrap();
+    assert_eq!(out, "$ ");
+
+    let mut keys = type_text("ls /");
+    keys.push(Key::Enter);
+    let (line, echo) = feed(&mut editor, &keys);
+    assert_eq!(line.as_deref(), Some("ls /"));
+    assert_eq!(echo, "ls /\n");
+    assert_eq!(editor.line(), "");
+}
+
+#[test_case]
+fn test_backspace_in_the_middle() {
+    let mut editor = LineEditor::new(40);
+    let mut keys = type_text("abc");
+    keys.extend([Key::Left, Key::Backspace]);
+    let (_, echo) = feed(&mut editor, &keys);
+    assert_eq!(editor.line(), "ac");
+    assert_eq!(editor.cursor(), 1);
+    // Back over "b", redraw "c", blank the old end and return to the cursor
+    assert!(echo.ends_with("\x08\x08c \x08\x08"));
+}
+
+#[test_case]
+fn test_home_end_and_delete() {
+    let mut editor = LineEditor::new(40);
+    let mut keys = type_text("cho");
+    keys.extend([Key::Home, Key::Char('e'), Key::End, Key::Char('!')]);
+    feed(&mut editor, &keys);
+    assert_eq!(editor.line(), "echo!");
+
+    feed(&mut editor, &[Key::Home, Key::Delete, Key::Right, Key::Right]);
+    assert_eq!(editor.line(), "cho!");
+    assert_eq!(editor.cursor(), 2);
+}
+
+#[test_case]
+fn test_line_length_is_limited() {
+    let mut editor = LineEditor::new(4);
+    feed(&mut editor, &type_text("abcdef"));
+    assert_eq!(editor.line(), "abcd");
+}
+
+#[test_case]
+fn test_history() {
+    let mut editor = LineEditor::new(40);
+    for line in ["pwd", "ls", "ls", "   "] {
+        let mut keys = type_text(line);
+        keys.push(Key::Enter);
+        feed(&mut editor, &keys);
+    }
+    assert_eq!(editor.history(), ["pwd", "ls"]);
+
+    feed(&mut editor, &type_text("draft"));
+    feed(&mut editor, &[Key::Up]);
+    assert_eq!(editor.line(), "ls");
+    feed(&mut editor, &[Key::Up, Key::Up]);
+    assert_eq!(editor.line(), "pwd");
+    feed(&mut editor, &[Key::Down]);
+    assert_eq!(editor.line(), "ls");
+    let (line, _) = feed(&mut editor, &[Key::Down, Key::Enter]);
+    assert_eq!(line.as_deref(), Some("draft"));
+
+    for index in 0..HISTORY_SIZE + 5 {
+        let mut keys = type_text(&alloc::format!("cmd{}", index));
+        keys.push(Key::Enter);
+        feed(&mut editor, &keys);
+    }
+    assert_eq!(editor.history().len(), HISTORY_SIZE);
+}
+
+#[test_case]
+fn test_interrupt_drops_the_line() {
+    let mut editor = LineEditor::new(40);
+    let mut out = String::new();
+    editor.start("$ ", &mut out).unwrap();
+    let (_, echo) = feed(&mut editor, &[Key::Char('x'), Key::Interrupt]);
+    assert_eq!(echo, "x^C\n$ ");
+    assert_eq!(editor.line(), "");
+}
+
+#[test_case]
+fn test_tab_completion() {
+    let mut editor = LineEditor::new(40);
+    let mut out = String::new();
+    feed(&mut editor, &type_text("to"));
+    editor.handle(Key::Tab, |_| vec![String::from("top")], &mut out).unwrap();
+    assert_eq!(editor.line(), "top ");
+
+    // Several candidates: fill in the shared part, then list them
+    let mut editor = LineEditor::new(40);
+    feed(&mut editor, &type_text("ls /u"));
+    let candidates = || vec![String::from("/usr/"), String::from("/usrlocal/")];
+    editor.handle(Key::Tab, |_| candidates(), &mut out).unwrap();
+    assert_eq!(editor.line(), "ls /usr");
+
+    out.clear();
+    editor.handle(Key::Tab, |_| candidates(), &mut out).unwrap();
+    assert_eq!(editor.line(), "ls /usr");
+    assert!(out.contains("usr/  usrlocal/"));
+}
+
+#[test_case]
+fn test_shell_completion() {
+    let ramfs = RamFs::new();
+    ops::init(ramfs.root_node());
+    ops::vfs_mkdir("/usr").unwrap();
+    ops::vfs_mkdir("/usr/bin").unwrap();
+    ops::vfs_create("/usr/readme").unwrap();
+
+    let shell = Shell::new();
+    assert_eq!(shell.complete("th"), ["threads"]);
+    assert_eq!(shell.complete("ls /u"), ["/usr/"]);
+    assert_eq!(shell.complete("ls /usr/"), ["/usr/bin/", "/usr/readme"]);
+    assert_eq!(shell.complete("cd us"), ["usr/"]);
+    assert!(shell.complete("ls /nowhere/").is_empty());
+}
+
+#[test_case]
+fn test_key_decoder() {
+    let mut decoder = KeyDecoder::new();
+    let keys: Vec<Key> = "aé\r\x7f\t\x03\x1b[A\x1b[D\x1bOH\x1b[3~\x1b[4~\x1b[15~z"
+        .bytes()
+        .filter_map(|byte| decoder.feed(byte))
+        .collect();
+    assert_eq!(
+        keys,
+        [
+            Key::Char('a'),
+            Key::Char('é'),
+            Key::Enter,
+            Key::Backspace,
+            Key::Tab,
+            Key::Interrupt,
+            Key::Up,
+            Key::Left,
+            Key::Home,
+            Key::Delete,
+            Key::End,
+            Key::Char('z'),
+        ]
+    );
+}
@@ -0,0 +1,66 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(lithos::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use lithos::acpi;
+use lithos::power::{self, PowerAction};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use lithos::allocator;
+    use lithos::memory::{self, BootInfoFrameAllocator};
+    use x86_64::VirtAddr;
+
+    lithos::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+    memory::install(mapper, frame_allocator);
+    acpi::init().expect("ACPI discovery failed");
+
+    test_main();
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    lithos::test_panic_handler(info)
+}
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+fn first_hook(action: PowerAction) {
+    assert_eq!(action, PowerAction::Reboot);
+    assert_eq!(CALLS.fetch_add(1, Ordering::SeqCst), 0);
+}
+
+fn second_hook(_action: PowerAction) {
+    assert_eq!(CALLS.fetch_add(1, Ordering::SeqCst), 1);
+}
+
+#[test_case]
+fn test_hooks_run_in_order_once() {
+    power::register_shutdown_hook("first", first_hook);
+    power::register_shutdown_hook("second", second_hook);
+
+    power::run_shutdown_hooks(PowerAction::Reboot);
+    assert_eq!(CALLS.load(Ordering::SeqCst), 2);
+
+    power::run_shutdown_hooks(PowerAction::Reboot);
+    assert_eq!(CALLS.load(Ordering::SeqCst), 2);
+}
+
+#[test_case]
+fn test_s5_sleep_type_found() {
+    let info = acpi::info().unwrap();
+    assert!(info.s5.is_some(), "no _S5_ package in DSDT");
+}
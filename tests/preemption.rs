@@ -0,0 +1,172 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use bootloader::{entry_point, BootInfo};
+use core::arch::asm;
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use lithos::serial_print;
+use lithos::task::{kernel_thread::KernelThread, thread_scheduler};
+
+entry_point!(main);
+
+/// Rounds each thread runs; every round spins long enough to be preempted
+const ROUNDS: usize = 4;
+
+/// Delay loop iterations per round
+const SPINS: u64 = 100_000_000;
+
+/// Context switches that must have happened before the test passes
+const MIN_SWITCHES: u64 = 20;
+
+/// RFLAGS direction flag, set during the spin to check RFLAGS is restored
+const RFLAGS_DF: u64 = 1 << 10;
+
+const THREADS: usize = 3;
+
+static FINISHED: AtomicUsize = AtomicUsize::new(0);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use lithos::allocator;
+    use lithos::memory::{self, BootInfoFrameAllocator};
+    use x86_64::VirtAddr;
+
+    serial_print!("preemption::registers_survive_preemption... ");
+
+    lithos::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+
+    thread_scheduler::add_kernel_thread(KernelThread::new(thread_one));
+    thread_scheduler::add_kernel_thread(KernelThread::new(thread_two));
+    thread_scheduler::add_kernel_thread(KernelThread::new(thread_three));
+
+    // The boot thread waits its turns like the others
+    while FINISHED.load(Ordering::SeqCst) < THREADS {
+        x86_64::instructions::hlt();
+    }
+
+    let switches = thread_scheduler::context_switches();
+    assert!(switches >= MIN_SWITCHES, "only {} context switches", switches);
+
+    lithos::serial_println!("[ok]");
+    lithos::exit_qemu(lithos::QemuExitCode::Success);
+    lithos::hlt_loop();
+}
+
+/// Fill every general purpose register with `seed + n`, set DF, spin, and
+/// return the registers (r15 first, down to rax) followed by RFLAGS
+fn spin_with_registers(seed: u64) -> [u64; 16] {
+    let mut out = [0u64; 16];
+    unsafe {
+        asm!(
+            "push rbx",
+            "push rbp",
+            "push rdi",
+            "push rdx",
+
+            "lea rax, [rsi + 1]",
+            "lea rbx, [rsi + 2]",
+            "lea rcx, [rsi + 3]",
+            "lea rdx, [rsi + 4]",
+            "lea rdi, [rsi + 6]",
+            "lea rbp, [rsi + 7]",
+            "lea r8, [rsi + 8]",
+            "lea r9, [rsi + 9]",
+            "lea r10, [rsi + 10]",
+            "lea r11, [rsi + 11]",
+            "lea r12, [rsi + 12]",
+            "lea r13, [rsi + 13]",
+            "lea r14, [rsi + 14]",
+            "lea r15, [rsi + 15]",
+            "add rsi, 5",
+            "std",
+
+            // Only memory is touched while the timer preempts us
+            "2:",
+            "dec qword ptr [rsp]",
+            "jnz 2b",
+
+            "pushfq",
+            "push rax",
+            "push rbx",
+            "push rcx",
+            "push rdx",
+            "push rsi",
+            "push rdi",
+            "push rbp",
+            "push r8",
+            "push r9",
+            "push r10",
+            "push r11",
+            "push r12",
+            "push r13",
+            "push r14",
+            "push r15",
+            "cld",
+
+            // 16 saved values and the spin counter sit above the out pointer
+            "mov rdi, [rsp + 136]",
+            "xor ecx, ecx",
+            "3:",
+            "pop rax",
+            "mov [rdi + rcx * 8], rax",
+            "inc rcx",
+            "cmp rcx, 16",
+            "jne 3b",
+
+            "add rsp, 16",
+            "pop rbp",
+            "pop rbx",
+            inout("rdi") out.as_mut_ptr() => _,
+            inout("rsi") seed => _,
+            inout("rdx") SPINS => _,
+            out("rax") _,
+            out("rcx") _,
+            out("r8") _,
+            out("r9") _,
+            out("r10") _,
+            out("r11") _,
+            out("r12") _,
+            out("r13") _,
+            out("r14") _,
+            out("r15") _,
+        );
+    }
+    out
+}
+
+fn check(seed: u64) {
+    for _ in 0..ROUNDS {
+        let regs = spin_with_registers(seed);
+        for (i, &value) in regs[..15].iter().enumerate() {
+            let expected = seed + (15 - i) as u64;
+            assert_eq!(value, expected, "register {} corrupted", i);
+        }
+        assert!(regs[15] & RFLAGS_DF != 0, "RFLAGS not preserved: {:#x}", regs[15]);
+    }
+
+    FINISHED.fetch_add(1, Ordering::SeqCst);
+    lithos::hlt_loop();
+}
+
+extern "C" fn thread_one() {
+    check(0x1111_0000_0000_0000);
+}
+
+extern "C" fn thread_two() {
+    check(0x2222_0000_0000_0000);
+}
+
+extern "C" fn thread_three() {
+    check(0x3333_0000_0000_0000);
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    lithos::test_panic_handler(info)
+}
@@ -0,0 +1,161 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(lithos::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::sync::Arc;
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicU32, Ordering};
+use core::time::Duration;
+use lithos::process::{self, ExitStatus, Pid, ProcessError, ProcessState, INIT_PID};
+use lithos::syscall::syscall_handler;
+use lithos::task::thread;
+use lithos::vfs::{fd_table::OpenFlags, ops, ramfs::RamFs};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use lithos::allocator;
+    use lithos::memory::{self, BootInfoFrameAllocator};
+    use x86_64::VirtAddr;
+
+    lithos::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+    memory::install(mapper, frame_allocator);
+
+    test_main();
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    lithos::test_panic_handler(info)
+}
+
+const SYS_GETPID: u64 = 39;
+const SYS_WAIT4: u64 = 61;
+const SYS_GETPPID: u64 = 110;
+const WNOHANG: u64 = 1;
+
+#[test_case]
+fn test_boot_thread_is_init() {
+    assert_eq!(process::getpid(), INIT_PID);
+    assert_eq!(process::getppid(), 0);
+    assert_eq!(syscall_handler(SYS_GETPID, 0, 0, 0, 0, 0, 0), 1);
+}
+
+#[test_case]
+fn test_child_exit_status() {
+    let seen = Arc::new(AtomicU32::new(0));
+    let child = {
+        let seen = seen.clone();
+        process::spawn("child", move || {
+            let ppid = syscall_handler(SYS_GETPPID, 0, 0, 0, 0, 0, 0);
+            seen.store(ppid as u32, Ordering::SeqCst);
+            assert_eq!(process::getpid(), process::current_pid());
+            42
+        })
+    };
+    assert_ne!(child, INIT_PID);
+
+    // A kernel address fails without reaping the child
+    let kernel = 0xFFFF_8000_0000_0000;
+    assert_eq!(syscall_handler(SYS_WAIT4, child.as_u32() as u64, kernel, 0, 0, 0, 0), -1);
+
+    let mut status = 0i32;
+    let reaped = syscall_handler(SYS_WAIT4, child.as_u32() as u64, &mut status as *mut i32 as u64, 0, 0, 0, 0);
+    assert_eq!(reaped, child.as_u32() as i64);
+    assert_eq!((status >> 8) & 0xff, 42);
+    assert_eq!(seen.load(Ordering::SeqCst), 1);
+    assert!(process::info(child).is_none(), "zombie not reaped");
+}
+
+#[test_case]
+fn test_explicit_exit() {
+    let child = process::spawn("exiter", || process::exit(7));
+    assert_eq!(process::wait(Some(child), false), Ok(Some((child, ExitStatus::Exited(7)))));
+}
+
+#[test_case]
+fn test_wait_nohang_and_zombies() {
+    let release = Arc::new(AtomicU32::new(0));
+    let child = {
+        let release = release.clone();
+        process::spawn("sleeper", move || {
+            while release.load(Ordering::SeqCst) == 0 {
+                thread::sleep(Duration::from_millis(10));
+            }
+            3
+        })
+    };
+
+    assert_eq!(syscall_handler(SYS_WAIT4, u64::MAX, 0, WNOHANG, 0, 0, 0), 0);
+    release.store(1, Ordering::SeqCst);
+
+    // The exited child stays a zombie until it is waited for
+    while process::info(child).unwrap().state != ProcessState::Zombie {
+        thread::yield_now();
+    }
+    assert_eq!(process::wait(None, true), Ok(Some((child, ExitStatus::Exited(3)))));
+}
+
+#[test_case]
+fn test_wait_without_children() {
+    assert_eq!(process::wait(None, false), Err(ProcessError::NoChildren));
+    assert_eq!(syscall_handler(SYS_WAIT4, u64::MAX, 0, 0, 0, 0, 0), -1);
+}
+
+#[test_case]
+fn test_orphans_are_reparented_to_init() {
+    let grandchild = Arc::new(AtomicU32::new(0));
+    let release = Arc::new(AtomicU32::new(0));
+
+    let child = {
+        let (grandchild, release) = (grandchild.clone(), release.clone());
+        process::spawn("parent", move || {
+            let pid = process::spawn("orphan", move || {
+                while release.load(Ordering::SeqCst) == 0 {
+                    thread::sleep(Duration::from_millis(10));
+                }
+                0
+            });
+            grandchild.store(pid.as_u32(), Ordering::SeqCst);
+            0
+        })
+    };
+    assert_eq!(process::wait(Some(child), false), Ok(Some((child, ExitStatus::Exited(0)))));
+
+    let orphan = Pid::new(grandchild.load(Ordering::SeqCst));
+    assert_eq!(process::info(orphan).unwrap().parent, Some(INIT_PID));
+
+    // init reaps adopted orphans on its own
+    release.store(1, Ordering::SeqCst);
+    while process::info(orphan).is_some() {
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(process::wait(None, true), Err(ProcessError::NoChildren));
+}
+
+#[test_case]
+fn test_fd_tables_are_per_process() {
+    ops::init(RamFs::new().root_node());
+    ops::vfs_create("/file").unwrap();
+    let fd = ops::vfs_open("/file", OpenFlags::read_only()).unwrap();
+
+    let child = process::spawn("fds", move || {
+        // The parent's descriptor means nothing here
+        match ops::vfs_close(fd) {
+            Ok(()) => 1,
+            Err(_) => 0,
+        }
+    });
+    assert_eq!(process::wait(Some(child), false), Ok(Some((child, ExitStatus::Exited(0)))));
+    assert!(ops::vfs_close(fd).is_ok());
+}
@@ -0,0 +1,73 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(lithos::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::panic::PanicInfo;
+use lithos::random::{self, chacha20};
+use lithos::syscall::{syscall_handler, Syscall};
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    lithos::init();
+    test_main();
+
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    lithos::test_panic_handler(info)
+}
+
+#[test_case]
+fn test_chacha20_rfc8439_block() {
+    // RFC 8439, section 2.3.2
+    let mut key_bytes = [0u8; 32];
+    for (i, byte) in key_bytes.iter_mut().enumerate() {
+        *byte = i as u8;
+    }
+    let key = chacha20::key_from_bytes(&key_bytes);
+    let block = chacha20::block(&key, 1 | (0x0900_0000 << 32), 0x4a00_0000);
+
+    let expected_start = [
+        0x10, 0xf1, 0xe7, 0xe4, 0xd1, 0x3b, 0x59, 0x15,
+        0x50, 0x0f, 0xdd, 0x1f, 0xa3, 0x20, 0x71, 0xc4,
+    ];
+    let expected_end = [
+        0xb5, 0x12, 0x9c, 0xd1, 0xde, 0x16, 0x4e, 0xb9,
+        0xcb, 0xd0, 0x83, 0xe8, 0xa2, 0x50, 0x3c, 0x4e,
+    ];
+    assert_eq!(block[..16], expected_start);
+    assert_eq!(block[48..], expected_end);
+}
+
+#[test_case]
+fn test_consecutive_reads_differ() {
+    let mut first = [0u8; 32];
+    let mut second = [0u8; 32];
+    random::fill_bytes(&mut first);
+    random::fill_bytes(&mut second);
+    assert_ne!(first, second);
+    assert_ne!(first, [0u8; 32]);
+}
+
+#[test_case]
+fn test_odd_sized_reads() {
+    let mut buf = [0u8; 100];
+    random::fill_bytes(&mut buf);
+    assert!(buf[64..].iter().any(|&b| b != 0));
+}
+
+#[test_case]
+fn test_getrandom_syscall() {
+    let mut buf = [0u8; 16];
+    let ptr = buf.as_mut_ptr() as u64;
+    assert_eq!(syscall_handler(Syscall::GetRandom as u64, ptr, 16, 0, 0, 0, 0), 16);
+    assert_ne!(buf, [0u8; 16]);
+
+    // Kernel addresses, and ranges running out of user space, are refused
+    assert_eq!(syscall_handler(Syscall::GetRandom as u64, 0xFFFF_8000_0000_0000, 16, 0, 0, 0, 0), -1);
+    assert_eq!(syscall_handler(Syscall::GetRandom as u64, 0x7FFF_FFFF_FFF8, 16, 0, 0, 0, 0), -1);
+}
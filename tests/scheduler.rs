@@ -0,0 +1,167 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(lithos::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use core::time::Duration;
+use lithos::task::kernel_thread::ThreadState;
+use lithos::task::policy::{mlfq, Mlfq, ReadyReason, SchedulingPolicy};
+use lithos::task::thread::{self, JoinHandle};
+use lithos::task::thread_scheduler;
+use lithos::task::TaskId;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use lithos::allocator;
+    use lithos::memory::{self, BootInfoFrameAllocator};
+    use x86_64::VirtAddr;
+
+    lithos::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+    memory::install(mapper, frame_allocator);
+
+    test_main();
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    lithos::test_panic_handler(info)
+}
+
+/// Real thread IDs to feed a standalone policy
+fn thread_ids(count: usize) -> (Vec<JoinHandle>, Vec<TaskId>) {
+    let handles: Vec<_> = (0..count).map(|_| thread::spawn(|| ())).collect();
+    let ids = handles.iter().map(|handle| handle.id()).collect();
+    (handles, ids)
+}
+
+#[test_case]
+fn test_mlfq_demotes_cpu_bound_thread() {
+    let (_handles, ids) = thread_ids(2);
+    let mut policy = Mlfq::new();
+    policy.add(ids[0], 0);
+    policy.add(ids[1], 0);
+    policy.make_ready(ids[0], ReadyReason::New);
+    policy.make_ready(ids[1], ReadyReason::New);
+
+    assert_eq!(policy.pick_next(), Some(ids[0]));
+    assert!(policy.tick(ids[0]), "level 0 slice is one tick");
+    assert_eq!(policy.priority(ids[0]), Some(1));
+
+    policy.make_ready(ids[0], ReadyReason::Preempted);
+    assert_eq!(policy.pick_next(), Some(ids[1]), "level 0 runs first");
+    assert_eq!(policy.pick_next(), Some(ids[0]));
+    assert_eq!(policy.pick_next(), None);
+}
+
+#[test_case]
+fn test_mlfq_higher_priority_preempts() {
+    let (_handles, ids) = thread_ids(2);
+    let mut policy = Mlfq::new();
+    policy.add(ids[0], 2);
+    policy.add(ids[1], 0);
+
+    policy.make_ready(ids[0], ReadyReason::New);
+    assert_eq!(policy.pick_next(), Some(ids[0]));
+    assert!(!policy.tick(ids[0]));
+
+    policy.make_ready(ids[1], ReadyReason::Woken);
+    assert!(policy.tick(ids[0]));
+}
+
+#[test_case]
+fn test_mlfq_boost_restores_base_priority() {
+    let (_handles, ids) = thread_ids(1);
+    let mut policy = Mlfq::new();
+    policy.add(ids[0], 0);
+
+    let mut ticks = 0;
+    while policy.priority(ids[0]) != Some(mlfq::LEVELS as u8 - 1) {
+        policy.tick(ids[0]);
+        ticks += 1;
+    }
+    while policy.priority(ids[0]) != Some(0) {
+        policy.tick(ids[0]);
+        ticks += 1;
+        assert!(ticks < 1000, "no priority boost");
+    }
+}
+
+#[test_case]
+fn test_idle_thread_exists() {
+    let _handle = thread::spawn(|| ());
+    let threads = thread::list();
+    assert_eq!(threads.iter().filter(|t| t.is_idle).count(), 1);
+    assert!(threads.iter().any(|t| t.name.as_deref() == Some("boot")));
+}
+
+#[test_case]
+fn test_sleeping_thread() {
+    let handle = thread::spawn(|| thread::sleep(Duration::from_millis(50)));
+
+    let mut seen_sleeping = false;
+    while !handle.is_finished() {
+        let info = thread::list().into_iter().find(|t| t.id == handle.id()).unwrap();
+        seen_sleeping |= info.state == ThreadState::Sleeping;
+        thread::yield_now();
+    }
+    assert!(seen_sleeping);
+    handle.join();
+}
+
+#[test_case]
+fn test_cpu_time_and_switches_tracked() {
+    let handle = thread::spawn(|| {
+        // Spin for a few ticks of our own CPU time
+        let me = thread::current().unwrap();
+        loop {
+            let info = thread::list().into_iter().find(|t| t.id == me).unwrap();
+            if info.cpu_ticks >= 3 {
+                return info.switches;
+            }
+        }
+    });
+    assert!(handle.join() >= 1);
+}
+
+#[test_case]
+fn test_wakeups_and_run_time_tracked() {
+    let handle = thread::spawn(|| {
+        for _ in 0..3 {
+            thread::sleep(Duration::from_millis(10));
+        }
+    });
+    let id = handle.id();
+    while !handle.is_finished() {
+        thread::yield_now();
+    }
+    let info = thread::info(id).unwrap();
+    assert!(info.wakeups >= 3, "only {} wakeups", info.wakeups);
+    assert!(info.cpu_time > Duration::ZERO);
+    handle.join();
+    assert!(thread::info(id).is_none());
+}
+
+#[test_case]
+fn test_idle_time_accounted() {
+    let _handle = thread::spawn(|| ());
+    let before = thread_scheduler::cpu_times();
+    thread::sleep(Duration::from_millis(50));
+    let after = thread_scheduler::cpu_times();
+
+    assert!(after.idle > before.idle, "sleeping left the CPU busy");
+    assert!(after.total() > before.total());
+    let idle = thread::list().into_iter().find(|t| t.is_idle).unwrap();
+    assert!(idle.cpu_time >= after.idle);
+}
@@ -0,0 +1,97 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(lithos::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use lithos::println;
+use lithos::serial::{self, ComPort};
+use lithos::sync::SpinLock;
+use lithos::task::executor::Executor;
+use lithos::task::{thread, Task};
+use lithos::tty::{self, Tty};
+use x86_64::instructions::port::Port;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use lithos::allocator;
+    use lithos::memory::{self, BootInfoFrameAllocator};
+    use x86_64::VirtAddr;
+
+    lithos::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+    memory::install(mapper, frame_allocator);
+
+    test_main();
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    lithos::test_panic_handler(info)
+}
+
+fn discard(_: &[u8]) {}
+
+static LOOPBACK: Tty = Tty::new("loopback", 24, 80, discard);
+
+#[test_case]
+fn test_ports_present() {
+    assert!(ComPort::Com1.is_present());
+    assert!(ComPort::Com2.is_present());
+}
+
+#[test_case]
+fn test_received_bytes_reach_tty() {
+    // Loop COM2's output back to its input; the receive interrupt still
+    // fires
+    let mut modem_control = Port::<u8>::new(ComPort::Com2.base() + 4);
+    unsafe { modem_control.write(0x1B) };
+
+    let executor = Executor::new();
+    executor.spawn(Task::new(serial::feed_terminal(ComPort::Com2, &LOOPBACK)));
+    executor.start(1);
+
+    serial::write_com2(b"ls -l\x7f\r");
+    let mut buf = [0; 16];
+    let count = LOOPBACK.read(&mut buf).unwrap();
+    unsafe { modem_control.write(0x0B) };
+
+    assert_eq!(&buf[..count], b"ls -\n");
+    assert_eq!(serial::dropped(ComPort::Com2), 0);
+}
+
+static CAPTURED: SpinLock<Vec<u8>> = SpinLock::new(Vec::new());
+
+fn capture(bytes: &[u8]) {
+    CAPTURED.lock().extend_from_slice(bytes);
+}
+
+static CAPTURE: Tty = Tty::new("capture", 24, 80, capture);
+
+#[test_case]
+fn test_prints_follow_redirect() {
+    let printer = thread::spawn(|| {
+        tty::with_output(&CAPTURE, || {
+            println!("to capture");
+            tty::with_output(tty::console(), || println!("to the console"));
+            // The breakpoint handler prints
+            x86_64::instructions::interrupts::int3();
+            println!("again");
+        });
+        assert!(tty::redirected().is_none());
+        println!("not captured");
+        0
+    });
+    printer.join();
+    assert_eq!(&CAPTURED.lock()[..], b"to capture\nagain\n");
+}
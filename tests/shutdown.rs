@@ -0,0 +1,38 @@
+#![no_std]
+#![no_main]
+
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use lithos::power::{self, PowerAction};
+use lithos::{exit_qemu, serial_print, serial_println, QemuExitCode};
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    serial_print!("shutdown::hooks_run_before_power_off... ");
+
+    // Registers the driver hooks, which run first
+    lithos::init();
+    power::register_shutdown_hook("first", first_hook);
+    power::register_shutdown_hook("last", last_hook);
+
+    // Powers QEMU off, failing the test, unless the hooks run first
+    power::shutdown();
+}
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+fn first_hook(action: PowerAction) {
+    assert_eq!(action, PowerAction::Shutdown);
+    assert_eq!(CALLS.fetch_add(1, Ordering::SeqCst), 0);
+}
+
+fn last_hook(_action: PowerAction) {
+    assert_eq!(CALLS.fetch_add(1, Ordering::SeqCst), 1);
+    serial_println!("[ok]");
+    exit_qemu(QemuExitCode::Success);
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    lithos::test_panic_handler(info)
+}
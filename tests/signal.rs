@@ -0,0 +1,354 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(lithos::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::vec;
+use alloc::vec::Vec;
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use core::time::Duration;
+use lithos::process::signal::{self, SigAction};
+use lithos::process::{self, ExitStatus, ProcessError, ProcessState, INIT_PID};
+use lithos::syscall::syscall_handler;
+use lithos::task::thread;
+use lithos::usermode;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use lithos::allocator;
+    use lithos::memory::{self, BootInfoFrameAllocator};
+    use x86_64::VirtAddr;
+
+    lithos::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+    memory::install(mapper, frame_allocator);
+
+    test_main();
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    lithos::test_panic_handler(info)
+}
+
+const SYS_RT_SIGACTION: u64 = 13;
+const SYS_RT_SIGPROCMASK: u64 = 14;
+const SYS_KILL: u64 = 62;
+
+/// Handler address for signals that kernel threads never deliver
+const DUMMY_HANDLER: u64 = 0x1000;
+
+#[test_case]
+fn test_blocked_signal_stays_pending() {
+    let child = process::spawn("masked", || {
+        let me = process::getpid();
+        signal::sigprocmask(signal::SIG_BLOCK, Some(signal::mask(signal::SIGUSR1))).unwrap();
+        signal::send(me, signal::SIGUSR1).unwrap();
+        assert_eq!(signal::pending(), signal::mask(signal::SIGUSR1));
+
+        // SIGKILL and SIGSTOP can't be blocked
+        let old = signal::sigprocmask(signal::SIG_SETMASK, Some(u64::MAX)).unwrap();
+        assert_eq!(old, signal::mask(signal::SIGUSR1));
+        let blocked = signal::sigprocmask(signal::SIG_UNBLOCK, Some(0)).unwrap();
+        assert_eq!(blocked & signal::mask(signal::SIGKILL), 0);
+        assert_eq!(blocked & signal::mask(signal::SIGSTOP), 0);
+        0
+    });
+    assert_eq!(process::wait(Some(child), false), Ok(Some((child, ExitStatus::Exited(0)))));
+}
+
+#[test_case]
+fn test_ignored_signals_are_discarded() {
+    let child = process::spawn("ignoring", || {
+        let me = process::getpid();
+        // SIGCHLD is ignored by default
+        signal::send(me, signal::SIGCHLD).unwrap();
+        assert_eq!(signal::pending(), 0);
+
+        let ignore = SigAction { handler: signal::SIG_IGN, ..SigAction::default() };
+        signal::send(me, signal::SIGUSR2).unwrap();
+        signal::sigaction(signal::SIGUSR2, Some(ignore)).unwrap();
+        assert_eq!(signal::pending(), 0, "SIG_IGN discards a pending signal");
+        signal::send(me, signal::SIGUSR2).unwrap();
+        assert_eq!(signal::pending(), 0);
+        0
+    });
+    assert_eq!(process::wait(Some(child), false), Ok(Some((child, ExitStatus::Exited(0)))));
+}
+
+#[test_case]
+fn test_kill_and_stop_cannot_be_caught() {
+    let action = SigAction { handler: signal::SIG_IGN, ..SigAction::default() };
+    assert_eq!(signal::sigaction(signal::SIGKILL, Some(action)), Err(ProcessError::InvalidSignal));
+    assert_eq!(signal::sigaction(signal::SIGSTOP, Some(action)), Err(ProcessError::InvalidSignal));
+    assert_eq!(signal::sigaction(0, None), Err(ProcessError::InvalidSignal));
+    assert_eq!(signal::sigaction(signal::NSIG + 1, None), Err(ProcessError::InvalidSignal));
+}
+
+#[test_case]
+fn test_sigaction_syscall() {
+    let child = process::spawn("sigaction", || {
+        let action = SigAction {
+            handler: DUMMY_HANDLER,
+            flags: signal::SA_RESTORER,
+            restorer: DUMMY_HANDLER,
+            mask: signal::mask(signal::SIGUSR2),
+        };
+        let act = &action as *const SigAction as u64;
+        assert_eq!(syscall_handler(SYS_RT_SIGACTION, signal::SIGUSR1 as u64, act, 0, 0, 0, 0), 0);
+
+        let mut old = SigAction::default();
+        let oldact = &mut old as *mut SigAction as u64;
+        assert_eq!(syscall_handler(SYS_RT_SIGACTION, signal::SIGUSR1 as u64, 0, oldact, 0, 0, 0), 0);
+        assert_eq!(old, action);
+
+        // Handlers need a restorer to return through
+        let action = SigAction { flags: 0, ..action };
+        let act = &action as *const SigAction as u64;
+        assert_eq!(syscall_handler(SYS_RT_SIGACTION, signal::SIGUSR2 as u64, act, 0, 0, 0, 0), -1);
+
+        // Handlers and restorers must be user addresses
+        let kernel = SigAction { handler: 0xFFFF_8000_0000_0000, ..old };
+        let act = &kernel as *const SigAction as u64;
+        assert_eq!(syscall_handler(SYS_RT_SIGACTION, signal::SIGUSR2 as u64, act, 0, 0, 0, 0), -1);
+        let kernel = SigAction { restorer: usermode::USER_SPACE_END, ..old };
+        let act = &kernel as *const SigAction as u64;
+        assert_eq!(syscall_handler(SYS_RT_SIGACTION, signal::SIGUSR2 as u64, act, 0, 0, 0, 0), -1);
+
+        // The action is read from, and the old one written to, user space only
+        let kernel = 0xFFFF_8000_0000_0000;
+        assert_eq!(syscall_handler(SYS_RT_SIGACTION, signal::SIGUSR2 as u64, kernel, 0, 0, 0, 0), -1);
+        assert_eq!(syscall_handler(SYS_RT_SIGACTION, signal::SIGUSR2 as u64, 0, kernel, 0, 0, 0), -1);
+
+        // Not truncated to SIGINT
+        assert_eq!(syscall_handler(SYS_RT_SIGACTION, 256 + signal::SIGINT as u64, 0, oldact, 0, 0, 0), -1);
+        0
+    });
+    assert_eq!(process::wait(Some(child), false), Ok(Some((child, ExitStatus::Exited(0)))));
+}
+
+#[test_case]
+fn test_sigprocmask_syscall() {
+    let child = process::spawn("sigprocmask", || {
+        let set = signal::mask(signal::SIGINT) | signal::mask(signal::SIGTERM);
+        let mut old = u64::MAX;
+        let (set_ptr, old_ptr) = (&set as *const u64 as u64, &mut old as *mut u64 as u64);
+        assert_eq!(syscall_handler(SYS_RT_SIGPROCMASK, signal::SIG_BLOCK, set_ptr, old_ptr, 0, 0, 0), 0);
+        assert_eq!(old, 0);
+        assert_eq!(syscall_handler(SYS_RT_SIGPROCMASK, signal::SIG_BLOCK, 0, old_ptr, 0, 0, 0), 0);
+        assert_eq!(old, set);
+        assert_eq!(syscall_handler(SYS_RT_SIGPROCMASK, 7, set_ptr, 0, 0, 0, 0), -1);
+
+        let kernel = 0xFFFF_8000_0000_0000;
+        assert_eq!(syscall_handler(SYS_RT_SIGPROCMASK, signal::SIG_SETMASK, kernel, 0, 0, 0, 0), -1);
+        assert_eq!(syscall_handler(SYS_RT_SIGPROCMASK, signal::SIG_BLOCK, 0, kernel, 0, 0, 0), -1);
+        assert_eq!(syscall_handler(SYS_RT_SIGPROCMASK, signal::SIG_BLOCK, 0, old_ptr, 0, 0, 0), 0);
+        assert_eq!(old, set);
+        0
+    });
+    assert_eq!(process::wait(Some(child), false), Ok(Some((child, ExitStatus::Exited(0)))));
+}
+
+#[test_case]
+fn test_kill_permissions() {
+    let child = process::spawn("unprivileged", || {
+        process::with_current(|process| {
+            process.credentials.uid = 1000;
+            process.credentials.euid = 1000;
+        });
+        let me = process::getpid();
+        assert_eq!(signal::kill(INIT_PID, signal::SIGUSR1), Err(ProcessError::PermissionDenied));
+        assert_eq!(syscall_handler(SYS_KILL, 1, signal::SIGUSR1 as u64, 0, 0, 0, 0), -1);
+        assert_eq!(syscall_handler(SYS_KILL, me.as_u32() as u64, 0, 0, 0, 0, 0), 0);
+        assert_eq!(syscall_handler(SYS_KILL, 9999, 0, 0, 0, 0, 0), -1);
+        0
+    });
+    assert_eq!(process::wait(Some(child), false), Ok(Some((child, ExitStatus::Exited(0)))));
+}
+
+#[test_case]
+fn test_child_exit_sends_sigchld() {
+    let catch = SigAction { handler: DUMMY_HANDLER, flags: signal::SA_RESTORER, ..SigAction::default() };
+    signal::sigaction(signal::SIGCHLD, Some(catch)).unwrap();
+
+    let child = process::spawn("child", || 0);
+    assert_eq!(process::wait(Some(child), false), Ok(Some((child, ExitStatus::Exited(0)))));
+    assert_ne!(signal::pending() & signal::mask(signal::SIGCHLD), 0);
+
+    signal::sigaction(signal::SIGCHLD, Some(SigAction::default())).unwrap();
+    assert_eq!(signal::pending(), 0);
+}
+
+// Ring 3 program that installs a SIGUSR1 handler and signals itself. The
+// handler records the signal number; the program exits with it once the
+// handler has returned through the restorer (or with 100+n on failure n).
+core::arch::global_asm!(
+    ".pushsection .rodata.signal_handler_test, \"a\"",
+    ".balign 16",
+    ".global signal_handler_test_start",
+    ".global signal_handler_test_end",
+    "signal_handler_test_start:",
+    "    sub rsp, 32",                                  // struct sigaction
+    "    lea rax, [rip + signal_handler_test_handler]",
+    "    mov [rsp], rax",
+    "    mov qword ptr [rsp + 8], 0x04000000",          // SA_RESTORER
+    "    lea rax, [rip + signal_handler_test_restorer]",
+    "    mov [rsp + 16], rax",
+    "    mov qword ptr [rsp + 24], 0",
+    "    mov rax, 13",                                  // rt_sigaction(SIGUSR1)
+    "    mov rdi, 10",
+    "    mov rsi, rsp",
+    "    xor edx, edx",
+    "    syscall",
+    "    mov rdi, 101",
+    "    test rax, rax",
+    "    jnz 3f",
+    "    mov rax, 39",                                  // getpid
+    "    syscall",
+    "    mov rbx, 0x1234",
+    "    mov rdi, rax",                                 // kill(getpid(), SIGUSR1)
+    "    mov rsi, 10",
+    "    mov rax, 62",
+    "    syscall",
+    "    mov rdi, 102",
+    "    test rax, rax",
+    "    jnz 3f",
+    "    mov rdi, 103",
+    "    cmp rbx, 0x1234",
+    "    jne 3f",
+    "    mov rax, 14",                                  // rt_sigprocmask(SIG_BLOCK, NULL, &old)
+    "    xor edi, edi",
+    "    xor esi, esi",
+    "    mov rdx, rsp",
+    "    syscall",
+    "    mov rdi, 104",
+    "    cmp qword ptr [rsp], 0",
+    "    jne 3f",
+    "    mov rdi, [rip + signal_handler_test_seen]",
+    "3:",
+    "    mov rax, 60",
+    "    syscall",
+    "    ud2",
+    "signal_handler_test_handler:",
+    "    mov [rip + signal_handler_test_seen], rdi",
+    "    mov rbx, 0",                                   // restored by rt_sigreturn
+    "    ret",
+    "signal_handler_test_restorer:",
+    "    mov rax, 15",
+    "    syscall",
+    "    ud2",
+    ".balign 8",
+    "signal_handler_test_seen:",
+    "    .quad 0",
+    "signal_handler_test_end:",
+    ".popsection",
+);
+
+// Ring 3 program that sends itself the signal in edi and exits with 0 if it
+// survives
+core::arch::global_asm!(
+    ".pushsection .rodata.signal_self_test, \"a\"",
+    ".balign 16",
+    ".global signal_self_test_start",
+    ".global signal_self_test_end",
+    "signal_self_test_start:",
+    "    mov r12, rdi",
+    "    mov rax, 39",
+    "    syscall",
+    "    mov rdi, rax",
+    "    mov rsi, r12",
+    "    mov rax, 62",
+    "    syscall",
+    "    xor edi, edi",
+    "    mov rax, 60",
+    "    syscall",
+    "    ud2",
+    "signal_self_test_end:",
+    ".popsection",
+);
+
+extern "C" {
+    static signal_handler_test_start: u8;
+    static signal_handler_test_end: u8;
+    static signal_self_test_start: u8;
+    static signal_self_test_end: u8;
+}
+
+fn handler_program() -> &'static [u8] {
+    unsafe {
+        let start = &raw const signal_handler_test_start;
+        let end = &raw const signal_handler_test_end;
+        core::slice::from_raw_parts(start, end as usize - start as usize)
+    }
+}
+
+/// Program that signals itself with `sig`
+fn self_signal_program(sig: u8) -> Vec<u8> {
+    let code = unsafe {
+        let start = &raw const signal_self_test_start;
+        let end = &raw const signal_self_test_end;
+        core::slice::from_raw_parts(start, end as usize - start as usize)
+    };
+    // Prefix the code with `mov edi, sig`
+    let mut program = vec![0xBF, sig, 0, 0, 0];
+    program.extend_from_slice(code);
+    program
+}
+
+#[test_case]
+fn test_handler_runs_in_user_mode() {
+    let child = process::spawn("handler", || usermode::run_program(handler_program()).unwrap() as i32);
+    let status = process::wait(Some(child), false);
+    assert_eq!(status, Ok(Some((child, ExitStatus::Exited(signal::SIGUSR1 as i32)))));
+}
+
+#[test_case]
+fn test_default_action_terminates() {
+    let child = process::spawn("terminated", || {
+        usermode::run_program(&self_signal_program(signal::SIGTERM)).unwrap() as i32
+    });
+    let status = process::wait(Some(child), false).unwrap().unwrap().1;
+    assert_eq!(status, ExitStatus::Signaled(signal::SIGTERM));
+    assert_eq!(status.wait_status(), signal::SIGTERM as i32);
+}
+
+#[test_case]
+fn test_default_ignore_returns_normally() {
+    let child = process::spawn("ignored", || {
+        usermode::run_program(&self_signal_program(signal::SIGCHLD)).unwrap() as i32
+    });
+    assert_eq!(process::wait(Some(child), false), Ok(Some((child, ExitStatus::Exited(0)))));
+}
+
+#[test_case]
+fn test_stop_and_continue() {
+    let child = process::spawn("stopped", || {
+        usermode::run_program(&self_signal_program(signal::SIGSTOP)).unwrap() as i32
+    });
+    while process::info(child).unwrap().state != ProcessState::Stopped {
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(process::wait(Some(child), true), Ok(None));
+
+    signal::send(child, signal::SIGCONT).unwrap();
+    assert_eq!(process::wait(Some(child), false), Ok(Some((child, ExitStatus::Exited(0)))));
+}
+
+#[test_case]
+fn test_signal_interrupts_user_loop() {
+    // `jmp .`: spins in ring 3 without ever making a system call
+    let child = process::spawn("spinner", || usermode::run_program(&[0xEB, 0xFE]).unwrap() as i32);
+    thread::sleep(Duration::from_millis(20));
+    signal::send(child, signal::SIGINT).unwrap();
+    let status = process::wait(Some(child), false).unwrap().unwrap().1;
+    assert_eq!(status, ExitStatus::Signaled(signal::SIGINT));
+}
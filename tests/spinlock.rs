@@ -0,0 +1,95 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(lithos::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use lithos::backtrace::Backtrace;
+use lithos::sync::spinlock::{self, SpinLock};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use lithos::allocator;
+    use lithos::memory::{self, BootInfoFrameAllocator};
+    use x86_64::VirtAddr;
+
+    lithos::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+    memory::install(mapper, frame_allocator);
+
+    test_main();
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    lithos::test_panic_handler(info)
+}
+
+/// The only lock class created in this file
+static SHARED: SpinLock<u64> = SpinLock::new(0);
+
+#[test_case]
+fn test_lock_and_try_lock() {
+    let mut guard = SHARED.lock();
+    *guard += 1;
+    assert!(SHARED.is_locked());
+    assert!(SHARED.try_lock().is_none());
+    drop(guard);
+    assert_eq!(*SHARED.try_lock().unwrap(), 1);
+}
+
+#[test_case]
+fn test_backtrace_has_frames() {
+    let trace = Backtrace::capture();
+    assert!(!trace.is_empty());
+    assert!(trace.frames().iter().all(|&address| address != 0));
+}
+
+#[test_case]
+fn test_stats_match_build() {
+    let _ = SHARED.lock();
+    assert_eq!(spinlock::stats().is_empty(), !spinlock::ENABLED);
+}
+
+#[cfg(feature = "lock-debug")]
+fn shared_stat() -> spinlock::LockStat {
+    spinlock::stats()
+        .into_iter()
+        .find(|stat| stat.site.file().ends_with("spinlock.rs") && stat.site.file().starts_with("tests"))
+        .expect("no class for SHARED")
+}
+
+#[cfg(feature = "lock-debug")]
+#[test_case]
+fn test_contention_counted_and_reported() {
+    use core::time::Duration;
+    use lithos::task::thread;
+
+    let before = shared_stat();
+    let guard = SHARED.lock();
+    let waiter = thread::spawn(|| {
+        *SHARED.lock() += 1;
+        0
+    });
+
+    // Hold on long enough for the waiter to be reported
+    while shared_stat().reports == before.reports {
+        thread::sleep(Duration::from_millis(100));
+    }
+    drop(guard);
+    waiter.join();
+
+    let after = shared_stat();
+    assert!(after.contended > before.contended);
+    assert!(after.acquisitions >= before.acquisitions + 2);
+    assert!(after.max_spin_cycles >= spinlock::REPORT_AFTER_CYCLES);
+}
@@ -0,0 +1,219 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(lithos::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use core::time::Duration;
+use lithos::sync::{Condvar, Mutex, RwLock, Semaphore, WaitQueue};
+use lithos::task::executor::Executor;
+use lithos::task::kernel_thread::ThreadState;
+use lithos::task::{thread, Task};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use lithos::allocator;
+    use lithos::memory::{self, BootInfoFrameAllocator};
+    use x86_64::VirtAddr;
+
+    lithos::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+    memory::install(mapper, frame_allocator);
+
+    test_main();
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    lithos::test_panic_handler(info)
+}
+
+/// Yield until `thread` is parked in the scheduler
+fn wait_until_blocked(thread: &thread::JoinHandle) {
+    loop {
+        let state = thread::list().into_iter().find(|t| t.id == thread.id()).map(|t| t.state);
+        if state == Some(ThreadState::Blocked) {
+            return;
+        }
+        thread::yield_now();
+    }
+}
+
+#[test_case]
+fn test_mutex_excludes() {
+    let counter = Arc::new(Mutex::new(0u64));
+    let handles: Vec<_> = (0..4)
+        .map(|_| {
+            let counter = counter.clone();
+            thread::spawn(move || {
+                for _ in 0..1000 {
+                    let mut value = counter.lock();
+                    let read = *value;
+                    // Invite preemption while holding the lock
+                    thread::yield_now();
+                    *value = read + 1;
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join();
+    }
+    assert_eq!(*counter.lock(), 4000);
+}
+
+#[test_case]
+fn test_mutex_parks_waiters_in_order() {
+    let mutex = Arc::new(Mutex::new(Vec::new()));
+    let guard = mutex.lock();
+    assert!(mutex.try_lock().is_none());
+
+    let waiters: Vec<_> = (0..3u64)
+        .map(|n| {
+            let mutex = mutex.clone();
+            let handle = thread::spawn(move || mutex.lock().push(n));
+            wait_until_blocked(&handle);
+            handle
+        })
+        .collect();
+
+    drop(guard);
+    for handle in waiters {
+        handle.join();
+    }
+    assert_eq!(*mutex.lock(), [0, 1, 2]);
+    assert!(!mutex.is_locked());
+}
+
+#[test_case]
+fn test_rwlock_readers_share_writers_exclude() {
+    let lock = Arc::new(RwLock::new(0u64));
+    let first = lock.read();
+    let second = lock.try_read().expect("readers should share");
+    assert_eq!(lock.reader_count(), 2);
+    assert!(lock.try_write().is_none());
+
+    let writer = {
+        let lock = lock.clone();
+        thread::spawn(move || *lock.write() += 1)
+    };
+    wait_until_blocked(&writer);
+
+    // A waiting writer holds off new readers
+    assert!(lock.try_read().is_none());
+    drop(first);
+    drop(second);
+    writer.join();
+    assert_eq!(*lock.read(), 1);
+}
+
+#[test_case]
+fn test_semaphore_limits_holders() {
+    let semaphore = Arc::new(Semaphore::new(2));
+    let inside = Arc::new(AtomicU64::new(0));
+    let most = Arc::new(AtomicU64::new(0));
+
+    let handles: Vec<_> = (0..5)
+        .map(|_| {
+            let (semaphore, inside, most) = (semaphore.clone(), inside.clone(), most.clone());
+            thread::spawn(move || {
+                semaphore.acquire();
+                let now = inside.fetch_add(1, Ordering::SeqCst) + 1;
+                most.fetch_max(now, Ordering::SeqCst);
+                thread::sleep(Duration::from_millis(20));
+                inside.fetch_sub(1, Ordering::SeqCst);
+                semaphore.release();
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join();
+    }
+    assert_eq!(most.load(Ordering::SeqCst), 2);
+    assert_eq!(semaphore.available(), 2);
+}
+
+#[test_case]
+fn test_condvar_wait_while() {
+    let state = Arc::new((Mutex::new(0u64), Condvar::new()));
+    let consumer = {
+        let state = state.clone();
+        thread::spawn(move || {
+            let (mutex, condvar) = &*state;
+            *condvar.wait_while(mutex.lock(), |value| *value < 3)
+        })
+    };
+
+    for _ in 0..3 {
+        let (mutex, condvar) = &*state;
+        *mutex.lock() += 1;
+        condvar.notify_all();
+        thread::yield_now();
+    }
+    assert_eq!(consumer.join(), 3);
+}
+
+#[test_case]
+fn test_wait_queue_notify() {
+    let queue = Arc::new(WaitQueue::new());
+    let flag = Arc::new(AtomicBool::new(false));
+    let waiter = {
+        let (queue, flag) = (queue.clone(), flag.clone());
+        thread::spawn(move || queue.wait_until(|| flag.load(Ordering::SeqCst)))
+    };
+    wait_until_blocked(&waiter);
+    assert_eq!(queue.len(), 1);
+
+    // Notified without the condition: it must go back to sleep
+    queue.notify_all();
+    wait_until_blocked(&waiter);
+
+    flag.store(true, Ordering::SeqCst);
+    assert_eq!(queue.notify_all(), 1);
+    waiter.join();
+    assert!(queue.is_empty());
+}
+
+#[test_case]
+fn test_async_primitives() {
+    let mutex = Arc::new(Mutex::new(0u64));
+    let semaphore = Arc::new(Semaphore::new(0));
+    let done = Arc::new(AtomicU64::new(0));
+
+    let executor = Executor::new();
+    for _ in 0..3 {
+        let (mutex, semaphore, done) = (mutex.clone(), semaphore.clone(), done.clone());
+        executor.spawn(Task::new(async move {
+            semaphore.acquire_async().await;
+            *mutex.lock_async().await += 1;
+            done.fetch_add(1, Ordering::SeqCst);
+        }));
+    }
+
+    // Hold the lock so the tasks have to wait for it asynchronously
+    let guard = mutex.lock();
+    executor.start(1);
+    for _ in 0..3 {
+        semaphore.release();
+    }
+    thread::sleep(Duration::from_millis(20));
+    assert_eq!(done.load(Ordering::SeqCst), 0);
+
+    drop(guard);
+    while done.load(Ordering::SeqCst) < 3 {
+        thread::yield_now();
+    }
+    assert_eq!(*mutex.lock(), 3);
+}
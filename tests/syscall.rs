@@ -0,0 +1,128 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(lithos::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader::{entry_point, BootInfo};
+use core::arch::asm;
+use core::panic::PanicInfo;
+use lithos::usermode;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use lithos::allocator;
+    use lithos::memory::{self, BootInfoFrameAllocator};
+    use x86_64::VirtAddr;
+
+    lithos::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+    memory::install(mapper, frame_allocator);
+
+    test_main();
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    lithos::test_panic_handler(info)
+}
+
+/// Issue a system call through the `int 0x80` gate from ring 0
+fn int80(num: u64, arg1: u64, arg2: u64, arg3: u64) -> i64 {
+    let ret: i64;
+    unsafe {
+        asm!(
+            "int 0x80",
+            inlateout("rax") num => ret,
+            in("rdi") arg1,
+            in("rsi") arg2,
+            in("rdx") arg3,
+        );
+    }
+    ret
+}
+
+#[test_case]
+fn test_int80_write_returns_count() {
+    let msg = b"int 0x80 ";
+    assert_eq!(int80(1, 1, msg.as_ptr() as u64, msg.len() as u64), msg.len() as i64);
+}
+
+#[test_case]
+fn test_int80_unknown_syscall_fails() {
+    assert_eq!(int80(999, 0, 0, 0), -1);
+}
+
+#[test_case]
+fn test_int80_rejects_null_buffer() {
+    assert_eq!(int80(1, 1, 0, 8), -1);
+}
+
+// Ring 3 program using the `syscall` instruction. It writes a message, checks
+// that an unknown syscall fails and that callee-saved registers survive, then
+// exits with the byte count of the write (or 100+n on failure n).
+core::arch::global_asm!(
+    ".pushsection .rodata.syscall_test, \"a\"",
+    ".balign 16",
+    ".global syscall_test_start",
+    ".global syscall_test_end",
+    "syscall_test_start:",
+    "    mov rbx, 0x1111",
+    "    mov rbp, 0x2222",
+    "    mov r13, 0x3333",
+    "    mov r14, 0x4444",
+    "    mov r15, 0x5555",
+    "    mov rax, 1",
+    "    mov rdi, 1",
+    "    lea rsi, [rip + syscall_test_msg]",
+    "    mov rdx, 8",
+    "    syscall",
+    "    mov r12, rax",
+    "    mov rax, 999",
+    "    syscall",
+    "    mov rdi, 101",
+    "    cmp rax, -1",
+    "    jne 3f",
+    "    mov rdi, 102",
+    "    cmp rbx, 0x1111",
+    "    jne 3f",
+    "    cmp rbp, 0x2222",
+    "    jne 3f",
+    "    cmp r13, 0x3333",
+    "    jne 3f",
+    "    cmp r14, 0x4444",
+    "    jne 3f",
+    "    cmp r15, 0x5555",
+    "    jne 3f",
+    "    mov rdi, r12",
+    "3:",
+    "    mov rax, 60",
+    "    syscall",
+    "    ud2",
+    "syscall_test_msg:",
+    "    .ascii \"syscall \"",
+    "syscall_test_end:",
+    ".popsection",
+);
+
+extern "C" {
+    static syscall_test_start: u8;
+    static syscall_test_end: u8;
+}
+
+#[test_case]
+fn test_syscall_instruction_from_ring3() {
+    let code = unsafe {
+        let start = &raw const syscall_test_start;
+        let end = &raw const syscall_test_end;
+        core::slice::from_raw_parts(start, end as usize - start as usize)
+    };
+    assert_eq!(usermode::run_program(code), Ok(8));
+}
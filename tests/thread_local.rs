@@ -0,0 +1,218 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(lithos::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use bootloader::{entry_point, BootInfo};
+use core::cell::{Cell, RefCell};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicU32, Ordering};
+use lithos::kernel_thread_local;
+use lithos::syscall::syscall_handler;
+use lithos::task::thread;
+use lithos::usermode;
+use x86_64::VirtAddr;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use lithos::allocator;
+    use lithos::memory::{self, BootInfoFrameAllocator};
+
+    lithos::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+    memory::install(mapper, frame_allocator);
+
+    test_main();
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    lithos::test_panic_handler(info)
+}
+
+const SYS_ARCH_PRCTL: u64 = 158;
+const ARCH_SET_GS: u64 = 0x1001;
+const ARCH_SET_FS: u64 = 0x1002;
+const ARCH_GET_FS: u64 = 0x1003;
+
+static INITIALISED: AtomicU32 = AtomicU32::new(0);
+static DROPPED: AtomicU32 = AtomicU32::new(0);
+
+struct Tracked(u32);
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        DROPPED.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+kernel_thread_local! {
+    static COUNTER: Cell<u32> = Cell::new(0);
+    static LOG: RefCell<Vec<u32>> = RefCell::new(Vec::new());
+    static TRACKED: Tracked = {
+        INITIALISED.fetch_add(1, Ordering::SeqCst);
+        Tracked(7)
+    };
+}
+
+fn bump() -> u32 {
+    COUNTER.with(|counter| {
+        counter.set(counter.get() + 1);
+        counter.get()
+    })
+}
+
+#[test_case]
+fn test_each_thread_has_its_own_value() {
+    bump();
+    let before = COUNTER.with(Cell::get);
+
+    let threads: Vec<_> = (0..3)
+        .map(|n| {
+            thread::spawn(move || {
+                for _ in 0..=n {
+                    bump();
+                    thread::yield_now();
+                }
+                COUNTER.with(Cell::get) as u64
+            })
+        })
+        .collect();
+    let counts: Vec<u64> = threads.into_iter().map(|thread| thread.join()).collect();
+
+    assert_eq!(counts, [1, 2, 3]);
+    assert_eq!(COUNTER.with(Cell::get), before);
+}
+
+#[test_case]
+fn test_values_can_change_in_place() {
+    LOG.with(|log| log.borrow_mut().extend([1, 2, 3]));
+    // `with` may nest, even across keys
+    let sum = LOG.with(|log| COUNTER.with(|_| log.borrow().iter().sum::<u32>()));
+    assert_eq!(sum, 6);
+}
+
+#[test_case]
+fn test_values_are_created_lazily_and_dropped_on_reap() {
+    let initialised = INITIALISED.load(Ordering::SeqCst);
+    let dropped = DROPPED.load(Ordering::SeqCst);
+
+    let untouched = thread::spawn(|| 0u64);
+    untouched.join();
+    let touched = thread::spawn(|| TRACKED.with(|tracked| tracked.0 as u64));
+    assert_eq!(touched.join(), 7);
+    thread::reap();
+
+    assert_eq!(INITIALISED.load(Ordering::SeqCst), initialised + 1);
+    assert_eq!(DROPPED.load(Ordering::SeqCst), dropped + 1);
+}
+
+#[test_case]
+fn test_fs_base_follows_threads() {
+    let threads: Vec<_> = (1..=3u64)
+        .map(|n| {
+            thread::spawn(move || {
+                let base = VirtAddr::new(n * 0x1000);
+                thread::set_fs_base(base);
+                for _ in 0..5 {
+                    thread::yield_now();
+                    assert_eq!(thread::fs_base(), base);
+                }
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join();
+    }
+}
+
+#[test_case]
+fn test_arch_prctl() {
+    let thread = thread::spawn(|| {
+        assert_eq!(syscall_handler(SYS_ARCH_PRCTL, ARCH_SET_FS, 0x7000_0000, 0, 0, 0, 0), 0);
+        let mut base = 0u64;
+        let out = &mut base as *mut u64 as u64;
+        assert_eq!(syscall_handler(SYS_ARCH_PRCTL, ARCH_GET_FS, out, 0, 0, 0, 0), 0);
+        assert_eq!(base, 0x7000_0000);
+
+        // Kernel and non-canonical addresses, and GS, are off limits
+        let kernel = 0xffff_8000_0000_0000;
+        assert_eq!(syscall_handler(SYS_ARCH_PRCTL, ARCH_SET_FS, kernel, 0, 0, 0, 0), -1);
+        assert_eq!(syscall_handler(SYS_ARCH_PRCTL, ARCH_SET_FS, 1 << 47, 0, 0, 0, 0), -1);
+        assert_eq!(syscall_handler(SYS_ARCH_PRCTL, ARCH_GET_FS, kernel, 0, 0, 0, 0), -1);
+        assert_eq!(syscall_handler(SYS_ARCH_PRCTL, ARCH_GET_FS, (1 << 47) - 4, 0, 0, 0, 0), -1);
+        assert_eq!(syscall_handler(SYS_ARCH_PRCTL, ARCH_SET_GS, 0x1000, 0, 0, 0, 0), -1);
+        assert_eq!(syscall_handler(SYS_ARCH_PRCTL, 0x9999, 0, 0, 0, 0, 0), -1);
+        assert_eq!(thread::fs_base().as_u64(), 0x7000_0000);
+    });
+    thread.join();
+}
+
+// Ring 3 program that points FS at its TLS block and exits with the value
+// read through `fs:` (or 100+n on failure n).
+core::arch::global_asm!(
+    ".pushsection .rodata.tls_test, \"a\"",
+    ".balign 16",
+    ".global tls_test_start",
+    ".global tls_test_end",
+    "tls_test_start:",
+    "    mov rax, 158",                 // arch_prctl(ARCH_SET_FS, &tls_block)
+    "    mov rdi, 0x1002",
+    "    lea rsi, [rip + tls_test_block]",
+    "    syscall",
+    "    mov rdi, 101",
+    "    test rax, rax",
+    "    jnz 3f",
+    "    mov rdi, qword ptr fs:[8]",
+    "3:",
+    "    mov rax, 60",
+    "    syscall",
+    "    ud2",
+    ".balign 8",
+    "tls_test_block:",
+    "    .quad 0",
+    "    .quad 42",
+    "tls_test_end:",
+    ".popsection",
+);
+
+extern "C" {
+    static tls_test_start: u8;
+    static tls_test_end: u8;
+}
+
+#[test_case]
+fn test_user_tls_through_fs() {
+    let code = unsafe {
+        let start = &raw const tls_test_start;
+        let end = &raw const tls_test_end;
+        core::slice::from_raw_parts(start, end as usize - start as usize)
+    };
+    let thread = thread::spawn(move || usermode::run_program(code).unwrap() as u64);
+    assert_eq!(thread.join(), 42);
+}
+
+#[test_case]
+fn test_clobbered_gs_base_is_restored() {
+    use x86_64::registers::model_specific::GsBase;
+
+    let thread = thread::spawn(|| {
+        COUNTER.with(|counter| counter.set(5));
+        let base = GsBase::read();
+        // What loading a GS selector in ring 3 leaves behind
+        GsBase::write(VirtAddr::zero());
+        let value = COUNTER.with(Cell::get);
+        assert_eq!(GsBase::read(), base);
+        value as u64
+    });
+    assert_eq!(thread.join(), 5);
+}
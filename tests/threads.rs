@@ -0,0 +1,138 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(lithos::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicU64, Ordering};
+use lithos::task::kernel_thread::ThreadState;
+use lithos::task::thread;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use lithos::allocator;
+    use lithos::memory::{self, BootInfoFrameAllocator};
+    use x86_64::VirtAddr;
+
+    lithos::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+    memory::install(mapper, frame_allocator);
+
+    test_main();
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    lithos::test_panic_handler(info)
+}
+
+fn returns_42() -> u64 {
+    42
+}
+
+fn exits_early() -> u64 {
+    thread::exit(7);
+}
+
+static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+fn count_and_return() -> u64 {
+    let n = COUNTER.fetch_add(1, Ordering::SeqCst) + 1;
+    thread::yield_now();
+    n
+}
+
+#[test_case]
+fn test_join_returns_exit_value() {
+    assert_eq!(thread::spawn(returns_42).join(), 42);
+}
+
+#[test_case]
+fn test_explicit_exit() {
+    assert_eq!(thread::spawn(exits_early).join(), 7);
+}
+
+#[test_case]
+fn test_joined_threads_are_removed() {
+    let handles: Vec<_> = (0..8).map(|_| thread::spawn(count_and_return)).collect();
+    let during = thread::list().len();
+
+    let sum: u64 = handles.into_iter().map(|handle| handle.join()).sum();
+    assert_eq!(sum, (1..=8).sum());
+    assert_eq!(thread::list().len(), during - 8);
+}
+
+#[test_case]
+fn test_finished_thread_is_zombie_until_joined() {
+    let handle = thread::spawn(returns_42);
+    while !handle.is_finished() {
+        thread::yield_now();
+    }
+    let zombie = thread::list().into_iter().find(|t| t.id == handle.id()).unwrap();
+    assert_eq!(zombie.state, ThreadState::Zombie);
+    assert_eq!(zombie.exit_value, Some(42));
+    assert_eq!(handle.join(), 42);
+}
+
+#[test_case]
+fn test_detached_threads_are_reaped() {
+    let ids: Vec<_> = (0..16).map(|_| thread::spawn(returns_42).id()).collect();
+
+    // Each exited thread is freed by the next reap
+    while thread::list().iter().any(|t| ids.contains(&t.id)) {
+        thread::yield_now();
+    }
+}
+
+#[test_case]
+fn test_closure_captures_data() {
+    let numbers = [1u64, 2, 3, 4];
+    let handle = thread::spawn(move || numbers.iter().sum::<u64>());
+    assert_eq!(handle.join(), 10);
+}
+
+#[test_case]
+fn test_closures_share_state() {
+    let total = Arc::new(AtomicU64::new(0));
+    let handles: Vec<_> = (1..=4u64)
+        .map(|n| {
+            let total = total.clone();
+            thread::spawn(move || {
+                total.fetch_add(n, Ordering::SeqCst);
+            })
+        })
+        .collect();
+
+    for handle in handles {
+        assert_eq!(handle.join(), 0);
+    }
+    assert_eq!(total.load(Ordering::SeqCst), 10);
+}
+
+#[test_case]
+fn test_builder_sets_name_and_stack_size() {
+    let handle = thread::Builder::new()
+        .name("worker")
+        .stack_size(64 * 1024)
+        .spawn(|| {
+            // Use a good part of the larger stack
+            let buffer = [1u8; 32 * 1024];
+            core::hint::black_box(&buffer).iter().map(|&b| b as u64).sum::<u64>()
+        });
+
+    let info = thread::list().into_iter().find(|t| t.id == handle.id()).unwrap();
+    assert_eq!(info.name.as_deref(), Some("worker"));
+    assert_eq!(info.stack_size, 64 * 1024);
+    assert_eq!(handle.join(), 32 * 1024);
+}
@@ -0,0 +1,120 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(lithos::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::sync::Arc;
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+use lithos::task::executor::Executor;
+use lithos::task::{thread, Task};
+use lithos::time::{self, Timer, TICK_HZ};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use lithos::allocator;
+    use lithos::memory::{self, BootInfoFrameAllocator};
+    use x86_64::VirtAddr;
+
+    lithos::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+    memory::install(mapper, frame_allocator);
+
+    test_main();
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    lithos::test_panic_handler(info)
+}
+
+#[test_case]
+fn test_duration_conversion() {
+    let tick = time::tick_duration();
+    assert_eq!(time::duration_to_ticks(Duration::ZERO), 0);
+    assert_eq!(time::duration_to_ticks(tick), 1);
+    assert_eq!(time::duration_to_ticks(tick + Duration::from_nanos(1)), 2);
+    assert_eq!(time::duration_to_ticks(Duration::from_secs(1)), TICK_HZ);
+    assert_eq!(time::ticks_to_duration(TICK_HZ), Duration::from_secs(1));
+}
+
+#[test_case]
+fn test_ticks_advance() {
+    let start = time::ticks();
+    while time::ticks() < start + 2 {
+        x86_64::instructions::hlt();
+    }
+    assert!(time::uptime() >= time::ticks_to_duration(start + 2));
+}
+
+/// Ticks that passed while a thread slept for `duration`
+fn measure_sleep(duration: Duration) -> u64 {
+    let handle = thread::spawn(move || {
+        let start = time::ticks();
+        thread::sleep(duration);
+        time::ticks() - start
+    });
+    handle.join()
+}
+
+#[test_case]
+fn test_thread_sleep_accuracy() {
+    for ms in [10, 30, 100] {
+        let duration = Duration::from_millis(ms);
+        let wanted = time::duration_to_ticks(duration);
+        let slept = measure_sleep(duration);
+        assert!(slept >= wanted, "slept {} ticks, wanted {}", slept, wanted);
+        assert!(slept <= wanted + 1, "slept {} ticks, wanted {}", slept, wanted);
+    }
+}
+
+#[test_case]
+fn test_sleepers_wake_in_deadline_order() {
+    let order = Arc::new(AtomicU64::new(0));
+    let spawn_sleeper = |ms: u64| {
+        let order = order.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(ms));
+            order.fetch_add(1, Ordering::SeqCst)
+        })
+    };
+
+    let late = spawn_sleeper(80);
+    let early = spawn_sleeper(20);
+    let middle = spawn_sleeper(50);
+    assert_eq!(early.join(), 0);
+    assert_eq!(middle.join(), 1);
+    assert_eq!(late.join(), 2);
+}
+
+#[test_case]
+fn test_async_timer() {
+    let fired_at = Arc::new(AtomicU64::new(0));
+    let deadline = time::ticks() + 5;
+
+    let executor = Executor::new();
+    let task_fired_at = fired_at.clone();
+    executor.spawn(Task::new(async move {
+        // A timer dropped before it fires must not wake anything
+        drop(Timer::after(Duration::from_millis(10)));
+        Timer::at(deadline).await;
+        task_fired_at.store(time::ticks(), Ordering::SeqCst);
+    }));
+    executor.start(1);
+
+    while fired_at.load(Ordering::SeqCst) == 0 {
+        thread::sleep(time::tick_duration());
+    }
+    let fired = fired_at.load(Ordering::SeqCst);
+    assert!(fired >= deadline && fired <= deadline + 1, "fired at {}, deadline {}", fired, deadline);
+}
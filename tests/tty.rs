@@ -0,0 +1,256 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(lithos::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use core::ptr;
+use lithos::process::{self, signal, ExitStatus};
+use lithos::syscall::{syscall_handler, Syscall};
+use lithos::task::thread;
+use lithos::tty::line_discipline::LineDiscipline;
+use lithos::tty::termios::{self, Termios, ECHO, TCGETS, TCSETS};
+use lithos::tty::{self, SetWhen, Tty};
+use lithos::vfs::devfs::DevDirectory;
+use lithos::vfs::fd_table::{FileDescriptor, OpenFlags};
+use lithos::vfs::{ops, ramfs::RamFs};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use lithos::allocator;
+    use lithos::memory::{self, BootInfoFrameAllocator};
+    use x86_64::VirtAddr;
+
+    lithos::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+    memory::install(mapper, frame_allocator);
+
+    test_main();
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    lithos::test_panic_handler(info)
+}
+
+/// Type `input`, returning the echo and any signal characters' signals
+fn type_into(discipline: &mut LineDiscipline, termios: &Termios, input: &[u8]) -> (String, Vec<u8>) {
+    let mut echo = String::new();
+    let mut signals = Vec::new();
+    for &byte in input {
+        let signal = discipline.receive(byte, termios, &mut |bytes: &[u8]| {
+            echo.push_str(core::str::from_utf8(bytes).unwrap())
+        });
+        signals.extend(signal);
+    }
+    (echo, signals)
+}
+
+fn read_all(discipline: &mut LineDiscipline, termios: &Termios) -> Option<Vec<u8>> {
+    let mut buf = [0; 64];
+    discipline.read(&mut buf, termios).map(|count| buf[..count].to_vec())
+}
+
+#[test_case]
+fn test_canonical_erase_and_kill() {
+    let termios = Termios::cooked();
+    let mut discipline = LineDiscipline::new();
+
+    let (echo, _) = type_into(&mut discipline, &termios, b"lx\x7fs");
+    assert_eq!(echo, "lx\x08 \x08s");
+    assert_eq!(discipline.line(), b"ls");
+    assert!(read_all(&mut discipline, &termios).is_none());
+
+    type_into(&mut discipline, &termios, b" /tmp\x15pwd\r");
+    assert_eq!(read_all(&mut discipline, &termios).as_deref(), Some(&b"pwd\n"[..]));
+    assert!(!discipline.readable(&termios));
+}
+
+#[test_case]
+fn test_erase_removes_whole_characters() {
+    let termios = Termios::cooked();
+    let mut discipline = LineDiscipline::new();
+    type_into(&mut discipline, &termios, "aé\x7f".as_bytes());
+    assert_eq!(discipline.line(), b"a");
+}
+
+#[test_case]
+fn test_one_line_per_read() {
+    let termios = Termios::cooked();
+    let mut discipline = LineDiscipline::new();
+    type_into(&mut discipline, &termios, b"one\ntwo\n");
+    assert_eq!(read_all(&mut discipline, &termios).as_deref(), Some(&b"one\n"[..]));
+    assert_eq!(read_all(&mut discipline, &termios).as_deref(), Some(&b"two\n"[..]));
+}
+
+#[test_case]
+fn test_end_of_file() {
+    let termios = Termios::cooked();
+    let mut discipline = LineDiscipline::new();
+    type_into(&mut discipline, &termios, b"abc\x04\x04");
+    assert_eq!(read_all(&mut discipline, &termios).as_deref(), Some(&b"abc"[..]));
+    assert_eq!(read_all(&mut discipline, &termios).as_deref(), Some(&b""[..]));
+    assert!(read_all(&mut discipline, &termios).is_none());
+}
+
+#[test_case]
+fn test_signal_characters() {
+    let termios = Termios::cooked();
+    let mut discipline = LineDiscipline::new();
+    let (echo, signals) = type_into(&mut discipline, &termios, b"sleep\x03\x1a");
+    assert_eq!(signals, [signal::SIGINT, signal::SIGTSTP]);
+    assert_eq!(echo, "sleep^C\n^Z\n");
+    assert!(discipline.line().is_empty());
+
+    // Passed through as bytes once signals are off
+    let raw = termios.raw();
+    let (_, signals) = type_into(&mut discipline, &raw, b"\x03");
+    assert!(signals.is_empty());
+    assert_eq!(read_all(&mut discipline, &raw).as_deref(), Some(&b"\x03"[..]));
+}
+
+#[test_case]
+fn test_raw_mode() {
+    let mut raw = Termios::cooked().raw();
+    let mut discipline = LineDiscipline::new();
+
+    let (echo, _) = type_into(&mut discipline, &raw, b"\x7f\r");
+    assert!(echo.is_empty());
+    assert_eq!(read_all(&mut discipline, &raw).as_deref(), Some(&b"\x7f\r"[..]));
+
+    raw.cc[termios::VMIN] = 3;
+    type_into(&mut discipline, &raw, b"ab");
+    assert!(read_all(&mut discipline, &raw).is_none());
+    type_into(&mut discipline, &raw, b"c");
+    assert_eq!(read_all(&mut discipline, &raw).as_deref(), Some(&b"abc"[..]));
+}
+
+#[test_case]
+fn test_half_typed_line_carries_into_raw_mode() {
+    let termios = Termios::cooked();
+    let mut discipline = LineDiscipline::new();
+    type_into(&mut discipline, &termios, b"ec");
+    discipline.set_canonical(false);
+    assert_eq!(read_all(&mut discipline, &termios.raw()).as_deref(), Some(&b"ec"[..]));
+}
+
+#[test_case]
+fn test_unfinished_raw_input_carries_into_canonical_mode() {
+    let termios = Termios::cooked();
+    let mut discipline = LineDiscipline::new();
+    type_into(&mut discipline, &termios.raw(), b"ab\ncd");
+    discipline.set_canonical(true);
+    assert_eq!(discipline.line(), b"cd");
+    assert_eq!(read_all(&mut discipline, &termios).as_deref(), Some(&b"ab\n"[..]));
+    assert!(read_all(&mut discipline, &termios).is_none(), "spurious end of file");
+
+    // The unfinished bytes are the line being edited
+    type_into(&mut discipline, &termios, b"\x7fe\n");
+    assert_eq!(read_all(&mut discipline, &termios).as_deref(), Some(&b"ce\n"[..]));
+    assert!(read_all(&mut discipline, &termios).is_none());
+}
+
+fn discard(_: &[u8]) {}
+
+static TEST_TTY: Tty = Tty::new("test", 25, 80, discard);
+
+#[test_case]
+fn test_reader_blocks_until_a_line() {
+    TEST_TTY.set_termios(Termios::cooked(), SetWhen::Flush);
+    let reader = thread::spawn(|| {
+        let mut buf = [0; 16];
+        TEST_TTY.read(&mut buf).unwrap() as u64
+    });
+    thread::yield_now();
+    TEST_TTY.receive(b"hi");
+    thread::yield_now();
+    TEST_TTY.receive(b"\r");
+    assert_eq!(reader.join(), 3);
+}
+
+#[test_case]
+fn test_interrupt_ends_a_blocked_read() {
+    TEST_TTY.set_termios(Termios::cooked(), SetWhen::Flush);
+    let pid = process::spawn("reader", || {
+        let mut buf = [0; 16];
+        match TEST_TTY.read(&mut buf) {
+            Err(tty::TtyError::Interrupted) => 7,
+            _ => 0,
+        }
+    });
+    TEST_TTY.set_foreground(Some(pid));
+    thread::yield_now();
+    TEST_TTY.receive(b"\x03");
+
+    let status = process::wait(Some(pid), false).unwrap();
+    TEST_TTY.set_foreground(None);
+    assert_eq!(status, Some((pid, ExitStatus::Exited(7))));
+}
+
+#[test_case]
+fn test_standard_descriptors_on_console() {
+    for fd in 0..3 {
+        let tty = ops::vfs_tty(FileDescriptor(fd)).unwrap();
+        assert!(ptr::eq(tty, tty::console()));
+    }
+    assert_eq!(ops::vfs_write(FileDescriptor(1), b"").unwrap(), 0);
+    assert!(ptr::eq(tty::controlling().unwrap(), tty::console()));
+}
+
+#[test_case]
+fn test_dev_nodes() {
+    let ramfs = RamFs::new();
+    ops::init(ramfs.root_node());
+    ops::vfs_mkdir("/dev").unwrap();
+    ops::mount("/dev", DevDirectory::new().root_node()).unwrap();
+
+    let entries = ops::vfs_readdir("/dev").unwrap();
+    assert!(entries.iter().any(|name| name == "tty"));
+    assert!(entries.iter().any(|name| name == "console"));
+
+    for path in ["/dev/tty", "/dev/console"] {
+        let fd = ops::vfs_open(path, OpenFlags::read_write()).unwrap();
+        assert!(ptr::eq(ops::vfs_tty(fd).unwrap(), tty::console()));
+        ops::vfs_close(fd).unwrap();
+    }
+    let fd = ops::vfs_open("/dev/null", OpenFlags::read_write()).unwrap();
+    assert!(ops::vfs_tty(fd).is_err());
+    ops::vfs_close(fd).unwrap();
+}
+
+#[test_case]
+fn test_termios_ioctls() {
+    let ioctl = |request: u64, termios: &mut Termios| {
+        syscall_handler(Syscall::Ioctl as u64, 0, request, termios as *mut Termios as u64, 0, 0, 0)
+    };
+    let mut settings = Termios::cooked().raw();
+    assert_eq!(ioctl(TCGETS, &mut settings), 0);
+    assert_eq!(settings, tty::console().termios());
+    let saved = settings;
+
+    settings.lflag &= !ECHO;
+    assert_eq!(ioctl(TCSETS, &mut settings), 0);
+    assert!(!tty::console().termios().has(ECHO));
+
+    let mut restore = saved;
+    assert_eq!(ioctl(TCSETS, &mut restore), 0);
+    assert_eq!(tty::console().termios(), saved);
+
+    // Not a terminal
+    let mut ignored = saved;
+    assert_eq!(syscall_handler(Syscall::Ioctl as u64, 42, TCGETS, &mut ignored as *mut Termios as u64, 0, 0, 0), -1);
+
+    // Kernel memory
+    assert_eq!(syscall_handler(Syscall::Ioctl as u64, 0, TCGETS, 0xFFFF_8000_0000_0000, 0, 0, 0), -1);
+}
@@ -0,0 +1,49 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(lithos::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use lithos::usermode::{self, test_program};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use lithos::allocator;
+    use lithos::memory::{self, BootInfoFrameAllocator};
+    use x86_64::VirtAddr;
+
+    lithos::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+    memory::install(mapper, frame_allocator);
+
+    test_main();
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    lithos::test_panic_handler(info)
+}
+
+#[test_case]
+fn test_program_exits_from_ring3() {
+    let code = usermode::run_program(test_program::code()).expect("failed to run program");
+    assert_eq!(code, test_program::EXPECTED_EXIT_CODE);
+}
+
+#[test_case]
+fn test_program_can_run_twice() {
+    for _ in 0..2 {
+        let code = usermode::run_program(test_program::code()).expect("failed to run program");
+        assert_eq!(code, test_program::EXPECTED_EXIT_CODE);
+    }
+    assert!(!usermode::user_program_running());
+}